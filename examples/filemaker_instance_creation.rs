@@ -10,7 +10,7 @@ async fn main() -> Result<()> {
     let table = "your_table";
 
     // Create a Filemaker instance
-    let filemaker = Filemaker::new(username, password, database, table).await?;
+    let _filemaker = Filemaker::new(username, password, database, table).await?;
     println!("Filemaker instance created successfully.");
 
     Ok(())
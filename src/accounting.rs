@@ -0,0 +1,232 @@
+//! Process-wide accounting of Data API sessions.
+//!
+//! Every session this process opens takes one of the server's connection licenses until it
+//! is logged out or expires. Sessions are registered when they are created and forgotten
+//! when they are ended or replaced, so [`Filemaker::open_sessions`] lists those still open
+//! and limits set with [`Filemaker::set_session_limits`] can flag leaks.
+
+use crate::{query, Filemaker};
+use log::*;
+use reqwest::Client;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+static SESSIONS: Mutex<BTreeMap<String, TrackedSession>> = Mutex::new(BTreeMap::new());
+static LIMITS: RwLock<SessionLimits> = RwLock::new(SessionLimits {
+    max_open: None,
+    max_age: None,
+    close_excess: false,
+});
+
+/// Thresholds above which the sessions of the process are reported as leaking.
+///
+/// Checked each time a session is created and by [`Filemaker::check_sessions`].
+///
+/// ```rust,ignore
+/// Filemaker::set_session_limits(SessionLimits {
+///     max_open: Some(20),
+///     max_age: Some(Duration::from_secs(60 * 60)),
+///     close_excess: true,
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SessionLimits {
+    /// The number of open sessions above which a warning is logged; `None` is unlimited.
+    pub max_open: Option<usize>,
+    /// The age above which a session is reported as leaked; `None` is unlimited.
+    pub max_age: Option<Duration>,
+    /// Whether to log out the least recently used sessions beyond `max_open`. Clients whose
+    /// session is closed this way log in again on their next request if they can.
+    pub close_excess: bool,
+}
+
+/// A session opened by this process, as listed by [`Filemaker::open_sessions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenSession {
+    /// The Data API base URL the session was opened on.
+    pub endpoint: String,
+    /// The database the session was opened for.
+    pub database: String,
+    /// The time since the session was opened.
+    pub age: Duration,
+    /// The time since the session was last used by a request.
+    pub idle: Duration,
+}
+
+struct TrackedSession {
+    endpoint: String,
+    database: String,
+    opened: Instant,
+    used: Instant,
+    // Sent along when the session is closed as excess
+    client: Client,
+    // Set once the session was reported as older than the maximum age
+    reported_old: bool,
+}
+
+impl TrackedSession {
+    fn info(&self) -> OpenSession {
+        OpenSession {
+            endpoint: self.endpoint.clone(),
+            database: self.database.clone(),
+            age: self.opened.elapsed(),
+            idle: self.used.elapsed(),
+        }
+    }
+}
+
+fn with_sessions<T>(f: impl FnOnce(&mut BTreeMap<String, TrackedSession>) -> T) -> T {
+    let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut sessions)
+}
+
+fn limits() -> SessionLimits {
+    *LIMITS.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Registers a session opened on `endpoint` and enforces the session limits.
+pub(crate) fn opened(client: &Client, endpoint: &str, database: &str, token: &str) {
+    let now = Instant::now();
+    let excess = with_sessions(|sessions| {
+        sessions.insert(
+            token.to_string(),
+            TrackedSession {
+                endpoint: endpoint.to_string(),
+                database: database.to_string(),
+                opened: now,
+                used: now,
+                client: client.clone(),
+                reported_old: false,
+            },
+        );
+        take_excess(sessions, limits())
+    });
+    report_old_sessions();
+    for (token, session) in excess {
+        tokio::spawn(async move {
+            let url = format!(
+                "{}/databases/{}/sessions/{}",
+                session.endpoint,
+                query::encode_url_component(&session.database),
+                token
+            );
+            if let Err(e) = session.client.delete(&url).send().await {
+                warn!("Failed to close excess session: {}", e);
+            }
+        });
+    }
+}
+
+/// Records that the session `token` was used by a request.
+pub(crate) fn used(token: &str) {
+    with_sessions(|sessions| {
+        if let Some(session) = sessions.get_mut(token) {
+            session.used = Instant::now();
+        }
+    });
+}
+
+/// Forgets the session `token`, because it was ended or has expired.
+pub(crate) fn closed(token: &str) {
+    with_sessions(|sessions| sessions.remove(token));
+}
+
+/// Warns if more sessions are open than allowed, and removes the least recently used ones
+/// beyond the limit if they should be closed.
+fn take_excess(
+    sessions: &mut BTreeMap<String, TrackedSession>,
+    limits: SessionLimits,
+) -> Vec<(String, TrackedSession)> {
+    let Some(max_open) = limits.max_open else {
+        return vec![];
+    };
+    if sessions.len() <= max_open {
+        return vec![];
+    }
+    warn!(
+        "{} Data API sessions are open, more than the limit of {}; sessions may be leaking",
+        sessions.len(),
+        max_open
+    );
+    if !limits.close_excess {
+        return vec![];
+    }
+    let mut by_use: Vec<(String, Instant)> = sessions
+        .iter()
+        .map(|(token, session)| (token.clone(), session.used))
+        .collect();
+    by_use.sort_by_key(|(_, used)| *used);
+    let excess = sessions.len() - max_open;
+    by_use
+        .into_iter()
+        .take(excess)
+        .filter_map(|(token, _)| {
+            let session = sessions.remove(&token)?;
+            info!(
+                "Closing session on {} for {}, unused for {:?}",
+                session.endpoint,
+                session.database,
+                session.used.elapsed()
+            );
+            Some((token, session))
+        })
+        .collect()
+}
+
+/// Warns once about every session older than the maximum age, and returns them.
+fn report_old_sessions() -> Vec<OpenSession> {
+    let Some(max_age) = limits().max_age else {
+        return vec![];
+    };
+    with_sessions(|sessions| {
+        sessions
+            .values_mut()
+            .filter(|session| session.opened.elapsed() > max_age)
+            .map(|session| {
+                if !session.reported_old {
+                    session.reported_old = true;
+                    warn!(
+                        "Data API session on {} for {} has been open for {:?}; it may have leaked",
+                        session.endpoint,
+                        session.database,
+                        session.opened.elapsed()
+                    );
+                }
+                session.info()
+            })
+            .collect()
+    })
+}
+
+impl Filemaker {
+    /// Sets the thresholds above which the sessions of the process are reported as leaking.
+    ///
+    /// The limits apply to every client of the process.
+    ///
+    /// # Arguments
+    /// * `limits` - The maximum number and age of open sessions
+    pub fn set_session_limits(limits: SessionLimits) {
+        debug!("Using session limits {:?}", limits);
+        *LIMITS.write().unwrap_or_else(|e| e.into_inner()) = limits;
+    }
+
+    /// Returns the sessions opened by this process that have not been ended or replaced,
+    /// oldest first.
+    ///
+    /// Sessions that expired on the server without a request noticing are still listed.
+    pub fn open_sessions() -> Vec<OpenSession> {
+        let mut open: Vec<OpenSession> =
+            with_sessions(|sessions| sessions.values().map(TrackedSession::info).collect());
+        open.sort_by_key(|session| std::cmp::Reverse(session.age));
+        open
+    }
+
+    /// Returns the open sessions older than [`SessionLimits::max_age`], warning about those
+    /// not reported before.
+    ///
+    /// Call it periodically, e.g. from a health check, to notice sessions that leak slowly.
+    pub fn check_sessions() -> Vec<OpenSession> {
+        report_old_sessions()
+    }
+}
@@ -0,0 +1,192 @@
+//! Grouped aggregates computed on the client.
+//!
+//! The Data API has no aggregate queries, so [`Filemaker::aggregate`] pages through the
+//! matching records and folds each page into running totals per group, without holding the
+//! records in memory.
+
+use crate::import::key_string;
+use crate::{Cursor, Filemaker};
+use anyhow::{Context, Result};
+use log::*;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// The number of records fetched per request.
+const AGGREGATE_PAGE_SIZE: u64 = 500;
+
+/// An aggregate computed for each group of records.
+///
+/// Values are read with the client's number format (see [`Filemaker::with_number_format`]);
+/// empty values are ignored, and a group without any value has no result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Aggregate {
+    /// The number of records in the group.
+    Count,
+    /// The sum of a numeric field.
+    Sum(String),
+    /// The mean of a numeric field.
+    Avg(String),
+    /// The smallest value of a numeric field.
+    Min(String),
+    /// The largest value of a numeric field.
+    Max(String),
+}
+
+impl Aggregate {
+    fn field(&self) -> Option<&str> {
+        match self {
+            Aggregate::Count => None,
+            Aggregate::Sum(field)
+            | Aggregate::Avg(field)
+            | Aggregate::Min(field)
+            | Aggregate::Max(field) => Some(field),
+        }
+    }
+}
+
+/// One group of records and its aggregates, as returned by [`Filemaker::aggregate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateGroup {
+    /// The values of the group-by fields, in the order they were given.
+    pub key: Vec<Value>,
+    /// The number of records in the group.
+    pub count: u64,
+    /// The result of each aggregate, in the order they were given; `None` if no record of
+    /// the group has a value in the field.
+    pub values: Vec<Option<f64>>,
+}
+
+/// The running totals of one aggregate over the values seen so far.
+#[derive(Debug, Clone, Copy, Default)]
+struct Accumulator {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: f64) {
+        match self.count {
+            0 => (self.min, self.max) = (value, value),
+            _ => (self.min, self.max) = (self.min.min(value), self.max.max(value)),
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    fn result(&self, aggregate: &Aggregate, records: u64) -> Option<f64> {
+        if let Aggregate::Count = aggregate {
+            return Some(records as f64);
+        }
+        if self.count == 0 {
+            return None;
+        }
+        match aggregate {
+            Aggregate::Count => unreachable!(),
+            Aggregate::Sum(_) => Some(self.sum),
+            Aggregate::Avg(_) => Some(self.sum / self.count as f64),
+            Aggregate::Min(_) => Some(self.min),
+            Aggregate::Max(_) => Some(self.max),
+        }
+    }
+}
+
+struct Group {
+    key: Vec<Value>,
+    count: u64,
+    accumulators: Vec<Accumulator>,
+}
+
+impl Filemaker {
+    /// Computes grouped counts, sums, means, minimums and maximums over the layout's records.
+    ///
+    /// The records matching `query` (all records if it is empty) are fetched page by page
+    /// and grouped by the values of `group_by`; with no group-by fields, all records form a
+    /// single group. Groups are returned ordered by their key.
+    ///
+    /// ```rust,ignore
+    /// let groups = filemaker
+    ///     .aggregate(
+    ///         &[HashMap::from([("Status".to_string(), "paid".to_string())])],
+    ///         &["Region"],
+    ///         &[Aggregate::Count, Aggregate::Avg("Total".to_string())],
+    ///     )
+    ///     .await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `query` - Find requests selecting the records, as for [`Filemaker::search`]
+    /// * `group_by` - The fields whose values form the groups
+    /// * `aggregations` - The aggregates to compute for each group
+    ///
+    /// # Returns
+    /// * `Result<Vec<AggregateGroup>>` - The groups and their aggregates, or an error if a page fails or a value is not a number
+    pub async fn aggregate(
+        &self,
+        query: &[HashMap<String, String>],
+        group_by: &[&str],
+        aggregations: &[Aggregate],
+    ) -> Result<Vec<AggregateGroup>> {
+        let mut groups: BTreeMap<Vec<String>, Group> = BTreeMap::new();
+        let mut records = 0u64;
+        let mut cursor = Cursor::new(AGGREGATE_PAGE_SIZE);
+        if !query.is_empty() {
+            cursor = cursor.with_query(query.to_vec());
+        }
+        loop {
+            let page = self.fetch_page(&cursor).await?;
+            for record in &page.records {
+                records += 1;
+                let fields = &record["fieldData"];
+                let key: Vec<Value> = group_by
+                    .iter()
+                    .map(|field| fields.get(*field).cloned().unwrap_or(json!("")))
+                    .collect();
+                let group = groups
+                    .entry(key.iter().map(key_string).collect())
+                    .or_insert_with(|| Group {
+                        key,
+                        count: 0,
+                        accumulators: vec![Accumulator::default(); aggregations.len()],
+                    });
+                group.count += 1;
+                for (aggregate, accumulator) in aggregations.iter().zip(&mut group.accumulators) {
+                    let Some(field) = aggregate.field() else {
+                        continue;
+                    };
+                    let value = fields.get(field).unwrap_or(&Value::Null);
+                    let number = self.to_number(value).with_context(|| {
+                        format!("Field {} of record {}", field, record["recordId"])
+                    })?;
+                    if let Some(number) = number {
+                        accumulator.add(number);
+                    }
+                }
+            }
+            match page.next {
+                Some(next) => cursor = next,
+                None => break,
+            }
+        }
+        debug!(
+            "Aggregated {} records of {} into {} groups",
+            records,
+            self.layout(),
+            groups.len()
+        );
+
+        Ok(groups
+            .into_values()
+            .map(|group| AggregateGroup {
+                values: aggregations
+                    .iter()
+                    .zip(&group.accumulators)
+                    .map(|(aggregate, accumulator)| accumulator.result(aggregate, group.count))
+                    .collect(),
+                key: group.key,
+                count: group.count,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,124 @@
+//! Stamping audit fields on every record write.
+
+use crate::coerce::format_timestamp;
+use crate::hooks::RecordHook;
+use crate::Filemaker;
+use anyhow::Result;
+use chrono::Utc;
+use chrono_tz::Tz;
+use futures::future::BoxFuture;
+use log::*;
+use serde_json::{Map, Value};
+
+/// The audit fields [`Filemaker::with_audit_fields`] sets on every create and update.
+///
+/// Fields left unset are not written.
+///
+/// ```rust,ignore
+/// let audit = AuditFields::default()
+///     .with_modified_by("ModifiedBy", "billing-sync")
+///     .with_modified_at("ModifiedAt")
+///     .with_source_system("SourceSystem", "billing");
+/// let filemaker = filemaker.with_audit_fields(audit);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditFields {
+    /// The field set to [`AuditFields::user`], e.g. `ModifiedBy`.
+    pub modified_by_field: Option<String>,
+    /// The user name written to the modified-by field.
+    pub user: String,
+    /// The timestamp field set to the time of the write, e.g. `ModifiedAt`.
+    pub modified_at_field: Option<String>,
+    /// The field set to [`AuditFields::source_system`], e.g. `SourceSystem`.
+    pub source_system_field: Option<String>,
+    /// The name of the system making the writes.
+    pub source_system: String,
+}
+
+impl AuditFields {
+    /// Sets `field` to `user` on every write.
+    pub fn with_modified_by(mut self, field: impl Into<String>, user: impl Into<String>) -> Self {
+        self.modified_by_field = Some(field.into());
+        self.user = user.into();
+        self
+    }
+
+    /// Sets the timestamp field `field` to the time of every write.
+    pub fn with_modified_at(mut self, field: impl Into<String>) -> Self {
+        self.modified_at_field = Some(field.into());
+        self
+    }
+
+    /// Sets `field` to `system` on every write.
+    pub fn with_source_system(
+        mut self,
+        field: impl Into<String>,
+        system: impl Into<String>,
+    ) -> Self {
+        self.source_system_field = Some(field.into());
+        self.source_system = system.into();
+        self
+    }
+}
+
+/// The hook stamping [`AuditFields`], with the time zone timestamps are written in.
+struct AuditStamp {
+    fields: AuditFields,
+    timezone: Tz,
+}
+
+impl AuditStamp {
+    fn stamp(&self, field_data: &mut Map<String, Value>) {
+        if let Some(field) = &self.fields.modified_by_field {
+            field_data.insert(field.clone(), Value::String(self.fields.user.clone()));
+        }
+        if let Some(field) = &self.fields.modified_at_field {
+            let now = format_timestamp(&Utc::now(), &self.timezone);
+            field_data.insert(field.clone(), Value::String(now));
+        }
+        if let Some(field) = &self.fields.source_system_field {
+            let system = Value::String(self.fields.source_system.clone());
+            field_data.insert(field.clone(), system);
+        }
+    }
+}
+
+impl RecordHook for AuditStamp {
+    fn before_create<'a>(
+        &'a self,
+        _layout: &'a str,
+        field_data: &'a mut Map<String, Value>,
+    ) -> BoxFuture<'a, Result<()>> {
+        self.stamp(field_data);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn before_update<'a>(
+        &'a self,
+        _layout: &'a str,
+        _record_id: &'a str,
+        field_data: &'a mut Map<String, Value>,
+    ) -> BoxFuture<'a, Result<()>> {
+        self.stamp(field_data);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Filemaker {
+    /// Sets the given audit fields on every record this client creates or updates.
+    ///
+    /// The fields are stamped by a [`RecordHook`], overwriting any value the caller passed,
+    /// so call sites no longer need to remember them. Timestamps are written in the server
+    /// time zone set with [`Filemaker::with_server_timezone`] before this call.
+    ///
+    /// # Arguments
+    /// * `fields` - The audit fields and the values written to them
+    ///
+    /// # Returns
+    /// * `Self` - The client stamping the audit fields
+    pub fn with_audit_fields(self, fields: AuditFields) -> Self {
+        debug!("Stamping audit fields {:?}", fields);
+        let timezone = self.server_timezone();
+        self.with_hook(AuditStamp { fields, timezone })
+    }
+}
@@ -0,0 +1,55 @@
+//! `fmsmoke` - an end-to-end smoke test of a FileMaker Server, e.g. after an upgrade.
+//!
+//! Connects, creates a test record, finds, updates and deletes it, and optionally downloads
+//! a container and runs a script, then prints a pass/fail report. Exits with status 1 if a
+//! step failed. Settings are read from the environment:
+//!
+//! * `FM_URL` - the Data API base URL, e.g. `https://fm.example.com/fmi/data/vLatest`
+//! * `FM_USERNAME` / `FM_PASSWORD` - the account to log in with
+//! * `FM_DATABASE` / `FM_LAYOUT` - where the test record is created
+//! * `FM_SMOKE_RECORD` - the test record's field data as a JSON object, e.g. `{"Name":"Smoke test"}`
+//! * `FM_SMOKE_UPDATE` - the field data the update step writes; defaults to `FM_SMOKE_RECORD`
+//! * `FM_SMOKE_CONTAINER_FIELD` - a container field to download from (optional)
+//! * `FM_SMOKE_SCRIPT` - a script to run on the layout (optional)
+//!
+//! Run with `cargo run --bin fmsmoke`.
+
+use anyhow::{anyhow, Context, Result};
+use filemaker_lib::{run_smoke, SmokeConfig};
+use serde_json::Value;
+use std::collections::HashMap;
+
+fn required(name: &str) -> Result<String> {
+    std::env::var(name).map_err(|_| anyhow!("{} is not set", name))
+}
+
+fn field_data(name: &str, json: &str) -> Result<HashMap<String, Value>> {
+    serde_json::from_str(json).with_context(|| format!("{} is not a JSON object", name))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut config = SmokeConfig::new(
+        required("FM_URL")?,
+        required("FM_USERNAME")?,
+        required("FM_PASSWORD")?,
+        required("FM_DATABASE")?,
+        required("FM_LAYOUT")?,
+    );
+    let record = required("FM_SMOKE_RECORD")?;
+    config.record = field_data("FM_SMOKE_RECORD", &record)?;
+    config.update = match std::env::var("FM_SMOKE_UPDATE") {
+        Ok(update) => field_data("FM_SMOKE_UPDATE", &update)?,
+        Err(_) => config.record.clone(),
+    };
+    config.container_field = std::env::var("FM_SMOKE_CONTAINER_FIELD").ok();
+    config.script = std::env::var("FM_SMOKE_SCRIPT").ok();
+
+    println!("Smoke testing {} ({})", config.endpoint, config.database);
+    let report = run_smoke(&config).await;
+    println!("{}", report);
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
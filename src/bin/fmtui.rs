@@ -0,0 +1,347 @@
+//! `fmtui` - a terminal browser for FileMaker databases.
+//!
+//! Lists the databases and layouts of a server, pages through records and runs ad-hoc finds.
+//! Connection settings are read from the environment:
+//!
+//! * `FM_URL` - the Data API base URL, e.g. `https://fm.example.com/fmi/data/vLatest`
+//! * `FM_USERNAME` / `FM_PASSWORD` - the account to log in with
+//!
+//! Build with `cargo run --features tui --bin fmtui`.
+
+use anyhow::{anyhow, Result};
+use filemaker_lib::{Cursor, Filemaker, Page, RecordTable};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, List, ListState, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+
+/// The number of records fetched per page.
+const PAGE_SIZE: u64 = 50;
+
+/// The maximum number of columns shown in the record table.
+const MAX_COLUMNS: usize = 8;
+
+// Only a handful of screens exist at a time, so their size does not matter
+#[allow(clippy::large_enum_variant)]
+enum Screen {
+    Databases {
+        names: Vec<String>,
+        state: ListState,
+    },
+    Layouts {
+        database: String,
+        names: Vec<String>,
+        state: ListState,
+    },
+    Records {
+        filemaker: Filemaker,
+        cursor: Cursor,
+        page: Page,
+        state: TableState,
+    },
+    Record {
+        json: String,
+        scroll: u16,
+    },
+}
+
+struct App {
+    username: String,
+    password: String,
+    screens: Vec<Screen>,
+    /// Text typed into the find prompt, while it is open.
+    find_input: Option<String>,
+    status: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let url = std::env::var("FM_URL").map_err(|_| anyhow!("FM_URL is not set"))?;
+    let username = std::env::var("FM_USERNAME").map_err(|_| anyhow!("FM_USERNAME is not set"))?;
+    let password = std::env::var("FM_PASSWORD").map_err(|_| anyhow!("FM_PASSWORD is not set"))?;
+    Filemaker::set_fm_url(url)?;
+
+    let names = Filemaker::get_databases(&username, &password).await?;
+    let mut app = App {
+        username,
+        password,
+        screens: vec![Screen::Databases {
+            names,
+            state: ListState::default().with_selected(Some(0)),
+        }],
+        find_input: None,
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal).await;
+    ratatui::restore();
+    result
+}
+
+impl App {
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let event = tokio::task::spawn_blocking(event::read).await??;
+            let Event::Key(key) = event else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match self.handle_key(key.code).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(e) => self.status = format!("Error: {:#}", e),
+            }
+        }
+    }
+
+    /// Handles a key press and returns whether the application should quit.
+    async fn handle_key(&mut self, code: KeyCode) -> Result<bool> {
+        if let Some(input) = &mut self.find_input {
+            match code {
+                KeyCode::Esc => self.find_input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => {
+                    let input = self.find_input.take().unwrap_or_default();
+                    self.find(&input).await?;
+                }
+                _ => {}
+            }
+            return Ok(false);
+        }
+        self.status.clear();
+        match code {
+            KeyCode::Char('q') => return Ok(true),
+            KeyCode::Esc | KeyCode::Backspace if self.screens.len() > 1 => {
+                self.screens.pop();
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Enter => self.open().await?,
+            KeyCode::Char('n') | KeyCode::PageDown => self.turn_page(true).await?,
+            KeyCode::Char('p') | KeyCode::PageUp => self.turn_page(false).await?,
+            KeyCode::Char('/') if matches!(self.screens.last(), Some(Screen::Records { .. })) => {
+                self.find_input = Some(String::new());
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn move_selection(&mut self, delta: i32) {
+        let step = |selected: Option<usize>, len: usize| {
+            let current = selected.unwrap_or(0) as i64;
+            Some((current + delta as i64).clamp(0, len.saturating_sub(1) as i64) as usize)
+        };
+        match self.screens.last_mut() {
+            Some(Screen::Databases { names, state })
+            | Some(Screen::Layouts { names, state, .. }) => {
+                state.select(step(state.selected(), names.len()));
+            }
+            Some(Screen::Records { page, state, .. }) => {
+                state.select(step(state.selected(), page.records.len()));
+            }
+            Some(Screen::Record { scroll, .. }) => {
+                *scroll = scroll.saturating_add_signed(delta as i16);
+            }
+            None => {}
+        }
+    }
+
+    /// Opens the selected database, layout or record.
+    async fn open(&mut self) -> Result<()> {
+        let next = match self.screens.last() {
+            Some(Screen::Databases { names, state }) => {
+                let Some(database) = state.selected().and_then(|i| names.get(i)) else {
+                    return Ok(());
+                };
+                let names =
+                    Filemaker::get_layouts(&self.username, &self.password, database).await?;
+                Screen::Layouts {
+                    database: database.clone(),
+                    names,
+                    state: ListState::default().with_selected(Some(0)),
+                }
+            }
+            Some(Screen::Layouts {
+                database,
+                names,
+                state,
+            }) => {
+                let Some(layout) = state.selected().and_then(|i| names.get(i)) else {
+                    return Ok(());
+                };
+                let filemaker =
+                    Filemaker::new(&self.username, &self.password, database, layout).await?;
+                let cursor = Cursor::new(PAGE_SIZE);
+                let page = filemaker.fetch_page(&cursor).await?;
+                Screen::Records {
+                    filemaker,
+                    cursor,
+                    page,
+                    state: TableState::default().with_selected(Some(0)),
+                }
+            }
+            Some(Screen::Records { page, state, .. }) => {
+                let Some(record) = state.selected().and_then(|i| page.records.get(i)) else {
+                    return Ok(());
+                };
+                Screen::Record {
+                    json: serde_json::to_string_pretty(record)?,
+                    scroll: 0,
+                }
+            }
+            _ => return Ok(()),
+        };
+        self.screens.push(next);
+        Ok(())
+    }
+
+    async fn turn_page(&mut self, forward: bool) -> Result<()> {
+        let Some(Screen::Records {
+            filemaker,
+            cursor,
+            page,
+            state,
+        }) = self.screens.last_mut()
+        else {
+            return Ok(());
+        };
+        let target = if forward {
+            page.next.clone()
+        } else {
+            page.prev.clone()
+        };
+        if let Some(target) = target {
+            *page = filemaker.fetch_page(&target).await?;
+            *cursor = target;
+            state.select(Some(0));
+        }
+        Ok(())
+    }
+
+    /// Runs a find from `Field=criteria` pairs separated by `;`, or shows all records if empty.
+    async fn find(&mut self, input: &str) -> Result<()> {
+        let Some(Screen::Records {
+            filemaker,
+            cursor,
+            page,
+            state,
+        }) = self.screens.last_mut()
+        else {
+            return Ok(());
+        };
+        let mut request = HashMap::new();
+        for pair in input.split(';').filter(|p| !p.trim().is_empty()) {
+            let (field, criteria) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected Field=criteria, got '{}'", pair))?;
+            request.insert(field.trim().to_string(), criteria.trim().to_string());
+        }
+        let target = match request.is_empty() {
+            true => Cursor::new(PAGE_SIZE),
+            false => Cursor::new(PAGE_SIZE).with_query(vec![request]),
+        };
+        *page = filemaker.fetch_page(&target).await?;
+        *cursor = target;
+        state.select(Some(0));
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(2)]).areas(frame.area());
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        let help = match self.screens.last_mut() {
+            Some(Screen::Databases { names, state }) => {
+                let list = List::new(names.iter().map(String::as_str))
+                    .block(Block::bordered().title("Databases"))
+                    .highlight_style(highlight);
+                frame.render_stateful_widget(list, main, state);
+                "Enter: open  q: quit"
+            }
+            Some(Screen::Layouts {
+                database,
+                names,
+                state,
+            }) => {
+                let list = List::new(names.iter().map(String::as_str))
+                    .block(Block::bordered().title(format!("Layouts of {}", database)))
+                    .highlight_style(highlight);
+                frame.render_stateful_widget(list, main, state);
+                "Enter: open  Esc: back  q: quit"
+            }
+            Some(Screen::Records {
+                filemaker,
+                cursor,
+                page,
+                state,
+            }) => {
+                let formatter = RecordTable::new().with_max_columns(MAX_COLUMNS);
+                let columns = formatter.columns(&page.records);
+                let header = Row::new(
+                    std::iter::once("recordId".to_string())
+                        .chain(columns.iter().cloned())
+                        .map(Cell::from),
+                )
+                .style(Style::default().add_modifier(Modifier::BOLD));
+                let rows = page.records.iter().map(|record| {
+                    let id = formatter.cell(record.get("recordId"));
+                    let values = columns
+                        .iter()
+                        .map(|c| formatter.cell(record.get("fieldData").and_then(|f| f.get(c))));
+                    Row::new(std::iter::once(id).chain(values).map(Cell::from))
+                });
+                let widths = std::iter::once(Constraint::Length(10))
+                    .chain(columns.iter().map(|_| Constraint::Fill(1)));
+                let last = (cursor.offset + page.records.len() as u64).saturating_sub(1);
+                let title = format!(
+                    "{} - records {}-{} of {}{}",
+                    filemaker.layout(),
+                    cursor.offset.min(last.max(1)),
+                    last,
+                    page.found_count,
+                    if cursor.query.is_some() {
+                        " (found set)"
+                    } else {
+                        ""
+                    }
+                );
+                let table = Table::new(rows, widths)
+                    .header(header)
+                    .block(Block::bordered().title(title))
+                    .row_highlight_style(highlight);
+                frame.render_stateful_widget(table, main, state);
+                "Enter: details  n/p: next/previous page  /: find  Esc: back  q: quit"
+            }
+            Some(Screen::Record { json, scroll }) => {
+                let paragraph = Paragraph::new(json.as_str())
+                    .block(Block::bordered().title("Record"))
+                    .wrap(Wrap { trim: false })
+                    .scroll((*scroll, 0));
+                frame.render_widget(paragraph, main);
+                "Up/Down: scroll  Esc: back  q: quit"
+            }
+            None => "",
+        };
+
+        let footer_lines = match &self.find_input {
+            Some(input) => vec![
+                Line::from(format!("Find (Field=criteria; ...): {}", input)),
+                Line::from("Enter: run  Esc: cancel"),
+            ],
+            None => vec![Line::from(self.status.as_str()), Line::from(help)],
+        };
+        frame.render_widget(Paragraph::new(footer_lines), footer);
+    }
+}
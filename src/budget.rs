@@ -0,0 +1,176 @@
+//! Limits on the failures and retries of a bulk operation as a whole.
+
+use crate::{ErrorKind, ErrorKindExt};
+use anyhow::{anyhow, Result};
+use log::*;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Limits the retries and failures of a bulk operation, shared by all of its rows.
+///
+/// A row that fails transiently (a locked record, a server error or a network failure) is
+/// retried up to `max_retries_per_row` times, as long as the operation has not used up
+/// `max_retries` retries in total. Once more than `max_failure_ratio` of the rows attempted
+/// so far have failed, after at least `min_attempts` rows, the operation stops attempting
+/// rows. The default never retries and never stops early.
+///
+/// ```rust,ignore
+/// // Retry each row twice, at most 50 times overall, and abort once 5% of the rows fail.
+/// let options = ImportOptions {
+///     budget: RetryBudget::new(2, 50, 0.05),
+///     ..ImportOptions::with_key("InvoiceNumber", DuplicatePolicy::Update)
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryBudget {
+    /// The number of times a single row is retried after a transient failure.
+    pub max_retries_per_row: u32,
+    /// The number of retries allowed over the whole operation.
+    pub max_retries: u64,
+    /// The fraction of failed rows above which the remaining rows are not attempted.
+    pub max_failure_ratio: f64,
+    /// The number of rows attempted before the failure ratio is enforced.
+    pub min_attempts: u64,
+    /// The pause before retrying a row.
+    pub retry_delay: Duration,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self {
+            max_retries_per_row: 0,
+            max_retries: 0,
+            max_failure_ratio: 1.0,
+            min_attempts: 20,
+            retry_delay: Duration::from_millis(200),
+        }
+    }
+}
+
+impl RetryBudget {
+    /// Creates a budget retrying each row up to `per_row` times and `total` times overall,
+    /// that stops once more than `max_failure_ratio` of the rows have failed.
+    pub fn new(per_row: u32, total: u64, max_failure_ratio: f64) -> Self {
+        Self {
+            max_retries_per_row: per_row,
+            max_retries: total,
+            max_failure_ratio,
+            ..Self::default()
+        }
+    }
+}
+
+/// How much of its [`RetryBudget`] a bulk operation used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BudgetStatus {
+    /// The number of rows attempted.
+    pub attempted: u64,
+    /// The number of attempted rows that failed after their retries.
+    pub failed: u64,
+    /// The number of retries spent.
+    pub retries: u64,
+    /// The number of rows not attempted because the failure ratio was exceeded.
+    pub skipped: u64,
+    /// Whether the failure ratio was exceeded and the operation stopped early.
+    pub exhausted: bool,
+}
+
+/// Tracks a [`RetryBudget`] across the rows of one operation, including concurrent ones.
+pub(crate) struct BudgetTracker {
+    budget: RetryBudget,
+    attempted: AtomicU64,
+    failed: AtomicU64,
+    retries: AtomicU64,
+    skipped: AtomicU64,
+    exhausted: AtomicBool,
+}
+
+impl BudgetTracker {
+    pub(crate) fn new(budget: RetryBudget) -> Self {
+        Self {
+            budget,
+            attempted: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Runs one row, retrying transient failures within the budget.
+    ///
+    /// Fails without running `operation` if the budget is exhausted.
+    pub(crate) async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if self.exhausted.load(Ordering::SeqCst) {
+            self.skipped.fetch_add(1, Ordering::SeqCst);
+            return Err(anyhow!(
+                "Not attempted: the operation exceeded its failure budget"
+            ));
+        }
+        self.attempted.fetch_add(1, Ordering::SeqCst);
+        let mut row_retries = 0;
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if row_retries >= self.budget.max_retries_per_row
+                || !is_transient(&error)
+                || !self.take_retry()
+            {
+                self.record_failure();
+                return Err(error);
+            }
+            row_retries += 1;
+            warn!(
+                "Retrying after transient failure ({}): {}",
+                row_retries, error
+            );
+            tokio::time::sleep(self.budget.retry_delay).await;
+        }
+    }
+
+    /// Spends one retry of the operation-wide allowance, if any is left.
+    fn take_retry(&self) -> bool {
+        self.retries
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < self.budget.max_retries).then_some(used + 1)
+            })
+            .is_ok()
+    }
+
+    fn record_failure(&self) {
+        let failed = self.failed.fetch_add(1, Ordering::SeqCst) + 1;
+        let attempted = self.attempted.load(Ordering::SeqCst);
+        if attempted >= self.budget.min_attempts
+            && failed as f64 > attempted as f64 * self.budget.max_failure_ratio
+            && !self.exhausted.swap(true, Ordering::SeqCst)
+        {
+            error!(
+                "{} of {} rows failed, stopping the operation",
+                failed, attempted
+            );
+        }
+    }
+
+    pub(crate) fn status(&self) -> BudgetStatus {
+        BudgetStatus {
+            attempted: self.attempted.load(Ordering::SeqCst),
+            failed: self.failed.load(Ordering::SeqCst),
+            retries: self.retries.load(Ordering::SeqCst),
+            skipped: self.skipped.load(Ordering::SeqCst),
+            exhausted: self.exhausted.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Returns whether an error may go away when the request is sent again.
+pub(crate) fn is_transient(error: &anyhow::Error) -> bool {
+    matches!(error.kind(), ErrorKind::Locked | ErrorKind::Server)
+        || error.downcast_ref::<reqwest::Error>().is_some()
+}
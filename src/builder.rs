@@ -0,0 +1,218 @@
+//! Step-by-step construction of clients.
+//!
+//! [`Filemaker::builder`] collects the credentials and connection options one call at a
+//! time, so new options do not keep widening the positional constructors.
+
+use crate::{
+    ApiVersion, CaBundle, ClientIdentity, Filemaker, FmConfig, ProxyAuth, SessionOptions, Timeouts,
+    TlsOptions, REDACTED,
+};
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use std::fmt;
+use std::time::Duration;
+
+/// Builds a [`Filemaker`] client; created with [`Filemaker::builder`].
+///
+/// ```rust,ignore
+/// let filemaker = Filemaker::builder()
+///     .url("https://fm.example.com")
+///     .username("user")
+///     .password("pass")
+///     .database("Contacts")
+///     .layout("People")
+///     .timeout(Duration::from_secs(30))
+///     .ca_bundle(CaBundle::from_pem_file("company-ca.pem")?)
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct FilemakerBuilder {
+    url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    database: Option<String>,
+    layout: Option<String>,
+    api_version: Option<ApiVersion>,
+    tls: TlsOptions,
+    timeouts: Timeouts,
+    session: SessionOptions,
+    proxy_auth: Option<ProxyAuth>,
+    client: Option<Client>,
+}
+
+impl fmt::Debug for FilemakerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FilemakerBuilder")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("database", &self.database)
+            .field("layout", &self.layout)
+            .field("api_version", &self.api_version)
+            .field("tls", &self.tls)
+            .field("timeouts", &self.timeouts)
+            .field("session", &self.session)
+            .field("proxy_auth", &self.proxy_auth)
+            .field("client", &self.client)
+            .finish()
+    }
+}
+
+impl FilemakerBuilder {
+    /// Sets the server, as a server URL or a full Data API URL; see [`FmConfig::new`].
+    ///
+    /// Without it, the client is created for `FM_URL` as it is when the client is built.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Sets the account name to log in with. Required.
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    /// Sets the password to log in with. Required.
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Sets the database to connect to. Required.
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.database = Some(database.into());
+        self
+    }
+
+    /// Sets the layout to operate on. Required.
+    pub fn layout(mut self, layout: impl Into<String>) -> Self {
+        self.layout = Some(layout.into());
+        self
+    }
+
+    /// Pins the Data API version, instead of the one named by the URL.
+    pub fn api_version(mut self, api_version: ApiVersion) -> Self {
+        self.api_version = Some(api_version);
+        self
+    }
+
+    /// Sets the time allowed for a whole request; see [`Timeouts::request`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.request = Some(timeout);
+        self
+    }
+
+    /// Sets the time allowed to establish a connection; see [`Timeouts::connect`].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// Sets whether certificates that do not verify, e.g. self-signed ones, are accepted.
+    /// Defaults to `false`; only accept them in development.
+    pub fn accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.tls.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Trusts the certificate authorities of `bundle` in addition to the system's.
+    pub fn ca_bundle(mut self, bundle: CaBundle) -> Self {
+        self.tls.ca_bundle = Some(bundle);
+        self
+    }
+
+    /// Sets the certificate presented to servers requiring mutual TLS.
+    pub fn client_identity(mut self, identity: ClientIdentity) -> Self {
+        self.tls.client_identity = Some(identity);
+        self
+    }
+
+    /// Sets all TLS options at once, replacing those set before.
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Sets the options sent with every login, e.g. credentials of external data sources.
+    pub fn session_options(mut self, session: SessionOptions) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Sets the credentials of a proxy in front of the Data API.
+    pub fn proxy_auth(mut self, proxy_auth: ProxyAuth) -> Self {
+        self.proxy_auth = Some(proxy_auth);
+        self
+    }
+
+    /// Sends requests with `client` instead of one built by the crate, e.g. one configured
+    /// with a proxy, connection pool settings or a custom DNS resolver.
+    ///
+    /// The client is used as it is: the TLS, timeout and proxy authentication settings of
+    /// the builder are ignored, so configure them on the client instead.
+    ///
+    /// ```rust,ignore
+    /// let client = reqwest::Client::builder()
+    ///     .proxy(reqwest::Proxy::https("http://proxy.internal:3128")?)
+    ///     .pool_max_idle_per_host(4)
+    ///     .build()?;
+    /// let filemaker = Filemaker::builder()
+    ///     .url("https://fm.example.com")
+    ///     .with_client(client)
+    ///     // ...
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Logs in and returns the client.
+    ///
+    /// # Returns
+    /// * `Result<Filemaker>` - The client, or an error if a required setting is missing,
+    ///   `FM_URL` is needed but not set, or the login fails
+    pub async fn build(self) -> Result<Filemaker> {
+        let required = |value: Option<String>, name: &str| {
+            value.ok_or_else(|| anyhow!("The {} of the client is not set", name))
+        };
+        let username = required(self.username, "username")?;
+        let password = required(self.password, "password")?;
+        let database = required(self.database, "database")?;
+        let layout = required(self.layout, "layout")?;
+        let url = match self.url {
+            Some(url) => url,
+            None => Filemaker::get_fm_url()?,
+        };
+
+        let mut config = FmConfig::new(url)
+            .with_tls(self.tls)
+            .with_timeouts(self.timeouts)
+            .with_session_options(self.session);
+        if let Some(api_version) = self.api_version {
+            config = config.with_api_version(api_version);
+        }
+        if let Some(proxy_auth) = self.proxy_auth {
+            config = config.with_proxy_auth(proxy_auth);
+        }
+        match self.client {
+            Some(client) => {
+                Filemaker::with_config_and_client(
+                    config, client, &username, &password, &database, &layout,
+                )
+                .await
+            }
+            None => Filemaker::with_config(config, &username, &password, &database, &layout).await,
+        }
+    }
+}
+
+impl Filemaker {
+    /// Returns a builder for a client, as an alternative to the positional constructors.
+    pub fn builder() -> FilemakerBuilder {
+        FilemakerBuilder::default()
+    }
+}
@@ -0,0 +1,239 @@
+use crate::budget::BudgetTracker;
+use crate::{
+    BudgetStatus, Danger, Filemaker, Pacing, RecordId, RetryBudget, NO_RECORDS_MATCH_CODE,
+};
+use anyhow::Result;
+use log::*;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+/// Outcome of a bulk deletion performed by [`Filemaker::delete_records`].
+#[derive(Debug, Default)]
+pub struct DeleteSummary {
+    /// IDs of the records that were deleted.
+    pub deleted: Vec<String>,
+    /// IDs of the records that no longer existed on the server.
+    pub not_found: Vec<String>,
+    /// IDs of the records that could not be deleted, together with the reason.
+    pub failed: Vec<(String, anyhow::Error)>,
+    /// How much of the retry budget the deletion used.
+    pub budget: BudgetStatus,
+}
+
+/// Configures [`Filemaker::clear_database_with`].
+#[derive(Debug, Clone)]
+pub struct ClearOptions {
+    /// Whether to keep the IDs of the records present before deletion in the report.
+    pub snapshot: bool,
+    /// The maximum number of delete passes; records that appear during a pass are deleted
+    /// by the next one.
+    pub max_passes: usize,
+    /// The maximum number of delete requests in flight at once.
+    pub concurrency: usize,
+    /// Limits on retrying failed deletions and on the share of records that may fail,
+    /// shared by all passes.
+    pub budget: RetryBudget,
+    /// Pauses between deletions, growing while the server is slow; `None` deletes flat out.
+    pub pacing: Option<Pacing>,
+}
+
+impl Default for ClearOptions {
+    fn default() -> Self {
+        Self {
+            snapshot: false,
+            max_passes: 3,
+            concurrency: 4,
+            budget: RetryBudget::default(),
+            pacing: None,
+        }
+    }
+}
+
+/// Outcome of [`Filemaker::clear_database_with`].
+#[derive(Debug, Default)]
+pub struct ClearReport {
+    /// The IDs of the records present before deletion, if a snapshot was requested.
+    pub snapshot: Option<Vec<RecordId>>,
+    /// The number of records present before deletion.
+    pub initial_count: usize,
+    /// The number of records deleted over all passes.
+    pub deleted: usize,
+    /// The number of records that appeared after the first pass started.
+    pub stragglers: usize,
+    /// The number of delete passes run.
+    pub passes: usize,
+    /// Records that could not be deleted, together with the reason.
+    pub failed: Vec<(String, anyhow::Error)>,
+    /// The IDs of the records still present after the last pass.
+    pub remaining: Vec<String>,
+    /// Whether the layout was verified to be empty afterwards.
+    pub verified: bool,
+    /// How much of the retry budget the passes used.
+    pub budget: BudgetStatus,
+}
+
+impl Filemaker {
+    /// Deletes a set of records by their IDs, running up to `concurrency` deletions in parallel.
+    ///
+    /// Unlike calling [`Filemaker::delete_record`] in a loop, a failing record does not abort
+    /// the operation; every ID is attempted and the outcome of each one is reported in the
+    /// returned [`DeleteSummary`].
+    ///
+    /// # Arguments
+    /// * `ids` - The IDs of the records to delete
+    /// * `concurrency` - The maximum number of delete requests in flight at once (at least 1)
+    ///
+    /// # Returns
+    /// * `Result<DeleteSummary>` - The deleted, missing and failed record IDs
+    pub async fn delete_records<I>(&self, ids: I, concurrency: usize) -> Result<DeleteSummary>
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        let budget = Arc::new(BudgetTracker::new(RetryBudget::default()));
+        let summary = self
+            .delete_records_within(ids, concurrency, None, &budget)
+            .await?;
+        info!(
+            "Bulk delete finished: {} deleted, {} not found, {} failed",
+            summary.deleted.len(),
+            summary.not_found.len(),
+            summary.failed.len()
+        );
+        Ok(summary)
+    }
+
+    /// Deletes records like [`Filemaker::delete_records`], paced by `pacing`, retrying and
+    /// giving up on them within `budget`.
+    pub(crate) async fn delete_records_within<I>(
+        &self,
+        ids: I,
+        concurrency: usize,
+        pacing: Option<Pacing>,
+        budget: &Arc<BudgetTracker>,
+    ) -> Result<DeleteSummary>
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        let limiter = self.limiter(concurrency, pacing);
+        let mut tasks = JoinSet::new();
+
+        for id in ids {
+            let id = id.to_string();
+            let filemaker = self.clone();
+            let budget = budget.clone();
+            let mut permit = limiter.acquire().await;
+            tasks.spawn(async move {
+                let result = budget.run(|| filemaker.delete_record_found(&id)).await;
+                if let Err(e) = &result {
+                    permit.observe_error(e);
+                }
+                drop(permit);
+                (id, result)
+            });
+        }
+
+        let mut summary = DeleteSummary::default();
+        while let Some(joined) = tasks.join_next().await {
+            let (id, result) = joined?;
+            match result {
+                Ok(true) => summary.deleted.push(id),
+                Ok(false) => summary.not_found.push(id),
+                Err(e) => {
+                    error!("Failed to delete record ID {}: {}", id, e);
+                    summary.failed.push((id, e));
+                }
+            }
+        }
+        summary.budget = budget.status();
+        Ok(summary)
+    }
+
+    /// Deletes a record, returning `false` if no record has the ID.
+    async fn delete_record_found(&self, id: &str) -> Result<bool> {
+        let response = self.delete_record_response(id).await?;
+        match Self::first_message_code(&response) {
+            Some("0") => Ok(true),
+            Some(NO_RECORDS_MATCH_CODE) => Ok(false),
+            _ => Err(Self::response_error(&response, "Failed to delete record")),
+        }
+    }
+
+    /// Deletes all records from the layout and verifies that it is empty afterwards.
+    ///
+    /// Record IDs are listed first (and kept in the report if `options.snapshot` is set), then
+    /// deleted with [`Filemaker::delete_records`]. The layout is listed again after each pass;
+    /// records created by other clients in the meantime are deleted by further passes, up to
+    /// `options.max_passes`.
+    ///
+    /// # Arguments
+    /// * `options` - Snapshot, pass and concurrency settings
+    /// * `danger` - Confirmation naming this client's database; see [`Danger`]
+    ///
+    /// # Returns
+    /// * `Result<ClearReport>` - What was deleted and whether the layout ended up empty, or an error if listing records fails
+    pub async fn clear_database_with(
+        &self,
+        options: &ClearOptions,
+        danger: &Danger,
+    ) -> Result<ClearReport> {
+        danger.check(&self.database())?;
+        let budget = Arc::new(BudgetTracker::new(options.budget));
+        let mut report = ClearReport::default();
+        loop {
+            let ids = self.get_record_ids(None).await?;
+            if report.passes == 0 {
+                report.initial_count = ids.len();
+                if options.snapshot {
+                    report.snapshot = Some(ids.clone());
+                }
+            } else {
+                report.stragglers += ids
+                    .iter()
+                    .filter(|id| !report.failed.iter().any(|(f, _)| *f == id.record_id))
+                    .count();
+            }
+            if ids.is_empty() {
+                report.verified = true;
+                break;
+            }
+            if report.passes >= options.max_passes.max(1) || report.budget.exhausted {
+                report.remaining = ids.into_iter().map(|id| id.record_id).collect();
+                break;
+            }
+
+            report.passes += 1;
+            debug!(
+                "Clear pass {}: deleting {} records",
+                report.passes,
+                ids.len()
+            );
+            let summary = self
+                .delete_records_within(
+                    ids.into_iter().map(|id| id.record_id),
+                    options.concurrency,
+                    options.pacing,
+                    &budget,
+                )
+                .await?;
+            report.deleted += summary.deleted.len();
+            report.failed = summary.failed;
+            report.budget = summary.budget;
+        }
+
+        if report.verified {
+            info!(
+                "Cleared {} records in {} passes ({} stragglers)",
+                report.deleted, report.passes, report.stragglers
+            );
+        } else {
+            warn!(
+                "Layout not empty after {} passes: {} records remain",
+                report.passes,
+                report.remaining.len()
+            );
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,227 @@
+//! Change detection by polling record and modification IDs.
+//!
+//! The Data API has no change notifications. [`Filemaker::watch_changes`] lists the
+//! `recordId`/`modId` pairs of the table at a fixed interval and compares each listing with the
+//! previous one: new IDs are creations, changed modification IDs are updates and missing IDs
+//! are deletions. A [`ChangeFeed`] runs one such poller in the background and broadcasts its
+//! events to any number of subscribers.
+
+use crate::{ErrorKind, ErrorKindExt, Filemaker, RecordId};
+use anyhow::Result;
+use futures::stream::{self, Stream, StreamExt};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// The number of events a [`ChangeFeed`] buffers for subscribers that fall behind.
+const FEED_CAPACITY: usize = 1024;
+
+/// What happened to a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The record did not exist in the previous listing.
+    Created,
+    /// The record's modification ID changed.
+    Updated,
+    /// The record is no longer listed.
+    Deleted,
+}
+
+/// A change to a record detected by polling.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// What happened to the record.
+    pub change: ChangeKind,
+    /// The layout the record was seen on.
+    pub layout: String,
+    /// The ID of the record.
+    #[serde(rename = "recordId")]
+    pub record_id: String,
+    /// The new modification ID; `None` for deletions.
+    #[serde(rename = "modId")]
+    pub mod_id: Option<String>,
+    /// The record as returned by the server; `None` for deletions.
+    pub record: Option<Value>,
+}
+
+impl Filemaker {
+    /// Polls the table every `interval` and yields a [`ChangeEvent`] for every record that was
+    /// created, updated or deleted since the previous poll.
+    ///
+    /// The first listing only establishes the baseline and yields nothing. Failed polls yield
+    /// the error and polling continues with the next interval, so a transient outage does not
+    /// end the stream. Changes made and reverted between two polls, or several updates within
+    /// one interval, are reported at most once.
+    ///
+    /// # Arguments
+    /// * `interval` - The time between two listings of the table
+    ///
+    /// # Returns
+    /// * `impl Stream<Item = Result<ChangeEvent>>` - The detected changes, in detection order
+    pub fn watch_changes(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<ChangeEvent>> + 'static {
+        struct State {
+            filemaker: Filemaker,
+            known: Option<HashMap<String, String>>,
+            pending: VecDeque<ChangeEvent>,
+        }
+
+        let state = State {
+            filemaker: self.clone(),
+            known: None,
+            pending: VecDeque::new(),
+        };
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((Ok(event), state));
+                }
+                if state.known.is_some() {
+                    tokio::time::sleep(interval).await;
+                }
+                let ids = match state.filemaker.get_record_ids(None).await {
+                    Ok(ids) => ids,
+                    Err(e) => {
+                        warn!("Failed to poll for changes: {:#}", e);
+                        if state.known.is_none() {
+                            tokio::time::sleep(interval).await;
+                        }
+                        return Some((Err(e), state));
+                    }
+                };
+                let current: HashMap<String, String> = ids
+                    .into_iter()
+                    .map(|RecordId { record_id, mod_id }| (record_id, mod_id))
+                    .collect();
+                let Some(known) = state.known.replace(current.clone()) else {
+                    debug!("Watching {} records for changes", current.len());
+                    continue;
+                };
+                match state.filemaker.diff(&known, &current).await {
+                    Ok(events) => state.pending.extend(events),
+                    Err(e) => return Some((Err(e), state)),
+                }
+            }
+        })
+    }
+
+    /// Starts polling the table in the background and broadcasting its changes.
+    ///
+    /// Polling errors are logged and skipped. The feed stops once an event is detected and no
+    /// subscriber is left to receive it, or when the [`ChangeFeed`] is dropped.
+    ///
+    /// # Arguments
+    /// * `interval` - The time between two listings of the table
+    ///
+    /// # Returns
+    /// * `ChangeFeed` - The running feed; call [`ChangeFeed::subscribe`] to receive events
+    pub fn change_feed(&self, interval: Duration) -> ChangeFeed {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        let changes = self.watch_changes(interval);
+        let feed_sender = sender.clone();
+        let layout = self.layout();
+        let task = tokio::spawn(async move {
+            let mut changes = Box::pin(changes);
+            while let Some(change) = changes.next().await {
+                let Ok(event) = change else {
+                    continue;
+                };
+                if feed_sender.send(event).is_err() {
+                    info!("Stopping change feed of {}: no subscribers left", layout);
+                    break;
+                }
+            }
+        });
+        ChangeFeed { sender, task }
+    }
+
+    /// Builds the events for the differences between two listings, fetching changed records.
+    async fn diff(
+        &self,
+        known: &HashMap<String, String>,
+        current: &HashMap<String, String>,
+    ) -> Result<Vec<ChangeEvent>> {
+        let layout = self.layout();
+        let mut events = Vec::new();
+        for (id, mod_id) in current {
+            let change = match known.get(id) {
+                None => ChangeKind::Created,
+                Some(known_mod_id) if known_mod_id != mod_id => ChangeKind::Updated,
+                Some(_) => continue,
+            };
+            let record = match self.get_record_by_id(id).await {
+                Ok(record) => record,
+                // Deleted since the listing; reported as deleted by the next poll
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let mod_id = record
+                .get("modId")
+                .and_then(|m| m.as_str())
+                .unwrap_or(mod_id)
+                .to_string();
+            events.push(ChangeEvent {
+                change,
+                layout: layout.clone(),
+                record_id: id.clone(),
+                mod_id: Some(mod_id),
+                record: Some(record),
+            });
+        }
+        for id in known.keys().filter(|id| !current.contains_key(*id)) {
+            events.push(ChangeEvent {
+                change: ChangeKind::Deleted,
+                layout: layout.clone(),
+                record_id: id.clone(),
+                mod_id: None,
+                record: None,
+            });
+        }
+        if !events.is_empty() {
+            debug!("Detected {} changes on {}", events.len(), layout);
+        }
+        Ok(events)
+    }
+}
+
+/// Broadcasts the changes of one table to any number of subscribers.
+///
+/// Created with [`Filemaker::change_feed`]. All subscribers share a single poller, so adding
+/// subscribers does not add load on the server.
+pub struct ChangeFeed {
+    sender: broadcast::Sender<ChangeEvent>,
+    task: JoinHandle<()>,
+}
+
+impl ChangeFeed {
+    /// Subscribes to the events detected from now on.
+    ///
+    /// A subscriber that falls more than 1024 events behind misses the oldest ones and is told
+    /// so by [`broadcast::error::RecvError::Lagged`].
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Returns the number of current subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Returns whether the feed has stopped polling.
+    pub fn is_closed(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for ChangeFeed {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,86 @@
+//! Authentication with FileMaker Cloud.
+//!
+//! FileMaker Cloud hosts do not accept FileMaker account names and passwords. Users sign in
+//! with their Claris ID (an Amazon Cognito user pool) and the Data API session is created
+//! with the resulting ID token in an `Authorization: FMID <token>` header. Obtaining the
+//! token is left to the application, e.g. with the AWS Cognito SDK.
+
+use crate::{Filemaker, SessionOptions};
+use anyhow::Result;
+use log::*;
+use reqwest::Client;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Supplies the Claris ID token to log in with when a session expires.
+pub(crate) type FmidTokens = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+impl Filemaker {
+    /// Creates a client for a FileMaker Cloud host, logging in with a Claris ID token.
+    ///
+    /// The token is kept to log in again when the session expires. Claris ID tokens are
+    /// only valid for an hour, so long-running clients should also set a provider of fresh
+    /// tokens with [`Filemaker::with_fmid_token_provider`].
+    ///
+    /// ```rust,ignore
+    /// Filemaker::set_fm_url("https://example.account.filemaker-cloud.com/fmi/data/vLatest")?;
+    /// let filemaker = Filemaker::new_with_fmid_token(&id_token, "Contacts", "People").await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `id_token` - The Claris ID token of the signed-in user
+    /// * `database` - The name of the FileMaker database to connect to
+    /// * `table` - The name of the table/layout to operate on
+    ///
+    /// # Returns
+    /// * `Result<Self>` - A new Filemaker instance, or an error if the host rejects the token
+    pub async fn new_with_fmid_token(id_token: &str, database: &str, table: &str) -> Result<Self> {
+        let mut filemaker = Self::offline(database, table)?;
+        let token = Self::get_fmid_session_token(
+            &filemaker.client,
+            &Self::get_fm_url()?,
+            database,
+            id_token,
+            &filemaker.session_options,
+        )
+        .await?;
+        filemaker.token = Arc::new(Mutex::new(Some(token)));
+        let id_token = id_token.to_string();
+        filemaker.fmid_tokens = Some(Arc::new(move || Ok(id_token.clone())));
+        info!("Filemaker instance created with a Claris ID token");
+        Ok(filemaker)
+    }
+
+    /// Sets the provider of the Claris ID tokens used to log in again when the session
+    /// expires.
+    ///
+    /// The provider is asked each time a new session is needed, so it can return a token
+    /// refreshed with the Claris ID refresh token. It takes precedence over the username and
+    /// password a client was created with.
+    ///
+    /// # Arguments
+    /// * `provider` - Returns a valid Claris ID token
+    ///
+    /// # Returns
+    /// * `Self` - The client using the provider
+    pub fn with_fmid_token_provider<F>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Result<String> + Send + Sync + 'static,
+    {
+        debug!("Using a Claris ID token provider for new sessions");
+        self.fmid_tokens = Some(Arc::new(provider));
+        self
+    }
+
+    /// Gets a session token from the Data API at `base_url` with a Claris ID token.
+    pub(crate) async fn get_fmid_session_token(
+        client: &Client,
+        base_url: &str,
+        database: &str,
+        id_token: &str,
+        options: &SessionOptions,
+    ) -> Result<String> {
+        let auth_header = format!("FMID {}", id_token);
+        Self::create_session(client, base_url, database, &auth_header, options).await
+    }
+}
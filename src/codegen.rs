@@ -0,0 +1,176 @@
+//! Generating typed layout handles and record models from layout metadata.
+
+use crate::metadata::FieldMetadata;
+use crate::Filemaker;
+use anyhow::Result;
+use log::*;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Rust keywords that cannot be used as field names.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+    "use", "where", "while",
+];
+
+impl Filemaker {
+    /// Generates Rust source for a `layouts` module with typed handles for `layouts`.
+    ///
+    /// For each layout, the module contains a record model with one [`crate::FmValue`] per
+    /// field on the layout (named in snake case and renamed to the field name for serde),
+    /// implementing [`crate::FmRecordModel`], and a marker type named after the layout
+    /// implementing [`crate::Layout`]. Calculation, summary and container fields are read
+    /// but never written, and fields excluded by [`Filemaker::with_field_filter`], if set,
+    /// are left out. Write the output to a source file, e.g. from a build script or a
+    /// maintenance command, and open layouts with [`Filemaker::open`]:
+    ///
+    /// ```rust,ignore
+    /// let source = filemaker.generate_layouts(["Invoices", "Customers"]).await?;
+    /// std::fs::write("src/layouts.rs", source)?;
+    /// // in the application:
+    /// let invoices = filemaker.open::<layouts::Invoices>();
+    /// ```
+    ///
+    /// # Arguments
+    /// * `layouts` - The names of the layouts to generate handles for
+    ///
+    /// # Returns
+    /// * `Result<String>` - The generated source, or an error if layout metadata cannot be read
+    pub async fn generate_layouts<I, S>(&self, layouts: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut source = String::from(
+            "//! Typed layout handles generated by filemaker-lib from layout metadata. Do not edit.\n\n\
+             pub mod layouts {\n    \
+             #![allow(dead_code)]\n\n    \
+             use filemaker_lib::{FmRecordModel, FmValue, Layout};\n    \
+             use serde::{Deserialize, Serialize};\n",
+        );
+        let mut type_names = HashSet::new();
+        for layout in layouts {
+            let layout = layout.as_ref();
+            let mut fields = self.with_layout(layout).layout_fields().await?;
+            if let Some(filter) = self.field_filter() {
+                fields.retain(|field| !filter.excludes(&field.name));
+            }
+            let name = unique(type_name(layout), &mut type_names);
+            debug!(
+                "Generating {} for layout {} ({} fields)",
+                name,
+                layout,
+                fields.len()
+            );
+            write_layout(&mut source, layout, &name, &fields);
+        }
+        source.push_str("}\n");
+        Ok(source)
+    }
+}
+
+/// Appends the record model and marker type of one layout.
+fn write_layout(source: &mut String, layout: &str, name: &str, fields: &[FieldMetadata]) {
+    let literal = format!("{:?}", layout);
+    let _ = write!(
+        source,
+        "\n    /// A record of the {literal} layout.\n    \
+         #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]\n    \
+         pub struct {name}Record {{\n"
+    );
+    let mut field_names = HashSet::new();
+    for field in fields {
+        let ident = unique(field_name(&field.name), &mut field_names);
+        let read_only =
+            matches!(field.kind.as_str(), "calculation" | "summary") || field.result == "container";
+        let skip = if read_only { ", skip_serializing" } else { "" };
+        let _ = write!(
+            source,
+            "        #[serde(rename = {:?}, default{})]\n        pub {}: FmValue,\n",
+            field.name, skip, ident
+        );
+    }
+    let _ = write!(
+        source,
+        "    }}\n\n    \
+         impl FmRecordModel for {name}Record {{\n        \
+         const LAYOUT: &'static str = {literal};\n    \
+         }}\n\n    \
+         /// The {literal} layout.\n    \
+         #[derive(Debug, Clone, Copy, PartialEq, Eq)]\n    \
+         pub struct {name};\n\n    \
+         impl Layout for {name} {{\n        \
+         const NAME: &'static str = {literal};\n        \
+         type Record = {name}Record;\n    \
+         }}\n"
+    );
+}
+
+/// Splits a FileMaker name into lowercase alphanumeric words.
+fn words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            previous_lower = false;
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        // Split camel case: "InvoiceTotal" -> "invoice", "total"
+        if c.is_ascii_uppercase() && previous_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        current.push(c.to_ascii_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Converts a layout name into a PascalCase type name.
+fn type_name(layout: &str) -> String {
+    let name: String = words(layout)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    match name.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() => name,
+        _ => format!("Layout{}", name),
+    }
+}
+
+/// Converts a field name into a snake_case field identifier.
+fn field_name(field: &str) -> String {
+    let name = words(field).join("_");
+    match name.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() => match KEYWORDS.contains(&name.as_str()) {
+            true => format!("{}_", name),
+            false => name,
+        },
+        _ => format!("field_{}", name),
+    }
+}
+
+/// Makes `name` unique among `taken` by appending a number.
+fn unique(name: String, taken: &mut HashSet<String>) -> String {
+    let mut candidate = name.clone();
+    let mut suffix = 2;
+    while !taken.insert(candidate.clone()) {
+        candidate = format!("{}_{}", name, suffix);
+        suffix += 1;
+    }
+    candidate
+}
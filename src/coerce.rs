@@ -0,0 +1,350 @@
+//! Conversion of FileMaker field values into Rust types.
+//!
+//! The Data API returns number fields either as JSON numbers or, depending on the field
+//! and the file's locale, as formatted text, and timestamps as naive local times of the
+//! server. The helpers in this module turn those values into native types according to the
+//! number format and time zone configured on the [`Filemaker`] client.
+
+use crate::Filemaker;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::*;
+use serde_json::Value;
+
+/// Timestamp formats produced by the Data API (US default and ISO 8601 `dateformats`).
+const TIMESTAMP_FORMATS: [&str; 3] = [
+    "%m/%d/%Y %H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+];
+
+/// Date formats produced by the Data API (US default and ISO 8601 `dateformats`).
+const DATE_FORMATS: [&str; 2] = ["%m/%d/%Y", "%Y-%m-%d"];
+
+/// Describes how numbers are written in text values of a FileMaker file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Character separating the integer part from the fraction, e.g. `.` or `,`.
+    pub decimal_separator: char,
+    /// Character separating groups of thousands, if the file uses one.
+    pub grouping_separator: Option<char>,
+}
+
+impl NumberFormat {
+    /// `1,234.56` - the format used by files with US/UK locale settings.
+    pub const US: NumberFormat = NumberFormat {
+        decimal_separator: '.',
+        grouping_separator: Some(','),
+    };
+
+    /// `1.234,56` - the format used by files with most continental European locale settings.
+    pub const EUROPEAN: NumberFormat = NumberFormat {
+        decimal_separator: ',',
+        grouping_separator: Some('.'),
+    };
+
+    /// Parses a formatted number.
+    ///
+    /// Grouping separators are only accepted between complete groups of three digits, so a
+    /// value written in a different locale (e.g. `1.5` under [`NumberFormat::EUROPEAN`]) is
+    /// rejected instead of silently turning into a different number.
+    ///
+    /// # Arguments
+    /// * `text` - The formatted number, optionally signed and surrounded by whitespace
+    ///
+    /// # Returns
+    /// * `Result<f64>` - The parsed number, or an error if the text does not match the format
+    pub fn parse(&self, text: &str) -> Result<f64> {
+        let trimmed = text.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+
+        let mut parts = unsigned.split(self.decimal_separator);
+        let integer = parts.next().unwrap_or_default();
+        let fraction = parts.next();
+        if parts.next().is_some() {
+            return Err(anyhow!(
+                "Invalid number '{}': repeated decimal separator",
+                text
+            ));
+        }
+
+        let integer_digits = match self.grouping_separator {
+            Some(separator) if integer.contains(separator) => {
+                let groups: Vec<&str> = integer.split(separator).collect();
+                let valid = groups.iter().enumerate().all(|(i, group)| {
+                    let expected = if i == 0 { 1..=3 } else { 3..=3 };
+                    expected.contains(&group.len()) && group.chars().all(|c| c.is_ascii_digit())
+                });
+                if !valid {
+                    return Err(anyhow!(
+                        "Invalid number '{}': misplaced grouping separator",
+                        text
+                    ));
+                }
+                groups.concat()
+            }
+            _ => integer.to_string(),
+        };
+
+        let fraction_digits = fraction.unwrap_or_default();
+        let is_empty = integer_digits.is_empty() && fraction_digits.is_empty();
+        let is_numeric = integer_digits
+            .chars()
+            .chain(fraction_digits.chars())
+            .all(|c| c.is_ascii_digit());
+        if is_empty || !is_numeric {
+            return Err(anyhow!("Invalid number '{}'", text));
+        }
+
+        let normalized = format!(
+            "{}{}.{}",
+            if negative { "-" } else { "" },
+            if integer_digits.is_empty() {
+                "0"
+            } else {
+                &integer_digits
+            },
+            if fraction_digits.is_empty() {
+                "0"
+            } else {
+                fraction_digits
+            }
+        );
+        normalized
+            .parse::<f64>()
+            .map_err(|e| anyhow!("Invalid number '{}': {}", text, e))
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self::US
+    }
+}
+
+/// Converts a field value into a number using the given format.
+///
+/// JSON numbers are returned as-is, text values are parsed with `format`. Empty text and
+/// `null` yield `None`.
+///
+/// # Arguments
+/// * `value` - The field value as returned by the Data API
+/// * `format` - The number format used by the FileMaker file
+///
+/// # Returns
+/// * `Result<Option<f64>>` - The number, `None` for empty values, or an error
+pub fn to_number(value: &Value, format: &NumberFormat) -> Result<Option<f64>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Number(n) => Ok(n.as_f64()),
+        Value::String(s) if s.trim().is_empty() => Ok(None),
+        Value::String(s) => format.parse(s).map(Some),
+        other => Err(anyhow!("Expected a number, found {}", other)),
+    }
+}
+
+/// Converts a date field value into a [`NaiveDate`].
+///
+/// Accepts the US (`MM/dd/yyyy`) and ISO 8601 (`yyyy-MM-dd`) formats. Empty text and `null`
+/// yield `None`.
+pub fn to_date(value: &Value) -> Result<Option<NaiveDate>> {
+    let Some(text) = non_empty_text(value)? else {
+        return Ok(None);
+    };
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+        .map(Some)
+        .ok_or_else(|| anyhow!("Invalid date '{}'", text))
+}
+
+/// Converts a timestamp field value into a [`NaiveDateTime`] without any time zone.
+///
+/// Accepts the US (`MM/dd/yyyy HH:mm:ss`) and ISO 8601 (`yyyy-MM-dd HH:mm:ss`) formats.
+/// Empty text and `null` yield `None`.
+pub fn to_naive_timestamp(value: &Value) -> Result<Option<NaiveDateTime>> {
+    let Some(text) = non_empty_text(value)? else {
+        return Ok(None);
+    };
+    TIMESTAMP_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .map(Some)
+        .ok_or_else(|| anyhow!("Invalid timestamp '{}'", text))
+}
+
+/// Converts a timestamp field value into a time zone aware timestamp.
+///
+/// FileMaker stores timestamps as local times of the server, so the value is interpreted in
+/// `timezone`. Times that occur twice because of a daylight saving transition resolve to the
+/// earlier instant; times that do not exist in `timezone` are an error.
+///
+/// # Arguments
+/// * `value` - The field value as returned by the Data API
+/// * `timezone` - The time zone of the FileMaker server
+///
+/// # Returns
+/// * `Result<Option<DateTime<Tz>>>` - The timestamp, `None` for empty values, or an error
+pub fn to_timestamp(value: &Value, timezone: &Tz) -> Result<Option<DateTime<Tz>>> {
+    let Some(naive) = to_naive_timestamp(value)? else {
+        return Ok(None);
+    };
+    match timezone.from_local_datetime(&naive) {
+        LocalResult::Single(timestamp) => Ok(Some(timestamp)),
+        LocalResult::Ambiguous(earliest, _) => Ok(Some(earliest)),
+        LocalResult::None => Err(anyhow!(
+            "Timestamp {} does not exist in time zone {}",
+            naive,
+            timezone
+        )),
+    }
+}
+
+/// Formats a timestamp as the server's local time in the Data API's default format.
+///
+/// # Arguments
+/// * `timestamp` - The timestamp in any time zone
+/// * `timezone` - The time zone of the FileMaker server
+pub fn format_timestamp<T: TimeZone>(timestamp: &DateTime<T>, timezone: &Tz) -> String {
+    timestamp
+        .with_timezone(timezone)
+        .format(TIMESTAMP_FORMATS[0])
+        .to_string()
+}
+
+/// Converts a field value into a boolean following FileMaker's conventions.
+///
+/// FileMaker has no boolean field type; flags are stored as numbers or text. `1`/`0`,
+/// `Yes`/`No`, `True`/`False` and `Y`/`N` (case-insensitive) are recognized, any other
+/// non-zero number is `true`, and empty text or `null` is `false`.
+///
+/// # Arguments
+/// * `value` - The field value as returned by the Data API
+///
+/// # Returns
+/// * `Result<bool>` - The flag, or an error for text that is not a recognized boolean
+pub fn to_bool(value: &Value) -> Result<bool> {
+    match value {
+        Value::Null => Ok(false),
+        Value::Bool(b) => Ok(*b),
+        Value::Number(n) => Ok(n.as_f64().is_some_and(|n| n != 0.0)),
+        Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "" | "0" | "no" | "n" | "false" => Ok(false),
+            "1" | "yes" | "y" | "true" => Ok(true),
+            other => other
+                .parse::<f64>()
+                .map(|n| n != 0.0)
+                .map_err(|_| anyhow!("Expected a boolean, found '{}'", s)),
+        },
+        other => Err(anyhow!("Expected a boolean, found {}", other)),
+    }
+}
+
+/// Serde helpers for `bool` fields stored with FileMaker's boolean conventions.
+///
+/// Values are read with [`to_bool`] and written back as `1`/`0`.
+///
+/// ```rust,ignore
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Invoice {
+///     #[serde(rename = "Paid", with = "filemaker_lib::coerce::fm_bool")]
+///     paid: bool,
+/// }
+/// ```
+pub mod fm_bool {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde_json::Value;
+
+    /// Writes a boolean as `1` or `0`.
+    pub fn serialize<S: Serializer>(value: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(u8::from(*value))
+    }
+
+    /// Reads a boolean following FileMaker's conventions.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        super::to_bool(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Returns the trimmed text of a value, `None` for empty text or `null`.
+fn non_empty_text(value: &Value) -> Result<Option<&str>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) if s.trim().is_empty() => Ok(None),
+        Value::String(s) => Ok(Some(s.trim())),
+        other => Err(anyhow!("Expected text, found {}", other)),
+    }
+}
+
+impl Filemaker {
+    /// Sets the number format used when coercing text values into numbers.
+    ///
+    /// # Arguments
+    /// * `format` - The number format used by the FileMaker file
+    ///
+    /// # Returns
+    /// * `Self` - The client with the new number format
+    pub fn with_number_format(mut self, format: NumberFormat) -> Self {
+        debug!("Using number format {:?}", format);
+        self.number_format = format;
+        self
+    }
+
+    /// Returns the number format used when coercing text values into numbers.
+    pub fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    /// Converts a field value into a number using the client's number format.
+    ///
+    /// See [`to_number`] for the conversion rules.
+    pub fn to_number(&self, value: &Value) -> Result<Option<f64>> {
+        to_number(value, &self.number_format)
+    }
+
+    /// Sets the time zone of the FileMaker server, used to interpret timestamp fields.
+    ///
+    /// Defaults to UTC.
+    ///
+    /// # Arguments
+    /// * `timezone` - The time zone the FileMaker server runs in
+    ///
+    /// # Returns
+    /// * `Self` - The client with the new server time zone
+    pub fn with_server_timezone(mut self, timezone: Tz) -> Self {
+        debug!("Using server time zone {}", timezone);
+        self.server_timezone = timezone;
+        self
+    }
+
+    /// Returns the time zone used to interpret timestamp fields.
+    pub fn server_timezone(&self) -> Tz {
+        self.server_timezone
+    }
+
+    /// Converts a timestamp field value into a timestamp in the server's time zone.
+    ///
+    /// See [`to_timestamp`] for the conversion rules.
+    pub fn to_timestamp(&self, value: &Value) -> Result<Option<DateTime<Tz>>> {
+        to_timestamp(value, &self.server_timezone)
+    }
+
+    /// Converts a timestamp field value into a UTC timestamp.
+    ///
+    /// See [`to_timestamp`] for the conversion rules.
+    pub fn to_utc_timestamp(&self, value: &Value) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.to_timestamp(value)?.map(|t| t.with_timezone(&Utc)))
+    }
+
+    /// Formats a timestamp for writing into a timestamp field of the server.
+    ///
+    /// See [`format_timestamp`].
+    pub fn format_timestamp<T: TimeZone>(&self, timestamp: &DateTime<T>) -> String {
+        format_timestamp(timestamp, &self.server_timezone)
+    }
+}
@@ -0,0 +1,104 @@
+//! Compressing export output.
+
+use anyhow::Result;
+use std::pin::Pin;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+/// How export output is compressed.
+///
+/// gzip requires the `gzip` feature, zstd the `zstd` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Output is written as is.
+    #[default]
+    None,
+    /// gzip, for files ending in `.gz`.
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// Zstandard, for files ending in `.zst`.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// A writer boxed so that compressed and uncompressed output share one type.
+pub(crate) type BoxedWriter<'a> = Pin<Box<dyn AsyncWrite + Send + 'a>>;
+
+/// A reader boxed so that compressed and uncompressed input share one type.
+pub(crate) type BoxedReader<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+impl Compression {
+    /// Picks the compression from the last extension of a file name or object key.
+    ///
+    /// `.gz` selects gzip and `.zst` zstd; other names are not compressed.
+    ///
+    /// # Returns
+    /// * `Result<Compression>` - The compression, or an error if its feature is not enabled
+    pub fn from_path(path: &str) -> Result<Self> {
+        match path.rsplit_once('.').map(|(_, extension)| extension) {
+            #[cfg(feature = "gzip")]
+            Some("gz") => Ok(Self::Gzip),
+            #[cfg(feature = "zstd")]
+            Some("zst") => Ok(Self::Zstd),
+            #[cfg(not(feature = "gzip"))]
+            Some("gz") => Err(anyhow::anyhow!(
+                "gzip output of {} requires the gzip feature",
+                path
+            )),
+            #[cfg(not(feature = "zstd"))]
+            Some("zst") => Err(anyhow::anyhow!(
+                "zstd output of {} requires the zstd feature",
+                path
+            )),
+            _ => Ok(Self::None),
+        }
+    }
+
+    /// Returns the file extension of this compression, including the dot, or `""`.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Self::None => "",
+            #[cfg(feature = "gzip")]
+            Self::Gzip => ".gz",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => ".zst",
+        }
+    }
+
+    /// Returns `path` without the extension of this compression.
+    pub(crate) fn strip_extension(self, path: &str) -> &str {
+        match self.extension() {
+            "" => path,
+            extension => path.strip_suffix(extension).unwrap_or(path),
+        }
+    }
+
+    /// Wraps `writer` so that everything written to it is compressed.
+    ///
+    /// The compressed stream is only complete once the returned writer is shut down.
+    pub(crate) fn wrap<'a, W>(self, writer: W) -> BoxedWriter<'a>
+    where
+        W: AsyncWrite + Send + 'a,
+    {
+        match self {
+            Self::None => Box::pin(writer),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Box::pin(async_compression::tokio::write::GzipEncoder::new(writer)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::pin(async_compression::tokio::write::ZstdEncoder::new(writer)),
+        }
+    }
+
+    /// Wraps `reader` so that everything read from it is decompressed.
+    pub(crate) fn wrap_reader<'a, R>(self, reader: R) -> BoxedReader<'a>
+    where
+        R: AsyncBufRead + Send + 'a,
+    {
+        match self {
+            Self::None => Box::pin(reader),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Box::pin(async_compression::tokio::bufread::GzipDecoder::new(reader)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::pin(async_compression::tokio::bufread::ZstdDecoder::new(reader)),
+        }
+    }
+}
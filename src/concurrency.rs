@@ -0,0 +1,247 @@
+//! Limiting, pacing and optionally tuning the parallelism of bulk operations.
+
+use crate::budget::is_transient;
+use crate::Filemaker;
+use log::*;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Tunes the concurrency of bulk operations to the server, additive-increase /
+/// multiplicative-decrease (AIMD) style.
+///
+/// A bulk operation starts with the concurrency it was given. Each write that completes
+/// within `target_latency` raises the limit a little, by one per `limit` successful writes;
+/// a write that takes longer or fails transiently (a locked record, a server error or a
+/// network failure) multiplies it by `decrease_factor`. The limit stays between `min` and
+/// `max`.
+///
+/// ```rust,ignore
+/// let filemaker = filemaker.with_adaptive_concurrency(AdaptiveConcurrency {
+///     max: 32,
+///     target_latency: Duration::from_millis(500),
+///     ..Default::default()
+/// });
+/// filemaker.delete_records(ids, 4).await?; // starts at 4, then tunes between 1 and 32
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveConcurrency {
+    /// The lowest concurrency.
+    pub min: usize,
+    /// The highest concurrency.
+    pub max: usize,
+    /// The latency above which a write counts as a sign of an overloaded server.
+    pub target_latency: Duration,
+    /// The factor the concurrency is multiplied by when the server is overloaded.
+    pub decrease_factor: f64,
+}
+
+impl Default for AdaptiveConcurrency {
+    fn default() -> Self {
+        Self {
+            min: 1,
+            max: 16,
+            target_latency: Duration::from_secs(1),
+            decrease_factor: 0.5,
+        }
+    }
+}
+
+/// Pauses between the writes of a bulk operation, pausing longer while the server is slow.
+///
+/// Each write waits `delay` before it starts. A write taking longer than `target_latency`
+/// doubles the pause, plus the time by which it overran, up to `max_delay`; faster writes
+/// halve it again, down to `delay`. This keeps long imports and deletions from starving
+/// interactive users of the same host.
+///
+/// ```rust,ignore
+/// let options = ClearOptions {
+///     pacing: Some(Pacing::new(Duration::from_millis(50))),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pacing {
+    /// The pause before each write while the server keeps up.
+    pub delay: Duration,
+    /// The latency above which writes are slowed down.
+    pub target_latency: Duration,
+    /// The longest pause.
+    pub max_delay: Duration,
+}
+
+impl Pacing {
+    /// Creates pacing that waits `delay` before each write and slows down above 500ms latency.
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            target_latency: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+
+    /// Returns the pause following a write that took `latency`, given the current pause.
+    fn next_delay(&self, current: Duration, latency: Duration) -> Duration {
+        let max_delay = self.max_delay.max(self.delay);
+        match latency.checked_sub(self.target_latency) {
+            Some(overrun) if !overrun.is_zero() => (current * 2 + overrun).min(max_delay),
+            _ => (current / 2).max(self.delay),
+        }
+    }
+}
+
+/// Admits the requests of one bulk operation up to its current concurrency limit.
+pub(crate) struct Limiter {
+    adaptive: Option<AdaptiveConcurrency>,
+    pacing: Option<Pacing>,
+    state: Mutex<LimiterState>,
+    released: Notify,
+}
+
+struct LimiterState {
+    limit: f64,
+    in_flight: usize,
+    // Incremented on every decrease, so requests started before it do not decrease again
+    generation: u64,
+    // The pause before the next request
+    delay: Duration,
+}
+
+/// A slot of a [`Limiter`], released when dropped.
+pub(crate) struct LimiterPermit {
+    limiter: Arc<Limiter>,
+    started: Instant,
+    generation: u64,
+    overloaded: bool,
+}
+
+impl Limiter {
+    /// Creates a limiter admitting `concurrency` requests at once, tuned if `adaptive` is set
+    /// and paced if `pacing` is set.
+    pub(crate) fn new(
+        concurrency: usize,
+        adaptive: Option<AdaptiveConcurrency>,
+        pacing: Option<Pacing>,
+    ) -> Arc<Self> {
+        let mut limit = concurrency.max(1);
+        if let Some(adaptive) = adaptive {
+            limit = limit.clamp(adaptive.min.max(1), adaptive.max.max(adaptive.min).max(1));
+        }
+        Arc::new(Self {
+            adaptive,
+            pacing,
+            state: Mutex::new(LimiterState {
+                limit: limit as f64,
+                in_flight: 0,
+                generation: 0,
+                delay: pacing.map(|pacing| pacing.delay).unwrap_or_default(),
+            }),
+            released: Notify::new(),
+        })
+    }
+
+    /// Waits until a request may start.
+    pub(crate) async fn acquire(self: &Arc<Self>) -> LimiterPermit {
+        let mut permit = loop {
+            let released = self.released.notified();
+            {
+                let mut state = self.lock();
+                if state.in_flight < (state.limit as usize).max(1) {
+                    state.in_flight += 1;
+                    break LimiterPermit {
+                        limiter: self.clone(),
+                        started: Instant::now(),
+                        generation: state.generation,
+                        overloaded: false,
+                    };
+                }
+            }
+            released.await;
+        };
+        let delay = self.lock().delay;
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+            permit.started = Instant::now();
+        }
+        permit
+    }
+
+    fn release(&self, generation: u64, latency: Duration, overloaded: bool) {
+        let mut state = self.lock();
+        state.in_flight -= 1;
+        if let Some(adaptive) = self.adaptive {
+            let max = adaptive.max.max(adaptive.min).max(1) as f64;
+            let min = adaptive.min.max(1) as f64;
+            if overloaded || latency > adaptive.target_latency {
+                if generation == state.generation {
+                    state.limit = (state.limit * adaptive.decrease_factor).max(min);
+                    state.generation += 1;
+                    debug!(
+                        "Lowered bulk concurrency to {} after a {:?} request",
+                        state.limit as usize, latency
+                    );
+                }
+            } else {
+                let limit = (state.limit + 1.0 / state.limit).min(max);
+                if limit as usize > state.limit as usize {
+                    debug!("Raised bulk concurrency to {}", limit as usize);
+                }
+                state.limit = limit;
+            }
+        }
+        if let Some(pacing) = self.pacing {
+            let delay = pacing.next_delay(state.delay, latency);
+            if delay > state.delay {
+                debug!(
+                    "Pausing {:?} between bulk writes after a {:?} request",
+                    delay, latency
+                );
+            }
+            state.delay = delay;
+        }
+        drop(state);
+        self.released.notify_waiters();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl LimiterPermit {
+    /// Records that the request failed; transient failures count as overload.
+    pub(crate) fn observe_error(&mut self, error: &anyhow::Error) {
+        self.overloaded = is_transient(error);
+    }
+}
+
+impl Drop for LimiterPermit {
+    fn drop(&mut self) {
+        self.limiter
+            .release(self.generation, self.started.elapsed(), self.overloaded);
+    }
+}
+
+impl Filemaker {
+    /// Tunes the concurrency of bulk operations to the server's latency and errors.
+    ///
+    /// Applies to [`Filemaker::delete_records`], [`Filemaker::upsert_many`],
+    /// [`Filemaker::clear_database_with`], [`Filemaker::purge_older_than`] and seeding;
+    /// their concurrency becomes the starting point.
+    ///
+    /// # Arguments
+    /// * `adaptive` - The bounds and thresholds of the tuning
+    ///
+    /// # Returns
+    /// * `Self` - The client with adaptive concurrency
+    pub fn with_adaptive_concurrency(mut self, adaptive: AdaptiveConcurrency) -> Self {
+        debug!("Using adaptive concurrency {:?}", adaptive);
+        self.adaptive_concurrency = Some(adaptive);
+        self
+    }
+
+    /// Creates the limiter of a bulk operation running up to `concurrency` requests at once.
+    pub(crate) fn limiter(&self, concurrency: usize, pacing: Option<Pacing>) -> Arc<Limiter> {
+        Limiter::new(concurrency, self.adaptive_concurrency, pacing)
+    }
+}
@@ -0,0 +1,510 @@
+//! Connection settings of a client.
+//!
+//! `FM_URL` is shared by every client of the process. An [`FmConfig`] holds the server URL
+//! and connection settings of one client instead, so one process can talk to several
+//! servers, each with its own TLS settings and timeouts.
+
+use crate::{session, Filemaker, SessionOptions, REDACTED};
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use log::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, PROXY_AUTHORIZATION};
+use reqwest::{Certificate, Client, Identity};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// A version of the Data API, as named in its URL path `/fmi/data/{version}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ApiVersion {
+    /// Version 1 (`v1`), available since FileMaker Server 17.
+    V1,
+    /// Version 2 (`v2`), available since FileMaker Server 19.
+    V2,
+    /// The latest version the server supports (`vLatest`).
+    #[default]
+    Latest,
+}
+
+impl ApiVersion {
+    /// Returns the version as written in URLs, e.g. `v1`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+            ApiVersion::Latest => "vLatest",
+        }
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = anyhow::Error;
+
+    /// Parses a version as written in URLs, ignoring case.
+    fn from_str(version: &str) -> Result<Self> {
+        match version.to_ascii_lowercase().as_str() {
+            "v1" => Ok(ApiVersion::V1),
+            "v2" => Ok(ApiVersion::V2),
+            "vlatest" => Ok(ApiVersion::Latest),
+            _ => Err(anyhow!("Unknown Data API version: {}", version)),
+        }
+    }
+}
+
+/// The server and connection settings of a client created with [`Filemaker::with_config`].
+///
+/// ```rust,ignore
+/// let config = FmConfig::new("https://fm.example.com").with_timeouts(Timeouts {
+///     connect: Some(Duration::from_secs(5)),
+///     request: Some(Duration::from_secs(30)),
+/// });
+/// let filemaker = Filemaker::with_config(config, "user", "pass", "Contacts", "People").await?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FmConfig {
+    /// The URL of the server, e.g. `https://fm.example.com`, without the Data API path.
+    pub base_url: String,
+    /// The Data API version requests are sent to.
+    pub api_version: ApiVersion,
+    /// How the server certificate is verified.
+    pub tls: TlsOptions,
+    /// Limits on how long connections and requests may take.
+    pub timeouts: Timeouts,
+    /// Sent with every login, e.g. credentials of external data sources.
+    pub session: SessionOptions,
+    /// Credentials of a proxy in front of the Data API, sent with every request.
+    pub proxy_auth: Option<ProxyAuth>,
+}
+
+/// A header authorizing requests with a proxy in front of the Data API.
+///
+/// The `Authorization` header carries the FileMaker session token, so the proxy's
+/// credentials go in a header of their own, `Proxy-Authorization` unless changed with
+/// [`ProxyAuth::with_header`]. The value is not printed by `Debug`.
+///
+/// ```rust,ignore
+/// let config = FmConfig::new("https://fm.example.com")
+///     .with_proxy_auth(ProxyAuth::bearer(gateway_token).with_header("X-Gateway-Authorization"));
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProxyAuth {
+    header: String,
+    value: String,
+}
+
+impl ProxyAuth {
+    /// Authorizes with a username and password, as HTTP Basic credentials.
+    pub fn basic(username: &str, password: &str) -> Self {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        Self::new(format!("Basic {}", credentials))
+    }
+
+    /// Authorizes with a bearer token.
+    pub fn bearer(token: &str) -> Self {
+        Self::new(format!("Bearer {}", token))
+    }
+
+    /// Authorizes with the given header value, e.g. a custom scheme.
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            header: PROXY_AUTHORIZATION.to_string(),
+            value: value.into(),
+        }
+    }
+
+    /// Sends the credentials in the header `name` instead of `Proxy-Authorization`.
+    pub fn with_header(mut self, name: impl Into<String>) -> Self {
+        self.header = name.into();
+        self
+    }
+
+    /// Returns the name of the header the credentials are sent in.
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+}
+
+impl fmt::Debug for ProxyAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyAuth")
+            .field("header", &self.header)
+            .field("value", &REDACTED)
+            .finish()
+    }
+}
+
+/// Applied to clients using `FM_URL`; see [`Filemaker::set_tls_options`].
+static TLS_OPTIONS: RwLock<Option<TlsOptions>> = RwLock::new(None);
+
+/// How a client verifies the server certificate.
+///
+/// By default, the certificate must be issued for the server by a certificate authority
+/// the system trusts.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TlsOptions {
+    /// Whether certificates that do not verify, e.g. self-signed ones, are accepted. This
+    /// leaves the connection open to interception, so only use it in development.
+    pub accept_invalid_certs: bool,
+    /// Certificate authorities trusted in addition to the system's, e.g. the CA of a
+    /// company issuing the server certificate.
+    pub ca_bundle: Option<CaBundle>,
+    /// Keys the server certificate must hold; see [`Filemaker::set_certificate_pins`]. When
+    /// set, they replace certificate verification.
+    #[cfg(feature = "rustls")]
+    pub pinned_keys: Vec<crate::SpkiPin>,
+    /// The certificate presented to servers requiring mutual TLS, e.g. a reverse proxy in
+    /// front of the Data API.
+    pub client_identity: Option<ClientIdentity>,
+}
+
+/// A client certificate and its private key, for servers requiring mutual TLS.
+///
+/// The key is not printed by `Debug`.
+///
+/// ```rust,ignore
+/// let identity = ClientIdentity::from_pem_files("kiosk.pem", "kiosk.key")?;
+/// let config = FmConfig::new("https://fm.example.com").with_tls(TlsOptions {
+///     client_identity: Some(identity),
+///     ..TlsOptions::default()
+/// });
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    certificate_pem: String,
+    key_pem: String,
+}
+
+impl ClientIdentity {
+    /// Creates an identity from PEM text.
+    ///
+    /// # Arguments
+    /// * `certificate_pem` - The client certificate, followed by any intermediate certificates
+    /// * `key_pem` - The private key of the certificate in PKCS #8 format (`BEGIN PRIVATE KEY`)
+    pub fn from_pem(certificate_pem: impl Into<String>, key_pem: impl Into<String>) -> Self {
+        Self {
+            certificate_pem: certificate_pem.into(),
+            key_pem: key_pem.into(),
+        }
+    }
+
+    /// Reads an identity from PEM files; see [`ClientIdentity::from_pem`].
+    ///
+    /// # Returns
+    /// * `Result<ClientIdentity>` - The identity, or an error if a file cannot be read
+    pub fn from_pem_files(
+        certificate_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))
+        };
+        Ok(Self::from_pem(
+            read(certificate_path.as_ref())?,
+            read(key_path.as_ref())?,
+        ))
+    }
+
+    /// Returns the certificate chain as PEM text.
+    pub(crate) fn certificate_pem(&self) -> &str {
+        &self.certificate_pem
+    }
+
+    /// Returns the private key as PEM text.
+    pub(crate) fn key_pem(&self) -> &str {
+        &self.key_pem
+    }
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientIdentity")
+            .field("certificate_pem", &self.certificate_pem)
+            .field("key_pem", &REDACTED)
+            .finish()
+    }
+}
+
+/// Certificates of certificate authorities to trust, read from PEM text.
+///
+/// ```rust,ignore
+/// let config = FmConfig::new("https://fm.internal").with_tls(TlsOptions {
+///     ca_bundle: Some(CaBundle::from_pem_file("company-ca.pem")?),
+///     ..TlsOptions::default()
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaBundle {
+    pem: String,
+}
+
+impl CaBundle {
+    /// Creates a bundle from PEM text holding one or more certificates.
+    pub fn from_pem(pem: impl Into<String>) -> Self {
+        Self { pem: pem.into() }
+    }
+
+    /// Reads a bundle from a PEM file; see [`CaBundle::from_pem`].
+    ///
+    /// # Returns
+    /// * `Result<CaBundle>` - The bundle, or an error if the file cannot be read
+    pub fn from_pem_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Self::from_pem(pem))
+    }
+
+    /// Parses the certificates of the bundle.
+    fn certificates(&self) -> Result<Vec<Certificate>> {
+        let certificates = Certificate::from_pem_bundle(self.pem.as_bytes())
+            .context("Failed to read the CA bundle")?;
+        if certificates.is_empty() {
+            return Err(anyhow!("The CA bundle holds no certificates"));
+        }
+        Ok(certificates)
+    }
+}
+
+/// Limits on how long a client waits for the server; `None` waits indefinitely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timeouts {
+    /// The time allowed to establish a connection.
+    pub connect: Option<Duration>,
+    /// The time allowed for a whole request, from connecting until the response is read.
+    pub request: Option<Duration>,
+}
+
+impl FmConfig {
+    /// Creates a configuration for the server at `url` that verifies certificates.
+    ///
+    /// `url` is either the server URL, e.g. `https://fm.example.com`, or a full Data API URL
+    /// as used for `FM_URL`, e.g. `https://fm.example.com/fmi/data/v1`, whose version is
+    /// then used. Without one, or with a version not known to [`ApiVersion`], requests go to
+    /// [`ApiVersion::Latest`].
+    pub fn new(url: impl Into<String>) -> Self {
+        let url = url.into();
+        let url = url.trim_end_matches('/');
+        let (base_url, api_version) = match url.split_once("/fmi/data/") {
+            Some((base_url, version)) => {
+                let api_version = version.parse().unwrap_or_else(|e| {
+                    warn!("{}, using {}", e, ApiVersion::Latest);
+                    ApiVersion::Latest
+                });
+                (base_url, api_version)
+            }
+            None => (url, ApiVersion::Latest),
+        };
+        Self {
+            base_url: base_url.to_string(),
+            api_version,
+            tls: TlsOptions::default(),
+            timeouts: Timeouts::default(),
+            session: SessionOptions::default(),
+            proxy_auth: None,
+        }
+    }
+
+    /// Pins the Data API version requests are sent to.
+    ///
+    /// Servers may change what `vLatest` means on upgrade; a pinned version keeps the client
+    /// on the behavior it was tested against while using the same server URL.
+    pub fn with_api_version(mut self, api_version: ApiVersion) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// Sets how the server certificate is verified.
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Sets the connection and request timeouts.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Sets the options sent with every login.
+    pub fn with_session_options(mut self, session: SessionOptions) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Sets the credentials of a proxy in front of the Data API.
+    pub fn with_proxy_auth(mut self, proxy_auth: ProxyAuth) -> Self {
+        self.proxy_auth = Some(proxy_auth);
+        self
+    }
+
+    /// Returns the base URL of the Data API, e.g. `https://fm.example.com/fmi/data/vLatest`.
+    pub fn data_api_url(&self) -> String {
+        format!("{}/fmi/data/{}", self.base_url, self.api_version)
+    }
+}
+
+/// Builds the HTTP client used for Data API requests.
+pub(crate) fn build_client(
+    tls: &TlsOptions,
+    timeouts: &Timeouts,
+    proxy_auth: Option<&ProxyAuth>,
+) -> Result<Client> {
+    let builder = Client::builder().danger_accept_invalid_certs(tls.accept_invalid_certs);
+    let builder = match proxy_auth {
+        Some(proxy_auth) => {
+            let name = HeaderName::from_bytes(proxy_auth.header.as_bytes())
+                .with_context(|| format!("Invalid proxy header name {}", proxy_auth.header))?;
+            let mut value = HeaderValue::from_str(&proxy_auth.value)
+                .context("Invalid proxy authorization value")?;
+            value.set_sensitive(true);
+            builder.default_headers(HeaderMap::from_iter([(name, value)]))
+        }
+        None => builder,
+    };
+    let builder = match timeouts.connect {
+        Some(timeout) => builder.connect_timeout(timeout),
+        None => builder,
+    };
+    let builder = match timeouts.request {
+        Some(timeout) => builder.timeout(timeout),
+        None => builder,
+    };
+    let builder = match &tls.ca_bundle {
+        Some(bundle) => bundle
+            .certificates()?
+            .into_iter()
+            .fold(builder, |builder, certificate| {
+                builder.add_root_certificate(certificate)
+            }),
+        None => builder,
+    };
+    let builder = match &tls.client_identity {
+        Some(identity) => builder.identity(client_identity(identity)?),
+        None => builder,
+    };
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+    // Pinned keys replace certificate verification entirely
+    #[cfg(feature = "rustls")]
+    let builder =
+        match crate::pinning::pinned_tls_config(&tls.pinned_keys, tls.client_identity.as_ref())? {
+            Some(config) => builder.use_preconfigured_tls(config),
+            None => builder,
+        };
+    builder.build().map_err(|e| {
+        error!("Failed to build client: {}", e);
+        anyhow::anyhow!(e)
+    })
+}
+
+/// Converts `identity` for the TLS backend in use: rustls with the `rustls` feature, the
+/// platform's TLS otherwise.
+fn client_identity(identity: &ClientIdentity) -> Result<Identity> {
+    #[cfg(feature = "rustls")]
+    let identity = Identity::from_pem(
+        format!("{}\n{}", identity.certificate_pem(), identity.key_pem()).as_bytes(),
+    );
+    #[cfg(not(feature = "rustls"))]
+    let identity = Identity::from_pkcs8_pem(
+        identity.certificate_pem().as_bytes(),
+        identity.key_pem().as_bytes(),
+    );
+    identity.context("Failed to load the client certificate")
+}
+
+impl Filemaker {
+    /// Sets how clients using `FM_URL` verify the server certificate.
+    ///
+    /// Applies to clients created afterwards, including their logins. By default they
+    /// verify certificates like any HTTPS client; accepting invalid certificates, e.g. the
+    /// self-signed one FileMaker Server is installed with, has to be asked for. Pins set with
+    /// [`Filemaker::set_certificate_pins`] are used unless `tls` holds pins of its own.
+    ///
+    /// ```rust,ignore
+    /// // Development only
+    /// Filemaker::set_tls_options(TlsOptions {
+    ///     accept_invalid_certs: true,
+    ///     ..TlsOptions::default()
+    /// });
+    /// ```
+    pub fn set_tls_options(tls: TlsOptions) {
+        debug!("Setting TLS options of FM_URL clients to {:?}", tls);
+        *TLS_OPTIONS.write().unwrap_or_else(|e| e.into_inner()) = Some(tls);
+    }
+
+    /// Returns how clients using `FM_URL` verify the server certificate.
+    pub fn tls_options() -> TlsOptions {
+        TLS_OPTIONS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// Creates a client for the server of `config`, logging in with the given credentials.
+    ///
+    /// Unlike [`Filemaker::new`], the client does not use `FM_URL`; requests and later
+    /// logins go to the configured server, so clients of different servers can be used
+    /// side by side.
+    ///
+    /// # Arguments
+    /// * `config` - The server and connection settings
+    /// * `username` - The username for FileMaker authentication
+    /// * `password` - The password for FileMaker authentication
+    /// * `database` - The name of the FileMaker database to connect to
+    /// * `table` - The name of the table/layout to operate on
+    ///
+    /// # Returns
+    /// * `Result<Self>` - A new Filemaker instance, or an error if the login fails
+    pub async fn with_config(
+        config: FmConfig,
+        username: &str,
+        password: &str,
+        database: &str,
+        table: &str,
+    ) -> Result<Self> {
+        let client = build_client(&config.tls, &config.timeouts, config.proxy_auth.as_ref())?;
+        Self::with_config_and_client(config, client, username, password, database, table).await
+    }
+
+    /// Like [`Filemaker::with_config`], but sends requests with `client` instead of one built
+    /// from the TLS, timeout and proxy settings of `config`.
+    pub(crate) async fn with_config_and_client(
+        config: FmConfig,
+        client: Client,
+        username: &str,
+        password: &str,
+        database: &str,
+        table: &str,
+    ) -> Result<Self> {
+        let base_url = config.data_api_url();
+        let token = Self::get_session_token_at(
+            &client,
+            &base_url,
+            database,
+            username,
+            password,
+            &config.session,
+        )
+        .await?;
+        let mut filemaker = Self::offline(database, table)?;
+        filemaker.client = client;
+        filemaker.base_url = Some(base_url.clone());
+        filemaker.token = Arc::new(Mutex::new(Some(token)));
+        filemaker.credentials = Some(session::fixed_credentials(username, password));
+        filemaker.session_options = config.session;
+        info!("Filemaker instance created for {}", base_url);
+        Ok(filemaker)
+    }
+}
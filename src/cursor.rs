@@ -0,0 +1,209 @@
+//! Cursor-based pagination over records and find results.
+
+use crate::options::sort_spec;
+use crate::{query, response_warnings, Filemaker, ResponseWarning, NO_RECORDS_MATCH_CODE};
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use log::*;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// The position of a page within a table or a find's found set.
+///
+/// A cursor carries everything needed to fetch its page (offset, limit, query and sort), so it
+/// can be handed to web clients as an opaque token with [`Cursor::encode`] and restored with
+/// [`Cursor::decode`] on the next request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// The 1-based position of the first record of the page.
+    pub offset: u64,
+    /// The maximum number of records on the page.
+    pub limit: u64,
+    /// Find criteria; `None` pages through the whole table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<Vec<HashMap<String, String>>>,
+    /// Field names to sort by.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sort: Vec<String>,
+    /// Whether to sort in ascending (true) or descending (false) order.
+    #[serde(default = "default_ascending")]
+    pub ascending: bool,
+}
+
+fn default_ascending() -> bool {
+    true
+}
+
+/// One page of records fetched with a [`Cursor`].
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// The records of the page.
+    pub records: Vec<Value>,
+    /// The number of records in the table or found set.
+    pub found_count: u64,
+    /// The cursor of the following page, if there is one.
+    pub next: Option<Cursor>,
+    /// The cursor of the preceding page, if there is one.
+    pub prev: Option<Cursor>,
+    /// Non-fatal problems the server reported along with the page.
+    pub warnings: Vec<ResponseWarning>,
+}
+
+impl Cursor {
+    /// Creates a cursor for the first page of the whole table.
+    ///
+    /// # Arguments
+    /// * `limit` - The maximum number of records per page (at least 1)
+    pub fn new(limit: u64) -> Self {
+        Self {
+            offset: 1,
+            limit: limit.max(1),
+            query: None,
+            sort: vec![],
+            ascending: true,
+        }
+    }
+
+    /// Restricts the cursor to the found set of a query.
+    pub fn with_query(mut self, query: Vec<HashMap<String, String>>) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    /// Sorts the pages by the given fields.
+    pub fn with_sort(mut self, sort: Vec<String>, ascending: bool) -> Self {
+        self.sort = sort;
+        self.ascending = ascending;
+        self
+    }
+
+    /// Returns the cursor of the following page.
+    pub fn next_page(&self) -> Cursor {
+        Cursor {
+            offset: self.offset + self.limit,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the cursor of the preceding page, or `None` on the first page.
+    pub fn prev_page(&self) -> Option<Cursor> {
+        (self.offset > 1).then(|| Cursor {
+            offset: self.offset.saturating_sub(self.limit).max(1),
+            ..self.clone()
+        })
+    }
+
+    /// Serializes the cursor into an opaque, URL-safe token.
+    pub fn encode(&self) -> Result<String> {
+        let json = serde_json::to_vec(self)?;
+        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json))
+    }
+
+    /// Restores a cursor from a token produced by [`Cursor::encode`].
+    pub fn decode(token: &str) -> Result<Cursor> {
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|e| anyhow!("Invalid cursor: {}", e))?;
+        let cursor: Cursor =
+            serde_json::from_slice(&json).map_err(|e| anyhow!("Invalid cursor: {}", e))?;
+        Ok(cursor)
+    }
+}
+
+impl Filemaker {
+    /// Fetches the page a cursor points to.
+    ///
+    /// # Arguments
+    /// * `cursor` - The position of the page
+    ///
+    /// # Returns
+    /// * `Result<Page>` - The records of the page and the cursors of its neighbours, or an error
+    pub async fn fetch_page(&self, cursor: &Cursor) -> Result<Page> {
+        let response = match &cursor.query {
+            Some(query) => {
+                let url = format!(
+                    "{}/databases/{}/layouts/{}/_find",
+                    self.base_url()?,
+                    self.database,
+                    self.table
+                );
+                let mut body = json!({
+                    "query": query,
+                    "offset": cursor.offset,
+                    "limit": cursor.limit,
+                });
+                if !cursor.sort.is_empty() {
+                    body["sort"] = Value::Array(sort_spec(
+                        &cursor.sort,
+                        cursor.ascending,
+                        self.sort_tiebreaker(),
+                    ));
+                }
+                self.authenticated_request(&url, Method::POST, Some(body))
+                    .await?
+            }
+            None => {
+                let mut url = format!(
+                    "{}/databases/{}/layouts/{}/records?_offset={}&_limit={}",
+                    self.base_url()?,
+                    self.database,
+                    self.table,
+                    cursor.offset,
+                    cursor.limit
+                );
+                if !cursor.sort.is_empty() {
+                    let sort = Value::Array(sort_spec(
+                        &cursor.sort,
+                        cursor.ascending,
+                        self.sort_tiebreaker(),
+                    ))
+                    .to_string();
+                    url.push_str("&_sort=");
+                    url.push_str(&query::encode_url_component(&sort));
+                }
+                self.authenticated_request(&url, Method::GET, None).await?
+            }
+        };
+
+        if Self::first_message_code(&response) == Some(NO_RECORDS_MATCH_CODE) {
+            return Ok(Page {
+                records: vec![],
+                found_count: 0,
+                next: None,
+                prev: cursor.prev_page(),
+                warnings: vec![],
+            });
+        }
+
+        let Some(records) = response
+            .get("response")
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.as_array())
+        else {
+            error!("Failed to retrieve page from response: {:?}", response);
+            return Err(Self::response_error(&response, "Failed to retrieve page"));
+        };
+        let found_count = response
+            .get("response")
+            .and_then(|r| r.get("dataInfo"))
+            .and_then(|d| d.get("foundCount"))
+            .and_then(|c| c.as_u64())
+            .unwrap_or_default();
+
+        let next = cursor.next_page();
+        debug!(
+            "Fetched page at offset {} with {} records",
+            cursor.offset,
+            records.len()
+        );
+        Ok(Page {
+            records: records.clone(),
+            found_count,
+            next: (next.offset <= found_count).then_some(next),
+            prev: cursor.prev_page(),
+            warnings: response_warnings(&response),
+        })
+    }
+}
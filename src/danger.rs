@@ -0,0 +1,68 @@
+//! Confirmation required by destructive operations.
+//!
+//! [`Filemaker::clear_database`](crate::Filemaker::clear_database) and
+//! [`Filemaker::delete_database`](crate::Filemaker::delete_database) take a [`Danger`] token
+//! naming the database they are meant to destroy, and refuse to run unless the
+//! `FM_ALLOW_DESTRUCTIVE` environment variable is set. A client pointed at the wrong database
+//! by a misconfigured profile therefore fails instead of wiping it.
+
+use anyhow::{anyhow, Result};
+use log::*;
+
+/// The environment variable that must be set to `1`, `true` or `yes` for destructive
+/// operations to run.
+pub const ALLOW_DESTRUCTIVE_ENV: &str = "FM_ALLOW_DESTRUCTIVE";
+
+/// Confirmation that the caller means to destroy data in a specific database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Danger {
+    database: String,
+}
+
+impl Danger {
+    /// Confirms a destructive operation on `database`.
+    ///
+    /// # Arguments
+    /// * `database` - The name of the database the operation is meant for
+    pub fn confirm(database: impl Into<String>) -> Self {
+        Self {
+            database: database.into(),
+        }
+    }
+
+    /// Returns the database the confirmation is for.
+    pub fn database(&self) -> &str {
+        &self.database
+    }
+
+    /// Fails unless the confirmation matches `database` and destructive operations are
+    /// allowed by the environment.
+    pub(crate) fn check(&self, database: &str) -> Result<()> {
+        if self.database != database {
+            error!(
+                "Destructive operation confirmed for database {} but targets {}",
+                self.database, database
+            );
+            return Err(anyhow!(
+                "Destructive operation confirmed for database {} but targets {}",
+                self.database,
+                database
+            ));
+        }
+        let allowed = std::env::var(ALLOW_DESTRUCTIVE_ENV).is_ok_and(|value| {
+            matches!(
+                value.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes"
+            )
+        });
+        if !allowed {
+            error!("Destructive operations are disabled in this environment");
+            return Err(anyhow!(
+                "Destructive operations are disabled; set {}=1 to allow them",
+                ALLOW_DESTRUCTIVE_ENV
+            ));
+        }
+        warn!("Running destructive operation on database {}", database);
+        Ok(())
+    }
+}
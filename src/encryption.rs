@@ -0,0 +1,336 @@
+//! Encrypting export output with AES-256-GCM.
+//!
+//! Encrypted output starts with a header holding a format marker and a random nonce prefix,
+//! followed by the plaintext in chunks of 64 KiB, each sealed with AES-256-GCM using the
+//! STREAM construction. A chunk's nonce includes its position and whether it is the last
+//! one, so reordered, modified or truncated output fails to decrypt.
+
+use crate::{Filemaker, StreamOptions};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{KeyInit, OsRng};
+use aes_gcm::Aes256Gcm;
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Marks the start of encrypted output.
+const MAGIC: &[u8; 8] = b"FMLIBENC";
+
+/// The version of the encrypted format.
+const FORMAT_VERSION: u8 = 1;
+
+/// The size of the random nonce prefix; the remaining 5 bytes hold the chunk position.
+const NONCE_PREFIX_SIZE: usize = 7;
+
+const HEADER_SIZE: usize = MAGIC.len() + 1 + NONCE_PREFIX_SIZE;
+
+/// The number of plaintext bytes sealed per chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The size of the authentication tag added to every chunk.
+const TAG_SIZE: usize = 16;
+
+/// A 256-bit AES key for encrypting and decrypting exports.
+///
+/// The key is not printed by `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Creates a key from its 32 bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parses a key written as 64 hexadecimal digits.
+    ///
+    /// # Returns
+    /// * `Result<EncryptionKey>` - The key, or an error if `hex` is not 32 bytes of hexadecimal
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(anyhow!("An encryption key must be 64 hexadecimal digits"));
+        }
+        let mut bytes = [0u8; 32];
+        for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits)?;
+            *byte = u8::from_str_radix(digits, 16)
+                .map_err(|_| anyhow!("An encryption key must be 64 hexadecimal digits"))?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// Generates a random key.
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    /// Returns the key as 64 hexadecimal digits, for storing it in a secret manager.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0.into())
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// Encrypts everything written to it before passing it on to the inner writer.
+///
+/// The output is only complete once the writer is shut down, which seals the last chunk;
+/// flushing passes on the chunks sealed so far.
+pub struct EncryptingWriter<W> {
+    inner: W,
+    encryptor: Option<EncryptorBE32<Aes256Gcm>>,
+    plaintext: Vec<u8>,
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl<W> EncryptingWriter<W> {
+    /// Creates a writer encrypting with `key` into `inner`.
+    pub fn new(inner: W, key: &EncryptionKey) -> Self {
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut nonce_prefix);
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.push(FORMAT_VERSION);
+        header.extend_from_slice(&nonce_prefix);
+        Self {
+            inner,
+            encryptor: Some(EncryptorBE32::from_aead(key.cipher(), &nonce_prefix.into())),
+            plaintext: Vec::with_capacity(CHUNK_SIZE),
+            pending: header,
+            written: 0,
+        }
+    }
+
+    /// Returns the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> EncryptingWriter<W> {
+    /// Writes the sealed output not yet passed on to the inner writer.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.pending.len() {
+            let written =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.written..]))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += written;
+        }
+        self.pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for EncryptingWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        let Some(encryptor) = this.encryptor.as_mut() else {
+            return Poll::Ready(Err(io::Error::other("Write after shutdown")));
+        };
+        let accepted = buf.len().min(CHUNK_SIZE - this.plaintext.len());
+        this.plaintext.extend_from_slice(&buf[..accepted]);
+        if this.plaintext.len() == CHUNK_SIZE {
+            this.pending = encryptor
+                .encrypt_next(this.plaintext.as_slice())
+                .map_err(|_| io::Error::other("Failed to encrypt chunk"))?;
+            this.plaintext.clear();
+        }
+        Poll::Ready(Ok(accepted))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        if let Some(encryptor) = this.encryptor.take() {
+            this.pending = encryptor
+                .encrypt_last(this.plaintext.as_slice())
+                .map_err(|_| io::Error::other("Failed to encrypt chunk"))?;
+            this.plaintext.clear();
+            ready!(this.poll_drain(cx))?;
+        }
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Decrypts output written by [`EncryptingWriter`] while reading it.
+///
+/// Reading fails with [`io::ErrorKind::InvalidData`] if the input was not encrypted with the
+/// key, was modified or is incomplete.
+pub struct DecryptingReader<R> {
+    inner: R,
+    cipher: Option<Aes256Gcm>,
+    decryptor: Option<DecryptorBE32<Aes256Gcm>>,
+    input: Vec<u8>,
+    filled: usize,
+    plaintext: Vec<u8>,
+    read: usize,
+    finished: bool,
+}
+
+impl<R> DecryptingReader<R> {
+    /// Creates a reader decrypting `inner` with `key`.
+    pub fn new(inner: R, key: &EncryptionKey) -> Self {
+        Self {
+            inner,
+            cipher: Some(key.cipher()),
+            decryptor: None,
+            input: vec![0; HEADER_SIZE],
+            filled: 0,
+            plaintext: vec![],
+            read: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> DecryptingReader<R> {
+    /// Reads until the input buffer is full or the inner reader ends; returns whether it ended.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        while self.filled < self.input.len() {
+            let mut buf = ReadBuf::new(&mut self.input[self.filled..]);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+            let read = buf.filled().len();
+            if read == 0 {
+                return Poll::Ready(Ok(true));
+            }
+            self.filled += read;
+        }
+        Poll::Ready(Ok(false))
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        let header = &self.input[..self.filled];
+        if self.filled < HEADER_SIZE
+            || &header[..MAGIC.len()] != MAGIC
+            || header[MAGIC.len()] != FORMAT_VERSION
+        {
+            return Err(invalid_data(
+                "Input is not encrypted output of this library",
+            ));
+        }
+        let nonce_prefix: [u8; NONCE_PREFIX_SIZE] = header[MAGIC.len() + 1..]
+            .try_into()
+            .map_err(|_| invalid_data("Truncated header"))?;
+        let cipher = self
+            .cipher
+            .take()
+            .ok_or_else(|| invalid_data("Header read twice"))?;
+        self.decryptor = Some(DecryptorBE32::from_aead(cipher, &nonce_prefix.into()));
+        self.input = vec![0; CHUNK_SIZE + TAG_SIZE];
+        self.filled = 0;
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecryptingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.read < this.plaintext.len() {
+                let available = &this.plaintext[this.read..];
+                let copied = available.len().min(buf.remaining());
+                buf.put_slice(&available[..copied]);
+                this.read += copied;
+                return Poll::Ready(Ok(()));
+            }
+            if this.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            let ended = ready!(this.poll_fill(cx))?;
+            if this.decryptor.is_none() {
+                this.start()?;
+                continue;
+            }
+            let chunk = &this.input[..this.filled];
+            this.plaintext = if ended {
+                this.finished = true;
+                let decryptor = this.decryptor.take().expect("decryptor is set");
+                decryptor
+                    .decrypt_last(chunk)
+                    .map_err(|_| invalid_data("Failed to decrypt the last chunk"))?
+            } else {
+                let decryptor = this.decryptor.as_mut().expect("decryptor is set");
+                decryptor
+                    .decrypt_next(chunk)
+                    .map_err(|_| invalid_data("Failed to decrypt a chunk"))?
+            };
+            this.read = 0;
+            this.filled = 0;
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Filemaker {
+    /// Exports every record of the table into a new file at `path`, encrypted with `key`.
+    ///
+    /// The format and compression follow the file's extensions like for
+    /// [`Filemaker::export_to_file`], ignoring a trailing `.enc`, e.g. `people.jsonl.gz.enc`.
+    /// The output is compressed before it is encrypted. Read it back through a
+    /// [`DecryptingReader`].
+    ///
+    /// # Arguments
+    /// * `path` - The file to create
+    /// * `key` - The key to encrypt with
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error if the extension is not supported or the export fails
+    pub async fn export_to_file_encrypted(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        key: &EncryptionKey,
+        options: StreamOptions,
+    ) -> Result<u64> {
+        let path = path.as_ref();
+        let name = path.to_string_lossy();
+        let (format, compression) =
+            crate::export::export_format_of(name.strip_suffix(".enc").unwrap_or(&name))?;
+        let file = tokio::fs::File::create(path).await?;
+        self.export_compressed(
+            EncryptingWriter::new(file, key),
+            format,
+            compression,
+            options,
+        )
+        .await
+    }
+}
@@ -0,0 +1,333 @@
+//! Error types that callers may want to match on.
+//!
+//! Operations return [`anyhow::Error`]; the types in this module can be recovered from it
+//! with [`anyhow::Error::downcast_ref`].
+
+use crate::policy::Access;
+use serde_json::Value;
+use std::fmt;
+
+/// A record with the same key value already exists.
+///
+/// Returned by [`crate::Filemaker::assert_unique`] and [`crate::Filemaker::add_unique_record`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKeyError {
+    /// The key field that was checked.
+    pub field: String,
+    /// The value that already exists.
+    pub value: String,
+    /// The ID of the existing record.
+    pub record_id: String,
+}
+
+impl fmt::Display for DuplicateKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Duplicate key: {} = '{}' already exists in record {}",
+            self.field, self.value, self.record_id
+        )
+    }
+}
+
+impl std::error::Error for DuplicateKeyError {}
+
+/// A request was refused by the client's [`crate::LayoutPolicy`] before it was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyError {
+    /// The layout the request targeted.
+    pub layout: String,
+    /// The access the request needed.
+    pub access: Access,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Layout policy does not permit {} access to layout '{}'",
+            self.access, self.layout
+        )
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// A record is leased by another owner.
+///
+/// Returned by [`crate::Filemaker::lease_record`] and when renewing or releasing a lease that
+/// was lost. Its [`ErrorKind`] is [`ErrorKind::Locked`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseHeldError {
+    /// The ID of the leased record.
+    pub record_id: String,
+    /// The owner holding the lease, empty if the record is no longer leased.
+    pub owner: String,
+    /// When the lease expires.
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl fmt::Display for LeaseHeldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.owner.is_empty() {
+            true => write!(f, "Record {} is no longer leased", self.record_id),
+            false => write!(
+                f,
+                "Record {} is leased by {} until {}",
+                self.record_id, self.owner, self.expires_at
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LeaseHeldError {}
+
+/// A record could not be deserialized into the requested type.
+///
+/// Carries the JSON path of the value that did not match, e.g. `fieldData.InvoiceTotal`, so
+/// the offending field can be found without inspecting the whole record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeserializeError {
+    /// The path of the mismatched value, with array indices in brackets.
+    pub path: String,
+    /// What serde expected and found at that path.
+    pub message: String,
+}
+
+impl DeserializeError {
+    /// Returns the name of the field the mismatched value belongs to, if the path contains one.
+    pub fn field(&self) -> Option<&str> {
+        self.path
+            .split_once("fieldData.")
+            .map(|(_, rest)| rest.split(['.', '[']).next().unwrap_or(rest))
+    }
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to deserialize {}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+/// Deserializes `value`, reporting the path of a mismatched value below `root`.
+///
+/// # Arguments
+/// * `value` - The JSON to deserialize
+/// * `root` - The path of `value` within the response, e.g. `fieldData`; may be empty
+pub(crate) fn deserialize_at<T>(value: &Value, root: &str) -> Result<T, DeserializeError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let path = e.path().to_string();
+        let path = match (root.is_empty(), path.as_str()) {
+            (true, _) => path,
+            (false, ".") => root.to_string(),
+            (false, _) if path.starts_with('[') => format!("{}{}", root, path),
+            (false, _) => format!("{}.{}", root, path),
+        };
+        DeserializeError {
+            path,
+            message: e.into_inner().to_string(),
+        }
+    })
+}
+
+/// An error reported by the FileMaker Data API in the `messages` of a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    /// The FileMaker error code, e.g. `401` for "No records match the request".
+    pub code: u32,
+    /// The message accompanying the code.
+    pub message: String,
+}
+
+impl ApiError {
+    /// Returns the category of the error code.
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::from_code(self.code)
+    }
+
+    /// Returns the specific error the code stands for.
+    pub fn error(&self) -> FilemakerError {
+        FilemakerError::from_code(self.code)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FileMaker error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// The FileMaker errors callers commonly handle, named after their codes.
+///
+/// Unlike [`ErrorKind`], which groups codes into categories, this tells apart e.g. a find
+/// that matched nothing from a record that was deleted. Codes without a variant of their own
+/// are kept in [`FilemakerError::Other`].
+///
+/// ```rust,ignore
+/// match error.filemaker_error() {
+///     Some(FilemakerError::RecordNotFound) => Ok(vec![]),
+///     Some(FilemakerError::FieldValidationFailed(code)) => Err(anyhow!("Invalid input ({code})")),
+///     _ => Err(error),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilemakerError {
+    /// The account lacks the privileges for the request (9).
+    InsufficientPrivileges,
+    /// The record does not exist, e.g. it was deleted (101).
+    RecordMissing,
+    /// The field does not exist on the layout (102).
+    FieldMissing,
+    /// The script does not exist (104).
+    ScriptMissing,
+    /// The layout does not exist (105).
+    LayoutMissing,
+    /// The account name or password is wrong (212).
+    InvalidCredentials,
+    /// The record is being modified by another user (301).
+    RecordLocked,
+    /// The modification ID sent with an update is not the record's current one (306).
+    ModIdMismatch,
+    /// The find request has no criteria (400).
+    EmptyFindCriteria,
+    /// No records match the find request (401).
+    RecordNotFound,
+    /// A field value does not meet the field's validation options (500-599).
+    FieldValidationFailed(u32),
+    /// The database file could not be opened, e.g. it is closed on the server (802).
+    DatabaseUnavailable,
+    /// The session token is invalid or has expired (952).
+    TokenExpired,
+    /// Any other code.
+    Other(u32),
+}
+
+impl FilemakerError {
+    /// Maps a FileMaker error code to the error it stands for.
+    pub fn from_code(code: u32) -> FilemakerError {
+        match code {
+            9 => Self::InsufficientPrivileges,
+            101 => Self::RecordMissing,
+            102 => Self::FieldMissing,
+            104 => Self::ScriptMissing,
+            105 => Self::LayoutMissing,
+            212 => Self::InvalidCredentials,
+            301 => Self::RecordLocked,
+            306 => Self::ModIdMismatch,
+            400 => Self::EmptyFindCriteria,
+            401 => Self::RecordNotFound,
+            500..=599 => Self::FieldValidationFailed(code),
+            802 => Self::DatabaseUnavailable,
+            952 => Self::TokenExpired,
+            _ => Self::Other(code),
+        }
+    }
+
+    /// Returns the FileMaker error code.
+    pub fn code(self) -> u32 {
+        match self {
+            Self::InsufficientPrivileges => 9,
+            Self::RecordMissing => 101,
+            Self::FieldMissing => 102,
+            Self::ScriptMissing => 104,
+            Self::LayoutMissing => 105,
+            Self::InvalidCredentials => 212,
+            Self::RecordLocked => 301,
+            Self::ModIdMismatch => 306,
+            Self::EmptyFindCriteria => 400,
+            Self::RecordNotFound => 401,
+            Self::FieldValidationFailed(code) | Self::Other(code) => code,
+            Self::DatabaseUnavailable => 802,
+            Self::TokenExpired => 952,
+        }
+    }
+
+    /// Returns the category of the error.
+    pub fn kind(self) -> ErrorKind {
+        ErrorKind::from_code(self.code())
+    }
+}
+
+/// Broad categories of FileMaker errors, so callers can react without memorizing codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Authentication or privileges: invalid credentials, expired session token (952),
+    /// insufficient access privileges (9, 200-299).
+    Auth,
+    /// A file, record, field, script, layout or table does not exist (100-110), or a find
+    /// matched no records (401).
+    NotFound,
+    /// The request was rejected because of its content: find criteria (400-499), field
+    /// validation (500-599) or malformed Data API parameters (1600-1799).
+    Validation,
+    /// A record or table is in use by another user (300-399).
+    Locked,
+    /// A script or calculation failed (3, 4, 1200-1299).
+    Scripting,
+    /// The server could not process the request: file, host or general errors (800-999).
+    Server,
+    /// The request was refused by the client's layout policy and never sent.
+    Policy,
+    /// Not a FileMaker error, e.g. a network failure, or an unrecognized code.
+    Other,
+}
+
+impl ErrorKind {
+    /// Maps a FileMaker error code to its category.
+    pub fn from_code(code: u32) -> ErrorKind {
+        match code {
+            9 | 200..=299 | 952 => ErrorKind::Auth,
+            100..=110 | 401 => ErrorKind::NotFound,
+            400..=499 | 500..=599 | 1600..=1799 => ErrorKind::Validation,
+            300..=399 => ErrorKind::Locked,
+            3 | 4 | 1200..=1299 => ErrorKind::Scripting,
+            800..=999 => ErrorKind::Server,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Classifies errors returned by this crate.
+///
+/// Implemented for [`anyhow::Error`], so `error.kind()` works on any error returned by a
+/// [`crate::Filemaker`] operation.
+pub trait ErrorKindExt {
+    /// Returns the category of the FileMaker error behind this error, or [`ErrorKind::Other`].
+    fn kind(&self) -> ErrorKind;
+
+    /// Returns the FileMaker error behind this error, if there is one.
+    fn api_error(&self) -> Option<&ApiError>;
+
+    /// Returns the specific FileMaker error behind this error, if there is one.
+    fn filemaker_error(&self) -> Option<FilemakerError> {
+        self.api_error().map(ApiError::error)
+    }
+}
+
+impl ErrorKindExt for anyhow::Error {
+    fn kind(&self) -> ErrorKind {
+        if self.downcast_ref::<DuplicateKeyError>().is_some() {
+            return ErrorKind::Validation;
+        }
+        if self.downcast_ref::<PolicyError>().is_some() {
+            return ErrorKind::Policy;
+        }
+        if self.downcast_ref::<LeaseHeldError>().is_some() {
+            return ErrorKind::Locked;
+        }
+        self.api_error()
+            .map(ApiError::kind)
+            .unwrap_or(ErrorKind::Other)
+    }
+
+    fn api_error(&self) -> Option<&ApiError> {
+        self.downcast_ref::<ApiError>()
+    }
+}
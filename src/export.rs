@@ -0,0 +1,342 @@
+//! Exporting tables to files and other writers.
+
+use crate::{Compression, Filemaker, StreamOptions};
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use log::*;
+use serde_json::Value;
+use std::future::Future;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// A destination for the records of an export.
+///
+/// [`Filemaker::export_to`] calls `start` once, then `write_record` for every record in table
+/// order, then `finish`. Records are streamed with bounded memory, so a sink that writes
+/// slowly pauses fetching instead of letting records pile up. [`JsonlSink`] and [`CsvSink`]
+/// write to any [`AsyncWrite`]; implement the trait to export elsewhere, e.g. to a
+/// multipart upload or another database:
+///
+/// ```rust,ignore
+/// struct Counter(u64);
+///
+/// impl ExportSink for Counter {
+///     async fn start(&mut self, _fields: &[String]) -> Result<()> {
+///         Ok(())
+///     }
+///     async fn write_record(&mut self, _record: &Value) -> Result<()> {
+///         self.0 += 1;
+///         Ok(())
+///     }
+///     async fn finish(&mut self) -> Result<()> {
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait ExportSink {
+    /// Prepares the sink, given the names of the exported fields in layout order.
+    fn start(&mut self, fields: &[String]) -> impl Future<Output = Result<()>> + Send;
+
+    /// Writes one raw record, the object holding `fieldData`, `portalData`, `recordId` and
+    /// `modId`.
+    fn write_record(&mut self, record: &Value) -> impl Future<Output = Result<()>> + Send;
+
+    /// Completes the export, e.g. by flushing buffered output.
+    fn finish(&mut self) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Writes each record as one line of JSON.
+#[derive(Debug)]
+pub struct JsonlSink<W> {
+    writer: W,
+}
+
+impl<W> JsonlSink<W> {
+    /// Creates a sink writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Returns the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W> ExportSink for JsonlSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    async fn start(&mut self, _fields: &[String]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn write_record(&mut self, record: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Writes the field data of each record as a CSV row, after a header row of field names.
+///
+/// Fields are quoted when they contain commas, quotes or line breaks. Text, numbers and
+/// booleans are written as is, empty fields as nothing; portal data is left out.
+#[derive(Debug)]
+pub struct CsvSink<W> {
+    writer: W,
+    fields: Vec<String>,
+}
+
+impl<W> CsvSink<W> {
+    /// Creates a sink writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            fields: vec![],
+        }
+    }
+
+    /// Returns the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W> ExportSink for CsvSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    async fn start(&mut self, fields: &[String]) -> Result<()> {
+        self.fields = fields.to_vec();
+        let header = csv_row(self.fields.iter().map(String::as_str));
+        self.writer.write_all(header.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn write_record(&mut self, record: &Value) -> Result<()> {
+        let field_data = record.get("fieldData");
+        let values: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| match field_data.and_then(|f| f.get(field)) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(text)) => text.clone(),
+                Some(other) => other.to_string(),
+            })
+            .collect();
+        let row = csv_row(values.iter().map(String::as_str));
+        self.writer.write_all(row.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// The export formats with a built-in sink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// JSON Lines, written by [`JsonlSink`].
+    Jsonl,
+    /// CSV, written by [`CsvSink`].
+    Csv,
+}
+
+impl ExportFormat {
+    /// Picks the format from the extension of a file name or object key: `.jsonl` or
+    /// `.ndjson` for JSON Lines, `.csv` for CSV.
+    ///
+    /// A compression extension is not skipped; see [`Filemaker::export_to_file`].
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Formats one CSV line, quoting values that need it.
+fn csv_row<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    let mut row = values
+        .map(|value| {
+            if value.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push('\n');
+    row
+}
+
+impl Filemaker {
+    /// Exports every record of the table into `sink`.
+    ///
+    /// The exported field names are read from the layout metadata and passed to
+    /// [`ExportSink::start`]. Records are streamed with [`Filemaker::stream_records_with`], so
+    /// memory use is bounded by `options`. Fields excluded by [`Filemaker::with_field_filter`],
+    /// if set, are left out.
+    ///
+    /// # Arguments
+    /// * `sink` - The destination of the records
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error
+    pub async fn export_to<S>(&self, sink: &mut S, options: StreamOptions) -> Result<u64>
+    where
+        S: ExportSink,
+    {
+        let fields: Vec<String> = self
+            .layout_fields()
+            .await?
+            .into_iter()
+            .map(|field| field.name)
+            .filter(|name| !self.field_filter().is_some_and(|f| f.excludes(name)))
+            .collect();
+        sink.start(&fields).await?;
+
+        let mut records = std::pin::pin!(self.stream_records_with(options));
+        let mut count = 0u64;
+        while let Some(mut record) = records.try_next().await? {
+            if let Some(filter) = self.field_filter() {
+                filter.strip(&mut record);
+            }
+            sink.write_record(&record).await?;
+            count += 1;
+        }
+        sink.finish().await?;
+        info!("Exported {} records of {}", count, self.layout());
+        Ok(count)
+    }
+
+    /// Exports every record of the table as JSON Lines, one raw record per line.
+    ///
+    /// Equivalent to [`Filemaker::export_to`] with a [`JsonlSink`].
+    ///
+    /// # Arguments
+    /// * `writer` - The destination, e.g. a `tokio::fs::File`
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error
+    pub async fn export_jsonl<W>(&self, writer: &mut W, options: StreamOptions) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        self.export_to(&mut JsonlSink::new(writer), options).await
+    }
+
+    /// Exports the field data of every record of the table as CSV, with a header row.
+    ///
+    /// Equivalent to [`Filemaker::export_to`] with a [`CsvSink`].
+    ///
+    /// # Arguments
+    /// * `writer` - The destination, e.g. a `tokio::fs::File`
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error
+    pub async fn export_csv<W>(&self, writer: &mut W, options: StreamOptions) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        self.export_to(&mut CsvSink::new(writer), options).await
+    }
+
+    /// Exports every record of the table to `writer` in `format`.
+    ///
+    /// # Arguments
+    /// * `writer` - The destination, e.g. a `tokio::fs::File`
+    /// * `format` - The format to write
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error
+    pub async fn export_as<W>(
+        &self,
+        writer: &mut W,
+        format: ExportFormat,
+        options: StreamOptions,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        match format {
+            ExportFormat::Jsonl => self.export_jsonl(writer, options).await,
+            ExportFormat::Csv => self.export_csv(writer, options).await,
+        }
+    }
+
+    /// Exports every record of the table to `writer` in `format`, compressed with `compression`.
+    ///
+    /// `writer` is shut down at the end, which completes the compressed stream.
+    ///
+    /// # Arguments
+    /// * `writer` - The destination, e.g. a `tokio::fs::File`
+    /// * `format` - The format to write
+    /// * `compression` - How the output is compressed
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error
+    pub async fn export_compressed<W>(
+        &self,
+        writer: W,
+        format: ExportFormat,
+        compression: Compression,
+        options: StreamOptions,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Send,
+    {
+        let mut writer = compression.wrap(writer);
+        let exported = self.export_as(&mut writer, format, options).await?;
+        writer.shutdown().await?;
+        Ok(exported)
+    }
+
+    /// Exports every record of the table into a new file at `path`.
+    ///
+    /// The format and compression follow the file's extensions, e.g. `people.jsonl`,
+    /// `people.csv.gz` or `people.jsonl.zst`; see [`ExportFormat::from_path`] and
+    /// [`Compression::from_path`].
+    ///
+    /// # Arguments
+    /// * `path` - The file to create
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error if the extension is not supported or the export fails
+    pub async fn export_to_file(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        options: StreamOptions,
+    ) -> Result<u64> {
+        let path = path.as_ref();
+        let (format, compression) = export_format_of(&path.to_string_lossy())?;
+        let file = tokio::fs::File::create(path).await?;
+        self.export_compressed(file, format, compression, options)
+            .await
+    }
+}
+
+/// Picks the format and compression of an export from the extensions of `path`.
+pub(crate) fn export_format_of(path: &str) -> Result<(ExportFormat, Compression)> {
+    let compression = Compression::from_path(path)?;
+    let format = ExportFormat::from_path(compression.strip_extension(path))
+        .ok_or_else(|| anyhow!("No export format for the extension of {}", path))?;
+    Ok((format, compression))
+}
@@ -0,0 +1,335 @@
+//! A simplified JSON REST service over the Data API.
+//!
+//! [`router`] exposes list, find, create, update and delete endpoints for every layout the
+//! client may access, so applications in other languages can use FileMaker without learning
+//! the Data API's envelopes, session handling and error codes. Requests go through the given
+//! [`Filemaker`] client, with its shared session, failover endpoints and layout policy.
+//!
+//! | Method   | Path                              | Body                                   |
+//! |----------|-----------------------------------|----------------------------------------|
+//! | `GET`    | `/layouts/{layout}/records`       | query: `limit`, `offset`, `sort`, `descending`, `cursor` |
+//! | `POST`   | `/layouts/{layout}/_find`         | `{ "query": [..], "sort": [..], "ascending", "limit", "offset" }` |
+//! | `POST`   | `/layouts/{layout}/records`       | `{ "fields": { .. } }`                 |
+//! | `GET`    | `/layouts/{layout}/records/{id}`  |                                        |
+//! | `PATCH`  | `/layouts/{layout}/records/{id}`  | `{ "fields": { .. }, "modId": ".." }`  |
+//! | `DELETE` | `/layouts/{layout}/records/{id}`  |                                        |
+//! | `GET`    | `/layouts/{layout}/changes`       | Server-Sent Events                     |
+//!
+//! Records are returned as `{ "id", "modId", "fields" }`. Errors are returned as
+//! `{ "error": { "kind", "code", "message" } }` with a status derived from the error kind.
+//!
+//! The changes endpoint streams a `change` event carrying a [`ChangeEvent`] for every record
+//! created, updated or deleted on the layout. All subscribers of a layout share one
+//! [`ChangeFeed`], which is started by the first subscriber and stops at the first change
+//! detected after the last one has left.
+//!
+//! Requires the `http-facade` feature.
+
+use crate::{ChangeEvent, ChangeFeed, Cursor, ErrorKind, ErrorKindExt, Filemaker, Page};
+use anyhow::{anyhow, Result};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::stream::{self, Stream};
+use log::*;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::broadcast::error::RecvError;
+
+/// The page size used when a request does not specify one.
+const DEFAULT_LIMIT: u64 = 100;
+
+/// The interval at which layouts with change subscribers are polled by default.
+const DEFAULT_CHANGE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+struct FacadeState {
+    filemaker: Filemaker,
+    change_poll_interval: Duration,
+    /// The running change feed of each layout with subscribers.
+    feeds: Arc<Mutex<HashMap<String, Arc<ChangeFeed>>>>,
+}
+
+/// Builds the facade's routes, backed by `filemaker`.
+///
+/// The layout in each path replaces the client's own layout; the client's session is shared
+/// by all requests. Layouts with change subscribers are polled every 5 seconds.
+pub fn router(filemaker: Filemaker) -> Router {
+    router_with_change_poll_interval(filemaker, DEFAULT_CHANGE_POLL_INTERVAL)
+}
+
+/// Builds the facade's routes like [`router`], polling layouts with change subscribers every
+/// `interval`.
+pub fn router_with_change_poll_interval(filemaker: Filemaker, interval: Duration) -> Router {
+    let state = FacadeState {
+        filemaker,
+        change_poll_interval: interval,
+        feeds: Arc::new(Mutex::new(HashMap::new())),
+    };
+    Router::new()
+        .route("/layouts/{layout}/records", get(list).post(create))
+        .route("/layouts/{layout}/_find", post(find))
+        .route(
+            "/layouts/{layout}/records/{id}",
+            get(fetch).patch(update).delete(remove),
+        )
+        .route("/layouts/{layout}/changes", get(changes))
+        .with_state(state)
+}
+
+/// Serves the facade on `address` until the process ends.
+///
+/// # Arguments
+/// * `filemaker` - The client requests are sent through
+/// * `address` - The address to listen on, e.g. `"127.0.0.1:8080"`
+///
+/// # Returns
+/// * `Result<()>` - An error if the address cannot be bound or the server fails
+pub async fn serve(filemaker: Filemaker, address: impl ToSocketAddrs) -> Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    info!("HTTP facade listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(filemaker)).await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct ListParams {
+    limit: Option<u64>,
+    offset: Option<u64>,
+    /// Comma separated field names.
+    sort: Option<String>,
+    #[serde(default)]
+    descending: bool,
+    /// A `next` or `prev` token of a previous response.
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FindBody {
+    query: Vec<HashMap<String, String>>,
+    #[serde(default)]
+    sort: Vec<String>,
+    #[serde(default = "default_ascending")]
+    ascending: bool,
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+fn default_ascending() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct WriteBody {
+    fields: Map<String, Value>,
+    #[serde(rename = "modId")]
+    mod_id: Option<String>,
+}
+
+/// An error returned by a facade endpoint.
+struct FacadeError(anyhow::Error);
+
+impl From<anyhow::Error> for FacadeError {
+    fn from(error: anyhow::Error) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for FacadeError {
+    fn into_response(self) -> Response {
+        let kind = self.0.kind();
+        let status = match kind {
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::Validation => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::Locked => StatusCode::CONFLICT,
+            ErrorKind::Auth | ErrorKind::Policy => StatusCode::FORBIDDEN,
+            ErrorKind::Scripting | ErrorKind::Server => StatusCode::BAD_GATEWAY,
+            ErrorKind::Other => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let code = self.0.api_error().map(|e| e.code);
+        warn!("HTTP facade request failed: {:#}", self.0);
+        let body = json!({
+            "error": {
+                "kind": format!("{:?}", kind),
+                "code": code,
+                "message": format!("{:#}", self.0),
+            }
+        });
+        (status, Json(body)).into_response()
+    }
+}
+
+type FacadeResult<T> = std::result::Result<T, FacadeError>;
+
+async fn list(
+    State(FacadeState { filemaker, .. }): State<FacadeState>,
+    Path(layout): Path<String>,
+    Query(params): Query<ListParams>,
+) -> FacadeResult<Json<Value>> {
+    let cursor = match params.cursor {
+        Some(token) => {
+            let cursor = Cursor::decode(&token)?;
+            if cursor.query.is_some() {
+                return Err(anyhow!("Cursor belongs to a find; use the _find endpoint").into());
+            }
+            cursor
+        }
+        None => {
+            let sort = params
+                .sort
+                .map(|s| s.split(',').map(|f| f.trim().to_string()).collect())
+                .unwrap_or_default();
+            Cursor {
+                offset: params.offset.unwrap_or(1).max(1),
+                ..Cursor::new(params.limit.unwrap_or(DEFAULT_LIMIT))
+            }
+            .with_sort(sort, !params.descending)
+        }
+    };
+    let page = filemaker.with_layout(&layout).fetch_page(&cursor).await?;
+    Ok(Json(page_json(page)?))
+}
+
+async fn find(
+    State(FacadeState { filemaker, .. }): State<FacadeState>,
+    Path(layout): Path<String>,
+    Json(body): Json<FindBody>,
+) -> FacadeResult<Json<Value>> {
+    let cursor = Cursor {
+        offset: body.offset.unwrap_or(1).max(1),
+        ..Cursor::new(body.limit.unwrap_or(DEFAULT_LIMIT))
+    }
+    .with_query(body.query)
+    .with_sort(body.sort, body.ascending);
+    let page = filemaker.with_layout(&layout).fetch_page(&cursor).await?;
+    Ok(Json(page_json(page)?))
+}
+
+async fn fetch(
+    State(FacadeState { filemaker, .. }): State<FacadeState>,
+    Path((layout, id)): Path<(String, String)>,
+) -> FacadeResult<Json<Value>> {
+    let record = filemaker.with_layout(&layout).get_record_by_id(&id).await?;
+    Ok(Json(record_json(&record)))
+}
+
+async fn create(
+    State(FacadeState { filemaker, .. }): State<FacadeState>,
+    Path(layout): Path<String>,
+    Json(body): Json<WriteBody>,
+) -> FacadeResult<(StatusCode, Json<Value>)> {
+    let created = filemaker
+        .with_layout(&layout)
+        .create_record(body.fields.into_iter().collect())
+        .await?;
+    Ok((StatusCode::CREATED, Json(record_json(&created.record))))
+}
+
+async fn update(
+    State(FacadeState { filemaker, .. }): State<FacadeState>,
+    Path((layout, id)): Path<(String, String)>,
+    Json(body): Json<WriteBody>,
+) -> FacadeResult<Json<Value>> {
+    let filemaker = filemaker.with_layout(&layout);
+    let response = filemaker
+        .patch_record(&id, Value::Object(body.fields), body.mod_id.as_deref())
+        .await?;
+    if let Some(api_error) = Filemaker::api_error(&response) {
+        return Err(anyhow::Error::new(api_error)
+            .context("Failed to update record")
+            .into());
+    }
+    let record = filemaker.get_record_by_id(&id).await?;
+    Ok(Json(record_json(&record)))
+}
+
+async fn remove(
+    State(FacadeState { filemaker, .. }): State<FacadeState>,
+    Path((layout, id)): Path<(String, String)>,
+) -> FacadeResult<StatusCode> {
+    let response = filemaker
+        .with_layout(&layout)
+        .delete_record_response(&id)
+        .await?;
+    if let Some(api_error) = Filemaker::api_error(&response) {
+        return Err(anyhow::Error::new(api_error)
+            .context("Failed to delete record")
+            .into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn changes(
+    State(state): State<FacadeState>,
+    Path(layout): Path<String>,
+) -> FacadeResult<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
+    let filemaker = state.filemaker.with_layout(&layout);
+    // Surface policy and missing layouts before the stream starts
+    filemaker.get_number_of_records().await?;
+    let receiver = {
+        let mut feeds = state.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        let feed = feeds
+            .entry(layout.clone())
+            .and_modify(|feed| {
+                if feed.is_closed() {
+                    *feed = Arc::new(filemaker.change_feed(state.change_poll_interval));
+                }
+            })
+            .or_insert_with(|| Arc::new(filemaker.change_feed(state.change_poll_interval)));
+        feed.subscribe()
+    };
+    debug!("New change subscriber for layout {}", layout);
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(change) => change_event(&change),
+            Err(RecvError::Lagged(skipped)) => {
+                Event::default().event("lagged").data(skipped.to_string())
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn change_event(change: &ChangeEvent) -> Event {
+    let data = json!({
+        "change": change.change,
+        "layout": change.layout,
+        "record": change.record.as_ref().map(record_json).unwrap_or_else(|| json!({
+            "id": change.record_id,
+            "modId": change.mod_id,
+        })),
+    });
+    Event::default()
+        .event("change")
+        .id(change.record_id.clone())
+        .data(data.to_string())
+}
+
+/// Converts a Data API record to `{ "id", "modId", "fields" }`.
+fn record_json(record: &Value) -> Value {
+    json!({
+        "id": record.get("recordId").cloned().unwrap_or(Value::Null),
+        "modId": record.get("modId").cloned().unwrap_or(Value::Null),
+        "fields": record.get("fieldData").cloned().unwrap_or_else(|| json!({})),
+    })
+}
+
+fn page_json(page: Page) -> Result<Value> {
+    Ok(json!({
+        "records": page.records.iter().map(record_json).collect::<Vec<_>>(),
+        "foundCount": page.found_count,
+        "next": page.next.map(|c| c.encode()).transpose()?,
+        "prev": page.prev.map(|c| c.encode()).transpose()?,
+    }))
+}
@@ -0,0 +1,358 @@
+//! Failover between several Data API endpoints.
+//!
+//! A client created with [`Filemaker::new_with_endpoints`] keeps its session pinned to one
+//! endpoint and tracks the latency and failures of every endpoint it has used. When the
+//! pinned endpoint cannot be reached, the session is re-created on the healthiest remaining
+//! endpoint and the request is retried there.
+
+use crate::accounting;
+use crate::{query, Filemaker, NumberFormat, SessionOptions};
+use anyhow::{anyhow, Result};
+use log::*;
+use reqwest::Method;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long an endpoint that failed is avoided when choosing where to pin a session.
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Weight of the latest request in the moving average of an endpoint's latency.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// The health of one endpoint, as returned by [`Filemaker::endpoint_status`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointStatus {
+    /// The base URL of the endpoint.
+    pub url: String,
+    /// The moving average of the endpoint's request latency, if it has been used.
+    pub latency: Option<Duration>,
+    /// The number of failures since the endpoint last answered a request.
+    pub consecutive_failures: u32,
+    /// Whether the session is currently pinned to this endpoint.
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct EndpointHealth {
+    latency_ms: Option<f64>,
+    consecutive_failures: u32,
+    last_failure: Option<Instant>,
+}
+
+impl EndpointHealth {
+    fn cooling_down(&self) -> bool {
+        self.last_failure
+            .is_some_and(|at| at.elapsed() < FAILURE_COOLDOWN)
+    }
+}
+
+/// The endpoints of a client, the one its session is pinned to, and the credentials needed
+/// to re-create the session elsewhere.
+pub(crate) struct EndpointPool {
+    urls: Vec<String>,
+    health: StdMutex<Vec<EndpointHealth>>,
+    pinned: AtomicUsize,
+    username: String,
+    password: String,
+    repin: Mutex<()>,
+}
+
+impl EndpointPool {
+    fn new(urls: Vec<String>, username: &str, password: &str) -> Self {
+        let health = vec![EndpointHealth::default(); urls.len()];
+        Self {
+            urls,
+            health: StdMutex::new(health),
+            pinned: AtomicUsize::new(0),
+            username: username.to_string(),
+            password: password.to_string(),
+            repin: Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub(crate) fn password(&self) -> &str {
+        &self.password
+    }
+
+    /// Creates a pool over the same endpoints for another account, with fresh health.
+    pub(crate) fn with_credentials(&self, username: &str, password: &str) -> Self {
+        Self::new(self.urls.clone(), username, password)
+    }
+
+    /// Returns the base URL the session is pinned to.
+    pub(crate) fn pinned_url(&self) -> &str {
+        &self.urls[self.pinned.load(Ordering::SeqCst)]
+    }
+
+    /// Points a request URL built for any of the endpoints at the pinned one.
+    fn rebase(&self, url: &str) -> String {
+        let pinned = self.pinned_url();
+        match self.urls.iter().find(|base| url.starts_with(base.as_str())) {
+            Some(base) if base != pinned => format!("{}{}", pinned, &url[base.len()..]),
+            _ => url.to_string(),
+        }
+    }
+
+    fn record_success(&self, index: usize, latency: Duration) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = &mut health[index];
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        entry.latency_ms = Some(match entry.latency_ms {
+            Some(average) => average + LATENCY_SMOOTHING * (latency_ms - average),
+            None => latency_ms,
+        });
+        entry.consecutive_failures = 0;
+        entry.last_failure = None;
+    }
+
+    fn record_failure(&self, index: usize) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health[index].consecutive_failures += 1;
+        health[index].last_failure = Some(Instant::now());
+    }
+
+    /// Returns the endpoint indices from healthiest to least healthy.
+    ///
+    /// Endpoints that failed recently come last; the rest are ordered by failures, then by
+    /// latency, then by their position in the configured list.
+    fn by_health(&self) -> Vec<usize> {
+        let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        let mut indices: Vec<usize> = (0..self.urls.len()).collect();
+        indices.sort_by(|&a, &b| {
+            let (a_health, b_health) = (&health[a], &health[b]);
+            a_health
+                .cooling_down()
+                .cmp(&b_health.cooling_down())
+                .then(
+                    a_health
+                        .consecutive_failures
+                        .cmp(&b_health.consecutive_failures),
+                )
+                .then(
+                    a_health
+                        .latency_ms
+                        .unwrap_or_default()
+                        .total_cmp(&b_health.latency_ms.unwrap_or_default()),
+                )
+                .then(a.cmp(&b))
+        });
+        indices
+    }
+
+    fn status(&self) -> Vec<EndpointStatus> {
+        let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        let pinned = self.pinned.load(Ordering::SeqCst);
+        self.urls
+            .iter()
+            .zip(health.iter())
+            .enumerate()
+            .map(|(index, (url, health))| EndpointStatus {
+                url: url.clone(),
+                latency: health
+                    .latency_ms
+                    .map(|ms| Duration::from_secs_f64(ms / 1000.0)),
+                consecutive_failures: health.consecutive_failures,
+                pinned: index == pinned,
+            })
+            .collect()
+    }
+}
+
+impl Filemaker {
+    /// Creates a new `Filemaker` instance that fails over between several Data API endpoints.
+    ///
+    /// The session is created on the first endpoint that accepts the credentials, in list
+    /// order. Whenever the pinned endpoint cannot be reached, the session is re-created on
+    /// the healthiest other endpoint, judged by recent failures and latency, and the request
+    /// is retried there. `FM_URL` is not used by such a client.
+    ///
+    /// # Arguments
+    /// * `endpoints` - The base URLs of the Data API, e.g. `https://fm1.example.com/fmi/data/vLatest`
+    /// * `username` - The username for FileMaker authentication
+    /// * `password` - The password for FileMaker authentication
+    /// * `database` - The name of the FileMaker database to connect to
+    /// * `table` - The name of the table/layout to operate on
+    ///
+    /// # Returns
+    /// * `Result<Self>` - A new Filemaker instance, or an error if no endpoint accepted the session
+    pub async fn new_with_endpoints<I, S>(
+        endpoints: I,
+        username: &str,
+        password: &str,
+        database: &str,
+        table: &str,
+    ) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let urls: Vec<String> = endpoints
+            .into_iter()
+            .map(|url| url.into().trim_end_matches('/').to_string())
+            .collect();
+        if urls.is_empty() {
+            return Err(anyhow!("At least one endpoint is required"));
+        }
+
+        let filemaker = Self {
+            database: query::encode_url_component(database),
+            table: query::encode_url_component(table),
+            token: Arc::new(Mutex::new(None)),
+            client: Self::build_client()?,
+            number_format: NumberFormat::default(),
+            server_timezone: chrono_tz::UTC,
+            slow_operation_threshold: None,
+            layout_quota: None,
+            adaptive_concurrency: None,
+            layout_policy: None,
+            base_url: None,
+            endpoints: Some(Arc::new(EndpointPool::new(urls, username, password))),
+            lease_fields: None,
+            stats: Arc::default(),
+            recorder: None,
+            replayer: None,
+            post_processors: vec![],
+            hooks: vec![],
+            field_filter: None,
+            session_refresh: Arc::default(),
+            standby: None,
+            credentials: None,
+            sort_tiebreaker: None,
+            throttle: None,
+            fmid_tokens: None,
+            session_options: SessionOptions::default(),
+        };
+        if let Some(pool) = &filemaker.endpoints {
+            filemaker.pin_session(pool, None).await?;
+        }
+        info!("Filemaker instance created successfully");
+        Ok(filemaker)
+    }
+
+    /// Returns the health of each failover endpoint, in configured order.
+    ///
+    /// Empty for clients created with [`Filemaker::new`].
+    pub fn endpoint_status(&self) -> Vec<EndpointStatus> {
+        self.endpoints
+            .as_ref()
+            .map(|pool| pool.status())
+            .unwrap_or_default()
+    }
+
+    /// Sends a request to the pinned endpoint, re-pinning the session and retrying on the
+    /// next healthiest endpoint if it cannot be reached.
+    ///
+    /// A request rejected because the session expired is retried once on a new session.
+    pub(crate) async fn failover_request(
+        &self,
+        pool: &EndpointPool,
+        url: &str,
+        method: Method,
+        body: Option<Value>,
+    ) -> Result<Value> {
+        let mut attempts = 1;
+        let mut refreshed = false;
+        loop {
+            let index = pool.pinned.load(Ordering::SeqCst);
+            let url = pool.rebase(url);
+            let token = self.token.lock().await.clone();
+            let started = Instant::now();
+            let result = self
+                .send_authenticated_request(&url, method.clone(), body.clone())
+                .await;
+            let error = match result {
+                Ok(json) => {
+                    pool.record_success(index, started.elapsed());
+                    match token {
+                        Some(stale) if !refreshed && Self::is_session_expired(&json) => {
+                            self.refresh_session(Some(pool), &stale).await?;
+                            self.record_retry();
+                            refreshed = true;
+                            continue;
+                        }
+                        _ => return Ok(json),
+                    }
+                }
+                Err(e) => e,
+            };
+            pool.record_failure(index);
+            if !is_endpoint_failure(&error) || attempts >= pool.urls.len() {
+                return Err(error);
+            }
+
+            warn!("Endpoint {} failed: {}", pool.urls[index], error);
+            self.pin_session(pool, Some(index)).await?;
+            self.record_retry();
+            attempts += 1;
+        }
+    }
+
+    /// Creates a session on the healthiest endpoint other than `failed` and pins it.
+    ///
+    /// If another request already re-pinned the session away from `failed`, the existing
+    /// pin is kept.
+    pub(crate) async fn pin_session(
+        &self,
+        pool: &EndpointPool,
+        failed: Option<usize>,
+    ) -> Result<usize> {
+        let _guard = pool.repin.lock().await;
+        let current = pool.pinned.load(Ordering::SeqCst);
+        if failed.is_some_and(|failed| failed != current) {
+            return Ok(current);
+        }
+
+        let database = self.database();
+        let (username, password) = self.login_credentials(pool)?;
+        let mut last_error = anyhow!("No endpoint available");
+        for index in pool.by_health() {
+            if Some(index) == failed {
+                continue;
+            }
+            let url = &pool.urls[index];
+            let started = Instant::now();
+            match Self::get_session_token_at(
+                &self.client,
+                url,
+                &database,
+                &username,
+                &password,
+                &self.session_options,
+            )
+            .await
+            {
+                Ok(token) => {
+                    pool.record_success(index, started.elapsed());
+                    if let Some(replaced) = self.token.lock().await.replace(token) {
+                        accounting::closed(&replaced);
+                    }
+                    pool.pinned.store(index, Ordering::SeqCst);
+                    info!("Pinned session to endpoint {}", url);
+                    return Ok(index);
+                }
+                Err(e) => {
+                    warn!("Failed to create session on endpoint {}: {}", url, e);
+                    pool.record_failure(index);
+                    last_error = e;
+                }
+            }
+        }
+        error!("No endpoint accepted the session");
+        Err(last_error.context("No endpoint accepted the session"))
+    }
+}
+
+/// Returns whether an error means the endpoint could not be reached or answered with
+/// something other than a Data API response.
+fn is_endpoint_failure(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout() || e.is_request() || e.is_decode())
+}
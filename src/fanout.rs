@@ -0,0 +1,213 @@
+//! Running the same find on several layouts or databases at once.
+
+use crate::{Cursor, Filemaker};
+use anyhow::Result;
+use log::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::task::JoinSet;
+
+/// The number of records fetched per request while collecting a found set.
+const FAN_OUT_PAGE_SIZE: u64 = 1000;
+
+/// A record together with the database and layout it was found on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaggedRecord {
+    /// The database the record was found in.
+    pub database: String,
+    /// The layout the record was found on.
+    pub layout: String,
+    /// The record as returned by the server.
+    pub record: Value,
+}
+
+/// Outcome of a find run on several layouts or databases.
+#[derive(Debug, Default)]
+pub struct FanOutResults {
+    /// The matching records, grouped by source in the order the sources were given.
+    pub records: Vec<TaggedRecord>,
+    /// Sources whose find failed, by layout or database name, together with the reason.
+    pub failed: Vec<(String, anyhow::Error)>,
+}
+
+impl FanOutResults {
+    /// Returns the records found on `layout`.
+    pub fn for_layout<'a>(&'a self, layout: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.records
+            .iter()
+            .filter(move |r| r.layout == layout)
+            .map(|r| &r.record)
+    }
+
+    /// Returns the records found in `database`.
+    pub fn for_database<'a>(&'a self, database: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.records
+            .iter()
+            .filter(move |r| r.database == database)
+            .map(|r| &r.record)
+    }
+}
+
+impl Filemaker {
+    /// Runs the same find on several layouts of this database concurrently.
+    ///
+    /// Useful for solutions where one logical table is split across filtered layouts. Every
+    /// matching record is collected, tagged with its layout. A layout whose find fails does
+    /// not abort the others; it is reported in [`FanOutResults::failed`]. A find without
+    /// matches is not a failure.
+    ///
+    /// # Arguments
+    /// * `layouts` - The layouts to search
+    /// * `query` - The find criteria, as for [`Filemaker::search`]
+    ///
+    /// # Returns
+    /// * `Result<FanOutResults>` - The tagged records and the failed layouts
+    pub async fn search_layouts<I, S>(
+        &self,
+        layouts: I,
+        query: Vec<HashMap<String, String>>,
+    ) -> Result<FanOutResults>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let sources = layouts
+            .into_iter()
+            .map(|layout| {
+                (
+                    layout.as_ref().to_string(),
+                    self.with_layout(layout.as_ref()),
+                )
+            })
+            .collect();
+        let mut results = FanOutResults::default();
+        fan_out_find(sources, query, &mut results).await?;
+        Ok(results)
+    }
+
+    /// Runs the same find on this client's layout in several databases and merges the results.
+    ///
+    /// Meant for multi-file solutions sharded by year or region. A separate session is
+    /// opened for each database with [`Filemaker::with_database`], and the finds run
+    /// concurrently. Every matching record is collected, tagged with its database. A database
+    /// whose login or find fails does not abort the others; it is reported in
+    /// [`FanOutResults::failed`].
+    ///
+    /// # Arguments
+    /// * `databases` - The databases to search
+    /// * `username` - The username for FileMaker authentication
+    /// * `password` - The password for FileMaker authentication
+    /// * `query` - The find criteria, as for [`Filemaker::search`]
+    ///
+    /// # Returns
+    /// * `Result<FanOutResults>` - The tagged records, in database order, and the failed databases
+    pub async fn search_databases<I, S>(
+        &self,
+        databases: I,
+        username: &str,
+        password: &str,
+        query: Vec<HashMap<String, String>>,
+    ) -> Result<FanOutResults>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut tasks = JoinSet::new();
+        for (index, database) in databases.into_iter().enumerate() {
+            let database = database.as_ref().to_string();
+            let (filemaker, username, password) =
+                (self.clone(), username.to_string(), password.to_string());
+            tasks.spawn(async move {
+                let client = filemaker
+                    .with_database(&database, &username, &password)
+                    .await;
+                (index, database, client)
+            });
+        }
+        let mut sessions = Vec::with_capacity(tasks.len());
+        while let Some(joined) = tasks.join_next().await {
+            sessions.push(joined?);
+        }
+        sessions.sort_by_key(|(index, _, _)| *index);
+
+        let mut results = FanOutResults::default();
+        let mut sources = Vec::with_capacity(sessions.len());
+        for (_, database, client) in sessions {
+            match client {
+                Ok(client) => sources.push((database, client)),
+                Err(e) => {
+                    error!("Failed to open session for database {}: {}", database, e);
+                    results.failed.push((database, e));
+                }
+            }
+        }
+        fan_out_find(sources, query, &mut results).await?;
+        Ok(results)
+    }
+
+    /// Collects every record of a find's found set.
+    pub(crate) async fn find_all_pages(
+        &self,
+        query: Vec<HashMap<String, String>>,
+    ) -> Result<Vec<Value>> {
+        let mut cursor = Some(Cursor::new(FAN_OUT_PAGE_SIZE).with_query(query));
+        let mut records = Vec::new();
+        while let Some(current) = cursor {
+            let page = self.fetch_page(&current).await?;
+            records.extend(page.records);
+            cursor = page.next;
+        }
+        Ok(records)
+    }
+}
+
+/// Runs a find on every named client concurrently and adds the tagged results to `results`.
+///
+/// Failures are reported under the name given with the client.
+async fn fan_out_find(
+    sources: Vec<(String, Filemaker)>,
+    query: Vec<HashMap<String, String>>,
+    results: &mut FanOutResults,
+) -> Result<()> {
+    let mut tasks = JoinSet::new();
+    for (index, (name, client)) in sources.into_iter().enumerate() {
+        let query = query.clone();
+        tasks.spawn(async move {
+            let result = client.find_all_pages(query).await;
+            (index, name, client, result)
+        });
+    }
+
+    let mut outcomes = Vec::with_capacity(tasks.len());
+    while let Some(joined) = tasks.join_next().await {
+        outcomes.push(joined?);
+    }
+    outcomes.sort_by_key(|(index, _, _, _)| *index);
+
+    for (_, name, client, result) in outcomes {
+        let (database, layout) = (client.database(), client.layout());
+        match result {
+            Ok(records) => {
+                debug!("Found {} records on {}/{}", records.len(), database, layout);
+                results
+                    .records
+                    .extend(records.into_iter().map(|record| TaggedRecord {
+                        database: database.clone(),
+                        layout: layout.clone(),
+                        record,
+                    }));
+            }
+            Err(e) => {
+                error!("Find on {}/{} failed: {}", database, layout, e);
+                results.failed.push((name, e));
+            }
+        }
+    }
+    info!(
+        "Fanned out find: {} records, {} sources failed",
+        results.records.len(),
+        results.failed.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! Rules for leaving fields, such as globals, out of field lists, exports and codegen.
+
+use crate::Filemaker;
+use anyhow::Result;
+use log::*;
+use regex::Regex;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Decides which fields are excluded from field names, exports and generated models.
+///
+/// A field is excluded if its name starts with one of `prefixes`, matches one of
+/// `patterns`, or equals one of `names`. The default excludes fields starting with `g_`,
+/// the common naming convention for global fields.
+///
+/// ```rust,ignore
+/// let filter = FieldFilter::none()
+///     .with_prefix("g_")
+///     .with_pattern(r"(?i)^zz")?
+///     .with_name("Internal Notes");
+/// let filemaker = filemaker.with_field_filter(filter);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FieldFilter {
+    /// Fields whose names start with one of these are excluded.
+    pub prefixes: Vec<String>,
+    /// Fields whose names match one of these are excluded.
+    pub patterns: Vec<Regex>,
+    /// Fields with exactly these names are excluded.
+    pub names: Vec<String>,
+}
+
+impl Default for FieldFilter {
+    fn default() -> Self {
+        Self::none().with_prefix("g_")
+    }
+}
+
+impl PartialEq for FieldFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.prefixes == other.prefixes
+            && self.names == other.names
+            && self.patterns.len() == other.patterns.len()
+            && self
+                .patterns
+                .iter()
+                .zip(&other.patterns)
+                .all(|(a, b)| a.as_str() == b.as_str())
+    }
+}
+
+impl Eq for FieldFilter {}
+
+impl FieldFilter {
+    /// Creates a filter that excludes no fields.
+    pub fn none() -> Self {
+        Self {
+            prefixes: vec![],
+            patterns: vec![],
+            names: vec![],
+        }
+    }
+
+    /// Excludes fields whose names start with `prefix`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Excludes fields whose names match the regular expression `pattern`.
+    ///
+    /// # Returns
+    /// * `Result<Self>` - The filter, or an error if `pattern` is not a valid regular expression
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self> {
+        self.patterns.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Excludes the field named `name`.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Returns whether the field named `field` is excluded.
+    pub fn excludes(&self, field: &str) -> bool {
+        self.prefixes.iter().any(|prefix| field.starts_with(prefix))
+            || self.names.iter().any(|name| name == field)
+            || self.patterns.iter().any(|pattern| pattern.is_match(field))
+    }
+
+    /// Removes the excluded fields from a record's `fieldData`.
+    pub(crate) fn strip(&self, record: &mut Value) {
+        if let Some(fields) = record.get_mut("fieldData").and_then(|f| f.as_object_mut()) {
+            fields.retain(|name, _| !self.excludes(name));
+        }
+    }
+}
+
+impl Filemaker {
+    /// Sets the rules for excluding fields.
+    ///
+    /// The filter replaces the default `g_` prefix rule of [`Filemaker::get_row_names`], and
+    /// is also applied by [`Filemaker::export_jsonl`] and [`Filemaker::generate_layouts`],
+    /// which include every field unless a filter is set.
+    ///
+    /// # Arguments
+    /// * `filter` - The exclusion rules
+    ///
+    /// # Returns
+    /// * `Self` - The client with the new filter
+    pub fn with_field_filter(mut self, filter: FieldFilter) -> Self {
+        debug!("Excluding fields with {:?}", filter);
+        self.field_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Returns the configured field filter, if any.
+    pub(crate) fn field_filter(&self) -> Option<&FieldFilter> {
+        self.field_filter.as_deref()
+    }
+}
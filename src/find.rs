@@ -0,0 +1,195 @@
+//! Finds with configurable handling of empty found sets.
+
+use crate::options::{with_timeout, FindOptions};
+use crate::{query, DataInfo, Filemaker, FindResult, Record, Response, NO_RECORDS_MATCH_CODE};
+use anyhow::Result;
+use log::*;
+use reqwest::Method;
+use std::collections::{HashMap, HashSet};
+
+/// Number of key values combined into one find request by default.
+pub(crate) const DEFAULT_KEY_BATCH_SIZE: usize = 100;
+
+/// What a find should return when no records match.
+///
+/// The Data API reports an empty found set as error 401. Some call sites treat that as a
+/// normal outcome, others as a distinct case or as a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyFind {
+    /// Return a result without records.
+    #[default]
+    Empty,
+    /// Return `None`.
+    None,
+    /// Return the FileMaker error 401, which has [`crate::ErrorKind::NotFound`].
+    Error,
+}
+
+impl Filemaker {
+    /// Appends `field` as the last sort key of every sorted request of this client.
+    ///
+    /// Records with equal values in the sort fields come back in no guaranteed order, so a
+    /// paginated find sorted on a non-unique field can repeat or skip records at page
+    /// boundaries. Ending every sort with a unique field makes the order deterministic. The
+    /// Data API can only sort by fields, so `field` must be a field on the layout holding a
+    /// unique value, typically a calculation of `Get ( RecordID )`. Unsorted requests are
+    /// left as they are.
+    ///
+    /// # Arguments
+    /// * `field` - The unique field used as the final sort key, e.g. `RecordID`
+    ///
+    /// # Returns
+    /// * `Self` - The client sorting with the tiebreaker
+    pub fn with_sort_tiebreaker(mut self, field: impl Into<String>) -> Self {
+        let field = field.into();
+        debug!("Breaking sort ties by {}", field);
+        self.sort_tiebreaker = Some(field);
+        self
+    }
+
+    /// Returns the field appended to every sort, if one is configured.
+    pub fn sort_tiebreaker(&self) -> Option<&str> {
+        self.sort_tiebreaker.as_deref()
+    }
+
+    /// Searches like [`Filemaker::search`], with the Data API's optional find parameters.
+    ///
+    /// A find without matches is handled per [`FindOptions::on_empty`]. With
+    /// [`FindOptions::dedupe`], records matched by several requests are returned once.
+    ///
+    /// ```rust,ignore
+    /// let options = FindOptions {
+    ///     sort: vec!["Name".to_string()],
+    ///     limit: Some(50),
+    ///     portals: Some(vec![Portal::new("Orders").with_range(1, 10)]),
+    ///     on_empty: EmptyFind::None,
+    ///     ..Default::default()
+    /// };
+    /// let result = filemaker.search_with::<Value>(query, &options).await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `query` - Vector of field-value pairs to search for
+    /// * `options` - Sorting, paging, portals, scripts and other find parameters
+    ///
+    /// # Returns
+    /// * `Result<Option<FindResult<T>>>` - The matching records; `None` only if nothing matched and `on_empty` is [`EmptyFind::None`]
+    pub async fn search_with<T>(
+        &self,
+        query: Vec<HashMap<String, String>>,
+        options: &FindOptions,
+    ) -> Result<Option<FindResult<T>>>
+    where
+        T: serde::de::DeserializeOwned + Default,
+    {
+        let url = format!(
+            "{}/databases/{}/layouts/{}/_find",
+            self.base_url()?,
+            self.database,
+            self.table
+        );
+        let body = options.body(&query, self.sort_tiebreaker());
+        let response = with_timeout(
+            options.timeout,
+            self.authenticated_request(&url, Method::POST, Some(body)),
+        )
+        .await?;
+        if Self::first_message_code(&response) == Some(NO_RECORDS_MATCH_CODE) {
+            debug!("Find matched no records, returning {:?}", options.on_empty);
+            return match options.on_empty {
+                EmptyFind::Empty => Ok(Some(self.empty_find_result())),
+                EmptyFind::None => Ok(None),
+                EmptyFind::Error => Err(Self::response_error(&response, "Search failed")),
+            };
+        }
+        let mut result = self.parse_find_result(response)?;
+        if options.dedupe {
+            result.dedupe_by_record_id();
+        }
+        Ok(Some(result))
+    }
+
+    /// Finds the records whose `key_field` equals any of `keys`, like SQL's `WHERE id IN (...)`.
+    ///
+    /// The keys are matched exactly, with one find request per key OR-ed together, in
+    /// batches of 100 keys to stay within the server's limits. The batches' records are
+    /// merged, each record returned once; keys without a record are ignored.
+    ///
+    /// ```rust,ignore
+    /// let invoices = filemaker.find_by_keys::<Invoice, _>("InvoiceNumber", ["A-1", "A-7"]).await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `key_field` - The field holding the key
+    /// * `keys` - The key values to find
+    ///
+    /// # Returns
+    /// * `Result<Vec<Record<T>>>` - The matching records, in batch order
+    pub async fn find_by_keys<T, K>(
+        &self,
+        key_field: &str,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<Vec<Record<T>>>
+    where
+        T: serde::de::DeserializeOwned + Default,
+        K: ToString,
+    {
+        let keys: Vec<String> = keys.into_iter().map(|key| key.to_string()).collect();
+        self.find_by_keys_batched(key_field, &keys, DEFAULT_KEY_BATCH_SIZE)
+            .await
+    }
+
+    /// Finds the records whose `key_field` equals any of `keys`, `batch_size` keys per request.
+    pub(crate) async fn find_by_keys_batched<T>(
+        &self,
+        key_field: &str,
+        keys: &[String],
+        batch_size: usize,
+    ) -> Result<Vec<Record<T>>>
+    where
+        T: serde::de::DeserializeOwned + Default,
+    {
+        let options = FindOptions::default();
+        let mut seen = HashSet::new();
+        let mut records = Vec::new();
+        for batch in keys.chunks(batch_size.max(1)) {
+            let requests = batch
+                .iter()
+                .map(|key| HashMap::from([(key_field.to_string(), query::exact_match(key))]))
+                .collect();
+            let Some(found) = self.search_with::<T>(requests, &options).await? else {
+                continue;
+            };
+            records.extend(
+                found
+                    .response
+                    .data
+                    .into_iter()
+                    .filter(|record| seen.insert(record.record_id.clone())),
+            );
+        }
+        debug!(
+            "Found {} records for {} keys in field {}",
+            records.len(),
+            keys.len(),
+            key_field
+        );
+        Ok(records)
+    }
+
+    /// Returns a find result without records for this client's layout.
+    pub(crate) fn empty_find_result<T>(&self) -> FindResult<T> {
+        FindResult {
+            response: Response {
+                info: DataInfo {
+                    database: self.database(),
+                    layout: self.layout(),
+                    ..Default::default()
+                },
+                data: vec![],
+            },
+            messages: vec![],
+            warnings: vec![],
+        }
+    }
+}
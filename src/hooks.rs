@@ -0,0 +1,136 @@
+//! Hooks run around record writes.
+
+use crate::Filemaker;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use log::*;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// Runs around the record writes of a client, to keep cross-cutting concerns such as audit
+/// stamping or validation in one place.
+///
+/// Every method has a default that does nothing, so a hook implements only the points it
+/// needs. The `before_*` hooks may rewrite the field data before it is sent, or veto the
+/// write by returning an error, which the write returns without sending a request. The
+/// `after_*` hooks run once the server has accepted the write.
+///
+/// ```rust,ignore
+/// struct Stamp;
+///
+/// impl RecordHook for Stamp {
+///     fn before_update<'a>(
+///         &'a self,
+///         _layout: &'a str,
+///         _record_id: &'a str,
+///         field_data: &'a mut Map<String, Value>,
+///     ) -> BoxFuture<'a, Result<()>> {
+///         Box::pin(async move {
+///             field_data.insert("ModifiedBy".to_string(), json!("sync"));
+///             Ok(())
+///         })
+///     }
+/// }
+///
+/// let filemaker = filemaker.with_hook(Stamp);
+/// ```
+pub trait RecordHook: Send + Sync {
+    /// Runs before a record is created on `layout`; an error vetoes the create.
+    fn before_create<'a>(
+        &'a self,
+        _layout: &'a str,
+        _field_data: &'a mut Map<String, Value>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Runs after record `record_id` was created on `layout`.
+    fn after_create<'a>(&'a self, _layout: &'a str, _record_id: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    /// Runs before record `record_id` on `layout` is updated; an error vetoes the update.
+    fn before_update<'a>(
+        &'a self,
+        _layout: &'a str,
+        _record_id: &'a str,
+        _field_data: &'a mut Map<String, Value>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Runs after record `record_id` was deleted from `layout`.
+    fn after_delete<'a>(&'a self, _layout: &'a str, _record_id: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+impl Filemaker {
+    /// Adds a hook run around the record writes of this client.
+    ///
+    /// Hooks run in the order they were added; the first `before_*` hook to return an error
+    /// vetoes the write and the remaining hooks do not run. They apply to single-record
+    /// creates, updates and deletes, including those made by bulk operations, imports and
+    /// repositories.
+    ///
+    /// # Arguments
+    /// * `hook` - The hook to add
+    ///
+    /// # Returns
+    /// * `Self` - The client with the hook
+    pub fn with_hook(mut self, hook: impl RecordHook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        debug!("Added record hook, {} configured", self.hooks.len());
+        self
+    }
+
+    /// Runs the `before_create` hooks on the field data of a new record.
+    pub(crate) async fn before_create(&self, field_data: &mut Map<String, Value>) -> Result<()> {
+        let layout = self.layout();
+        for hook in &self.hooks {
+            if let Err(e) = hook.before_create(&layout, field_data).await {
+                warn!(
+                    "A record hook vetoed creating a record on {}: {}",
+                    layout, e
+                );
+                return Err(e.context("Record hook vetoed the create"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the `after_create` hooks for a created record.
+    pub(crate) async fn after_create(&self, record_id: &str) {
+        let layout = self.layout();
+        for hook in &self.hooks {
+            hook.after_create(&layout, record_id).await;
+        }
+    }
+
+    /// Runs the `before_update` hooks on the field data of an update.
+    pub(crate) async fn before_update(
+        &self,
+        record_id: &str,
+        field_data: &mut Map<String, Value>,
+    ) -> Result<()> {
+        let layout = self.layout();
+        for hook in &self.hooks {
+            if let Err(e) = hook.before_update(&layout, record_id, field_data).await {
+                warn!(
+                    "A record hook vetoed updating record {} on {}: {}",
+                    record_id, layout, e
+                );
+                return Err(e.context("Record hook vetoed the update"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the `after_delete` hooks for a deleted record.
+    pub(crate) async fn after_delete(&self, record_id: &str) {
+        let layout = self.layout();
+        for hook in &self.hooks {
+            hook.after_delete(&layout, record_id).await;
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Importing rows into a layout.
+
+use crate::budget::BudgetTracker;
+use crate::find::DEFAULT_KEY_BATCH_SIZE;
+use crate::{BudgetStatus, Filemaker, Pacing, RetryBudget};
+use anyhow::{anyhow, Result};
+use log::*;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tokio::task::JoinSet;
+
+/// What to do with an imported row whose key already exists in the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Leave the existing record untouched and skip the row.
+    #[default]
+    Skip,
+    /// Update the existing record with the row's field data.
+    Update,
+    /// Leave the existing record untouched and report the row as a duplicate.
+    Flag,
+}
+
+/// Configures [`Filemaker::import_records`].
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// The field identifying a row; when `None` every row is created without duplicate checks.
+    pub key_field: Option<String>,
+    /// What to do with rows whose key already exists.
+    pub duplicate_policy: DuplicatePolicy,
+    /// The number of key values looked up per find request.
+    pub key_batch_size: usize,
+    /// Limits on retrying failed rows and on the share of rows that may fail.
+    pub budget: RetryBudget,
+    /// Pauses between writes, growing while the server is slow; `None` writes flat out.
+    pub pacing: Option<Pacing>,
+}
+
+impl ImportOptions {
+    /// Creates options that check `key_field` for duplicates and apply `policy` to them.
+    pub fn with_key(key_field: impl Into<String>, policy: DuplicatePolicy) -> Self {
+        Self {
+            key_field: Some(key_field.into()),
+            duplicate_policy: policy,
+            key_batch_size: DEFAULT_KEY_BATCH_SIZE,
+            budget: RetryBudget::default(),
+            pacing: None,
+        }
+    }
+}
+
+/// Maps the columns of rows from an external source to the fields of a layout.
+///
+/// Columns are renamed per [`FieldMapping::map`]; ignored columns are dropped, and columns
+/// without a mapping keep their name unless [`FieldMapping::only_mapped`] is set.
+///
+/// ```rust,ignore
+/// let mapping = FieldMapping::new()
+///     .map("first_name", "First Name")
+///     .map("last_name", "Last Name")
+///     .ignore("internal_id");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FieldMapping {
+    fields: HashMap<String, String>,
+    ignored: HashSet<String>,
+    only_mapped: bool,
+}
+
+impl FieldMapping {
+    /// Creates a mapping that keeps every column under its own name.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes `column` into `field`.
+    pub fn map(mut self, column: impl Into<String>, field: impl Into<String>) -> Self {
+        self.fields.insert(column.into(), field.into());
+        self
+    }
+
+    /// Drops `column`.
+    pub fn ignore(mut self, column: impl Into<String>) -> Self {
+        self.ignored.insert(column.into());
+        self
+    }
+
+    /// Drops every column without a mapping.
+    pub fn only_mapped(mut self) -> Self {
+        self.only_mapped = true;
+        self
+    }
+
+    /// Returns the field a column is written to, or `None` if it is dropped.
+    pub fn field<'a>(&'a self, column: &'a str) -> Option<&'a str> {
+        if self.ignored.contains(column) {
+            return None;
+        }
+        match self.fields.get(column) {
+            Some(field) => Some(field),
+            None if self.only_mapped => None,
+            None => Some(column),
+        }
+    }
+
+    /// Converts a row of column values to field data.
+    pub fn apply(&self, row: HashMap<String, Value>) -> HashMap<String, Value> {
+        row.into_iter()
+            .filter_map(|(column, value)| {
+                let field = self.field(&column)?.to_string();
+                Some((field, value))
+            })
+            .collect()
+    }
+}
+
+/// Outcome of an import.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// The number of records created.
+    pub created: u64,
+    /// The number of existing records updated.
+    pub updated: u64,
+    /// The number of rows skipped because their key already existed.
+    pub skipped: u64,
+    /// Key values of rows that were reported as duplicates.
+    pub flagged: Vec<String>,
+    /// Rows that could not be imported, by index, together with the reason.
+    pub failed: Vec<(usize, anyhow::Error)>,
+    /// How much of the retry budget the import used.
+    pub budget: BudgetStatus,
+}
+
+/// Outcome of a single row of [`Filemaker::upsert_many`].
+#[derive(Debug)]
+pub enum UpsertOutcome {
+    /// No record had the row's key; a new record was created.
+    Created {
+        /// The ID of the created record.
+        record_id: String,
+    },
+    /// A record with the row's key existed and was updated.
+    Updated {
+        /// The ID of the updated record.
+        record_id: String,
+    },
+    /// The row could not be written.
+    Failed(anyhow::Error),
+}
+
+impl Filemaker {
+    /// Imports rows into the layout, optionally checking a key field for duplicates.
+    ///
+    /// When a key field is configured, existing keys are looked up with batched finds before
+    /// any row is written, and rows with existing keys are skipped, updated or flagged per
+    /// the configured [`DuplicatePolicy`]. A failing row does not abort the import; it is
+    /// reported in [`ImportSummary::failed`]. Transient failures are retried, and the import
+    /// stops attempting rows once too many fail, per `options.budget`.
+    ///
+    /// # Arguments
+    /// * `rows` - The field data of the rows to import
+    /// * `options` - Duplicate detection settings
+    ///
+    /// # Returns
+    /// * `Result<ImportSummary>` - Counts of created, updated and skipped rows, or an error if the key lookup fails
+    pub async fn import_records(
+        &self,
+        rows: Vec<HashMap<String, Value>>,
+        options: &ImportOptions,
+    ) -> Result<ImportSummary> {
+        let existing = match &options.key_field {
+            Some(key_field) => {
+                let keys: Vec<String> = rows
+                    .iter()
+                    .filter_map(|row| row.get(key_field).map(key_string))
+                    .collect();
+                self.find_record_ids_by_key(key_field, &keys, options.key_batch_size)
+                    .await?
+            }
+            None => HashMap::new(),
+        };
+
+        let budget = BudgetTracker::new(options.budget);
+        let limiter = self.limiter(1, options.pacing);
+        let mut summary = ImportSummary::default();
+        for (index, row) in rows.into_iter().enumerate() {
+            let key = options
+                .key_field
+                .as_ref()
+                .and_then(|key_field| row.get(key_field))
+                .map(key_string);
+            let existing_id = key.as_ref().and_then(|key| existing.get(key));
+
+            let writes = matches!(
+                (existing_id, options.duplicate_policy),
+                (None, _) | (Some(_), DuplicatePolicy::Update)
+            );
+            let mut permit = match writes {
+                true => Some(limiter.acquire().await),
+                false => None,
+            };
+            let result = match (existing_id, options.duplicate_policy) {
+                (None, _) => budget
+                    .run(|| self.create_record_ids(row.clone()))
+                    .await
+                    .map(|_| {
+                        summary.created += 1;
+                    }),
+                (Some(_), DuplicatePolicy::Skip) => {
+                    summary.skipped += 1;
+                    Ok(())
+                }
+                (Some(_), DuplicatePolicy::Flag) => {
+                    summary.flagged.push(key.clone().unwrap_or_default());
+                    Ok(())
+                }
+                (Some(id), DuplicatePolicy::Update) => budget
+                    .run(|| self.update_record_guarded(id, row.clone(), None))
+                    .await
+                    .map(|_| {
+                        summary.updated += 1;
+                    }),
+            };
+            if let (Some(permit), Err(e)) = (permit.as_mut(), &result) {
+                permit.observe_error(e);
+            }
+            drop(permit);
+            if let Err(e) = result {
+                error!("Failed to import row {}: {}", index, e);
+                summary.failed.push((index, e));
+            }
+        }
+        summary.budget = budget.status();
+
+        info!(
+            "Import finished: {} created, {} updated, {} skipped, {} flagged, {} failed",
+            summary.created,
+            summary.updated,
+            summary.skipped,
+            summary.flagged.len(),
+            summary.failed.len()
+        );
+        Ok(summary)
+    }
+
+    /// Creates or updates rows by key, running up to `concurrency` writes in parallel.
+    ///
+    /// Existing records are resolved for all keys up front with batched finds; rows whose key
+    /// exists update that record, all others create a new one. A failing row does not abort
+    /// the batch.
+    ///
+    /// # Arguments
+    /// * `key_field` - The field identifying a row
+    /// * `rows` - The field data of the rows, each containing `key_field`
+    /// * `concurrency` - The maximum number of writes in flight at once (at least 1)
+    ///
+    /// # Returns
+    /// * `Result<Vec<UpsertOutcome>>` - The outcome of each row, in input order, or an error if the key lookup fails
+    pub async fn upsert_many(
+        &self,
+        key_field: &str,
+        rows: Vec<HashMap<String, Value>>,
+        concurrency: usize,
+    ) -> Result<Vec<UpsertOutcome>> {
+        let keys: Vec<String> = rows
+            .iter()
+            .filter_map(|row| row.get(key_field).map(key_string))
+            .collect();
+        let existing = self
+            .find_record_ids_by_key(key_field, &keys, DEFAULT_KEY_BATCH_SIZE)
+            .await?;
+
+        let limiter = self.limiter(concurrency, None);
+        let mut tasks = JoinSet::new();
+        for (index, row) in rows.into_iter().enumerate() {
+            let Some(key) = row.get(key_field).map(key_string) else {
+                let error = anyhow!("Key field {} is missing from row {}", key_field, index);
+                tasks.spawn(async move { (index, UpsertOutcome::Failed(error)) });
+                continue;
+            };
+            let existing_id = existing.get(&key).cloned();
+            let filemaker = self.clone();
+            let mut permit = limiter.acquire().await;
+            tasks.spawn(async move {
+                let outcome = match existing_id {
+                    Some(record_id) => {
+                        match filemaker.update_record_guarded(&record_id, row, None).await {
+                            Ok(_) => UpsertOutcome::Updated { record_id },
+                            Err(e) => UpsertOutcome::Failed(e),
+                        }
+                    }
+                    None => match filemaker.create_record_ids(row).await {
+                        Ok(created) => UpsertOutcome::Created {
+                            record_id: created.record_id,
+                        },
+                        Err(e) => UpsertOutcome::Failed(e),
+                    },
+                };
+                if let UpsertOutcome::Failed(e) = &outcome {
+                    permit.observe_error(e);
+                }
+                drop(permit);
+                (index, outcome)
+            });
+        }
+
+        let mut outcomes: Vec<(usize, UpsertOutcome)> = Vec::with_capacity(keys.len());
+        while let Some(joined) = tasks.join_next().await {
+            outcomes.push(joined?);
+        }
+        outcomes.sort_by_key(|(index, _)| *index);
+
+        let failed = outcomes
+            .iter()
+            .filter(|(_, o)| matches!(o, UpsertOutcome::Failed(_)))
+            .count();
+        info!(
+            "Upserted {} rows by {}, {} failed",
+            outcomes.len(),
+            key_field,
+            failed
+        );
+        Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
+    }
+
+    /// Looks up the record IDs of the given key values with batched exact-match finds.
+    ///
+    /// # Arguments
+    /// * `key_field` - The field holding the key
+    /// * `keys` - The key values to look up
+    /// * `batch_size` - The number of key values per find request
+    ///
+    /// # Returns
+    /// * `Result<HashMap<String, String>>` - The record ID of each key value that exists
+    pub(crate) async fn find_record_ids_by_key(
+        &self,
+        key_field: &str,
+        keys: &[String],
+        batch_size: usize,
+    ) -> Result<HashMap<String, String>> {
+        let records = self
+            .find_by_keys_batched::<Value>(key_field, keys, batch_size)
+            .await?;
+        let mut existing = HashMap::new();
+        for record in records {
+            if let Some(key) = record.data.get(key_field) {
+                existing.entry(key_string(key)).or_insert(record.record_id);
+            }
+        }
+        Ok(existing)
+    }
+}
+
+/// Returns the text used to compare key values, independent of their JSON type.
+pub(crate) fn key_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,366 @@
+//! Timing and reporting of the requests a client sends.
+
+use crate::policy::request_layout;
+use crate::{ErrorKind, Filemaker};
+use log::*;
+use reqwest::Method;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Cumulative request counters, shared by a client and its clones.
+#[derive(Debug)]
+pub(crate) struct StatsCounters {
+    started: Instant,
+    requests: AtomicU64,
+    retries: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    latency_micros: AtomicU64,
+    errors: Mutex<HashMap<ErrorKind, u64>>,
+    layouts: Mutex<HashMap<String, LayoutCounters>>,
+}
+
+/// The counters of one layout, with the traffic of its current quota window.
+#[derive(Debug)]
+struct LayoutCounters {
+    stats: LayoutStats,
+    window_started: Instant,
+    window_requests: u64,
+    window_bytes: u64,
+    warned: bool,
+}
+
+impl Default for StatsCounters {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            latency_micros: AtomicU64::new(0),
+            errors: Mutex::new(HashMap::new()),
+            layouts: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// A snapshot of a client's request statistics, returned by [`Filemaker::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientStats {
+    /// The number of Data API requests sent.
+    pub requests: u64,
+    /// The number of requests sent again after a failure, e.g. on another endpoint.
+    pub retries: u64,
+    /// The number of request body bytes sent.
+    pub bytes_sent: u64,
+    /// The number of response body bytes received.
+    pub bytes_received: u64,
+    /// The number of failed requests by category; network failures count as [`ErrorKind::Other`].
+    pub errors: HashMap<ErrorKind, u64>,
+    /// The average time from sending a request to receiving its response.
+    pub average_latency: Duration,
+    /// The time since the client was created.
+    pub uptime: Duration,
+    /// The statistics of each layout requests were sent to, by layout name.
+    pub layouts: HashMap<String, LayoutStats>,
+}
+
+/// Request statistics of a single layout, part of [`ClientStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LayoutStats {
+    /// The number of requests sent to the layout.
+    pub requests: u64,
+    /// The number of request body bytes sent.
+    pub bytes_sent: u64,
+    /// The number of response body bytes received.
+    pub bytes_received: u64,
+    /// The number of failed requests.
+    pub errors: u64,
+    /// The number of windows in which the layout exceeded its [`LayoutQuota`].
+    pub quota_exceeded: u64,
+}
+
+/// Limits on the traffic of each layout within a time window, above which a warning is logged.
+///
+/// Requests are not refused; the warning names the layout, so a dashboard or job that
+/// hammers the server can be found. Each layout is warned about at most once per window.
+///
+/// ```rust,ignore
+/// let filemaker = filemaker.with_layout_quota(
+///     LayoutQuota::new(Duration::from_secs(60))
+///         .with_max_requests(600)
+///         .with_max_bytes(50 * 1024 * 1024),
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutQuota {
+    /// The length of the window the limits apply to.
+    pub window: Duration,
+    /// The number of requests per window; `None` is unlimited.
+    pub max_requests: Option<u64>,
+    /// The number of bytes sent and received per window; `None` is unlimited.
+    pub max_bytes: Option<u64>,
+}
+
+impl LayoutQuota {
+    /// Creates a quota over windows of `window` without limits.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            max_requests: None,
+            max_bytes: None,
+        }
+    }
+
+    /// Limits the number of requests per window.
+    pub fn with_max_requests(mut self, max_requests: u64) -> Self {
+        self.max_requests = Some(max_requests);
+        self
+    }
+
+    /// Limits the number of bytes sent and received per window.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    fn exceeded(&self, requests: u64, bytes: u64) -> bool {
+        self.max_requests.is_some_and(|max| requests > max)
+            || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+}
+
+impl ClientStats {
+    /// Returns the total number of failed requests.
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+impl Filemaker {
+    /// Returns the request statistics accumulated since the client was created.
+    ///
+    /// Counters are shared with clients derived from this one, such as those returned by
+    /// [`Filemaker::with_layout`]. Error responses are counted by their [`ErrorKind`],
+    /// including finds without matches (error 401).
+    ///
+    /// # Returns
+    /// * `ClientStats` - Request, retry, byte, error and latency totals
+    pub fn stats(&self) -> ClientStats {
+        let stats = &self.stats;
+        let requests = stats.requests.load(Ordering::Relaxed);
+        let latency_micros = stats.latency_micros.load(Ordering::Relaxed);
+        ClientStats {
+            requests,
+            retries: stats.retries.load(Ordering::Relaxed),
+            bytes_sent: stats.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: stats.bytes_received.load(Ordering::Relaxed),
+            errors: stats
+                .errors
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            average_latency: Duration::from_micros(
+                latency_micros.checked_div(requests).unwrap_or(0),
+            ),
+            uptime: stats.started.elapsed(),
+            layouts: stats
+                .layouts
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .map(|(layout, counters)| (layout.clone(), counters.stats))
+                .collect(),
+        }
+    }
+
+    /// Counts a sent request, failed with `error` if it did not succeed.
+    pub(crate) fn record_request(
+        &self,
+        url: &str,
+        bytes_sent: usize,
+        bytes_received: usize,
+        latency: Duration,
+        error: Option<ErrorKind>,
+    ) {
+        let stats = &self.stats;
+        stats.requests.fetch_add(1, Ordering::Relaxed);
+        stats
+            .bytes_sent
+            .fetch_add(bytes_sent as u64, Ordering::Relaxed);
+        stats
+            .bytes_received
+            .fetch_add(bytes_received as u64, Ordering::Relaxed);
+        stats
+            .latency_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+        if let Some(kind) = error {
+            *stats
+                .errors
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(kind)
+                .or_default() += 1;
+        }
+        if let Some(layout) = request_layout(url) {
+            self.record_layout_request(
+                layout,
+                bytes_sent as u64,
+                bytes_received as u64,
+                error.is_some(),
+            );
+        }
+    }
+
+    /// Counts a request to `layout` and warns if it exceeds the layout quota.
+    fn record_layout_request(
+        &self,
+        layout: String,
+        bytes_sent: u64,
+        bytes_received: u64,
+        failed: bool,
+    ) {
+        let mut layouts = self.stats.layouts.lock().unwrap_or_else(|e| e.into_inner());
+        let counters = layouts
+            .entry(layout.clone())
+            .or_insert_with(|| LayoutCounters {
+                stats: LayoutStats::default(),
+                window_started: Instant::now(),
+                window_requests: 0,
+                window_bytes: 0,
+                warned: false,
+            });
+        counters.stats.requests += 1;
+        counters.stats.bytes_sent += bytes_sent;
+        counters.stats.bytes_received += bytes_received;
+        counters.stats.errors += failed as u64;
+
+        let Some(quota) = self.layout_quota else {
+            return;
+        };
+        if counters.window_started.elapsed() >= quota.window {
+            counters.window_started = Instant::now();
+            counters.window_requests = 0;
+            counters.window_bytes = 0;
+            counters.warned = false;
+        }
+        counters.window_requests += 1;
+        counters.window_bytes += bytes_sent + bytes_received;
+        if !counters.warned && quota.exceeded(counters.window_requests, counters.window_bytes) {
+            counters.warned = true;
+            counters.stats.quota_exceeded += 1;
+            warn!(
+                layout = layout.as_str(),
+                requests = counters.window_requests,
+                bytes = counters.window_bytes;
+                "Layout {} exceeded its quota: {} requests and {} bytes within {:?}",
+                layout, counters.window_requests, counters.window_bytes, quota.window
+            );
+        }
+    }
+
+    /// Counts a request sent again after a failure.
+    pub(crate) fn record_retry(&self) {
+        self.stats.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Sets the latency above which a request is reported as slow.
+    ///
+    /// Slow requests are logged as a warning carrying the operation, layout, duration and
+    /// number of returned records as key-value pairs, which makes layouts with expensive
+    /// unstored calculations easy to find.
+    ///
+    /// # Arguments
+    /// * `threshold` - The latency above which a warning is logged
+    ///
+    /// # Returns
+    /// * `Self` - The client with the new threshold
+    pub fn with_slow_operation_threshold(mut self, threshold: Duration) -> Self {
+        debug!("Using slow operation threshold of {:?}", threshold);
+        self.slow_operation_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets the traffic per layout above which a warning is logged.
+    ///
+    /// Per-layout counters are kept in [`ClientStats::layouts`] either way; the quota only
+    /// adds the warnings, logged with the layout, request count and bytes as key-value pairs.
+    ///
+    /// # Arguments
+    /// * `quota` - The limits per layout and window
+    ///
+    /// # Returns
+    /// * `Self` - The client with the new quota
+    pub fn with_layout_quota(mut self, quota: LayoutQuota) -> Self {
+        debug!("Using layout quota {:?}", quota);
+        self.layout_quota = Some(quota);
+        self
+    }
+
+    /// Logs a warning if a request took longer than the configured threshold.
+    pub(crate) fn report_duration(
+        &self,
+        method: &Method,
+        url: &str,
+        duration: Duration,
+        response: &Value,
+    ) {
+        let Some(threshold) = self.slow_operation_threshold else {
+            return;
+        };
+        if duration <= threshold {
+            return;
+        }
+
+        let operation = operation_name(method, url);
+        let layout = self.layout();
+        let duration_ms = duration.as_millis() as u64;
+        let record_count = response
+            .get("response")
+            .and_then(|r| r.get("dataInfo"))
+            .and_then(|d| d.get("returnedCount"))
+            .and_then(|c| c.as_u64())
+            .unwrap_or_default();
+        warn!(
+            operation = operation,
+            layout = layout.as_str(),
+            duration_ms = duration_ms,
+            record_count = record_count;
+            "Slow FileMaker operation: {} on layout {} took {} ms ({} records)",
+            operation, layout, duration_ms, record_count
+        );
+    }
+}
+
+/// Derives a short operation name from a Data API request.
+pub(crate) fn operation_name(method: &Method, url: &str) -> &'static str {
+    let path = url.split('?').next().unwrap_or(url);
+    let has_record_id = path
+        .rsplit_once("/records/")
+        .is_some_and(|(_, id)| !id.is_empty());
+    if path.ends_with("/_find") {
+        "find"
+    } else if path.contains("/script/") {
+        "script"
+    } else if has_record_id {
+        match *method {
+            Method::GET => "get_record",
+            Method::PATCH => "update_record",
+            Method::DELETE => "delete_record",
+            _ => "record",
+        }
+    } else if path.ends_with("/records") {
+        match *method {
+            Method::POST => "create_record",
+            _ => "get_records",
+        }
+    } else if path.contains("/layouts/") {
+        "layout_metadata"
+    } else {
+        "other"
+    }
+}
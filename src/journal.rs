@@ -0,0 +1,295 @@
+//! Append-only operation journal for crash recovery.
+//!
+//! A [`Journal`] writes one JSON line per event to disk before and after each write of a
+//! [`crate::Transaction`] it is attached to. After a crash the file can be loaded with
+//! [`Journal::load`] to see which operations completed, which were in flight, and to roll
+//! the completed ones back.
+
+use crate::{Filemaker, RollbackReport, TransactionOperation};
+use anyhow::{anyhow, Result};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// A write that is about to be sent to the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum PlannedOperation {
+    /// A record is about to be created.
+    Create {
+        /// The field data of the new record.
+        field_data: Map<String, Value>,
+    },
+    /// A record is about to be updated.
+    Update {
+        /// The ID of the record.
+        record_id: String,
+        /// The new values of the changed fields.
+        field_data: Map<String, Value>,
+        /// The values of the changed fields before the update.
+        previous: Map<String, Value>,
+    },
+    /// A record is about to be deleted.
+    Delete {
+        /// The ID of the record.
+        record_id: String,
+        /// The field data of the record before deletion.
+        previous: Map<String, Value>,
+    },
+}
+
+/// One line of the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// An operation is about to be sent.
+    Pending {
+        /// The sequence number shared by all events of the operation.
+        seq: u64,
+        /// The layout the operation targets.
+        layout: String,
+        /// The operation.
+        planned: PlannedOperation,
+    },
+    /// The operation with the same sequence number succeeded.
+    Completed {
+        /// The sequence number of the operation.
+        seq: u64,
+        /// What was done, including what is needed to revert it.
+        result: TransactionOperation,
+    },
+    /// The operation with the same sequence number failed.
+    Failed {
+        /// The sequence number of the operation.
+        seq: u64,
+        /// The reason of the failure.
+        error: String,
+    },
+}
+
+/// The state of a journal reconstructed from its file.
+#[derive(Debug, Clone, Default)]
+pub struct JournalState {
+    /// Operations that completed, oldest first.
+    pub completed: Vec<TransactionOperation>,
+    /// Operations that were sent but never confirmed, oldest first. They may or may not
+    /// have been applied by the server.
+    pub in_doubt: Vec<(String, PlannedOperation)>,
+    /// Operations that failed, with the reason.
+    pub failed: Vec<(PlannedOperation, String)>,
+}
+
+/// An append-only journal file.
+///
+/// Every event is flushed and synced to disk before the operation proceeds.
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+    next_seq: AtomicU64,
+}
+
+impl Journal {
+    /// Opens a journal file for appending, creating it if needed.
+    ///
+    /// Sequence numbers continue after the entries already in the file.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Journal> {
+        let path = path.as_ref().to_path_buf();
+        let next_seq = match tokio::fs::try_exists(&path).await? {
+            true => Self::read(&path)
+                .await?
+                .iter()
+                .map(JournalEntry::seq)
+                .max()
+                .map_or(0, |seq| seq + 1),
+            false => 0,
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|e| anyhow!("Failed to open journal {}: {}", path.display(), e))?;
+        debug!("Opened journal {}", path.display());
+        Ok(Journal {
+            path,
+            file: Mutex::new(file),
+            next_seq: AtomicU64::new(next_seq),
+        })
+    }
+
+    /// Returns the path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads all entries of a journal file.
+    ///
+    /// A truncated last line, as left by a crash during a write, is ignored.
+    pub async fn read(path: impl AsRef<Path>) -> Result<Vec<JournalEntry>> {
+        let path = path.as_ref();
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow!("Failed to read journal {}: {}", path.display(), e))?;
+        let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+        let mut entries = Vec::with_capacity(lines.len());
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) if index + 1 == lines.len() => {
+                    warn!("Ignoring truncated last journal line: {}", e);
+                }
+                Err(e) => return Err(anyhow!("Corrupt journal line {}: {}", index + 1, e)),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Reads a journal file and reconstructs which operations completed, failed or are in doubt.
+    pub async fn load(path: impl AsRef<Path>) -> Result<JournalState> {
+        let mut pending: BTreeMap<u64, (String, PlannedOperation)> = BTreeMap::new();
+        let mut state = JournalState::default();
+        for entry in Self::read(path).await? {
+            match entry {
+                JournalEntry::Pending {
+                    seq,
+                    layout,
+                    planned,
+                } => {
+                    pending.insert(seq, (layout, planned));
+                }
+                JournalEntry::Completed { seq, result } => {
+                    pending.remove(&seq);
+                    state.completed.push(result);
+                }
+                JournalEntry::Failed { seq, error } => {
+                    if let Some((_, planned)) = pending.remove(&seq) {
+                        state.failed.push((planned, error));
+                    }
+                }
+            }
+        }
+        state.in_doubt = pending.into_values().collect();
+        Ok(state)
+    }
+
+    /// Records that an operation is about to be sent and returns its sequence number.
+    pub async fn pending(&self, layout: &str, planned: PlannedOperation) -> Result<u64> {
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        self.append(&JournalEntry::Pending {
+            seq,
+            layout: layout.to_string(),
+            planned,
+        })
+        .await?;
+        Ok(seq)
+    }
+
+    /// Records that the operation `seq` succeeded.
+    pub async fn completed(&self, seq: u64, result: TransactionOperation) -> Result<()> {
+        self.append(&JournalEntry::Completed { seq, result }).await
+    }
+
+    /// Records that the operation `seq` failed.
+    pub async fn failed(&self, seq: u64, error: &anyhow::Error) -> Result<()> {
+        self.append(&JournalEntry::Failed {
+            seq,
+            error: error.to_string(),
+        })
+        .await
+    }
+
+    async fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+}
+
+impl JournalEntry {
+    /// Returns the sequence number of the operation the entry belongs to.
+    pub fn seq(&self) -> u64 {
+        match self {
+            JournalEntry::Pending { seq, .. }
+            | JournalEntry::Completed { seq, .. }
+            | JournalEntry::Failed { seq, .. } => *seq,
+        }
+    }
+}
+
+impl Filemaker {
+    /// Reverts the completed operations of a journal, newest first.
+    ///
+    /// In-doubt operations are not touched; inspect [`JournalState::in_doubt`] and verify
+    /// against the server whether they were applied.
+    pub async fn rollback_journal(&self, state: &JournalState) -> Result<RollbackReport> {
+        let mut report = RollbackReport::default();
+        for operation in state.completed.iter().rev() {
+            match self.revert(operation).await {
+                Ok(()) => report.reverted += 1,
+                Err(e) => {
+                    error!("Failed to revert {:?}: {}", operation, e);
+                    report.failed.push((operation.clone(), e));
+                }
+            }
+        }
+        info!(
+            "Rolled back journal: {} reverted, {} failed",
+            report.reverted,
+            report.failed.len()
+        );
+        Ok(report)
+    }
+
+    /// Sends a planned operation, e.g. to resume an in-doubt operation of a journal.
+    pub async fn apply_planned(&self, planned: &PlannedOperation) -> Result<TransactionOperation> {
+        match planned {
+            PlannedOperation::Create { field_data } => {
+                let created = self
+                    .create_record_ids(field_data.clone().into_iter().collect())
+                    .await?;
+                Ok(TransactionOperation::Created {
+                    record_id: created.record_id,
+                })
+            }
+            PlannedOperation::Update {
+                record_id,
+                field_data,
+                previous,
+            } => {
+                self.update_record_guarded(
+                    record_id,
+                    field_data.clone().into_iter().collect(),
+                    None,
+                )
+                .await?;
+                Ok(TransactionOperation::Updated {
+                    record_id: record_id.clone(),
+                    previous: previous.clone(),
+                })
+            }
+            PlannedOperation::Delete {
+                record_id,
+                previous,
+            } => {
+                let response = self.delete_record_response(record_id).await?;
+                if let Some(api_error) = Self::api_error(&response) {
+                    return Err(anyhow::Error::new(api_error).context("Failed to delete record"));
+                }
+                Ok(TransactionOperation::Deleted {
+                    record_id: record_id.clone(),
+                    previous: previous.clone(),
+                })
+            }
+        }
+    }
+}
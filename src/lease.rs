@@ -0,0 +1,258 @@
+//! Advisory record locks held for a limited time.
+
+use crate::error::LeaseHeldError;
+use crate::Filemaker;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The fields a lease is stored in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseFields {
+    /// The field receiving the name of the lease owner.
+    pub owner: String,
+    /// The timestamp field receiving the time the lease expires.
+    pub expires: String,
+}
+
+impl Default for LeaseFields {
+    fn default() -> Self {
+        Self {
+            owner: "LockOwner".to_string(),
+            expires: "LockExpires".to_string(),
+        }
+    }
+}
+
+/// A lease on a record, returned by [`Filemaker::lease_record`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    /// The ID of the leased record.
+    pub record_id: String,
+    /// The owner holding the lease.
+    pub owner: String,
+    /// When the lease expires unless renewed.
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Filemaker {
+    /// Sets the fields leases are stored in.
+    ///
+    /// Defaults to `LockOwner` and `LockExpires`.
+    ///
+    /// # Arguments
+    /// * `fields` - The owner and expiry fields of the layout
+    ///
+    /// # Returns
+    /// * `Self` - The client with the new lease fields
+    pub fn with_lease_fields(mut self, fields: LeaseFields) -> Self {
+        debug!("Storing leases in {} and {}", fields.owner, fields.expires);
+        self.lease_fields = Some(Arc::new(fields));
+        self
+    }
+
+    /// Takes an advisory lock on a record for `ttl`.
+    ///
+    /// The record's lock fields are read, and if no other owner holds an unexpired lease they
+    /// are set to `owner` and the expiry time with an update guarded by the record's `modId`.
+    /// Cooperating services that lease records before editing them thereby never edit the
+    /// same record at once. Leasing a record already held by `owner` extends the lease.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the record
+    /// * `owner` - The name of the service or worker taking the lease
+    /// * `ttl` - How long the lease lasts unless renewed
+    ///
+    /// # Returns
+    /// * `Result<Lease>` - The lease, or a [`LeaseHeldError`] if another owner holds it
+    pub async fn lease_record(
+        &self,
+        id: impl std::fmt::Display,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<Lease> {
+        let id = id.to_string();
+        let (holder, mod_id) = self.current_lease(&id).await?;
+        let held_by_other =
+            holder.filter(|(holder, expires_at)| holder != owner && *expires_at > Utc::now());
+        if let Some((holder, expires_at)) = held_by_other {
+            warn!("Record {} is leased by {} until {}", id, holder, expires_at);
+            return Err(LeaseHeldError {
+                record_id: id,
+                owner: holder,
+                expires_at,
+            }
+            .into());
+        }
+        self.write_lease(&id, owner, ttl, &mod_id).await
+    }
+
+    /// Extends a lease held by `lease.owner` by `ttl` from now.
+    ///
+    /// # Arguments
+    /// * `lease` - The lease to renew
+    /// * `ttl` - How long the lease lasts from now
+    ///
+    /// # Returns
+    /// * `Result<Lease>` - The renewed lease, or a [`LeaseHeldError`] if the lease was lost
+    pub async fn renew_lease(&self, lease: &Lease, ttl: Duration) -> Result<Lease> {
+        let mod_id = self.held_lease(lease).await?;
+        self.write_lease(&lease.record_id, &lease.owner, ttl, &mod_id)
+            .await
+    }
+
+    /// Releases a lease by clearing the record's lock fields.
+    ///
+    /// Nothing is changed if the lease has already expired and been taken by another owner;
+    /// that case is reported as a [`LeaseHeldError`].
+    ///
+    /// # Arguments
+    /// * `lease` - The lease to release
+    pub async fn release_lease(&self, lease: &Lease) -> Result<()> {
+        let mod_id = self.held_lease(lease).await?;
+        self.clear_lease(&lease.record_id, &mod_id).await?;
+        info!(
+            "Released lease on record {} held by {}",
+            lease.record_id, lease.owner
+        );
+        Ok(())
+    }
+
+    /// Clears every expired lease on the layout.
+    ///
+    /// Each record is cleared with an update guarded by its `modId`, so a lease renewed in
+    /// the meantime is kept.
+    ///
+    /// # Returns
+    /// * `Result<usize>` - The number of leases cleared, or an error
+    pub async fn expire_leases(&self) -> Result<usize> {
+        let fields = self.lease_fields();
+        let expired = self
+            .find_all_pages(vec![HashMap::from([
+                (fields.owner.clone(), "*".to_string()),
+                (
+                    fields.expires.clone(),
+                    format!("<{}", self.format_timestamp(&Utc::now())),
+                ),
+            ])])
+            .await?;
+        let mut cleared = 0;
+        for record in &expired {
+            let (Some(id), Some(mod_id)) = (
+                record.get("recordId").and_then(|v| v.as_str()),
+                record.get("modId").and_then(|v| v.as_str()),
+            ) else {
+                continue;
+            };
+            match self.clear_lease(id, mod_id).await {
+                Ok(()) => cleared += 1,
+                Err(e) => debug!("Leaving lease on record {}: {}", id, e),
+            }
+        }
+        info!("Cleared {} of {} expired leases", cleared, expired.len());
+        Ok(cleared)
+    }
+
+    /// Returns the configured lease fields.
+    fn lease_fields(&self) -> LeaseFields {
+        self.lease_fields.as_deref().cloned().unwrap_or_default()
+    }
+
+    /// Reads the current holder and expiry of a record's lease, and the record's `modId`.
+    async fn current_lease(&self, id: &str) -> Result<(Option<(String, DateTime<Utc>)>, String)> {
+        let fields = self.lease_fields();
+        let record = self.get_record_by_id(id).await?;
+        let field_data = record.get("fieldData").cloned().unwrap_or(Value::Null);
+        let holder = field_data
+            .get(&fields.owner)
+            .and_then(|v| v.as_str())
+            .filter(|owner| !owner.is_empty())
+            .map(str::to_string);
+        let expires_at = match field_data.get(&fields.expires) {
+            Some(value) => self.to_utc_timestamp(value)?,
+            None => None,
+        };
+        let mod_id = record
+            .get("modId")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default()
+            .to_string();
+        Ok((holder.zip(expires_at), mod_id))
+    }
+
+    /// Checks that `lease` is still held and returns the record's `modId`.
+    async fn held_lease(&self, lease: &Lease) -> Result<String> {
+        let (holder, mod_id) = self.current_lease(&lease.record_id).await?;
+        match holder {
+            Some((owner, _)) if owner == lease.owner => Ok(mod_id),
+            Some((owner, expires_at)) => Err(LeaseHeldError {
+                record_id: lease.record_id.clone(),
+                owner,
+                expires_at,
+            }
+            .into()),
+            None => Err(LeaseHeldError {
+                record_id: lease.record_id.clone(),
+                owner: String::new(),
+                expires_at: lease.expires_at,
+            }
+            .into()),
+        }
+    }
+
+    /// Writes a lease guarded by `mod_id`.
+    async fn write_lease(
+        &self,
+        id: &str,
+        owner: &str,
+        ttl: Duration,
+        mod_id: &str,
+    ) -> Result<Lease> {
+        let fields = self.lease_fields();
+        let expires_at = Utc::now() + chrono::Duration::from_std(ttl)?;
+        let response = self
+            .patch_record(
+                id,
+                json!({
+                    &fields.owner: owner,
+                    &fields.expires: self.format_timestamp(&expires_at),
+                }),
+                Some(mod_id),
+            )
+            .await?;
+        if Self::first_message_code(&response) != Some("0") {
+            error!("Failed to lease record {}: {:?}", id, response);
+            return Err(Self::response_error(&response, "Failed to lease record"));
+        }
+        info!("Record {} leased by {} until {}", id, owner, expires_at);
+        Ok(Lease {
+            record_id: id.to_string(),
+            owner: owner.to_string(),
+            // The server stores whole seconds
+            expires_at: self
+                .to_utc_timestamp(&json!(self.format_timestamp(&expires_at)))?
+                .unwrap_or(expires_at),
+        })
+    }
+
+    /// Clears a record's lock fields, guarded by `mod_id`.
+    async fn clear_lease(&self, id: &str, mod_id: &str) -> Result<()> {
+        let fields = self.lease_fields();
+        let response = self
+            .patch_record(
+                id,
+                json!({ &fields.owner: "", &fields.expires: "" }),
+                Some(mod_id),
+            )
+            .await?;
+        if Self::first_message_code(&response) != Some("0") {
+            error!("Failed to clear lease on record {}: {:?}", id, response);
+            return Err(Self::response_error(&response, "Failed to clear lease"));
+        }
+        Ok(())
+    }
+}
@@ -1,18 +1,170 @@
-#![doc = include_str!("../README.MD")]
+#![cfg_attr(not(doctest), doc = include_str!("../README.MD"))]
 
 use anyhow::{anyhow, Result};
 use base64::Engine;
+use failover::EndpointPool;
 use log::*;
-use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use reqwest::{Client, Method};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+mod accounting;
+mod aggregate;
+mod audit;
+mod budget;
+mod builder;
+mod bulk;
+mod changes;
+mod cloud;
+mod codegen;
+pub mod coerce;
+mod compression;
+mod concurrency;
+mod config;
+mod cursor;
+mod danger;
+#[cfg(feature = "encryption")]
+mod encryption;
+pub mod error;
+mod export;
+#[cfg(feature = "http-facade")]
+pub mod facade;
+mod failover;
+mod fanout;
+mod field_filter;
+mod find;
+mod hooks;
+mod import;
+mod instrument;
+mod journal;
+mod lease;
+mod metadata;
+#[cfg(feature = "sqlite")]
+mod mirror;
+#[cfg(feature = "object-store")]
+mod object_export;
+mod options;
+#[cfg(feature = "rustls")]
+mod pinning;
+mod policy;
+mod portal;
+mod postprocess;
+mod preview;
+mod purge;
+pub mod query;
+mod queue;
+mod raw;
+mod recording;
+mod repository;
+mod sample;
+mod schema;
+mod script;
+mod seed;
+mod session;
+mod smoke;
+mod snapshot;
+#[cfg(feature = "sql-import")]
+mod sql_import;
+mod standby;
+mod stream;
+mod summary;
+mod table;
+mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod throttle;
+mod transaction;
+mod value;
+mod verify;
+mod view;
+mod warnings;
+
+pub use accounting::{OpenSession, SessionLimits};
+pub use aggregate::{Aggregate, AggregateGroup};
+pub use audit::AuditFields;
+pub use budget::{BudgetStatus, RetryBudget};
+pub use builder::FilemakerBuilder;
+pub use bulk::{ClearOptions, ClearReport, DeleteSummary};
+pub use changes::{ChangeEvent, ChangeFeed, ChangeKind};
+pub use chrono;
+pub use chrono_tz;
+pub use coerce::NumberFormat;
+pub use compression::Compression;
+pub use concurrency::{AdaptiveConcurrency, Pacing};
+pub use config::{ApiVersion, CaBundle, ClientIdentity, FmConfig, ProxyAuth, Timeouts, TlsOptions};
+pub use cursor::{Cursor, Page};
+pub use danger::{Danger, ALLOW_DESTRUCTIVE_ENV};
+#[cfg(feature = "encryption")]
+pub use encryption::{DecryptingReader, EncryptingWriter, EncryptionKey};
+pub use error::{
+    ApiError, DeserializeError, DuplicateKeyError, ErrorKind, ErrorKindExt, FilemakerError,
+    LeaseHeldError, PolicyError,
+};
+pub use export::{CsvSink, ExportFormat, ExportSink, JsonlSink};
+pub use failover::EndpointStatus;
+pub use fanout::{FanOutResults, TaggedRecord};
+pub use field_filter::FieldFilter;
+pub use find::EmptyFind;
+pub use hooks::RecordHook;
+pub use import::{DuplicatePolicy, FieldMapping, ImportOptions, ImportSummary, UpsertOutcome};
+pub use instrument::{ClientStats, LayoutQuota, LayoutStats};
+pub use journal::{Journal, JournalEntry, JournalState, PlannedOperation};
+pub use lease::{Lease, LeaseFields};
+pub use metadata::{FieldMetadata, LayoutMetadata, ValueList, ValueListItem};
+#[cfg(feature = "sqlite")]
+pub use mirror::MirrorReport;
+#[cfg(feature = "object-store")]
+pub use object_store;
+pub use options::{
+    DateFormat, DeleteOptions, FindOptions, GetOptions, Portal, ScriptCall, Scripts, WriteOptions,
+};
+#[cfg(feature = "rustls")]
+pub use pinning::SpkiPin;
+pub use policy::{Access, LayoutPolicy};
+pub use portal::PortalRow;
+pub use postprocess::RecordPostProcessor;
+pub use preview::RequestPreview;
+pub use purge::{PurgeOptions, PurgeProgress, PurgeReport};
+pub use queue::{FmQueue, QueueConfig, QueueItem};
+pub use raw::WithRaw;
+pub use recording::{read_recording, RecordedExchange, REDACTED};
+pub use repository::{FmRecordModel, Layout, Repository};
+pub use sample::SampleStrategy;
+pub use schema::{infer_schema, FieldProfile, InferredType, SchemaReport, ValueRange};
+pub use script::ScriptResult;
+pub use seed::SeedSummary;
+pub use session::{DataSource, FilemakerSession, SessionOptions};
+pub use smoke::{run_smoke, SmokeConfig, SmokeOutcome, SmokeReport, SmokeStep};
+pub use snapshot::{
+    RestoreFilter, RestoreOptions, RestorePlan, RestoreReport, SnapshotManifest, SnapshotOptions,
+    MANIFEST_FILE,
+};
+#[cfg(feature = "sql-import")]
+pub use sqlx;
+pub use standby::StandbyOptions;
+pub use stream::StreamOptions;
+pub use summary::SummaryReport;
+pub use table::{RecordTable, TableStyle};
+pub use template::QueryTemplate;
+pub use throttle::FmThrottle;
+pub use transaction::{RollbackReport, Transaction, TransactionOperation};
+pub use value::FmValue;
+pub use verify::{MistypedField, ModelField, ModelFieldType, ModelReport};
+pub use view::{ReadOnlyView, ScopedClient};
+pub use warnings::{response_warnings, ResponseWarning, WarningSource};
+
 static FM_URL: RwLock<Option<String>> = RwLock::new(None);
 
+/// Number of records requested per page when listing record IDs.
+const RECORD_ID_PAGE_SIZE: u64 = 1000;
+
+/// FileMaker error code returned when a find or lookup matches no records.
+const NO_RECORDS_MATCH_CODE: &str = "401";
+
 /// Represents a single record from a database query.
 ///
 /// The generic type `T` represents the structure of the field data.
@@ -22,7 +174,7 @@ pub struct Record<T> {
     #[serde(rename = "fieldData")]
     pub data: T,
     /// Related data from portal tables, stored as a generic JSON Value.
-    #[serde(rename = "portalData")]
+    #[serde(rename = "portalData", default)]
     pub portal_data: Value,
     /// Unique identifier for the record in the database.
     #[serde(rename = "recordId")]
@@ -32,6 +184,17 @@ pub struct Record<T> {
     pub mod_id: String,
 }
 
+/// A record created with [`Filemaker::create_record`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatedRecord {
+    /// The ID the server assigned to the record.
+    pub record_id: String,
+    /// The modification ID of the new record.
+    pub mod_id: String,
+    /// The record as stored by the server, including auto-entered values.
+    pub record: Value,
+}
+
 /// Container for the complete result of a find operation, including response data and messages.
 ///
 /// The generic type `T` represents the structure of individual record data.
@@ -41,6 +204,34 @@ pub struct FindResult<T> {
     pub response: Response<T>,
     /// List of messages returned by the database operation, often containing status or error information.
     pub messages: Vec<Message>,
+    /// Non-fatal problems the server reported along with the records, such as errors of
+    /// scripts run with the find.
+    #[serde(skip)]
+    pub warnings: Vec<ResponseWarning>,
+}
+
+impl<T> FindResult<T> {
+    /// Removes records whose `recordId` appeared earlier in the result.
+    ///
+    /// Finds with several requests on layouts involving certain relationships can return the
+    /// same record more than once. The first occurrence of each record is kept and
+    /// `returned_count` is updated.
+    ///
+    /// # Returns
+    /// * `usize` - The number of duplicates removed
+    pub fn dedupe_by_record_id(&mut self) -> usize {
+        let before = self.response.data.len();
+        let mut seen = std::collections::HashSet::new();
+        self.response
+            .data
+            .retain(|record| seen.insert(record.record_id.clone()));
+        let removed = before - self.response.data.len();
+        if removed > 0 {
+            debug!("Removed {} duplicate records from find result", removed);
+            self.response.info.returned_count = self.response.data.len() as u64;
+        }
+        removed
+    }
 }
 
 /// Contains the response data from a find operation.
@@ -66,6 +257,20 @@ pub struct Message {
     pub code: String,
 }
 
+/// Identifies a single record without carrying any of its field data.
+///
+/// Returned by id-only listings such as [`Filemaker::get_record_ids`], and by
+/// [`Filemaker::create_record_ids`].
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq, Hash)]
+pub struct RecordId {
+    /// Unique identifier for the record in the database.
+    #[serde(rename = "recordId")]
+    pub record_id: String,
+    /// Modification identifier for the record, used for optimistic locking.
+    #[serde(rename = "modId")]
+    pub mod_id: String,
+}
+
 /// Metadata about the data returned from a database query.
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DataInfo {
@@ -101,12 +306,58 @@ pub struct Filemaker {
     table: String,
     // HTTP client for making API requests
     client: Client,
+    // Format of numbers stored as text, used by the coercion helpers
+    number_format: NumberFormat,
+    // Time zone the server stores timestamps in, used by the coercion helpers
+    server_timezone: chrono_tz::Tz,
+    // Latency above which requests are logged as slow operations
+    slow_operation_threshold: Option<Duration>,
+    // Traffic per layout above which a warning is logged
+    layout_quota: Option<instrument::LayoutQuota>,
+    // Bounds within which bulk operations tune their concurrency; None keeps it fixed
+    adaptive_concurrency: Option<concurrency::AdaptiveConcurrency>,
+    // Layouts the client may read or write; None permits all
+    layout_policy: Option<Arc<LayoutPolicy>>,
+    // Data API URL of a client created with a configuration; None uses FM_URL
+    base_url: Option<String>,
+    // Data API endpoints to fail over between; None uses FM_URL
+    endpoints: Option<Arc<EndpointPool>>,
+    // Fields advisory leases are stored in; None uses the defaults
+    lease_fields: Option<Arc<lease::LeaseFields>>,
+    // Request counters shared by the client and its clones
+    stats: Arc<instrument::StatsCounters>,
+    // Writes every exchange to an NDJSON recording
+    recorder: Option<Arc<recording::Recorder>>,
+    // Answers requests from a recording instead of a server
+    replayer: Option<Arc<recording::Replayer>>,
+    // Rewrite each received record before it is returned
+    post_processors: Vec<Arc<dyn postprocess::RecordPostProcessor>>,
+    // Run around record creates, updates and deletes
+    hooks: Vec<Arc<dyn hooks::RecordHook>>,
+    // Fields left out of field names, exports and codegen; None uses the defaults
+    field_filter: Option<Arc<FieldFilter>>,
+    // Held while an expired session is replaced, so only one request logs in again
+    session_refresh: Arc<Mutex<()>>,
+    // Second session swapped in before the primary expires
+    standby: Option<Arc<standby::Standby>>,
+    // Logs in again when the session expires; failover clients default to their pool's
+    credentials: Option<session::Credentials>,
+    // Field appended to every sort so records with equal sort values keep a fixed order
+    sort_tiebreaker: Option<String>,
+    // Request budget shared with other clients of the process
+    throttle: Option<throttle::FmThrottle>,
+    // Logs in again with Claris ID tokens instead of credentials, for FileMaker Cloud
+    fmid_tokens: Option<cloud::FmidTokens>,
+    // Sent with every login, e.g. credentials of external data sources
+    session_options: SessionOptions,
 }
 impl Filemaker {
     /// Creates a new `Filemaker` instance.
     ///
     /// Initializes a connection to a FileMaker database with the provided credentials.
     /// This function performs authentication and sets up the HTTP client with appropriate configuration.
+    /// The credentials are kept to log in again when the session expires (error 952), after
+    /// which the failed request is retried once.
     ///
     /// # Arguments
     /// * `username` - The username for FileMaker authentication
@@ -117,21 +368,52 @@ impl Filemaker {
     /// # Returns
     /// * `Result<Self>` - A new Filemaker instance or an error
     pub async fn new(username: &str, password: &str, database: &str, table: &str) -> Result<Self> {
+        Self::new_with_options(
+            username,
+            password,
+            database,
+            table,
+            SessionOptions::default(),
+        )
+        .await
+    }
+
+    /// Creates a new `Filemaker` instance, logging in with the given session options.
+    ///
+    /// Same as [`Filemaker::new`]; the options are sent with this and every later login of
+    /// the client, e.g. to authenticate against externally referenced FileMaker files.
+    ///
+    /// ```rust,ignore
+    /// let options = SessionOptions::default().with_data_source("Inventory", "user", "pass");
+    /// let filemaker =
+    ///     Filemaker::new_with_options("user", "pass", "Orders", "Orders", options).await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `username` - The username for FileMaker authentication
+    /// * `password` - The password for FileMaker authentication
+    /// * `database` - The name of the FileMaker database to connect to
+    /// * `table` - The name of the table/layout to operate on
+    /// * `options` - The external data sources to log in to along with the database
+    ///
+    /// # Returns
+    /// * `Result<Self>` - A new Filemaker instance or an error
+    pub async fn new_with_options(
+        username: &str,
+        password: &str,
+        database: &str,
+        table: &str,
+        options: SessionOptions,
+    ) -> Result<Self> {
         // URL-encode database and table names to handle spaces and special characters
-        let encoded_database = utf8_percent_encode(database, NON_ALPHANUMERIC).to_string();
-        let encoded_table = utf8_percent_encode(table, NON_ALPHANUMERIC).to_string();
+        let encoded_database = query::encode_url_component(database);
+        let encoded_table = query::encode_url_component(table);
 
-        // Create an HTTP client that accepts invalid SSL certificates (for development)
-        let client = Client::builder()
-            .danger_accept_invalid_certs(true) // Disable SSL verification
-            .build()
-            .map_err(|e| {
-                error!("Failed to build client: {}", e);
-                anyhow::anyhow!(e)
-            })?;
+        let client = Self::build_client()?;
 
         // Authenticate with FileMaker and get a session token
-        let token = Self::get_session_token(&client, database, username, password).await?;
+        let token =
+            Self::get_session_token(&client, database, username, password, &options).await?;
         info!("Filemaker instance created successfully");
 
         // Return the initialized Filemaker instance
@@ -140,9 +422,152 @@ impl Filemaker {
             table: encoded_table,
             token: Arc::new(Mutex::new(Some(token))), // Wrap token in a thread-safe container
             client,
+            number_format: NumberFormat::default(),
+            server_timezone: chrono_tz::UTC,
+            slow_operation_threshold: None,
+            layout_quota: None,
+            adaptive_concurrency: None,
+            layout_policy: None,
+            base_url: None,
+            endpoints: None,
+            lease_fields: None,
+            stats: Arc::default(),
+            recorder: None,
+            replayer: None,
+            post_processors: vec![],
+            hooks: vec![],
+            field_filter: None,
+            session_refresh: Arc::default(),
+            standby: None,
+            credentials: Some(session::fixed_credentials(username, password)),
+            sort_tiebreaker: None,
+            throttle: None,
+            fmid_tokens: None,
+            session_options: options,
+        })
+    }
+
+    /// Creates a client without a session, for answering requests locally.
+    fn offline(database: &str, table: &str) -> Result<Self> {
+        Ok(Self {
+            database: query::encode_url_component(database),
+            table: query::encode_url_component(table),
+            token: Arc::new(Mutex::new(Some(String::new()))),
+            client: Self::build_client()?,
+            number_format: NumberFormat::default(),
+            server_timezone: chrono_tz::UTC,
+            slow_operation_threshold: None,
+            layout_quota: None,
+            adaptive_concurrency: None,
+            layout_policy: None,
+            base_url: None,
+            endpoints: None,
+            lease_fields: None,
+            stats: Arc::default(),
+            recorder: None,
+            replayer: None,
+            post_processors: vec![],
+            hooks: vec![],
+            field_filter: None,
+            session_refresh: Arc::default(),
+            standby: None,
+            credentials: None,
+            sort_tiebreaker: None,
+            throttle: None,
+            fmid_tokens: None,
+            session_options: SessionOptions::default(),
         })
     }
 
+    /// Builds the HTTP client used for Data API requests of clients using `FM_URL`.
+    fn build_client() -> Result<Client> {
+        #[allow(unused_mut)]
+        let mut tls = Self::tls_options();
+        #[cfg(feature = "rustls")]
+        if tls.pinned_keys.is_empty() {
+            tls.pinned_keys = Self::certificate_pins();
+        }
+        config::build_client(&tls, &Timeouts::default(), None)
+    }
+
+    /// Returns a client operating on another layout of the same database.
+    ///
+    /// The returned client shares the session token and HTTP client of this one, so no
+    /// additional authentication is performed.
+    ///
+    /// # Arguments
+    /// * `layout` - The name of the layout the new client operates on
+    ///
+    /// # Returns
+    /// * `Filemaker` - A client bound to `layout`
+    pub fn with_layout(&self, layout: &str) -> Filemaker {
+        Filemaker {
+            table: query::encode_url_component(layout),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a client operating on the same layout of another database.
+    ///
+    /// A new session is created for `database` on the same server, or the same failover
+    /// endpoints, with the given credentials. The returned client keeps this client's
+    /// settings, such as its layout policy and coercion formats.
+    ///
+    /// # Arguments
+    /// * `database` - The name of the database the new client operates on
+    /// * `username` - The username for FileMaker authentication
+    /// * `password` - The password for FileMaker authentication
+    ///
+    /// # Returns
+    /// * `Result<Filemaker>` - A client bound to `database`, or an error if the login fails
+    pub async fn with_database(
+        &self,
+        database: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Filemaker> {
+        let mut filemaker = Filemaker {
+            database: query::encode_url_component(database),
+            token: Arc::new(Mutex::new(None)),
+            session_refresh: Arc::default(),
+            standby: None,
+            credentials: Some(session::fixed_credentials(username, password)),
+            fmid_tokens: None,
+            ..self.clone()
+        };
+        match &self.endpoints {
+            Some(pool) => {
+                let pool = Arc::new(pool.with_credentials(username, password));
+                filemaker.pin_session(&pool, None).await?;
+                filemaker.endpoints = Some(pool);
+            }
+            None => {
+                let token = Self::get_session_token_at(
+                    &filemaker.client,
+                    &filemaker.base_url()?,
+                    database,
+                    username,
+                    password,
+                    &filemaker.session_options,
+                )
+                .await?;
+                *filemaker.token.lock().await = Some(token);
+            }
+        }
+        info!("Opened session for database {}", database);
+        Ok(filemaker)
+    }
+
+    /// Returns the name of the database this client operates on.
+    pub fn database(&self) -> String {
+        query::decode_url_component(&self.database)
+    }
+
+    /// Returns the name of the layout this client operates on.
+    pub fn layout(&self) -> String {
+        query::decode_url_component(&self.table)
+    }
+
     /// Sets the `FM_URL` to the specified value.
     ///
     /// This function accepts a URL as an input parameter and updates the globally shared `FM_URL` variable.
@@ -161,7 +586,7 @@ impl Filemaker {
     ///   or another thread panicked while holding the lock.
     ///
     /// # Examples
-    /// ```rust
+    /// ```rust,ignore
     /// set_fm_url("https://example.com")?;
     /// ```
     ///
@@ -200,7 +625,7 @@ impl Filemaker {
     ///
     /// # Example
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// match get_fm_url() {
     ///     Ok(url) => println!("FM_URL: {}", url),
     ///     Err(e) => eprintln!("Error retrieving FM_URL: {}", e),
@@ -213,6 +638,22 @@ impl Filemaker {
         rwlock.clone().ok_or(anyhow!("FM_URL is not set"))
     }
 
+    /// Returns the base URL of the Data API this client sends requests to.
+    ///
+    /// This is the endpoint the session is pinned to when failover endpoints are configured,
+    /// the configured server of clients created with [`Filemaker::with_config`], and `FM_URL`
+    /// otherwise.
+    fn base_url(&self) -> Result<String> {
+        if self.replayer.is_some() {
+            return Ok(String::new());
+        }
+        match (&self.endpoints, &self.base_url) {
+            (Some(pool), _) => Ok(pool.pinned_url().to_string()),
+            (None, Some(base_url)) => Ok(base_url.clone()),
+            (None, None) => Self::get_fm_url(),
+        }
+    }
+
     /// Gets a session token from the FileMaker Data API.
     ///
     /// Performs authentication against the FileMaker Data API and retrieves a session token
@@ -223,6 +664,7 @@ impl Filemaker {
     /// * `database` - The name of the FileMaker database to authenticate against
     /// * `username` - The username for FileMaker authentication
     /// * `password` - The password for FileMaker authentication
+    /// * `options` - The external data sources to log in to along with the database
     ///
     /// # Returns
     /// * `Result<String>` - The session token or an error
@@ -231,18 +673,46 @@ impl Filemaker {
         database: &str,
         username: &str,
         password: &str,
+        options: &SessionOptions,
     ) -> Result<String> {
-        // URL-encode the database name to handle spaces and special characters
-        let database = utf8_percent_encode(database, NON_ALPHANUMERIC).to_string();
-
-        // Construct the URL for the session endpoint
-        let url = format!("{}/databases/{}/sessions", Self::get_fm_url()?, database);
+        let base_url = Self::get_fm_url()?;
+        Self::get_session_token_at(client, &base_url, database, username, password, options).await
+    }
 
+    /// Gets a session token from the Data API at `base_url`.
+    ///
+    /// Same as [`Filemaker::get_session_token`], for a server other than `FM_URL`.
+    async fn get_session_token_at(
+        client: &Client,
+        base_url: &str,
+        database: &str,
+        username: &str,
+        password: &str,
+        options: &SessionOptions,
+    ) -> Result<String> {
         // Create a Base64-encoded Basic authentication header
         let auth_header = format!(
             "Basic {}",
             base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password))
         );
+        Self::create_session(client, base_url, database, &auth_header, options).await
+    }
+
+    /// Creates a session at `base_url`, authenticating with the given `Authorization` header.
+    async fn create_session(
+        client: &Client,
+        base_url: &str,
+        database: &str,
+        auth_header: &str,
+        options: &SessionOptions,
+    ) -> Result<String> {
+        // Construct the URL for the session endpoint, URL-encoding the database name to
+        // handle spaces and special characters
+        let url = format!(
+            "{}/databases/{}/sessions",
+            base_url,
+            query::encode_url_component(database)
+        );
 
         debug!("Requesting session token from URL: {}", url);
 
@@ -251,7 +721,7 @@ impl Filemaker {
             .post(&url)
             .header("Authorization", auth_header)
             .header("Content-Type", "application/json")
-            .body("{}") // Empty JSON body for session creation
+            .body(options.body().to_string()) // Credentials of external data sources, if any
             .send()
             .await
             .map_err(|e| {
@@ -272,20 +742,25 @@ impl Filemaker {
             .and_then(|t| t.as_str())
         {
             info!("Session token retrieved successfully");
+            accounting::opened(client, base_url, database, token);
             Ok(token.to_string())
         } else {
             error!(
                 "Failed to get token from FileMaker API response: {:?}",
                 json
             );
-            Err(anyhow::anyhow!("Failed to get token from FileMaker API"))
+            Err(Self::response_error(
+                &json,
+                "Failed to get token from FileMaker API",
+            ))
         }
     }
 
     /// Sends an authenticated HTTP request to the FileMaker Data API.
     ///
     /// This method handles adding the authentication token to requests and processing
-    /// the response from the FileMaker Data API.
+    /// the response from the FileMaker Data API. A request rejected because the session
+    /// expired is retried once on a new session.
     ///
     /// # Arguments
     /// * `url` - The endpoint URL to send the request to
@@ -299,6 +774,22 @@ impl Filemaker {
         url: &str,
         method: Method,
         body: Option<Value>,
+    ) -> Result<Value> {
+        self.check_layout_policy(&method, url)?;
+        let response = match (&self.replayer, &self.endpoints) {
+            (Some(replayer), _) => replayer.respond(&method, url).await?,
+            (None, Some(pool)) => self.failover_request(pool, url, method, body).await?,
+            (None, None) => self.reauthenticating_request(url, method, body).await?,
+        };
+        Ok(self.post_process(response))
+    }
+
+    /// Sends a single authenticated request, without failover.
+    async fn send_authenticated_request(
+        &self,
+        url: &str,
+        method: Method,
+        body: Option<Value>,
     ) -> Result<Value> {
         // Retrieve the session token from the shared state
         let token = self.token.lock().await.clone();
@@ -308,38 +799,103 @@ impl Filemaker {
         }
 
         // Create Bearer authentication header with the token
-        let auth_header = format!("Bearer {}", token.unwrap());
+        let token = token.unwrap();
+        accounting::used(&token);
+        let auth_header = format!("Bearer {}", token);
+
+        if log_enabled!(Level::Trace) {
+            let preview = RequestPreview {
+                method: method.clone(),
+                url: url.to_string(),
+                body: body.clone(),
+            };
+            trace!("Equivalent request: {}", preview.to_curl());
+        }
 
         // Start building the request with appropriate headers
         let mut request = self
             .client
-            .request(method, url)
+            .request(method.clone(), url)
             .header("Authorization", auth_header)
             .header("Content-Type", "application/json");
 
         // Add the JSON body to the request if provided
-        if let Some(body_content) = body {
-            let json_body = serde_json::to_string(&body_content).map_err(|e| {
+        let mut bytes_sent = 0;
+        if let Some(body_content) = &body {
+            let json_body = serde_json::to_string(body_content).map_err(|e| {
                 error!("Failed to serialize request body: {}", e);
                 anyhow::anyhow!(e)
             })?;
             debug!("Request body: {}", json_body);
+            bytes_sent = json_body.len();
             request = request.body(json_body);
         }
 
         debug!("Sending authenticated request to URL: {}", url);
 
+        // Wait for a slot of the shared throttle, held until the response is read
+        let permit = self.throttle_permit().await;
+
         // Send the request and handle any network errors
+        let started = Instant::now();
         let response = request.send().await.map_err(|e| {
             error!("Failed to send authenticated request: {}", e);
+            self.record_request(
+                url,
+                bytes_sent,
+                0,
+                started.elapsed(),
+                Some(ErrorKind::Other),
+            );
             anyhow::anyhow!(e)
         })?;
+        let bytes = response.bytes().await.map_err(|e| {
+            error!("Failed to read authenticated request response: {}", e);
+            self.record_request(
+                url,
+                bytes_sent,
+                0,
+                started.elapsed(),
+                Some(ErrorKind::Other),
+            );
+            anyhow::anyhow!(e)
+        })?;
+        drop(permit);
 
         // Parse the response JSON and handle parsing errors
-        let json: Value = response.json().await.map_err(|e| {
+        let json: Value = serde_json::from_slice(&bytes).map_err(|e| {
             error!("Failed to parse authenticated request response: {}", e);
+            self.record_request(
+                url,
+                bytes_sent,
+                bytes.len(),
+                started.elapsed(),
+                Some(ErrorKind::Other),
+            );
             anyhow::anyhow!(e)
         })?;
+        let error = match Self::first_message_code(&json) {
+            Some("0") | None => None,
+            Some(code) => Some(
+                code.parse()
+                    .map(ErrorKind::from_code)
+                    .unwrap_or(ErrorKind::Other),
+            ),
+        };
+        self.record_request(url, bytes_sent, bytes.len(), started.elapsed(), error);
+        if let Some(standby) = &self.standby {
+            standby.primary_used();
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&method, url, body.as_ref(), &json, started.elapsed());
+        }
+        self.report_duration(&method, url, started.elapsed(), &json);
+        for warning in response_warnings(&json) {
+            warn!(
+                "Request to {} reported {} ({:?}): {}",
+                url, warning.code, warning.source, warning.message
+            );
+        }
 
         info!("Authenticated request to {} completed successfully", url);
         Ok(json)
@@ -360,7 +916,7 @@ impl Filemaker {
         // Construct the URL for the FileMaker Data API records endpoint
         let url = format!(
             "{}/databases/{}/layouts/{}/records?_offset={}&_limit={}",
-            Self::get_fm_url()?,
+            self.base_url()?,
             self.database,
             self.table,
             start,
@@ -378,7 +934,10 @@ impl Filemaker {
         } else {
             // Log and return error if the expected data structure is not found
             error!("Failed to retrieve records from response: {:?}", response);
-            Err(anyhow::anyhow!("Failed to retrieve records"))
+            Err(Self::response_error(
+                &response,
+                "Failed to retrieve records",
+            ))
         }
     }
 
@@ -423,7 +982,7 @@ impl Filemaker {
     ///
     /// # Examples
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// #[derive(serde::Deserialize)]
     /// struct MyRecord {
     ///     id: u32,
@@ -446,9 +1005,9 @@ impl Filemaker {
         let mut items: Vec<T> = vec![];
         for item in raw {
             if let Some(data) = item.get("fieldData") {
-                let deserialized: T = serde_json::from_value(data.clone()).map_err(|e| {
+                let deserialized: T = error::deserialize_at(data, "fieldData").map_err(|e| {
                     error!("Failed to deserialize record: {}. Response: {:?}", e, data);
-                    anyhow::anyhow!(e)
+                    anyhow::Error::new(e)
                 })?;
                 items.push(deserialized);
             } else {
@@ -473,7 +1032,7 @@ impl Filemaker {
         // Construct the URL for the FileMaker Data API records endpoint
         let url = format!(
             "{}/databases/{}/layouts/{}/records",
-            Self::get_fm_url()?,
+            self.base_url()?,
             self.database,
             self.table
         );
@@ -497,8 +1056,139 @@ impl Filemaker {
                 "Failed to retrieve total record count from response: {:?}",
                 response
             );
-            Err(anyhow::anyhow!("Failed to retrieve total record count"))
+            Err(Self::response_error(
+                &response,
+                "Failed to retrieve total record count",
+            ))
+        }
+    }
+
+    /// Retrieves only the record IDs and modification IDs of the table, or of a query's found set.
+    ///
+    /// Records are fetched page by page without portal data and only the `recordId`/`modId`
+    /// pair of each record is kept, which makes this suitable for bulk deletes and sync jobs
+    /// on large tables.
+    ///
+    /// # Arguments
+    /// * `query` - Optional find criteria; when `None` every record of the table is listed
+    ///
+    /// # Returns
+    /// * `Result<Vec<RecordId>>` - The identifiers of all matching records, or an error
+    pub async fn get_record_ids(
+        &self,
+        query: Option<Vec<HashMap<String, String>>>,
+    ) -> Result<Vec<RecordId>> {
+        let mut ids = Vec::new();
+        let mut offset = 1;
+        loop {
+            let response = match &query {
+                Some(query) => {
+                    let url = format!(
+                        "{}/databases/{}/layouts/{}/_find",
+                        self.base_url()?,
+                        self.database,
+                        self.table
+                    );
+                    let body = json!({
+                        "query": query,
+                        "offset": offset,
+                        "limit": RECORD_ID_PAGE_SIZE,
+                        "portal": [],
+                    });
+                    self.authenticated_request(&url, Method::POST, Some(body))
+                        .await?
+                }
+                None => {
+                    let url = format!(
+                        "{}/databases/{}/layouts/{}/records?_offset={}&_limit={}&portal=%5B%5D",
+                        self.base_url()?,
+                        self.database,
+                        self.table,
+                        offset,
+                        RECORD_ID_PAGE_SIZE
+                    );
+                    self.authenticated_request(&url, Method::GET, None).await?
+                }
+            };
+
+            // A find without matches is reported as error 401 rather than an empty set
+            if Self::first_message_code(&response) == Some(NO_RECORDS_MATCH_CODE) {
+                break;
+            }
+
+            let Some(page) = response
+                .get("response")
+                .and_then(|r| r.get("data"))
+                .and_then(|d| d.as_array())
+            else {
+                error!(
+                    "Failed to retrieve record ids from response: {:?}",
+                    response
+                );
+                return Err(Self::response_error(
+                    &response,
+                    "Failed to retrieve record ids",
+                ));
+            };
+
+            for record in page {
+                let id: RecordId = serde_json::from_value(record.clone()).map_err(|e| {
+                    error!(
+                        "Failed to read record id from record: {}. Record: {:?}",
+                        e, record
+                    );
+                    anyhow::anyhow!(e)
+                })?;
+                ids.push(id);
+            }
+
+            if (page.len() as u64) < RECORD_ID_PAGE_SIZE {
+                break;
+            }
+            offset += RECORD_ID_PAGE_SIZE;
         }
+
+        info!("Retrieved {} record ids", ids.len());
+        Ok(ids)
+    }
+
+    /// Returns the FileMaker error reported in a Data API response, if any.
+    pub(crate) fn api_error(response: &Value) -> Option<ApiError> {
+        let message = response
+            .get("messages")
+            .and_then(|m| m.as_array())
+            .and_then(|m| m.first())?;
+        let code = message
+            .get("code")
+            .and_then(|c| c.as_str())
+            .and_then(|c| c.parse::<u32>().ok())?;
+        (code != 0).then(|| ApiError {
+            code,
+            message: message
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        })
+    }
+
+    /// Builds the error for a failed operation, keeping the FileMaker error of the response
+    /// so it can be inspected with [`ErrorKindExt`].
+    pub(crate) fn response_error(response: &Value, context: &'static str) -> anyhow::Error {
+        match Self::api_error(response) {
+            Some(api_error) => anyhow::Error::new(api_error).context(context),
+            None => anyhow::anyhow!(context),
+        }
+    }
+
+    /// Returns the first message code of a Data API response, if any.
+    fn first_message_code(response: &Value) -> Option<&str> {
+        response
+            .get("messages")
+            .and_then(|m| m.as_array())
+            .and_then(|m| m.first())
+            .and_then(|m| m.get("code"))
+            .and_then(|c| c.as_str())
     }
 
     /// Searches the database for records matching specified criteria.
@@ -521,60 +1211,45 @@ impl Filemaker {
     where
         T: serde::de::DeserializeOwned + Default,
     {
-        // Construct the URL for the FileMaker Data API find endpoint
-        let url = format!(
-            "{}/databases/{}/layouts/{}/_find",
-            Self::get_fm_url()?,
-            self.database,
-            self.table
+        let request = self.search_request(query, sort, ascending, limit)?;
+        debug!(
+            "Executing search query with URL: {}. Body: {:?}",
+            request.url, request.body
         );
 
-        // Determine sort order based on ascending parameter
-        let sort_order = if ascending { "ascend" } else { "descend" };
-
-        // Transform the sort fields into the format expected by FileMaker API
-        let sort_map: Vec<_> = sort
-            .into_iter()
-            .map(|s| {
-                let mut map = HashMap::new();
-                map.insert("fieldName".to_string(), s);
-                map.insert("sortOrder".to_string(), sort_order.to_string());
-                map
-            })
-            .collect();
-
-        // Construct the request body with query and sort parameters
-        let mut body: HashMap<String, Value> = HashMap::from([
-            ("query".to_string(), serde_json::to_value(query)?),
-            ("sort".to_string(), serde_json::to_value(sort_map)?),
-        ]);
-        if let Some(limit) = limit {
-            body.insert("limit".to_string(), serde_json::to_value(limit)?);
-        } else {
-            body.insert("limit".to_string(), serde_json::to_value(u32::MAX)?);
-        }
-        debug!("Executing search query with URL: {}. Body: {:?}", url, body);
-
         // Send authenticated POST request to the API endpoint
         let response = self
-            .authenticated_request(&url, Method::POST, Some(serde_json::to_value(body)?))
+            .authenticated_request(&request.url, request.method, request.body)
             .await?;
+        self.parse_find_result(response)
+    }
 
-        // Extract the search results and deserialize into the specified type
-        let deserialized: FindResult<T> =
-            serde_json::from_value(response.clone()).map_err(|e| {
+    /// Deserializes a find response, preferring the FileMaker error if the find failed.
+    pub(crate) fn parse_find_result<T>(&self, response: Value) -> Result<FindResult<T>>
+    where
+        T: serde::de::DeserializeOwned + Default,
+    {
+        let mut deserialized: FindResult<T> =
+            error::deserialize_at(&response, "").map_err(|e| {
                 error!(
                     "Failed to deserialize search results: {}. Response: {:?}",
                     e, response
                 );
-                anyhow::anyhow!(e)
+                match Self::api_error(&response) {
+                    Some(api_error) => anyhow::Error::new(api_error).context("Search failed"),
+                    None => anyhow::Error::new(e),
+                }
             })?;
+        deserialized.warnings = response_warnings(&response);
         info!("Search query executed successfully");
         Ok(deserialized)
     }
 
     /// Adds a record to the database.
     ///
+    /// Failures reported by the server are returned as `Ok` with `success` set to `false`;
+    /// prefer [`Filemaker::create_record`], which returns them as errors.
+    ///
     /// # Parameters
     /// - `field_data`: A `HashMap` representing the field data for the new record.
     ///
@@ -584,53 +1259,177 @@ impl Filemaker {
         &self,
         field_data: HashMap<String, Value>,
     ) -> Result<HashMap<String, Value>> {
-        // Define the URL for the FileMaker Data API endpoint
+        self.add_record_with(field_data, &WriteOptions::default())
+            .await
+    }
+
+    /// Creates a record and returns its IDs and the record as stored by the server.
+    ///
+    /// The record is read back after it is created, so auto-entered values are included.
+    /// Use [`Filemaker::create_record_ids`] to skip that request.
+    ///
+    /// # Arguments
+    /// * `field_data` - The field data of the new record
+    ///
+    /// # Returns
+    /// * `Result<CreatedRecord>` - The created record, or an error if the server rejects it
+    pub async fn create_record(&self, field_data: HashMap<String, Value>) -> Result<CreatedRecord> {
+        let ids = self.create_record_ids(field_data).await?;
+        let record = self.get_record_by_id(&ids.record_id).await?;
+        let mod_id = record
+            .get("modId")
+            .and_then(|m| m.as_str())
+            .map_or(ids.mod_id, str::to_string);
+        Ok(CreatedRecord {
+            record_id: ids.record_id,
+            mod_id,
+            record,
+        })
+    }
+
+    /// Creates a record and returns only the IDs from the create response.
+    ///
+    /// Unlike [`Filemaker::create_record`], the record is not read back, which halves the
+    /// requests per record on high-throughput ingestion paths.
+    ///
+    /// # Arguments
+    /// * `field_data` - The field data of the new record
+    ///
+    /// # Returns
+    /// * `Result<RecordId>` - The record ID and modification ID, or an error if the server rejects the record
+    pub async fn create_record_ids(&self, field_data: HashMap<String, Value>) -> Result<RecordId> {
         let url = format!(
             "{}/databases/{}/layouts/{}/records",
-            Self::get_fm_url()?,
+            self.base_url()?,
             self.database,
             self.table
         );
+        let mut field_data: serde_json::Map<String, Value> = field_data.into_iter().collect();
+        self.before_create(&mut field_data).await?;
+        let body = json!({ "fieldData": field_data });
+        debug!("Creating a record. URL: {}. Body: {:?}", url, body);
+        let response = self
+            .authenticated_request(&url, Method::POST, Some(body))
+            .await?;
+        let Some(record_id) = response
+            .get("response")
+            .and_then(|r| r.get("recordId"))
+            .and_then(|id| id.as_str())
+            .filter(|_| Self::api_error(&response).is_none())
+        else {
+            error!("Failed to create the record: {:?}", response);
+            return Err(Self::response_error(&response, "Failed to create record"));
+        };
+        let mod_id = response
+            .get("response")
+            .and_then(|r| r.get("modId"))
+            .and_then(|m| m.as_str())
+            .unwrap_or_default();
+        info!("Created record {} on {}", record_id, self.layout());
+        self.after_create(record_id).await;
+        Ok(RecordId {
+            record_id: record_id.to_string(),
+            mod_id: mod_id.to_string(),
+        })
+    }
 
-        // Prepare the request body
-        let field_data_map: serde_json::Map<String, Value> = field_data.into_iter().collect();
-        let body = HashMap::from([("fieldData".to_string(), Value::Object(field_data_map))]);
-
-        debug!("Adding a new record. URL: {}. Body: {:?}", url, body);
+    /// Verifies that no record has `value` in the field `field`.
+    ///
+    /// Performs an exact-match find for the value, which gives layouts without server-side
+    /// unique validation a client-side uniqueness check. The check and a following create are
+    /// not atomic, so concurrent writers can still race each other.
+    ///
+    /// # Arguments
+    /// * `field` - The name of the key field
+    /// * `value` - The value that must not exist yet
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the value is unused, a [`DuplicateKeyError`] if a record already has it, or another error
+    pub async fn assert_unique(&self, field: &str, value: impl ToString) -> Result<()> {
+        let value = value.to_string();
+        let url = format!(
+            "{}/databases/{}/layouts/{}/_find",
+            self.base_url()?,
+            self.database,
+            self.table
+        );
+        let body = json!({
+            "query": [{ field: query::exact_match(&value) }],
+            "limit": 1,
+        });
 
-        // Make the API call
+        debug!("Checking uniqueness of {} = '{}'", field, value);
         let response = self
-            .authenticated_request(&url, Method::POST, Some(serde_json::to_value(body)?))
+            .authenticated_request(&url, Method::POST, Some(body))
             .await?;
 
-        if let Some(record_id) = response
+        if Self::first_message_code(&response) == Some(NO_RECORDS_MATCH_CODE) {
+            return Ok(());
+        }
+
+        match response
             .get("response")
-            .and_then(|r| r.get("recordId"))
-            .and_then(|id| id.as_str())
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.as_array())
+            .and_then(|d| d.first())
         {
-            if let Ok(record_id) = record_id.parse::<u64>() {
-                debug!("Record added successfully. Record ID: {}", record_id);
-                let added_record = self.get_record_by_id(record_id).await?;
-                Ok(HashMap::from([
-                    ("success".to_string(), Value::Bool(true)),
-                    ("result".to_string(), added_record),
-                ]))
-            } else {
-                error!("Failed to parse record id {} - {:?}", record_id, response);
-                Ok(HashMap::from([
-                    ("success".to_string(), Value::Bool(false)),
-                    ("result".to_string(), response),
-                ]))
+            Some(existing) => {
+                let record_id = existing
+                    .get("recordId")
+                    .and_then(|id| id.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                warn!(
+                    "Duplicate key {} = '{}' found in record {}",
+                    field, value, record_id
+                );
+                Err(DuplicateKeyError {
+                    field: field.to_string(),
+                    value,
+                    record_id,
+                }
+                .into())
+            }
+            None => {
+                error!("Failed to check uniqueness: {:?}", response);
+                Err(Self::response_error(
+                    &response,
+                    "Failed to check uniqueness",
+                ))
             }
-        } else {
-            error!("Failed to add the record: {:?}", response);
-            Ok(HashMap::from([
-                ("success".to_string(), Value::Bool(false)),
-                ("result".to_string(), response),
-            ]))
         }
     }
 
+    /// Adds a record to the database after verifying that its key field value is unique.
+    ///
+    /// See [`Filemaker::assert_unique`] for how uniqueness is checked.
+    ///
+    /// # Arguments
+    /// * `field_data` - A `HashMap` representing the field data for the new record
+    /// * `key_field` - The field whose value must not exist yet
+    ///
+    /// # Returns
+    /// * `Result<HashMap<String, Value>>` - The same result as [`Filemaker::add_record`], or a [`DuplicateKeyError`]
+    pub async fn add_unique_record(
+        &self,
+        field_data: HashMap<String, Value>,
+        key_field: &str,
+    ) -> Result<HashMap<String, Value>> {
+        let key_value = match field_data.get(key_field) {
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+            None => {
+                error!("Key field {} is missing from the record", key_field);
+                return Err(anyhow::anyhow!(
+                    "Key field {} is missing from the record",
+                    key_field
+                ));
+            }
+        };
+        self.assert_unique(key_field, key_value).await?;
+        self.add_record(field_data).await
+    }
+
     /// Updates a record in the database using the FileMaker Data API.
     ///
     /// # Arguments
@@ -644,31 +1443,96 @@ impl Filemaker {
     /// * `T` - A type that can be used as a record identifier and meets various trait requirements
     pub async fn update_record<T>(&self, id: T, field_data: HashMap<String, Value>) -> Result<Value>
     where
-        T: Sized + Clone + std::fmt::Display + std::str::FromStr + TryFrom<usize>,
+        T: std::fmt::Display,
     {
-        // Construct the API endpoint URL for updating a specific record
+        self.update_record_with(id, field_data, &WriteOptions::default())
+            .await
+    }
+
+    /// Updates a record and returns its new modification ID.
+    ///
+    /// With `mod_id`, the server rejects the write with error 306 ([`ErrorKind::Locked`]) if
+    /// the record was modified since that ID was read. The returned ID can guard the next
+    /// update of the record without reading it again. Errors reported by the server are
+    /// returned as errors.
+    ///
+    /// ```rust,ignore
+    /// let mod_id = filemaker.update_record_guarded(&id, status("sent"), Some(&mod_id)).await?;
+    /// let mod_id = filemaker.update_record_guarded(&id, status("paid"), Some(&mod_id)).await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the record to update
+    /// * `field_data` - The field names and their new values
+    /// * `mod_id` - The modification ID the record must still have, if any
+    ///
+    /// # Returns
+    /// * `Result<String>` - The record's new modification ID, or an error
+    pub async fn update_record_guarded(
+        &self,
+        id: impl std::fmt::Display,
+        field_data: HashMap<String, Value>,
+        mod_id: Option<&str>,
+    ) -> Result<String> {
+        let response = self
+            .patch_record(&id.to_string(), json!(field_data), mod_id)
+            .await?;
+        if let Some(api_error) = Self::api_error(&response) {
+            error!("Failed to update record ID {}: {}", id, api_error);
+            return Err(anyhow::Error::new(api_error).context("Failed to update record"));
+        }
+        let new_mod_id = response
+            .get("response")
+            .and_then(|r| r.get("modId"))
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Update response for record {} has no modId", id))?;
+        info!("Record ID: {} updated to modId {}", id, new_mod_id);
+        Ok(new_mod_id.to_string())
+    }
+
+    /// Sends `field_data` as an update of record `id`, guarded by `mod_id` if given.
+    ///
+    /// With a modification ID the server rejects the write with error 306 if the record was
+    /// modified since that ID was read. The raw response is returned so callers can tell
+    /// conflicts apart from other failures. The `before_update` hooks run first.
+    pub(crate) async fn patch_record(
+        &self,
+        id: &str,
+        field_data: Value,
+        mod_id: Option<&str>,
+    ) -> Result<Value> {
+        self.patch_record_with(id, field_data, mod_id, None).await
+    }
+
+    /// Sends `field_data` and, if given, `portal_data` as an update of record `id`, guarded
+    /// by `mod_id` if given. See [`Filemaker::patch_record`].
+    pub(crate) async fn patch_record_with(
+        &self,
+        id: &str,
+        mut field_data: Value,
+        mod_id: Option<&str>,
+        portal_data: Option<Value>,
+    ) -> Result<Value> {
+        if let Some(fields) = field_data.as_object_mut() {
+            self.before_update(id, fields).await?;
+        }
         let url = format!(
             "{}/databases/{}/layouts/{}/records/{}",
-            Self::get_fm_url()?,
+            self.base_url()?,
             self.database,
             self.table,
             id
         );
-
-        // Convert the field data hashmap to the format expected by FileMaker Data API
-        let field_data_map: serde_json::Map<String, Value> = field_data.into_iter().collect();
-        // Create the request body with fieldData property
-        let body = HashMap::from([("fieldData".to_string(), Value::Object(field_data_map))]);
-
-        debug!("Updating record ID: {}. URL: {}. Body: {:?}", id, url, body);
-
-        // Send the PATCH request to update the record
-        let response = self
-            .authenticated_request(&url, Method::PATCH, Some(serde_json::to_value(body)?))
-            .await?;
-
-        info!("Record ID: {} updated successfully", id);
-        Ok(response)
+        let mut body = json!({ "fieldData": field_data });
+        if let Some(mod_id) = mod_id {
+            body["modId"] = Value::String(mod_id.to_string());
+        }
+        if let Some(portal_data) = portal_data {
+            body["portalData"] = portal_data;
+        }
+        debug!("Patching record ID: {} with modId {:?}", id, mod_id);
+        self.authenticated_request(&url, Method::PATCH, Some(body))
+            .await
     }
 
     /// Retrieves the list of databases accessible to the specified user.
@@ -733,7 +1597,10 @@ impl Filemaker {
         } else {
             // Handle case where response doesn't contain expected data structure
             error!("Failed to retrieve databases from response: {:?}", response);
-            Err(anyhow::anyhow!("Failed to retrieve databases"))
+            Err(Self::response_error(
+                &response,
+                "Failed to retrieve databases",
+            ))
         }
     }
 
@@ -752,7 +1619,7 @@ impl Filemaker {
         database: &str,
     ) -> Result<Vec<String>> {
         // URL encode the database name and construct the API endpoint URL
-        let encoded_database = utf8_percent_encode(database, NON_ALPHANUMERIC).to_string();
+        let encoded_database = query::encode_url_component(database);
         let url = format!(
             "{}/databases/{}/layouts",
             Self::get_fm_url()?,
@@ -763,12 +1630,18 @@ impl Filemaker {
 
         // Create HTTP client and get session token for authentication
         let client = Client::new();
-        let token = Self::get_session_token(&client, database, username, password)
-            .await
-            .map_err(|e| {
-                error!("Failed to get session token for layouts: {}", e);
-                anyhow::anyhow!(e)
-            })?;
+        let token = Self::get_session_token(
+            &client,
+            database,
+            username,
+            password,
+            &SessionOptions::default(),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to get session token for layouts: {}", e);
+            anyhow::anyhow!(e)
+        })?;
 
         // Create Bearer auth header from the session token
         let auth_header = format!("Bearer {}", token);
@@ -813,7 +1686,10 @@ impl Filemaker {
         } else {
             // Handle case where response doesn't contain expected data structure
             error!("Failed to retrieve layouts from response: {:?}", response);
-            Err(anyhow::anyhow!("Failed to retrieve layouts"))
+            Err(Self::response_error(
+                &response,
+                "Failed to retrieve layouts",
+            ))
         }
     }
 
@@ -826,11 +1702,11 @@ impl Filemaker {
     /// A JSON object representing the record.
     pub async fn get_record_by_id<T>(&self, id: T) -> Result<Value>
     where
-        T: Sized + Clone + std::fmt::Display + std::str::FromStr + TryFrom<usize>,
+        T: std::fmt::Display,
     {
         let url = format!(
             "{}/databases/{}/layouts/{}/records/{}",
-            Self::get_fm_url()?,
+            self.base_url()?,
             self.database,
             self.table,
             id
@@ -856,12 +1732,76 @@ impl Filemaker {
             }
         } else {
             error!("Failed to get record from response: {:?}", response);
-            Err(anyhow::anyhow!("Failed to get record"))
+            Err(Self::response_error(&response, "Failed to get record"))
+        }
+    }
+
+    /// Gets a record from the database by its ID only if it changed since it was last seen.
+    ///
+    /// The record is fetched without portal data and its `modId` compared with `known_mod_id`,
+    /// so cache layers can validate entries with a single lightweight request.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the record to check.
+    /// * `known_mod_id` - The modification ID of the cached copy of the record.
+    ///
+    /// # Returns
+    /// * `Result<Option<Value>>` - `None` if the record is unchanged, otherwise the current record, or an error
+    pub async fn get_record_if_modified<T>(
+        &self,
+        id: T,
+        known_mod_id: &str,
+    ) -> Result<Option<Value>>
+    where
+        T: std::fmt::Display,
+    {
+        let url = format!(
+            "{}/databases/{}/layouts/{}/records/{}?portal=%5B%5D",
+            self.base_url()?,
+            self.database,
+            self.table,
+            id
+        );
+
+        debug!(
+            "Checking record ID: {} against modId {} from URL: {}",
+            id, known_mod_id, url
+        );
+
+        let response = self
+            .authenticated_request(&url, Method::GET, None)
+            .await
+            .map_err(|e| {
+                error!("Failed to get record ID {}: {}", id, e);
+                anyhow::anyhow!(e)
+            })?;
+
+        let Some(record) = response
+            .get("response")
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.as_array())
+            .and_then(|arr| arr.first())
+        else {
+            error!("Failed to get record from response: {:?}", response);
+            return Err(Self::response_error(&response, "Failed to get record"));
+        };
+
+        if record.get("modId").and_then(|m| m.as_str()) == Some(known_mod_id) {
+            debug!("Record ID {} is unchanged", id);
+            Ok(None)
+        } else {
+            info!("Record ID {} was modified", id);
+            Ok(Some(record.clone()))
         }
     }
 
     /// Deletes a record from the database by its ID.
     ///
+    /// Errors reported by the server are returned with their [`ErrorKind`]: a missing record
+    /// is [`ErrorKind::NotFound`], a record in use [`ErrorKind::Locked`] and missing delete
+    /// privileges [`ErrorKind::Auth`]. Use [`Filemaker::delete_record_with`] to treat a
+    /// missing record as deleted.
+    ///
     /// # Arguments
     /// * `id` - The ID of the record to delete.
     ///
@@ -869,11 +1809,21 @@ impl Filemaker {
     /// A result indicating the deletion was successful or an error message.
     pub async fn delete_record<T>(&self, id: T) -> Result<Value>
     where
-        T: Sized + Clone + std::fmt::Display + std::str::FromStr + TryFrom<usize>,
+        T: std::fmt::Display,
     {
+        self.delete_record_with(id, &DeleteOptions::default())
+            .await?;
+        Ok(json!({"success": true}))
+    }
+
+    /// Sends the delete request for a record and returns the raw Data API response.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the record to delete.
+    pub(crate) async fn delete_record_response(&self, id: &str) -> Result<Value> {
         let url = format!(
             "{}/databases/{}/layouts/{}/records/{}",
-            Self::get_fm_url()?,
+            self.base_url()?,
             self.database,
             self.table,
             id
@@ -888,14 +1838,10 @@ impl Filemaker {
                 error!("Failed to delete record ID {}: {}", id, e);
                 anyhow::anyhow!(e)
             })?;
-
-        if response.is_object() {
-            info!("Record ID {} deleted successfully", id);
-            Ok(json!({"success": true}))
-        } else {
-            error!("Failed to delete record ID {}", id);
-            Err(anyhow::anyhow!("Failed to delete record"))
+        if Self::api_error(&response).is_none() {
+            self.after_delete(id).await;
         }
+        Ok(response)
     }
 
     /// Deletes the specified database.
@@ -904,19 +1850,32 @@ impl Filemaker {
     /// * `database` - The name of the database to delete.
     /// * `username` - The username for authentication.
     /// * `password` - The password for authentication.
-    pub async fn delete_database(database: &str, username: &str, password: &str) -> Result<()> {
-        let encoded_database = utf8_percent_encode(database, NON_ALPHANUMERIC).to_string();
+    /// * `danger` - Confirmation naming `database`; see [`Danger`].
+    pub async fn delete_database(
+        database: &str,
+        username: &str,
+        password: &str,
+        danger: &Danger,
+    ) -> Result<()> {
+        danger.check(database)?;
+        let encoded_database = query::encode_url_component(database);
         let url = format!("{}/databases/{}", Self::get_fm_url()?, encoded_database);
 
         debug!("Deleting database: {}", database);
 
         let client = Client::new();
-        let token = Self::get_session_token(&client, database, username, password)
-            .await
-            .map_err(|e| {
-                error!("Failed to get session token for database deletion: {}", e);
-                anyhow::anyhow!(e)
-            })?;
+        let token = Self::get_session_token(
+            &client,
+            database,
+            username,
+            password,
+            &SessionOptions::default(),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to get session token for database deletion: {}", e);
+            anyhow::anyhow!(e)
+        })?;
         let auth_header = format!("Bearer {}", token);
 
         client
@@ -936,60 +1895,54 @@ impl Filemaker {
 
     /// Deletes all records from the current database.
     ///
-    /// This function retrieves and systematically removes all records from the database.
-    /// It first checks if there are any records to delete, then proceeds with deletion
-    /// if records exist.
+    /// This function lists the IDs of all records (see [`Filemaker::get_record_ids`]) and
+    /// systematically removes them from the database. It first checks if there are any
+    /// records to delete, then proceeds with deletion if records exist.
+    ///
+    /// # Arguments
+    /// * `danger` - Confirmation naming this client's database; see [`Danger`]
     ///
     /// # Returns
     /// * `Result<()>` - Ok(()) if all records were successfully deleted, or an error
     ///
     /// # Errors
-    /// * Returns error if unable to retrieve records
+    /// * Returns error if unable to retrieve record IDs
     /// * Returns error if record ID parsing fails
     /// * Returns error if record deletion fails
-    pub async fn clear_database(&self) -> Result<()> {
+    /// * Returns error if `danger` does not confirm this database or destructive operations are disabled
+    ///
+    /// See [`Filemaker::clear_database_with`] for a variant that verifies the layout is empty
+    /// afterwards and can pause between deletions.
+    pub async fn clear_database(&self, danger: &Danger) -> Result<()> {
+        danger.check(&self.database())?;
         debug!("Clearing all records from the database");
-        // Get the total count of records in the database
-        let number_of_records = self.get_number_of_records().await?;
+        // Retrieve the ids of all records that need to be deleted
+        let ids = self.get_record_ids(None).await.map_err(|e| {
+            error!("Failed to retrieve records for clearing database: {}", e);
+            anyhow::anyhow!(e)
+        })?;
 
         // Check if there are any records to delete
-        if number_of_records == 0 {
+        if ids.is_empty() {
             warn!("No records found in the database. Nothing to clear");
             return Ok(());
         }
 
-        // Retrieve all records that need to be deleted
-        // The number_of_records value is used as limit to fetch all records at once
-        let records = self.get_records(1, number_of_records).await.map_err(|e| {
-            error!("Failed to retrieve records for clearing database: {}", e);
-            anyhow::anyhow!(e)
-        })?;
-
         // Iterate through each record and delete it individually
-        for record in records {
-            // Extract the record ID from the record data
-            if let Some(id) = record.get("recordId").and_then(|id| id.as_str()) {
-                // The record ID is usually marked as a string even though it's a u64,
-                // so we need to parse it to the correct type
-                if let Ok(id) = id.parse::<u64>() {
-                    debug!("Deleting record ID: {}", id);
-                    // Attempt to delete the record and handle any errors
-                    if let Err(e) = self.delete_record(id).await {
-                        error!("Failed to delete record ID {}: {}", id, e);
-                        return Err(anyhow::anyhow!(e));
-                    }
-                } else {
-                    // Handle case where ID exists but cannot be parsed as u64
-                    error!("Failed to parse record ID {} as u64", id);
-                    return Err(anyhow::anyhow!("Failed to parse record ID as u64"));
+        for id in ids {
+            // The record ID is usually marked as a string even though it's a u64,
+            // so we need to parse it to the correct type
+            if let Ok(id) = id.record_id.parse::<u64>() {
+                debug!("Deleting record ID: {}", id);
+                // Attempt to delete the record and handle any errors
+                if let Err(e) = self.delete_record(id).await {
+                    error!("Failed to delete record ID {}: {}", id, e);
+                    return Err(anyhow::anyhow!(e));
                 }
             } else {
-                // Handle case where record doesn't contain an ID field
-                error!("Record ID not found in record: {:?}", record);
-                return Err(anyhow::anyhow!(
-                    "Record ID not found in record: {:?}",
-                    record
-                ));
+                // Handle case where ID exists but cannot be parsed as u64
+                error!("Failed to parse record ID {} as u64", id.record_id);
+                return Err(anyhow::anyhow!("Failed to parse record ID as u64"));
             }
         }
 
@@ -1004,10 +1957,22 @@ impl Filemaker {
     /// # Returns
     /// An array of field names.
     pub fn get_row_names_by_example(record: &Value) -> Vec<String> {
+        Self::get_row_names_by_example_with(record, &FieldFilter::default())
+    }
+
+    /// Returns the names of fields in the given record that `filter` does not exclude.
+    ///
+    /// # Arguments
+    /// * `record` - An example record with 'fieldData' element containing field names as keys.
+    /// * `filter` - The rules for excluding fields
+    ///
+    /// # Returns
+    /// An array of field names.
+    pub fn get_row_names_by_example_with(record: &Value, filter: &FieldFilter) -> Vec<String> {
         let mut fields = Vec::new();
         if let Some(field_data) = record.get("fieldData").and_then(|fd| fd.as_object()) {
             for field in field_data.keys() {
-                if !field.starts_with("g_") {
+                if !filter.excludes(field) {
                     fields.push(field.clone());
                 }
             }
@@ -1019,7 +1984,9 @@ impl Filemaker {
     /// Gets the field names for the first record in the database.
     ///
     /// This function retrieves a single record from the database and extracts
-    /// field names from it. If no records exist, an empty vector is returned.
+    /// field names from it, excluding the fields of [`Filemaker::with_field_filter`] (by
+    /// default those starting with `g_`). On empty tables the server reports that no
+    /// records match; use [`Filemaker::get_row_names_from_metadata`] there.
     ///
     /// # Returns
     /// * `Result<Vec<String>>` - A vector of field names on success, or an error
@@ -1032,7 +1999,10 @@ impl Filemaker {
         if let Some(first_record) = records.first() {
             info!("Successfully fetched field names for the first record");
             // Extract field names from the first record using the helper method
-            return Ok(Self::get_row_names_by_example(first_record));
+            return Ok(match self.field_filter() {
+                Some(filter) => Self::get_row_names_by_example_with(first_record, filter),
+                None => Self::get_row_names_by_example(first_record),
+            });
         }
 
         // Handle the case where no records exist in the database
@@ -1057,7 +2027,7 @@ impl Filemaker {
     ) -> Result<Vec<Value>> {
         let url = format!(
             "{}/databases/{}/layouts/{}/_find",
-            Self::get_fm_url()?,
+            self.base_url()?,
             self.database,
             self.table
         );
@@ -1074,15 +2044,7 @@ impl Filemaker {
         );
 
         if !sort.is_empty() {
-            let sort_array: Vec<Value> = sort
-                .into_iter()
-                .map(|s| {
-                    json!({
-                        "fieldName": s,
-                        "sortOrder": if ascending { "ascend" } else { "descend" }
-                    })
-                })
-                .collect();
+            let sort_array = options::sort_spec(&sort, ascending, self.sort_tiebreaker());
             content.insert("sort".to_string(), Value::Array(sort_array));
         }
 
@@ -1107,8 +2069,9 @@ impl Filemaker {
             Ok(data.clone())
         } else {
             error!("Failed to retrieve advanced search results: {:?}", response);
-            Err(anyhow::anyhow!(
-                "Failed to retrieve advanced search results"
+            Err(Self::response_error(
+                &response,
+                "Failed to retrieve advanced search results",
             ))
         }
     }
@@ -0,0 +1,228 @@
+//! Layout metadata.
+
+use crate::{error, FieldFilter, Filemaker};
+use anyhow::Result;
+use log::*;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// The metadata of a layout, as returned by [`Filemaker::get_layout_metadata`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LayoutMetadata {
+    /// The fields placed directly on the layout, in layout order.
+    #[serde(default, rename = "fieldMetaData")]
+    pub fields: Vec<FieldMetadata>,
+    /// The fields of each portal on the layout, by portal object name (or related table
+    /// occurrence, for unnamed portals). Field names are qualified, e.g. `Lines::Amount`.
+    #[serde(default, rename = "portalMetaData")]
+    pub portals: HashMap<String, Vec<FieldMetadata>>,
+    /// The value lists used by the fields on the layout.
+    #[serde(default, rename = "valueLists")]
+    pub value_lists: Vec<ValueList>,
+}
+
+/// A field of a layout as described by the layout metadata endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FieldMetadata {
+    /// The field name as used in `fieldData`.
+    pub name: String,
+    /// The kind of field: `normal`, `calculation` or `summary`.
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    /// The data type of the field's values, e.g. `text`, `number`, `date` or `timeStamp`.
+    #[serde(default)]
+    pub result: String,
+    /// The number of repetitions the field has; 1 for a field without repetitions.
+    #[serde(default = "one", rename = "maxRepeat")]
+    pub repetitions: u32,
+    /// The value list attached to the field on the layout, if any.
+    #[serde(default, rename = "valueList", skip_serializing_if = "Option::is_none")]
+    pub value_list: Option<String>,
+    /// Whether the field uses global storage, sharing one value across all records.
+    #[serde(default)]
+    pub global: bool,
+    /// Whether the field must not be empty.
+    #[serde(default, rename = "notEmpty")]
+    pub not_empty: bool,
+    /// Whether the field is filled in automatically when a record is created.
+    #[serde(default, rename = "autoEnter")]
+    pub auto_enter: bool,
+    /// The maximum number of characters, or 0 if unlimited.
+    #[serde(default, rename = "maxCharacters")]
+    pub max_characters: u64,
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// A value list of a layout, with the values it offers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValueList {
+    /// The name of the value list.
+    pub name: String,
+    /// Where the values come from: `customList` or `byField`.
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    /// The values, in list order.
+    #[serde(default)]
+    pub values: Vec<ValueListItem>,
+}
+
+/// A value offered by a [`ValueList`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValueListItem {
+    /// The value stored in the field.
+    pub value: String,
+    /// The value shown to users, which differs from `value` for two-field value lists.
+    #[serde(default, rename = "displayValue")]
+    pub display_value: String,
+}
+
+impl FieldMetadata {
+    /// Returns the JSON Schema of the field's values in `fieldData`.
+    fn schema(&self) -> Value {
+        let mut schema = match self.result.as_str() {
+            // Empty number fields are returned as empty strings
+            "number" => json!({ "type": ["number", "string"], "maxLength": 0 }),
+            _ => json!({ "type": "string" }),
+        };
+        if self.max_characters > 0 && self.result == "text" {
+            schema["maxLength"] = json!(self.max_characters);
+        }
+        if matches!(self.kind.as_str(), "calculation" | "summary") || self.result == "container" {
+            schema["readOnly"] = json!(true);
+        }
+        schema["x-filemaker-result"] = json!(self.result);
+        schema
+    }
+}
+
+impl Filemaker {
+    /// Reads the layout metadata: the fields on the layout with their types and options, the
+    /// fields of its portals, and its value lists.
+    ///
+    /// Unlike guessing from the first record, this works on empty tables and tells number,
+    /// date and container fields apart, so records can be validated or code generated from it.
+    ///
+    /// # Returns
+    /// * `Result<LayoutMetadata>` - The layout metadata, or an error if it cannot be read
+    pub async fn get_layout_metadata(&self) -> Result<LayoutMetadata> {
+        let url = format!(
+            "{}/databases/{}/layouts/{}",
+            self.base_url()?,
+            self.database,
+            self.table
+        );
+        let response = self.authenticated_request(&url, Method::GET, None).await?;
+        let Some(metadata) = response
+            .get("response")
+            .filter(|r| r.get("fieldMetaData").is_some())
+        else {
+            error!(
+                "Failed to get layout metadata from response: {:?}",
+                response
+            );
+            return Err(Self::response_error(
+                &response,
+                "Failed to get layout metadata",
+            ));
+        };
+        let metadata: LayoutMetadata = error::deserialize_at(metadata, "response")?;
+        debug!(
+            "Layout {} has {} fields and {} portals",
+            self.layout(),
+            metadata.fields.len(),
+            metadata.portals.len()
+        );
+        Ok(metadata)
+    }
+
+    /// Fetches the fields placed on the layout, in layout order.
+    pub(crate) async fn layout_fields(&self) -> Result<Vec<FieldMetadata>> {
+        Ok(self.get_layout_metadata().await?.fields)
+    }
+
+    /// Returns the names of the fields on the layout, read from the layout metadata.
+    ///
+    /// Unlike [`Filemaker::get_row_names`], no record is needed, so this also works on empty
+    /// tables. Fields are returned in layout order, excluding the fields of
+    /// [`Filemaker::with_field_filter`] (by default those starting with `g_`).
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>>` - The field names, or an error if the metadata cannot be read
+    pub async fn get_row_names_from_metadata(&self) -> Result<Vec<String>> {
+        Ok(self
+            .get_field_types()
+            .await?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// Returns the names and result types of the fields on the layout, read from the layout
+    /// metadata.
+    ///
+    /// The result type is the data type of the field's values, e.g. `text`, `number`, `date`,
+    /// `time`, `timeStamp` or `container`. Fields are filtered like
+    /// [`Filemaker::get_row_names_from_metadata`].
+    ///
+    /// # Returns
+    /// * `Result<Vec<(String, String)>>` - The field names and result types in layout order, or an error
+    pub async fn get_field_types(&self) -> Result<Vec<(String, String)>> {
+        let default_filter = FieldFilter::default();
+        let filter = self.field_filter().unwrap_or(&default_filter);
+        let fields: Vec<(String, String)> = self
+            .layout_fields()
+            .await?
+            .into_iter()
+            .filter(|field| !filter.excludes(&field.name))
+            .map(|field| (field.name, field.result))
+            .collect();
+        info!("Read {} field names from layout metadata", fields.len());
+        Ok(fields)
+    }
+
+    /// Describes the layout's records as a JSON Schema document.
+    ///
+    /// The schema is generated from the layout metadata and describes a record as returned
+    /// by the Data API: `recordId`, `modId`, `fieldData` with one property per field on the
+    /// layout, and `portalData`. Number fields also accept the empty string FileMaker returns
+    /// for empty values, calculation, summary and container fields are marked `readOnly`,
+    /// and fields that must not be empty and are not auto-entered are `required`. Every field
+    /// carries its FileMaker result type as `x-filemaker-result`.
+    ///
+    /// # Returns
+    /// * `Result<Value>` - The JSON Schema (draft 2020-12) of the layout's records, or an error
+    pub async fn describe_layout(&self) -> Result<Value> {
+        let fields = self.layout_fields().await?;
+        let mut properties = Map::new();
+        let mut required = vec![];
+        for field in &fields {
+            properties.insert(field.name.clone(), field.schema());
+            if field.not_empty && !field.auto_enter {
+                required.push(field.name.clone());
+            }
+        }
+        Ok(json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": self.layout(),
+            "description": format!("A record of the {} layout in {}", self.layout(), self.database()),
+            "type": "object",
+            "properties": {
+                "recordId": { "type": "string" },
+                "modId": { "type": "string" },
+                "fieldData": {
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                    "additionalProperties": false,
+                },
+                "portalData": { "type": "object" },
+            },
+            "required": ["fieldData"],
+        }))
+    }
+}
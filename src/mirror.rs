@@ -0,0 +1,265 @@
+//! Incremental mirroring of layouts into a local SQLite database.
+//!
+//! [`Filemaker::mirror_to_sqlite`] keeps one SQLite table per layout in sync with the server,
+//! so the data can be queried with SQL without load on the FileMaker server. Each run only
+//! fetches the records whose modification ID changed since the previous run.
+//!
+//! Requires the `sqlite` feature.
+
+use crate::metadata::FieldMetadata;
+use crate::{Filemaker, RecordId};
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use log::*;
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{AssertSqlSafe, Connection, Row};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// The number of changed records above which the whole layout is paged through instead of
+/// fetching each changed record individually.
+const FETCH_BY_ID_LIMIT: usize = 100;
+
+/// The page size used when paging through the whole layout.
+const MIRROR_PAGE_SIZE: u64 = 500;
+
+/// Outcome of [`Filemaker::mirror_to_sqlite`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MirrorReport {
+    /// The name of the SQLite table the layout was mirrored into.
+    pub table: String,
+    /// The number of records added to the mirror.
+    pub inserted: usize,
+    /// The number of records whose mirrored copy was replaced.
+    pub updated: usize,
+    /// The number of records removed from the mirror because they no longer exist.
+    pub deleted: usize,
+    /// The number of records that were already up to date.
+    pub unchanged: usize,
+}
+
+impl Filemaker {
+    /// Mirrors the client's layout into a table of the SQLite database at `path`.
+    ///
+    /// The database is created if needed. The table is named after the layout and has a
+    /// `recordId` primary key, a `modId` column and one column per field from the layout
+    /// metadata: `REAL` for number fields and `TEXT` for all others, with empty values stored
+    /// as `NULL`. Fields added to the layout later are added as columns; removed fields keep
+    /// their column.
+    ///
+    /// Only records that are new or whose modification ID changed are fetched, and records
+    /// deleted on the server are deleted from the mirror. All changes of a run are written in
+    /// a single SQLite transaction. To mirror several layouts, call this on a client per
+    /// layout, e.g. via [`Filemaker::with_layout`].
+    ///
+    /// # Arguments
+    /// * `path` - The path of the SQLite database file
+    ///
+    /// # Returns
+    /// * `Result<MirrorReport>` - The number of inserted, updated and deleted rows, or an error
+    pub async fn mirror_to_sqlite(&self, path: impl AsRef<Path>) -> Result<MirrorReport> {
+        let path = path.as_ref();
+        let table = self.layout();
+        let fields = self.layout_fields().await?;
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let mut connection = SqliteConnection::connect_with(&options).await?;
+        ensure_table(&mut connection, &table, &fields).await?;
+
+        let local: HashMap<String, String> = sqlx::query(AssertSqlSafe(format!(
+            "SELECT \"recordId\", \"modId\" FROM {}",
+            quote(&table)
+        )))
+        .fetch_all(&mut connection)
+        .await?
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+        let remote = self.get_record_ids(None).await?;
+
+        let mut report = MirrorReport {
+            table: table.clone(),
+            ..Default::default()
+        };
+        let changed: HashSet<&str> = remote
+            .iter()
+            .filter(|RecordId { record_id, mod_id }| local.get(record_id) != Some(mod_id))
+            .map(|id| id.record_id.as_str())
+            .collect();
+        report.unchanged = remote.len() - changed.len();
+        let records = self.fetch_changed(&changed).await?;
+
+        let remote_ids: HashSet<&str> = remote.iter().map(|id| id.record_id.as_str()).collect();
+        let deleted: Vec<&String> = local
+            .keys()
+            .filter(|id| !remote_ids.contains(id.as_str()))
+            .collect();
+
+        let mut transaction = connection.begin().await?;
+        let upsert = upsert_statement(&table, &fields);
+        for record in &records {
+            let record_id = record["recordId"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Record has no recordId: {:?}", record))?;
+            let mut query = sqlx::query(AssertSqlSafe(upsert.clone()))
+                .bind(record_id)
+                .bind(record["modId"].as_str().unwrap_or_default());
+            for field in &fields {
+                let value = record.get("fieldData").and_then(|f| f.get(&field.name));
+                query = match is_number(field) {
+                    true => query.bind(number_value(value)),
+                    false => query.bind(text_value(value)),
+                };
+            }
+            query.execute(&mut *transaction).await?;
+            match local.contains_key(record_id) {
+                true => report.updated += 1,
+                false => report.inserted += 1,
+            }
+        }
+        let delete = format!("DELETE FROM {} WHERE \"recordId\" = ?", quote(&table));
+        for id in deleted {
+            sqlx::query(AssertSqlSafe(delete.clone()))
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+            report.deleted += 1;
+        }
+        transaction.commit().await?;
+
+        info!(
+            "Mirrored {} into {}: {} inserted, {} updated, {} deleted, {} unchanged",
+            table,
+            path.display(),
+            report.inserted,
+            report.updated,
+            report.deleted,
+            report.unchanged
+        );
+        Ok(report)
+    }
+
+    /// Fetches the records with the given IDs, one by one or by paging through the layout.
+    async fn fetch_changed(&self, changed: &HashSet<&str>) -> Result<Vec<Value>> {
+        if changed.is_empty() {
+            return Ok(vec![]);
+        }
+        if changed.len() > FETCH_BY_ID_LIMIT {
+            debug!(
+                "Paging through layout for {} changed records",
+                changed.len()
+            );
+            return self
+                .stream_records(MIRROR_PAGE_SIZE)
+                .try_filter(|record| {
+                    let id = record["recordId"].as_str().unwrap_or_default();
+                    futures::future::ready(changed.contains(id))
+                })
+                .try_collect()
+                .await;
+        }
+        let mut records = Vec::with_capacity(changed.len());
+        for id in changed {
+            records.push(self.get_record_by_id(id).await?);
+        }
+        Ok(records)
+    }
+}
+
+/// Creates the mirror table, or adds the columns of fields that are not mirrored yet.
+async fn ensure_table(
+    connection: &mut SqliteConnection,
+    table: &str,
+    fields: &[FieldMetadata],
+) -> Result<()> {
+    let columns: Vec<String> = fields
+        .iter()
+        .map(|field| format!("{} {}", quote(&field.name), column_type(field)))
+        .collect();
+    let create = format!(
+        "CREATE TABLE IF NOT EXISTS {} (\"recordId\" TEXT PRIMARY KEY, \"modId\" TEXT NOT NULL{}{})",
+        quote(table),
+        if columns.is_empty() { "" } else { ", " },
+        columns.join(", ")
+    );
+    sqlx::query(AssertSqlSafe(create))
+        .execute(&mut *connection)
+        .await?;
+
+    let existing: HashSet<String> = sqlx::query(AssertSqlSafe(format!(
+        "SELECT name FROM pragma_table_info({})",
+        quote_literal(table)
+    )))
+    .fetch_all(&mut *connection)
+    .await?
+    .into_iter()
+    .map(|row| row.get(0))
+    .collect();
+    for field in fields.iter().filter(|f| !existing.contains(&f.name)) {
+        debug!("Adding column {} to mirror table {}", field.name, table);
+        let alter = format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            quote(table),
+            quote(&field.name),
+            column_type(field)
+        );
+        sqlx::query(AssertSqlSafe(alter))
+            .execute(&mut *connection)
+            .await?;
+    }
+    Ok(())
+}
+
+fn upsert_statement(table: &str, fields: &[FieldMetadata]) -> String {
+    let columns: Vec<String> = ["recordId", "modId"]
+        .into_iter()
+        .chain(fields.iter().map(|f| f.name.as_str()))
+        .map(quote)
+        .collect();
+    format!(
+        "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+        quote(table),
+        columns.join(", "),
+        vec!["?"; columns.len()].join(", ")
+    )
+}
+
+fn is_number(field: &FieldMetadata) -> bool {
+    field.result == "number"
+}
+
+fn column_type(field: &FieldMetadata) -> &'static str {
+    match is_number(field) {
+        true => "REAL",
+        false => "TEXT",
+    }
+}
+
+fn number_value(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn text_value(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::Null => None,
+        Value::String(s) if s.is_empty() => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Quotes an SQL identifier.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Quotes an SQL string literal.
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,66 @@
+//! Exporting tables straight into object storage.
+
+use crate::export::export_format_of;
+use crate::{Filemaker, StreamOptions};
+use anyhow::Result;
+use log::*;
+use object_store::buffered::BufWriter;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use std::sync::Arc;
+
+impl Filemaker {
+    /// Exports every record of the table into the object at `location` of `store`.
+    ///
+    /// The object is uploaded in parts while records are streamed, so neither the table nor
+    /// the file is held in memory or written to local disk. The format and compression are
+    /// chosen from the extensions of `location`, as for [`Filemaker::export_to_file`]. The
+    /// object only appears once the export completed; a failed export aborts the upload.
+    ///
+    /// Enable the `aws`, `gcp` or `azure` feature of the re-exported `object_store` crate for
+    /// the store to write to:
+    ///
+    /// ```rust,ignore
+    /// use filemaker_lib::object_store::{aws::AmazonS3Builder, path::Path};
+    ///
+    /// let store = Arc::new(AmazonS3Builder::from_env().with_bucket_name("backups").build()?);
+    /// let path = Path::from("filemaker/people.jsonl");
+    /// let exported = filemaker.export_to_object_store(store, &path, StreamOptions::new(500)).await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `store` - The object store to write to
+    /// * `location` - The key of the object, with extensions naming the format and compression
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The number of exported records, or an error if the export or the upload fails
+    pub async fn export_to_object_store(
+        &self,
+        store: Arc<dyn ObjectStore>,
+        location: &Path,
+        options: StreamOptions,
+    ) -> Result<u64> {
+        let (format, compression) = export_format_of(location.as_ref())?;
+        let mut writer = BufWriter::new(store, location.clone());
+        // Shutting down the compressed writer completes the multipart upload
+        let result = self
+            .export_compressed(&mut writer, format, compression, options)
+            .await;
+        let exported = match result {
+            Ok(exported) => exported,
+            Err(e) => {
+                error!("Export to {} failed, aborting the upload: {}", location, e);
+                if let Err(abort_error) = writer.abort().await {
+                    warn!(
+                        "Failed to abort the upload to {}: {}",
+                        location, abort_error
+                    );
+                }
+                return Err(e);
+            }
+        };
+        info!("Uploaded {} records to {}", exported, location);
+        Ok(exported)
+    }
+}
@@ -0,0 +1,621 @@
+//! Options for record fetches, finds and writes.
+//!
+//! [`GetOptions`], [`FindOptions`] and [`WriteOptions`] collect the optional Data API parameters of a request,
+//! so new parameters can be supported by adding a field instead of changing method
+//! signatures.
+
+use crate::error::deserialize_at;
+use crate::{query, EmptyFind, Filemaker, PortalRow, Record, NO_RECORDS_MATCH_CODE};
+use anyhow::{anyhow, Result};
+use log::*;
+use reqwest::Method;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+/// FileMaker error returned when a record does not exist.
+const RECORD_MISSING_CODE: u32 = 101;
+
+/// A script run as part of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptCall {
+    /// The name of the script.
+    pub name: String,
+    /// The script parameter.
+    pub param: Option<String>,
+}
+
+impl ScriptCall {
+    /// Runs `name` without a parameter.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            param: None,
+        }
+    }
+
+    /// Passes `param` to the script.
+    pub fn with_param(mut self, param: impl Into<String>) -> Self {
+        self.param = Some(param.into());
+        self
+    }
+}
+
+/// The scripts run before and after a request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scripts {
+    /// Runs before the request is processed (`script.prerequest`).
+    pub pre_request: Option<ScriptCall>,
+    /// Runs after records are found but before they are sorted (`script.presort`).
+    pub pre_sort: Option<ScriptCall>,
+    /// Runs after the request, before the result is returned (`script`).
+    pub after: Option<ScriptCall>,
+}
+
+impl Scripts {
+    /// Returns the Data API parameter names and values of the configured scripts.
+    fn parameters(&self) -> Vec<(String, String)> {
+        let mut parameters = vec![];
+        for (prefix, call) in [
+            ("script.prerequest", &self.pre_request),
+            ("script.presort", &self.pre_sort),
+            ("script", &self.after),
+        ] {
+            if let Some(call) = call {
+                parameters.push((prefix.to_string(), call.name.clone()));
+                if let Some(param) = &call.param {
+                    parameters.push((format!("{}.param", prefix), param.clone()));
+                }
+            }
+        }
+        parameters
+    }
+}
+
+/// A portal to include in the response, with optional paging of its related records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Portal {
+    /// The object name of the portal on the layout.
+    pub name: String,
+    /// The 1-based position of the first related record returned.
+    pub offset: Option<u64>,
+    /// The maximum number of related records returned.
+    pub limit: Option<u64>,
+}
+
+impl Portal {
+    /// Includes the portal named `name` with the server's default paging.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            offset: None,
+            limit: None,
+        }
+    }
+
+    /// Returns related records starting at `offset`, at most `limit` of them.
+    pub fn with_range(mut self, offset: u64, limit: u64) -> Self {
+        self.offset = Some(offset);
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// How dates in find criteria and responses are formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `MM/DD/YYYY` (`dateformats` 0), the Data API default.
+    Us,
+    /// The format of the file's locale (`dateformats` 1).
+    FileLocale,
+    /// `YYYY-MM-DD` (`dateformats` 2).
+    Iso8601,
+}
+
+impl DateFormat {
+    fn code(self) -> u8 {
+        match self {
+            DateFormat::Us => 0,
+            DateFormat::FileLocale => 1,
+            DateFormat::Iso8601 => 2,
+        }
+    }
+}
+
+/// Optional parameters of [`Filemaker::get_records_with`] and [`Filemaker::get_record_by_id_with`].
+#[derive(Debug, Clone)]
+pub struct GetOptions {
+    /// The 1-based position of the first record; ignored for single records.
+    pub offset: Option<u64>,
+    /// The maximum number of records; ignored for single records.
+    pub limit: Option<u64>,
+    /// Field names to sort by; ignored for single records.
+    pub sort: Vec<String>,
+    /// Whether to sort in ascending (true) or descending (false) order.
+    pub ascending: bool,
+    /// Portals to include; `None` includes all portals on the layout, an empty list none.
+    pub portals: Option<Vec<Portal>>,
+    /// Scripts to run with the request.
+    pub scripts: Scripts,
+    /// The layout whose fields are returned, if different from the request layout.
+    pub response_layout: Option<String>,
+    /// The maximum time the request may take.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for GetOptions {
+    fn default() -> Self {
+        Self {
+            offset: None,
+            limit: None,
+            sort: vec![],
+            ascending: true,
+            portals: None,
+            scripts: Scripts::default(),
+            response_layout: None,
+            timeout: None,
+        }
+    }
+}
+
+impl GetOptions {
+    /// Returns the URL query parameters, starting with `?`, or an empty string.
+    fn query_string(&self, single_record: bool, tiebreaker: Option<&str>) -> String {
+        let mut parameters: Vec<(String, String)> = vec![];
+        if !single_record {
+            if let Some(offset) = self.offset {
+                parameters.push(("_offset".to_string(), offset.to_string()));
+            }
+            if let Some(limit) = self.limit {
+                parameters.push(("_limit".to_string(), limit.to_string()));
+            }
+            if !self.sort.is_empty() {
+                let sort =
+                    Value::Array(sort_spec(&self.sort, self.ascending, tiebreaker)).to_string();
+                parameters.push(("_sort".to_string(), sort));
+            }
+        }
+        if let Some(portals) = &self.portals {
+            let names: Vec<&str> = portals.iter().map(|p| p.name.as_str()).collect();
+            parameters.push(("portal".to_string(), json!(names).to_string()));
+            for portal in portals {
+                if let Some(offset) = portal.offset {
+                    parameters.push((format!("_offset.{}", portal.name), offset.to_string()));
+                }
+                if let Some(limit) = portal.limit {
+                    parameters.push((format!("_limit.{}", portal.name), limit.to_string()));
+                }
+            }
+        }
+        parameters.extend(self.scripts.parameters());
+        if let Some(layout) = &self.response_layout {
+            parameters.push(("layout.response".to_string(), layout.clone()));
+        }
+
+        if parameters.is_empty() {
+            return String::new();
+        }
+        let pairs: Vec<String> = parameters
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}={}",
+                    query::encode_url_component(name),
+                    query::encode_url_component(value)
+                )
+            })
+            .collect();
+        format!("?{}", pairs.join("&"))
+    }
+}
+
+/// Optional parameters of [`Filemaker::search_with`].
+#[derive(Debug, Clone)]
+pub struct FindOptions {
+    /// The 1-based position of the first record of the found set to return.
+    pub offset: Option<u64>,
+    /// The maximum number of records; `None` returns all matches.
+    pub limit: Option<u64>,
+    /// Field names to sort by.
+    pub sort: Vec<String>,
+    /// Whether to sort in ascending (true) or descending (false) order.
+    pub ascending: bool,
+    /// Portals to include; `None` includes all portals on the layout, an empty list none.
+    pub portals: Option<Vec<Portal>>,
+    /// Scripts to run with the request.
+    pub scripts: Scripts,
+    /// The layout whose fields are returned, if different from the request layout.
+    pub response_layout: Option<String>,
+    /// How dates in the criteria and the response are formatted.
+    pub date_format: Option<DateFormat>,
+    /// The maximum time the request may take.
+    pub timeout: Option<Duration>,
+    /// What to return when no records match.
+    pub on_empty: EmptyFind,
+    /// Whether to remove records returned more than once, see [`crate::FindResult::dedupe_by_record_id`].
+    pub dedupe: bool,
+}
+
+impl Default for FindOptions {
+    fn default() -> Self {
+        Self {
+            offset: None,
+            limit: None,
+            sort: vec![],
+            ascending: true,
+            portals: None,
+            scripts: Scripts::default(),
+            response_layout: None,
+            date_format: None,
+            timeout: None,
+            on_empty: EmptyFind::default(),
+            dedupe: false,
+        }
+    }
+}
+
+impl FindOptions {
+    /// Builds the body of a find request for `query`.
+    pub(crate) fn body(
+        &self,
+        query: &[HashMap<String, String>],
+        tiebreaker: Option<&str>,
+    ) -> Value {
+        let mut body = Map::new();
+        body.insert("query".to_string(), json!(query));
+        if !self.sort.is_empty() {
+            body.insert(
+                "sort".to_string(),
+                Value::Array(sort_spec(&self.sort, self.ascending, tiebreaker)),
+            );
+        }
+        if let Some(offset) = self.offset {
+            body.insert("offset".to_string(), json!(offset));
+        }
+        body.insert(
+            "limit".to_string(),
+            json!(self.limit.unwrap_or(u32::MAX as u64)),
+        );
+        if let Some(portals) = &self.portals {
+            let names: Vec<&str> = portals.iter().map(|p| p.name.as_str()).collect();
+            body.insert("portal".to_string(), json!(names));
+            for portal in portals {
+                if let Some(offset) = portal.offset {
+                    body.insert(format!("offset.{}", portal.name), json!(offset));
+                }
+                if let Some(limit) = portal.limit {
+                    body.insert(format!("limit.{}", portal.name), json!(limit));
+                }
+            }
+        }
+        for (name, value) in self.scripts.parameters() {
+            body.insert(name, Value::String(value));
+        }
+        if let Some(layout) = &self.response_layout {
+            body.insert("layout.response".to_string(), json!(layout));
+        }
+        if let Some(format) = self.date_format {
+            body.insert("dateformats".to_string(), json!(format.code()));
+        }
+        Value::Object(body)
+    }
+}
+
+/// Optional behavior of [`Filemaker::delete_record_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeleteOptions {
+    /// Whether a record that no longer exists counts as deleted instead of failing.
+    pub already_absent_ok: bool,
+}
+
+/// Optional parameters of [`Filemaker::add_record_with`] and [`Filemaker::update_record_with`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteOptions {
+    /// Rows written to related records through the portals of the layout, by portal object
+    /// name. Rows without a record ID create related records; rows with one edit that
+    /// related record.
+    pub portal_data: HashMap<String, Vec<PortalRow>>,
+}
+
+impl WriteOptions {
+    /// Adds `row` to the rows written through `portal`.
+    pub fn with_portal_row(mut self, portal: impl Into<String>, row: PortalRow) -> Self {
+        self.portal_data.entry(portal.into()).or_default().push(row);
+        self
+    }
+
+    /// Returns the `portalData` of the request body, if any rows are written.
+    fn portal_data_json(&self) -> Result<Option<Value>> {
+        match self.portal_data.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(serde_json::to_value(&self.portal_data)?)),
+        }
+    }
+}
+
+impl Filemaker {
+    /// Adds a record, writing related records through its portals in the same request.
+    ///
+    /// Failures reported by the server are returned as `Ok` with `success` set to `false`,
+    /// as with [`Filemaker::add_record`].
+    ///
+    /// # Arguments
+    /// * `field_data` - The field data of the new record
+    /// * `options` - The related records to create through the portals of the layout
+    ///
+    /// # Returns
+    /// * `Result<HashMap<String, Value>>` - `success` and the added record as `result`, or an error
+    pub async fn add_record_with(
+        &self,
+        field_data: HashMap<String, Value>,
+        options: &WriteOptions,
+    ) -> Result<HashMap<String, Value>> {
+        // Define the URL for the FileMaker Data API endpoint
+        let url = format!(
+            "{}/databases/{}/layouts/{}/records",
+            self.base_url()?,
+            self.database,
+            self.table
+        );
+
+        // Prepare the request body
+        let mut field_data_map: serde_json::Map<String, Value> = field_data.into_iter().collect();
+        self.before_create(&mut field_data_map).await?;
+        let mut body = json!({ "fieldData": field_data_map });
+        if let Some(portal_data) = options.portal_data_json()? {
+            body["portalData"] = portal_data;
+        }
+
+        debug!("Adding a new record. URL: {}. Body: {:?}", url, body);
+
+        // Make the API call
+        let response = self
+            .authenticated_request(&url, Method::POST, Some(body))
+            .await?;
+
+        if let Some(record_id) = response
+            .get("response")
+            .and_then(|r| r.get("recordId"))
+            .and_then(|id| id.as_str())
+        {
+            if let Ok(record_id) = record_id.parse::<u64>() {
+                debug!("Record added successfully. Record ID: {}", record_id);
+                self.after_create(&record_id.to_string()).await;
+                let added_record = self.get_record_by_id(record_id).await?;
+                Ok(HashMap::from([
+                    ("success".to_string(), Value::Bool(true)),
+                    ("result".to_string(), added_record),
+                ]))
+            } else {
+                error!("Failed to parse record id {} - {:?}", record_id, response);
+                Ok(HashMap::from([
+                    ("success".to_string(), Value::Bool(false)),
+                    ("result".to_string(), response),
+                ]))
+            }
+        } else {
+            error!("Failed to add the record: {:?}", response);
+            Ok(HashMap::from([
+                ("success".to_string(), Value::Bool(false)),
+                ("result".to_string(), response),
+            ]))
+        }
+    }
+
+    /// Updates a record, writing related records through its portals in the same request.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the record to update
+    /// * `field_data` - The field names and their new values
+    /// * `options` - The related records to create or edit through the portals of the layout
+    ///
+    /// # Returns
+    /// * `Result<Value>` - The server response as a JSON value or an error
+    pub async fn update_record_with(
+        &self,
+        id: impl std::fmt::Display,
+        field_data: HashMap<String, Value>,
+        options: &WriteOptions,
+    ) -> Result<Value> {
+        let field_data: Map<String, Value> = field_data.into_iter().collect();
+        debug!("Updating record ID: {}. Body: {:?}", id, field_data);
+        let response = self
+            .patch_record_with(
+                &id.to_string(),
+                Value::Object(field_data),
+                None,
+                options.portal_data_json()?,
+            )
+            .await?;
+        info!("Record ID: {} updated successfully", id);
+        Ok(response)
+    }
+
+    /// Deletes a record by its ID, checking the result the server reports.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the record to delete
+    /// * `options` - How to treat a record that no longer exists
+    ///
+    /// # Returns
+    /// * `Result<bool>` - `true` if the record was deleted, `false` if it was already absent and `already_absent_ok` is set, or the server's error
+    pub async fn delete_record_with(
+        &self,
+        id: impl std::fmt::Display,
+        options: &DeleteOptions,
+    ) -> Result<bool> {
+        let response = self.delete_record_response(&id.to_string()).await?;
+        match Self::api_error(&response) {
+            None => {
+                info!("Record ID {} deleted successfully", id);
+                Ok(true)
+            }
+            Some(api_error)
+                if options.already_absent_ok && api_error.code == RECORD_MISSING_CODE =>
+            {
+                debug!("Record ID {} was already absent", id);
+                Ok(false)
+            }
+            Some(api_error) => {
+                error!("Failed to delete record ID {}: {}", id, api_error);
+                Err(anyhow::Error::new(api_error).context("Failed to delete record"))
+            }
+        }
+    }
+
+    /// Retrieves records of the layout with the given options.
+    ///
+    /// # Arguments
+    /// * `options` - Paging, sorting, portals, scripts and other request parameters
+    ///
+    /// # Returns
+    /// * `Result<Vec<Value>>` - The raw records, or an error
+    pub async fn get_records_with(&self, options: &GetOptions) -> Result<Vec<Value>> {
+        let response = self.get_records_response(options).await?;
+        Self::records_in(&response)
+    }
+
+    /// Sends a record range request and returns the whole response.
+    pub(crate) async fn get_records_response(&self, options: &GetOptions) -> Result<Value> {
+        let url = format!(
+            "{}/databases/{}/layouts/{}/records{}",
+            self.base_url()?,
+            self.database,
+            self.table,
+            options.query_string(false, self.sort_tiebreaker())
+        );
+        debug!("Fetching records with options from URL: {}", url);
+        with_timeout(
+            options.timeout,
+            self.authenticated_request(&url, Method::GET, None),
+        )
+        .await
+    }
+
+    /// Returns the records of a record range response, or none if no records match.
+    pub(crate) fn records_in(response: &Value) -> Result<Vec<Value>> {
+        if Self::first_message_code(response) == Some(NO_RECORDS_MATCH_CODE) {
+            return Ok(vec![]);
+        }
+        match response
+            .get("response")
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.as_array())
+        {
+            Some(data) => Ok(data.clone()),
+            None => {
+                error!("Failed to retrieve records from response: {:?}", response);
+                Err(Self::response_error(response, "Failed to retrieve records"))
+            }
+        }
+    }
+
+    /// Retrieves a single record by its ID with the given options.
+    ///
+    /// Paging and sorting options are ignored.
+    ///
+    /// # Arguments
+    /// * `id` - The record ID
+    /// * `options` - Portals, scripts and other request parameters
+    ///
+    /// # Returns
+    /// * `Result<Value>` - The raw record, or an error
+    pub async fn get_record_by_id_with(
+        &self,
+        id: impl std::fmt::Display,
+        options: &GetOptions,
+    ) -> Result<Value> {
+        let url = format!(
+            "{}/databases/{}/layouts/{}/records/{}{}",
+            self.base_url()?,
+            self.database,
+            self.table,
+            id,
+            options.query_string(true, None)
+        );
+        let response = with_timeout(
+            options.timeout,
+            self.authenticated_request(&url, Method::GET, None),
+        )
+        .await?;
+        match response
+            .get("response")
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.as_array())
+            .and_then(|d| d.first())
+        {
+            Some(record) => Ok(record.clone()),
+            None => {
+                error!("Failed to get record {} from response: {:?}", id, response);
+                Err(Self::response_error(&response, "Failed to get record"))
+            }
+        }
+    }
+
+    /// Retrieves records of the layout with the given options, deserializing their field
+    /// data into `T` and keeping their portal rows.
+    ///
+    /// Use [`GetOptions::portals`] to choose the portals and page their related records;
+    /// read them with [`Record::portal`] or [`Record::portal_as`].
+    ///
+    /// # Arguments
+    /// * `options` - Paging, sorting, portals, scripts and other request parameters
+    ///
+    /// # Returns
+    /// * `Result<Vec<Record<T>>>` - The records, or an error naming the mismatched value
+    pub async fn get_records_as<T>(&self, options: &GetOptions) -> Result<Vec<Record<T>>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let records = self.get_records_with(options).await?;
+        Ok(deserialize_at(&Value::Array(records), "data")?)
+    }
+
+    /// Retrieves a single record by its ID with the given options, deserializing its field
+    /// data into `T` and keeping its portal rows.
+    ///
+    /// # Arguments
+    /// * `id` - The record ID
+    /// * `options` - Portals, scripts and other request parameters
+    ///
+    /// # Returns
+    /// * `Result<Record<T>>` - The record, or an error naming the mismatched value
+    pub async fn get_record_by_id_as<T>(
+        &self,
+        id: impl std::fmt::Display,
+        options: &GetOptions,
+    ) -> Result<Record<T>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let record = self.get_record_by_id_with(id, options).await?;
+        Ok(deserialize_at(&record, "")?)
+    }
+}
+
+/// Fails with a timeout error if `request` does not finish within `timeout`.
+pub(crate) async fn with_timeout<F>(timeout: Option<Duration>, request: F) -> Result<Value>
+where
+    F: Future<Output = Result<Value>>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, request)
+            .await
+            .map_err(|_| anyhow!("Request timed out after {:?}", timeout))?,
+        None => request.await,
+    }
+}
+
+/// Builds the sort specification of a request, ending with `tiebreaker` unless the sort is
+/// empty or already contains it.
+pub(crate) fn sort_spec(sort: &[String], ascending: bool, tiebreaker: Option<&str>) -> Vec<Value> {
+    let order = if ascending { "ascend" } else { "descend" };
+    let tiebreaker =
+        tiebreaker.filter(|field| !sort.is_empty() && !sort.iter().any(|sorted| sorted == field));
+    sort.iter()
+        .map(String::as_str)
+        .chain(tiebreaker)
+        .map(|field| json!({ "fieldName": field, "sortOrder": order }))
+        .collect()
+}
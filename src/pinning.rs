@@ -0,0 +1,218 @@
+//! Pinning the server certificate by its public key.
+//!
+//! A pin is the SHA-256 hash of a certificate's subject public key info (SPKI), written as
+//! `sha256/<base64>` like HTTP public key pins. Once pins are set with
+//! [`Filemaker::set_certificate_pins`], clients connect over rustls and only accept servers
+//! whose certificate holds one of the pinned keys, so a certificate issued by a compromised
+//! or intercepting CA is rejected. Requires the `rustls` feature.
+//!
+//! The pin of a certificate can be computed with OpenSSL:
+//!
+//! ```text
+//! openssl x509 -in server.pem -pubkey -noout | openssl pkey -pubin -outform der \
+//!     | openssl dgst -sha256 -binary | base64
+//! ```
+
+use crate::{ClientIdentity, Filemaker};
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use log::*;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+static PINS: RwLock<Vec<SpkiPin>> = RwLock::new(Vec::new());
+
+/// The SHA-256 hash of a certificate's subject public key info.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    /// Parses a pin written as `sha256/<base64>` or as the bare base64 hash.
+    ///
+    /// # Returns
+    /// * `Result<SpkiPin>` - The pin, or an error if it is not a base64 encoded SHA-256 hash
+    pub fn parse(pin: &str) -> Result<Self> {
+        let encoded = pin.trim();
+        let encoded = encoded.strip_prefix("sha256/").unwrap_or(encoded);
+        let hash = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| anyhow!("A certificate pin must be a base64 SHA-256 hash: {}", pin))?;
+        Ok(Self(hash))
+    }
+
+    /// Computes the pin of a DER encoded certificate.
+    ///
+    /// # Returns
+    /// * `Result<SpkiPin>` - The pin, or an error if `der` is not a certificate
+    pub fn from_certificate(der: &[u8]) -> Result<Self> {
+        let certificate = CertificateDer::from(der);
+        let certificate = webpki::EndEntityCert::try_from(&certificate)
+            .map_err(|e| anyhow!("Failed to parse certificate: {:?}", e))?;
+        let hash = Sha256::digest(certificate.subject_public_key_info().as_ref());
+        Ok(Self(hash.into()))
+    }
+
+    /// Computes the pin of the first certificate of a PEM file, e.g. the server certificate
+    /// shipped with a kiosk.
+    ///
+    /// # Returns
+    /// * `Result<SpkiPin>` - The pin, or an error if `pem` holds no certificate
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        let certificate = CertificateDer::from_pem_slice(pem.as_bytes())
+            .map_err(|e| anyhow!("Failed to read PEM certificate: {}", e))?;
+        Self::from_certificate(&certificate)
+    }
+}
+
+impl fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(self.0);
+        write!(f, "sha256/{}", encoded)
+    }
+}
+
+impl fmt::Debug for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpkiPin({})", self)
+    }
+}
+
+/// Accepts a server if its certificate holds a pinned key and it proves owning the key.
+///
+/// Certificate authorities, host names and validity periods are not consulted; the pin
+/// alone identifies the server, so self-signed certificates can be pinned as well.
+#[derive(Debug)]
+struct PinnedKeyVerifier {
+    pins: Vec<SpkiPin>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedKeyVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let pin = SpkiPin::from_certificate(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        if self.pins.contains(&pin) {
+            return Ok(ServerCertVerified::assertion());
+        }
+        warn!(
+            "Rejected the certificate of {}: its key {} is not pinned",
+            server_name.to_str(),
+            pin
+        );
+        Err(rustls::Error::InvalidCertificate(
+            CertificateError::ApplicationVerificationFailure,
+        ))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Returns the TLS configuration of a client accepting only `pins`, if there are any,
+/// presenting `identity` to servers that ask for a client certificate.
+pub(crate) fn pinned_tls_config(
+    pins: &[SpkiPin],
+    identity: Option<&ClientIdentity>,
+) -> Result<Option<ClientConfig>> {
+    if pins.is_empty() {
+        return Ok(None);
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinnedKeyVerifier {
+        pins: pins.to_vec(),
+        provider: provider.clone(),
+    };
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let config = match identity {
+        Some(identity) => {
+            let chain = CertificateDer::pem_slice_iter(identity.certificate_pem().as_bytes())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("Failed to read the client certificate: {}", e))?;
+            let key = PrivateKeyDer::from_pem_slice(identity.key_pem().as_bytes())
+                .map_err(|e| anyhow!("Failed to read the client key: {}", e))?;
+            builder.with_client_auth_cert(chain, key)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Some(config))
+}
+
+impl Filemaker {
+    /// Pins the keys the FileMaker server's certificate may hold.
+    ///
+    /// Applies to clients using `FM_URL` created afterwards, including their logins; existing
+    /// clients keep their connections. With pins set, a server is only trusted if its
+    /// certificate holds one of the pinned keys, whoever issued it. Pin the current and the
+    /// next key before rotating the server certificate. An empty list removes the pins.
+    /// Clients created with [`Filemaker::with_config`] use the pins of their
+    /// [`crate::TlsOptions`] instead.
+    ///
+    /// ```rust,ignore
+    /// Filemaker::set_certificate_pins([SpkiPin::parse(
+    ///     "sha256/jzntdlaoyc5NkkEBTOD9e/N8bW/rhzqpMbNr9ZixqLw=",
+    /// )?]);
+    /// let filemaker = Filemaker::new("user", "pass", "Kiosk", "Orders").await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `pins` - The accepted public key hashes
+    pub fn set_certificate_pins(pins: impl IntoIterator<Item = SpkiPin>) {
+        let pins: Vec<SpkiPin> = pins.into_iter().collect();
+        debug!("Pinning {} server certificate keys", pins.len());
+        *PINS.write().unwrap_or_else(|e| e.into_inner()) = pins;
+    }
+
+    /// Returns the pinned server certificate keys.
+    pub fn certificate_pins() -> Vec<SpkiPin> {
+        PINS.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
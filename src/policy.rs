@@ -0,0 +1,209 @@
+//! Restricting which layouts a client may read or write.
+
+use crate::error::PolicyError;
+use crate::{query, Filemaker};
+use log::*;
+use reqwest::Method;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// The kind of access a request needs to a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// Reading records or layout metadata.
+    Read,
+    /// Creating, updating or deleting records, or running scripts.
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// Which layouts a client may read and write.
+///
+/// A new policy permits everything. Adding layouts to an allowlist restricts that kind of
+/// access to the listed layouts; denied layouts are refused for both reading and writing,
+/// even if they are also allowed. Note that creating a record reads it back afterwards, so
+/// layouts that are written usually need read access as well; [`LayoutPolicy::allow`]
+/// grants both.
+///
+/// ```rust,ignore
+/// let policy = LayoutPolicy::new()
+///     .allow_read(["Customers", "Invoices"])
+///     .allow_write(["Invoices"])
+///     .deny(["Payroll"]);
+/// let filemaker = filemaker.with_layout_policy(policy);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutPolicy {
+    read: Option<HashSet<String>>,
+    write: Option<HashSet<String>>,
+    deny: HashSet<String>,
+}
+
+impl LayoutPolicy {
+    /// Creates a policy that permits all layouts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Permits reading the given layouts; once called, reading any other layout is refused.
+    pub fn allow_read<I, S>(mut self, layouts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.read
+            .get_or_insert_with(HashSet::new)
+            .extend(layouts.into_iter().map(Into::into));
+        self
+    }
+
+    /// Permits writing the given layouts; once called, writing any other layout is refused.
+    pub fn allow_write<I, S>(mut self, layouts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.write
+            .get_or_insert_with(HashSet::new)
+            .extend(layouts.into_iter().map(Into::into));
+        self
+    }
+
+    /// Permits reading and writing the given layouts.
+    pub fn allow<I, S>(self, layouts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let layouts: Vec<String> = layouts.into_iter().map(Into::into).collect();
+        self.allow_read(layouts.clone()).allow_write(layouts)
+    }
+
+    /// Refuses all access to the given layouts.
+    pub fn deny<I, S>(mut self, layouts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.deny.extend(layouts.into_iter().map(Into::into));
+        self
+    }
+
+    /// Refuses all writes, keeping the read rules of the policy.
+    pub fn read_only(mut self) -> Self {
+        self.write = Some(HashSet::new());
+        self
+    }
+
+    /// Narrows the policy to the given layouts, keeping any stricter rules already set.
+    ///
+    /// Unlike [`LayoutPolicy::allow`], which adds to the allowlists, this never permits a
+    /// layout the policy refused before.
+    pub fn restrict_to<I, S>(mut self, layouts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let layouts: HashSet<String> = layouts.into_iter().map(Into::into).collect();
+        for allowed in [&mut self.read, &mut self.write] {
+            *allowed = Some(match allowed.take() {
+                Some(existing) => existing.intersection(&layouts).cloned().collect(),
+                None => layouts.clone(),
+            });
+        }
+        self
+    }
+
+    /// Returns whether the policy permits `access` to `layout`.
+    pub fn permits(&self, layout: &str, access: Access) -> bool {
+        if self.deny.contains(layout) {
+            return false;
+        }
+        let allowed = match access {
+            Access::Read => &self.read,
+            Access::Write => &self.write,
+        };
+        allowed
+            .as_ref()
+            .is_none_or(|layouts| layouts.contains(layout))
+    }
+
+    /// Fails with a [`PolicyError`] unless the policy permits `access` to `layout`.
+    pub fn check(&self, layout: &str, access: Access) -> Result<(), PolicyError> {
+        if self.permits(layout, access) {
+            return Ok(());
+        }
+        warn!(
+            "Layout policy refused {} access to layout {}",
+            access, layout
+        );
+        Err(PolicyError {
+            layout: layout.to_string(),
+            access,
+        })
+    }
+}
+
+impl Filemaker {
+    /// Restricts the layouts this client may read or write.
+    ///
+    /// Every request is checked before it is sent; requests to layouts the policy does not
+    /// permit fail with a [`PolicyError`]. The policy is kept by clients derived from this one,
+    /// e.g. with [`Filemaker::with_layout`].
+    ///
+    /// # Arguments
+    /// * `policy` - The layouts the client may access
+    ///
+    /// # Returns
+    /// * `Self` - The client with the policy applied
+    pub fn with_layout_policy(mut self, policy: LayoutPolicy) -> Self {
+        debug!("Using layout policy {:?}", policy);
+        self.layout_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Returns the layout policy of this client, if one is set.
+    pub fn layout_policy(&self) -> Option<&LayoutPolicy> {
+        self.layout_policy.as_deref()
+    }
+
+    /// Checks a request against the layout policy before it is sent.
+    pub(crate) fn check_layout_policy(&self, method: &Method, url: &str) -> anyhow::Result<()> {
+        let Some(policy) = &self.layout_policy else {
+            return Ok(());
+        };
+        let Some(layout) = request_layout(url) else {
+            return Ok(());
+        };
+        policy.check(&layout, request_access(method, url))?;
+        Ok(())
+    }
+}
+
+/// Extracts the decoded layout name from a Data API URL.
+pub(crate) fn request_layout(url: &str) -> Option<String> {
+    let path = url.split('?').next().unwrap_or(url);
+    let (_, rest) = path.split_once("/layouts/")?;
+    let layout = rest.split('/').next().filter(|l| !l.is_empty())?;
+    Some(query::decode_url_component(layout))
+}
+
+/// Determines whether a request reads or writes its layout.
+fn request_access(method: &Method, url: &str) -> Access {
+    let path = url.split('?').next().unwrap_or(url);
+    match *method {
+        _ if path.contains("/script/") => Access::Write,
+        Method::GET => Access::Read,
+        Method::POST if path.ends_with("/_find") => Access::Read,
+        _ => Access::Write,
+    }
+}
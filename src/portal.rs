@@ -0,0 +1,99 @@
+//! Related records shown in the portals of a layout.
+
+use crate::error::deserialize_at;
+use crate::Record;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A related record in a portal, as returned in a record's `portalData`.
+///
+/// The Data API returns the related fields under their qualified names, e.g.
+/// `Orders::Total`; [`PortalRow::get`] also accepts the unqualified name. Rows written with
+/// [`crate::WriteOptions`] create a related record unless they have a record ID.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PortalRow {
+    /// The ID of the related record, empty for a related record to create.
+    #[serde(rename = "recordId", skip_serializing_if = "String::is_empty")]
+    pub record_id: String,
+    /// The modification ID of the related record; when writing, the edit is rejected if
+    /// the related record was modified since.
+    #[serde(rename = "modId", skip_serializing_if = "String::is_empty")]
+    pub mod_id: String,
+    /// The related field data, by qualified field name.
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+impl PortalRow {
+    /// Creates a row to write, with field values by qualified name, e.g. `Orders::Total`.
+    pub fn new<I, K>(fields: I) -> Self
+    where
+        I: IntoIterator<Item = (K, Value)>,
+        K: Into<String>,
+    {
+        Self {
+            fields: fields.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Makes the row an edit of the related record `record_id` instead of a new one.
+    pub fn with_record_id(mut self, record_id: impl Into<String>) -> Self {
+        self.record_id = record_id.into();
+        self
+    }
+
+    /// Returns the value of a field, given its qualified (`Orders::Total`) or unqualified
+    /// (`Total`) name.
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields.get(field).or_else(|| {
+            self.fields
+                .iter()
+                .find(|(name, _)| name.rsplit_once("::").is_some_and(|(_, f)| f == field))
+                .map(|(_, value)| value)
+        })
+    }
+}
+
+impl<T> Record<T> {
+    /// Returns the names of the portals included with the record.
+    pub fn portal_names(&self) -> Vec<&str> {
+        self.portal_data
+            .as_object()
+            .map(|portals| portals.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the related records of a portal, empty if the portal was not included.
+    ///
+    /// # Arguments
+    /// * `portal` - The object name of the portal, as passed in [`crate::Portal`]
+    ///
+    /// # Returns
+    /// * `Result<Vec<PortalRow>>` - The related records in portal order, or an error if they are malformed
+    pub fn portal(&self, portal: &str) -> Result<Vec<PortalRow>> {
+        self.portal_as(portal)
+    }
+
+    /// Returns the related records of a portal deserialized into `P`, empty if the portal
+    /// was not included.
+    ///
+    /// Fields of `P` must be named (or renamed) after the qualified field names, e.g.
+    /// `#[serde(rename = "Orders::Total")]`.
+    ///
+    /// # Arguments
+    /// * `portal` - The object name of the portal, as passed in [`crate::Portal`]
+    ///
+    /// # Returns
+    /// * `Result<Vec<P>>` - The related records in portal order, or an error naming the mismatched value
+    pub fn portal_as<P>(&self, portal: &str) -> Result<Vec<P>>
+    where
+        P: serde::de::DeserializeOwned,
+    {
+        match self.portal_data.get(portal) {
+            Some(rows) => Ok(deserialize_at(rows, &format!("portalData.{}", portal))?),
+            None => Ok(vec![]),
+        }
+    }
+}
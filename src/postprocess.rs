@@ -0,0 +1,78 @@
+//! Rewriting records right after they are parsed.
+
+use crate::Filemaker;
+use log::*;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Rewrites the JSON of each record a client receives.
+///
+/// Post-processors run on every record of a response (the object holding `fieldData`,
+/// `portalData`, `recordId` and `modId`) right after the response is parsed, before raw
+/// records are returned or typed records are deserialized. Use them for rewriting that every
+/// consumer would otherwise repeat, e.g. dropping global fields or normalizing key casing.
+///
+/// Closures taking the layout name and the record implement the trait:
+///
+/// ```rust,ignore
+/// let filemaker = filemaker.with_post_processor(|_layout: &str, record: &mut Value| {
+///     if let Some(fields) = record["fieldData"].as_object_mut() {
+///         fields.retain(|name, _| !name.starts_with("g_"));
+///     }
+/// });
+/// ```
+pub trait RecordPostProcessor: Send + Sync {
+    /// Rewrites `record`, received on `layout`.
+    fn process(&self, layout: &str, record: &mut Value);
+}
+
+impl<F> RecordPostProcessor for F
+where
+    F: Fn(&str, &mut Value) + Send + Sync,
+{
+    fn process(&self, layout: &str, record: &mut Value) {
+        self(layout, record)
+    }
+}
+
+impl Filemaker {
+    /// Adds a post-processor run on every record this client receives.
+    ///
+    /// Post-processors run in the order they were added. Recordings made with
+    /// [`Filemaker::with_recording`] keep the records as the server sent them.
+    ///
+    /// # Arguments
+    /// * `processor` - The post-processor to add
+    ///
+    /// # Returns
+    /// * `Self` - The client with the post-processor
+    pub fn with_post_processor(mut self, processor: impl RecordPostProcessor + 'static) -> Self {
+        self.post_processors.push(Arc::new(processor));
+        debug!(
+            "Added record post-processor, {} configured",
+            self.post_processors.len()
+        );
+        self
+    }
+
+    /// Runs the post-processors on the records of `response`.
+    pub(crate) fn post_process(&self, mut response: Value) -> Value {
+        if self.post_processors.is_empty() {
+            return response;
+        }
+        let Some(records) = response
+            .get_mut("response")
+            .and_then(|r| r.get_mut("data"))
+            .and_then(|d| d.as_array_mut())
+        else {
+            return response;
+        };
+        let layout = self.layout();
+        for record in records {
+            for processor in &self.post_processors {
+                processor.process(&layout, record);
+            }
+        }
+        response
+    }
+}
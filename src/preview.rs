@@ -0,0 +1,108 @@
+use crate::options::sort_spec;
+use crate::Filemaker;
+use anyhow::Result;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Describes a Data API request exactly as it would be sent, without sending it.
+///
+/// Produced by [`Filemaker::explain`] to debug find behavior or to replay the request
+/// in other tools.
+#[derive(Debug, Clone)]
+pub struct RequestPreview {
+    /// The HTTP method of the request.
+    pub method: Method,
+    /// The fully qualified URL of the request.
+    pub url: String,
+    /// The JSON body of the request, if it has one.
+    pub body: Option<Value>,
+}
+
+impl RequestPreview {
+    /// Renders the request as an equivalent `curl` command.
+    ///
+    /// The session token is never included; the `Authorization` header carries a
+    /// `<token>` placeholder so the command can be shared safely and completed by
+    /// whoever reproduces the issue.
+    ///
+    /// # Returns
+    /// * `String` - A single-line curl command
+    pub fn to_curl(&self) -> String {
+        let mut command = format!(
+            "curl -X {} {} -H {} -H {}",
+            self.method,
+            shell_quote(&self.url),
+            shell_quote("Authorization: Bearer <token>"),
+            shell_quote("Content-Type: application/json")
+        );
+        if let Some(body) = &self.body {
+            command.push_str(" -d ");
+            command.push_str(&shell_quote(&body.to_string()));
+        }
+        command
+    }
+}
+
+/// Wraps a value in single quotes for POSIX shells, escaping embedded single quotes.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+impl Filemaker {
+    /// Describes the request [`Filemaker::search`] would send for the given arguments.
+    ///
+    /// Nothing is sent to the server; the returned preview contains the URL, method and
+    /// JSON body of the find request.
+    ///
+    /// # Arguments
+    /// * `query` - Vector of field-value pairs to search for
+    /// * `sort` - Vector of field names to sort by
+    /// * `ascending` - Whether to sort in ascending (true) or descending (false) order
+    /// * `limit` - If None, all results will be requested; otherwise, the specified limit will be applied
+    ///
+    /// # Returns
+    /// * `Result<RequestPreview>` - The request that would be sent, or an error
+    pub fn explain(
+        &self,
+        query: Vec<HashMap<String, String>>,
+        sort: Vec<String>,
+        ascending: bool,
+        limit: Option<u64>,
+    ) -> Result<RequestPreview> {
+        self.search_request(query, sort, ascending, limit)
+    }
+
+    /// Builds the find request used by [`Filemaker::search`] and [`Filemaker::explain`].
+    pub(crate) fn search_request(
+        &self,
+        query: Vec<HashMap<String, String>>,
+        sort: Vec<String>,
+        ascending: bool,
+        limit: Option<u64>,
+    ) -> Result<RequestPreview> {
+        // Construct the URL for the FileMaker Data API find endpoint
+        let url = format!(
+            "{}/databases/{}/layouts/{}/_find",
+            self.base_url()?,
+            self.database,
+            self.table
+        );
+
+        // Transform the sort fields into the format expected by FileMaker API
+        let sort_map = sort_spec(&sort, ascending, self.sort_tiebreaker());
+
+        // Construct the request body with query, sort and limit parameters
+        let body = json!({
+            "query": query,
+            "sort": sort_map,
+            "limit": limit.unwrap_or(u32::MAX as u64),
+        });
+
+        Ok(RequestPreview {
+            method: Method::POST,
+            url,
+            body: Some(body),
+        })
+    }
+}
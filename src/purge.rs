@@ -0,0 +1,180 @@
+//! Retention cleanup of records older than a cutoff.
+
+use crate::budget::BudgetTracker;
+use crate::{BudgetStatus, Filemaker, RetryBudget};
+use anyhow::Result;
+use chrono::{DateTime, TimeZone};
+use log::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Progress of a purge, reported after each batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PurgeProgress {
+    /// The number of matching records handled so far.
+    pub processed: usize,
+    /// The number of records that matched the cutoff.
+    pub total: usize,
+    /// The number of records deleted so far.
+    pub deleted: usize,
+    /// The number of records that could not be deleted so far.
+    pub failed: usize,
+}
+
+/// Configures [`Filemaker::purge_older_than`].
+#[derive(Clone)]
+pub struct PurgeOptions {
+    /// The number of records deleted per batch.
+    pub batch_size: usize,
+    /// The maximum number of delete requests in flight at once within a batch.
+    pub concurrency: usize,
+    /// Whether to only report the matching records without deleting them.
+    pub dry_run: bool,
+    /// Called after each batch with the progress so far.
+    pub progress: Option<Arc<dyn Fn(PurgeProgress) + Send + Sync>>,
+    /// Limits on retrying failed deletions and on the share of records that may fail,
+    /// shared by all batches.
+    pub budget: RetryBudget,
+}
+
+impl Default for PurgeOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            concurrency: 4,
+            dry_run: false,
+            progress: None,
+            budget: RetryBudget::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for PurgeOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PurgeOptions")
+            .field("batch_size", &self.batch_size)
+            .field("concurrency", &self.concurrency)
+            .field("dry_run", &self.dry_run)
+            .field("progress", &self.progress.is_some())
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+impl PurgeOptions {
+    /// Calls `progress` after each batch.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(PurgeProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+}
+
+/// Outcome of [`Filemaker::purge_older_than`].
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    /// The IDs of the records older than the cutoff.
+    pub matched: Vec<String>,
+    /// The number of records deleted.
+    pub deleted: usize,
+    /// The number of matching records that were already gone when they were deleted.
+    pub not_found: usize,
+    /// Records that could not be deleted, together with the reason.
+    pub failed: Vec<(String, anyhow::Error)>,
+    /// Whether this was a dry run that deleted nothing.
+    pub dry_run: bool,
+    /// How much of the retry budget the purge used.
+    pub budget: BudgetStatus,
+}
+
+impl Filemaker {
+    /// Deletes the records whose timestamp `field` is older than `cutoff`.
+    ///
+    /// Meant for enforcing retention on tables used as queues or logs. The matching records
+    /// are found with a `<` find on `field`, with the cutoff formatted in the server's time
+    /// zone (see [`Filemaker::with_server_timezone`]); records with an empty `field` are kept.
+    /// They are then deleted in batches of `options.batch_size` with
+    /// [`Filemaker::delete_records`], and `options.progress` is called after each batch. A
+    /// failing record does not abort the purge, unless more fail than `options.budget`
+    /// allows. With `options.dry_run`, the matching records are only reported.
+    ///
+    /// ```rust,ignore
+    /// let cutoff = chrono::Utc::now() - chrono::Duration::days(30);
+    /// let options = PurgeOptions::default().with_progress(|p| {
+    ///     println!("{}/{} purged", p.processed, p.total);
+    /// });
+    /// let report = filemaker.purge_older_than("Created", &cutoff, &options).await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `field` - The timestamp field compared to the cutoff
+    /// * `cutoff` - Records whose `field` is before this timestamp are deleted
+    /// * `options` - Batch, concurrency, dry-run and progress settings
+    ///
+    /// # Returns
+    /// * `Result<PurgeReport>` - The matching records and what was deleted, or an error if the find fails
+    pub async fn purge_older_than<T: TimeZone>(
+        &self,
+        field: &str,
+        cutoff: &DateTime<T>,
+        options: &PurgeOptions,
+    ) -> Result<PurgeReport> {
+        let criterion = format!("<{}", self.format_timestamp(cutoff));
+        debug!("Finding records to purge with {} {}", field, criterion);
+        let records = self
+            .find_all_pages(vec![HashMap::from([(field.to_string(), criterion)])])
+            .await?;
+        let mut report = PurgeReport {
+            matched: records
+                .iter()
+                .filter_map(|r| r.get("recordId").and_then(|id| id.as_str()))
+                .map(str::to_string)
+                .collect(),
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
+        let total = report.matched.len();
+
+        if options.dry_run {
+            info!(
+                "Dry run: {} records of {} are older than the cutoff",
+                total,
+                self.layout()
+            );
+            return Ok(report);
+        }
+
+        let budget = Arc::new(BudgetTracker::new(options.budget));
+        let mut processed = 0;
+        for batch in report.matched.clone().chunks(options.batch_size.max(1)) {
+            let summary = self
+                .delete_records_within(batch.iter(), options.concurrency, None, &budget)
+                .await?;
+            report.deleted += summary.deleted.len();
+            report.not_found += summary.not_found.len();
+            report.failed.extend(summary.failed);
+            report.budget = summary.budget;
+            processed += batch.len();
+            debug!("Purged {}/{} records", processed, total);
+            if let Some(progress) = &options.progress {
+                progress(PurgeProgress {
+                    processed,
+                    total,
+                    deleted: report.deleted,
+                    failed: report.failed.len(),
+                });
+            }
+        }
+
+        info!(
+            "Purged {} of {} records older than the cutoff from {} ({} failed)",
+            report.deleted,
+            total,
+            self.layout(),
+            report.failed.len()
+        );
+        Ok(report)
+    }
+}
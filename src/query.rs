@@ -0,0 +1,177 @@
+//! Helpers for building find criteria and encoding names in request URLs.
+//!
+//! The helpers are deterministic and pure, so they can be checked with property tests and
+//! fuzzing; [`fuzz_query_escaping`] bundles their invariants for fuzz targets.
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+
+/// Characters that FileMaker interprets as find operators.
+const FIND_OPERATORS: [char; 15] = [
+    '\\', '=', '!', '<', '>', '≤', '≥', '…', '?', '@', '#', '*', '"', '~', '/',
+];
+
+/// Escapes a value so that FileMaker matches it literally in a find request.
+///
+/// Every find operator (`=`, `<`, `*`, `@`, `"`, `..`, ...) is preceded by a backslash, so
+/// user supplied values cannot change the meaning of a query.
+///
+/// # Arguments
+/// * `value` - The literal value to search for
+///
+/// # Returns
+/// * `String` - The escaped value
+pub fn escape_find_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    let mut previous_dot = false;
+    while let Some(c) = chars.next() {
+        // A single dot is a regular character; two or more form the range operator
+        let is_range_dot = c == '.' && (previous_dot || chars.peek() == Some(&'.'));
+        if FIND_OPERATORS.contains(&c) || is_range_dot {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        previous_dot = c == '.';
+    }
+    escaped
+}
+
+/// Builds find criteria that match a field exactly equal to `value`.
+///
+/// # Arguments
+/// * `value` - The literal value the field must equal
+///
+/// # Returns
+/// * `String` - The criteria, using the `==` operator and an escaped value
+pub fn exact_match(value: &str) -> String {
+    format!("=={}", escape_find_value(value))
+}
+
+/// Reverses [`escape_find_value`], removing the backslash in front of every escaped character.
+///
+/// # Arguments
+/// * `criteria` - Escaped find criteria
+///
+/// # Returns
+/// * `String` - The literal value
+pub fn unescape_find_value(criteria: &str) -> String {
+    let mut unescaped = String::with_capacity(criteria.len());
+    let mut chars = criteria.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Returns whether FileMaker would match `criteria` literally, i.e. it contains no unescaped
+/// find operator.
+///
+/// # Arguments
+/// * `criteria` - Find criteria as sent to the server
+///
+/// # Returns
+/// * `bool` - `true` if every operator in `criteria` is escaped
+pub fn is_literal(criteria: &str) -> bool {
+    let mut chars = criteria.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // A trailing backslash escapes nothing and is an operator itself
+            '\\' if chars.next().is_none() => return false,
+            '\\' => {}
+            '.' if chars.peek() == Some(&'.') => return false,
+            c if FIND_OPERATORS.contains(&c) => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+/// Percent-encodes a database, layout or other name for use as a URL path segment or query
+/// value.
+///
+/// Every character except ASCII letters and digits is encoded, so names containing spaces,
+/// slashes, `?`, `#` or non-ASCII characters cannot change the structure of a request URL.
+/// The names `.` and `..` are the exception: URL parsers resolve them as dot segments even
+/// when encoded, so layouts or databases with these names cannot be addressed.
+///
+/// # Arguments
+/// * `name` - The name to encode
+///
+/// # Returns
+/// * `String` - The encoded name
+pub fn encode_url_component(name: &str) -> String {
+    utf8_percent_encode(name, NON_ALPHANUMERIC).to_string()
+}
+
+/// Reverses [`encode_url_component`].
+///
+/// # Arguments
+/// * `component` - An encoded name
+///
+/// # Returns
+/// * `String` - The decoded name; invalid UTF-8 sequences are replaced
+pub fn decode_url_component(component: &str) -> String {
+    percent_decode_str(component)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Checks the escaping and encoding invariants for one input, panicking on a violation.
+///
+/// Intended as the body of fuzz targets and property tests:
+/// * escaped values are literal and unescape to the input;
+/// * exact-match criteria are literal after the leading `==`;
+/// * encoded names contain only ASCII letters, digits and `%`, decode to the input, and
+///   (except for `.` and `..`) produce a request URL whose path segments decode back to the
+///   input.
+///
+/// # Arguments
+/// * `input` - Any string, e.g. a user supplied search value or layout name
+pub fn fuzz_query_escaping(input: &str) {
+    let escaped = escape_find_value(input);
+    assert!(
+        is_literal(&escaped),
+        "escaped value is not literal: {escaped:?}"
+    );
+    assert_eq!(unescape_find_value(&escaped), input);
+
+    let criteria = exact_match(input);
+    let literal = criteria
+        .strip_prefix("==")
+        .expect("exact match criteria start with ==");
+    assert!(
+        is_literal(literal),
+        "exact match is not literal: {criteria:?}"
+    );
+
+    let encoded = encode_url_component(input);
+    assert!(
+        encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '%'),
+        "encoded name contains reserved characters: {encoded:?}"
+    );
+    assert_eq!(decode_url_component(&encoded), input);
+
+    // URL parsers treat "." and ".." as dot segments even when percent-encoded
+    if input == "." || input == ".." {
+        return;
+    }
+    let url = format!(
+        "https://fm.example.com/fmi/data/vLatest/databases/{0}/layouts/{0}/records?_sort={0}",
+        encoded
+    );
+    let parsed = reqwest::Url::parse(&url).expect("request URL is well-formed");
+    let segments: Vec<&str> = parsed.path_segments().expect("URL has a path").collect();
+    assert_eq!(segments.len(), 8, "name changed the URL path: {url:?}");
+    assert_eq!(decode_url_component(segments[4]), input);
+    assert_eq!(decode_url_component(segments[6]), input);
+    let sort = parsed
+        .query_pairs()
+        .find(|(key, _)| key == "_sort")
+        .map(|(_, value)| value.into_owned());
+    assert_eq!(sort.as_deref(), Some(input));
+}
@@ -0,0 +1,344 @@
+//! Work-queue semantics over a FileMaker table.
+
+use crate::{query, Filemaker, NO_RECORDS_MATCH_CODE};
+use anyhow::Result;
+use chrono::Utc;
+use log::*;
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+
+/// The number of pending records fetched per claim attempt.
+const CLAIM_CANDIDATES: u64 = 10;
+
+/// The number of times a claim re-reads the queue after losing every candidate to other
+/// workers.
+const MAX_CLAIM_ROUNDS: usize = 3;
+
+/// FileMaker error returned when a record's `modId` no longer matches.
+const MOD_ID_MISMATCH_CODE: &str = "306";
+
+/// FileMaker error returned when a record is being edited by another user.
+const RECORD_IN_USE_CODE: &str = "301";
+
+/// FileMaker error returned when a record no longer exists.
+const RECORD_MISSING_CODE: &str = "101";
+
+/// Describes how queue state is stored on the layout.
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// The field holding each record's queue status.
+    pub status_field: String,
+    /// The status of records waiting to be processed.
+    pub pending: String,
+    /// The status of records claimed by a worker.
+    pub processing: String,
+    /// The status of records processed successfully.
+    pub done: String,
+    /// The status of records whose processing failed.
+    pub failed: String,
+    /// The field receiving the name of the worker that claimed a record.
+    pub claimed_by_field: String,
+    /// The timestamp field receiving the time a record was claimed.
+    pub claimed_at_field: String,
+    /// The field receiving the reason a record failed, if any.
+    pub error_field: Option<String>,
+    /// The field pending records are claimed in ascending order of, e.g. a creation timestamp.
+    pub order_field: Option<String>,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            status_field: "Status".to_string(),
+            pending: "pending".to_string(),
+            processing: "processing".to_string(),
+            done: "done".to_string(),
+            failed: "failed".to_string(),
+            claimed_by_field: "ClaimedBy".to_string(),
+            claimed_at_field: "ClaimedAt".to_string(),
+            error_field: None,
+            order_field: None,
+        }
+    }
+}
+
+/// A record claimed from an [`FmQueue`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueItem {
+    /// The ID of the claimed record.
+    pub record_id: String,
+    /// The modification ID of the record after it was claimed.
+    pub mod_id: String,
+    /// The record's field data as it was when claimed.
+    pub field_data: Value,
+}
+
+/// Treats the records of a layout as a work queue.
+///
+/// Workers [`claim`](FmQueue::claim) the next pending record by switching its status to
+/// processing with an update guarded by the record's `modId`, so two workers cannot claim
+/// the same record. Claimed records are then [`complete`](FmQueue::complete)d or
+/// [`fail`](FmQueue::fail)ed, again guarded by the `modId` of the claim. Claims of workers
+/// that died are returned to the queue with [`requeue_stale`](FmQueue::requeue_stale).
+///
+/// ```rust,ignore
+/// let queue = filemaker.with_layout("Jobs").queue(QueueConfig::default(), "worker-1");
+/// while let Some(item) = queue.claim().await? {
+///     match process(&item.field_data).await {
+///         Ok(()) => queue.complete(&item).await?,
+///         Err(e) => queue.fail(&item, &e.to_string()).await?,
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct FmQueue {
+    filemaker: Filemaker,
+    config: QueueConfig,
+    worker: String,
+}
+
+impl Filemaker {
+    /// Creates a queue over this client's layout, claiming records as `worker`.
+    ///
+    /// # Arguments
+    /// * `config` - The fields and status values the queue uses
+    /// * `worker` - The name written to the claimed-by field of claimed records
+    pub fn queue(&self, config: QueueConfig, worker: impl Into<String>) -> FmQueue {
+        FmQueue {
+            filemaker: self.clone(),
+            config,
+            worker: worker.into(),
+        }
+    }
+}
+
+impl FmQueue {
+    /// Returns the name this queue claims records as.
+    pub fn worker(&self) -> &str {
+        &self.worker
+    }
+
+    /// Claims the next pending record.
+    ///
+    /// Pending records are read in `order_field` order and claimed one by one until an
+    /// update succeeds. Records claimed by another worker in the meantime are skipped.
+    ///
+    /// # Returns
+    /// * `Result<Option<QueueItem>>` - The claimed record, `None` if no record is pending, or an error
+    pub async fn claim(&self) -> Result<Option<QueueItem>> {
+        for round in 0..MAX_CLAIM_ROUNDS {
+            let candidates = self.pending_candidates().await?;
+            if candidates.is_empty() {
+                return Ok(None);
+            }
+            for candidate in candidates {
+                let (Some(record_id), Some(mod_id)) = (
+                    candidate.get("recordId").and_then(|v| v.as_str()),
+                    candidate.get("modId").and_then(|v| v.as_str()),
+                ) else {
+                    continue;
+                };
+                let claim = json!({
+                    &self.config.status_field: self.config.processing,
+                    &self.config.claimed_by_field: self.worker,
+                    &self.config.claimed_at_field: self.filemaker.format_timestamp(&Utc::now()),
+                });
+                let response = self
+                    .filemaker
+                    .patch_record(record_id, claim.clone(), Some(mod_id))
+                    .await?;
+                match Filemaker::first_message_code(&response) {
+                    Some("0") => {
+                        let mut field_data = candidate
+                            .get("fieldData")
+                            .cloned()
+                            .unwrap_or_else(|| json!({}));
+                        if let (Some(fields), Some(claim)) =
+                            (field_data.as_object_mut(), claim.as_object())
+                        {
+                            fields.extend(claim.clone());
+                        }
+                        info!("Worker {} claimed record {}", self.worker, record_id);
+                        return Ok(Some(QueueItem {
+                            record_id: record_id.to_string(),
+                            mod_id: new_mod_id(&response, mod_id),
+                            field_data,
+                        }));
+                    }
+                    Some(MOD_ID_MISMATCH_CODE)
+                    | Some(RECORD_IN_USE_CODE)
+                    | Some(RECORD_MISSING_CODE) => {
+                        debug!(
+                            "Record {} was claimed or changed by someone else, trying the next one",
+                            record_id
+                        );
+                    }
+                    _ => {
+                        error!("Failed to claim record {}: {:?}", record_id, response);
+                        return Err(Filemaker::response_error(
+                            &response,
+                            "Failed to claim record",
+                        ));
+                    }
+                }
+            }
+            debug!("Lost every candidate in claim round {}", round + 1);
+        }
+        warn!(
+            "Worker {} could not claim a record after {} rounds",
+            self.worker, MAX_CLAIM_ROUNDS
+        );
+        Ok(None)
+    }
+
+    /// Marks a claimed record as done.
+    ///
+    /// Fails with error 306 ([`crate::ErrorKind::Locked`]) if the record was changed since it
+    /// was claimed, e.g. because the claim was re-queued as stale.
+    ///
+    /// # Arguments
+    /// * `item` - The claimed record
+    pub async fn complete(&self, item: &QueueItem) -> Result<()> {
+        let fields = json!({ &self.config.status_field: self.config.done });
+        self.finish(item, fields, "Failed to complete queue item")
+            .await
+    }
+
+    /// Marks a claimed record as failed, recording `reason` in the error field if configured.
+    ///
+    /// Fails with error 306 ([`crate::ErrorKind::Locked`]) if the record was changed since it
+    /// was claimed.
+    ///
+    /// # Arguments
+    /// * `item` - The claimed record
+    /// * `reason` - Why processing failed
+    pub async fn fail(&self, item: &QueueItem, reason: &str) -> Result<()> {
+        let mut fields = Map::new();
+        fields.insert(self.config.status_field.clone(), json!(self.config.failed));
+        if let Some(error_field) = &self.config.error_field {
+            fields.insert(error_field.clone(), json!(reason));
+        }
+        self.finish(item, Value::Object(fields), "Failed to fail queue item")
+            .await
+    }
+
+    /// Returns records claimed longer than `older_than` ago to the pending state.
+    ///
+    /// Each record is reset with an update guarded by its `modId`, so a record completed by
+    /// its worker while being re-queued is left alone.
+    ///
+    /// # Arguments
+    /// * `older_than` - How long a claim may last before it is considered abandoned
+    ///
+    /// # Returns
+    /// * `Result<usize>` - The number of records returned to the queue, or an error
+    pub async fn requeue_stale(&self, older_than: Duration) -> Result<usize> {
+        let cutoff = Utc::now() - chrono::Duration::from_std(older_than)?;
+        let stale = self
+            .filemaker
+            .find_all_pages(vec![[
+                (
+                    self.config.status_field.clone(),
+                    query::exact_match(&self.config.processing),
+                ),
+                (
+                    self.config.claimed_at_field.clone(),
+                    format!("<{}", self.filemaker.format_timestamp(&cutoff)),
+                ),
+            ]
+            .into()])
+            .await?;
+
+        let mut requeued = 0;
+        for record in &stale {
+            let (Some(record_id), Some(mod_id)) = (
+                record.get("recordId").and_then(|v| v.as_str()),
+                record.get("modId").and_then(|v| v.as_str()),
+            ) else {
+                continue;
+            };
+            let reset = json!({
+                &self.config.status_field: self.config.pending,
+                &self.config.claimed_by_field: "",
+                &self.config.claimed_at_field: "",
+            });
+            let response = self
+                .filemaker
+                .patch_record(record_id, reset, Some(mod_id))
+                .await?;
+            match Filemaker::first_message_code(&response) {
+                Some("0") => requeued += 1,
+                Some(MOD_ID_MISMATCH_CODE) | Some(RECORD_IN_USE_CODE) => {
+                    debug!("Stale record {} changed meanwhile, leaving it", record_id)
+                }
+                _ => {
+                    error!("Failed to requeue record {}: {:?}", record_id, response);
+                    return Err(Filemaker::response_error(
+                        &response,
+                        "Failed to requeue record",
+                    ));
+                }
+            }
+        }
+        info!("Requeued {} of {} stale claims", requeued, stale.len());
+        Ok(requeued)
+    }
+
+    /// Reads the next pending records, oldest first.
+    async fn pending_candidates(&self) -> Result<Vec<Value>> {
+        let mut body = json!({
+            "query": [{ &self.config.status_field: query::exact_match(&self.config.pending) }],
+            "limit": CLAIM_CANDIDATES,
+        });
+        if let Some(order_field) = &self.config.order_field {
+            body["sort"] = json!([{ "fieldName": order_field, "sortOrder": "ascend" }]);
+        }
+        let url = format!(
+            "{}/databases/{}/layouts/{}/_find",
+            self.filemaker.base_url()?,
+            self.filemaker.database,
+            self.filemaker.table
+        );
+        let response = self
+            .filemaker
+            .authenticated_request(&url, reqwest::Method::POST, Some(body))
+            .await?;
+        if Filemaker::first_message_code(&response) == Some(NO_RECORDS_MATCH_CODE) {
+            return Ok(vec![]);
+        }
+        response
+            .get("response")
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.as_array())
+            .cloned()
+            .ok_or_else(|| Filemaker::response_error(&response, "Failed to read queue"))
+    }
+
+    /// Writes the final state of a claimed record, guarded by the claim's `modId`.
+    async fn finish(&self, item: &QueueItem, fields: Value, context: &'static str) -> Result<()> {
+        let response = self
+            .filemaker
+            .patch_record(&item.record_id, fields, Some(&item.mod_id))
+            .await?;
+        if Filemaker::first_message_code(&response) != Some("0") {
+            error!("{} {}: {:?}", context, item.record_id, response);
+            return Err(Filemaker::response_error(&response, context));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the `modId` reported by an update response, or `previous` if it is missing.
+fn new_mod_id(response: &Value, previous: &str) -> String {
+    match response
+        .get("response")
+        .and_then(|r| r.get("modId"))
+        .and_then(|m| m.as_str())
+    {
+        Some(mod_id) => mod_id.to_string(),
+        None => {
+            warn!("Update response has no modId, assuming {}", previous);
+            previous.to_string()
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Typed values that keep the JSON they were deserialized from.
+
+use serde::de::{DeserializeOwned, Error};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::ops::{Deref, DerefMut};
+
+/// A typed value together with the untouched JSON it was deserialized from.
+///
+/// `WithRaw<T>` can be used wherever the crate deserializes a `T`, so code written against
+/// raw JSON can adopt typed APIs one field at a time without losing the keys `T` does not
+/// map. Wrapping a record model keeps the raw `fieldData` of every record:
+///
+/// ```rust,ignore
+/// let invoices = filemaker.get_all_records::<WithRaw<Invoice>>().await?;
+/// for invoice in &invoices {
+///     println!("{} {}", invoice.typed.number, invoice.raw["Legacy Notes"]);
+/// }
+/// ```
+///
+/// It dereferences to `T`, so typed fields are also reachable directly.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WithRaw<T> {
+    /// The typed value.
+    pub typed: T,
+    /// The JSON the value was deserialized from.
+    pub raw: Value,
+}
+
+impl<T> WithRaw<T> {
+    /// Returns the typed value, dropping the raw JSON.
+    pub fn into_typed(self) -> T {
+        self.typed
+    }
+}
+
+impl<'de, T> Deserialize<'de> for WithRaw<T>
+where
+    T: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Value::deserialize(deserializer)?;
+        let typed = serde_path_to_error::deserialize(&raw).map_err(|e| {
+            match e.path().to_string().as_str() {
+                "." => D::Error::custom(e.into_inner()),
+                path => D::Error::custom(format!("{}: {}", path, e.into_inner())),
+            }
+        })?;
+        Ok(Self { typed, raw })
+    }
+}
+
+impl<T> Deref for WithRaw<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.typed
+    }
+}
+
+impl<T> DerefMut for WithRaw<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.typed
+    }
+}
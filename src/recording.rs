@@ -0,0 +1,224 @@
+//! Recording Data API exchanges to NDJSON and replaying them without a server.
+//!
+//! A recording made in production can be replayed into a client created with
+//! [`Filemaker::replay`], so the same calls run through the same parsing code offline.
+
+use crate::Filemaker;
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Mutex as AsyncMutex;
+
+/// The value written in place of redacted field values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// One request and the response it received, as stored in a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedExchange {
+    /// The HTTP method of the request.
+    pub method: String,
+    /// The request path below the Data API base URL, starting with `/databases`.
+    pub path: String,
+    /// The request body, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    /// The parsed response body.
+    pub response: Value,
+    /// How long the request took, in milliseconds.
+    #[serde(default)]
+    pub duration_ms: u64,
+}
+
+/// Appends exchanges to an NDJSON file.
+pub(crate) struct Recorder {
+    file: Mutex<File>,
+    redacted: HashSet<String>,
+}
+
+impl Recorder {
+    /// Writes one exchange, redacting the configured fields.
+    pub(crate) fn record(
+        &self,
+        method: &Method,
+        url: &str,
+        request: Option<&Value>,
+        response: &Value,
+        duration: Duration,
+    ) {
+        let exchange = RecordedExchange {
+            method: method.to_string(),
+            path: relative_path(url).to_string(),
+            request: request.map(|body| self.redact(body)),
+            response: self.redact(response),
+            duration_ms: duration.as_millis() as u64,
+        };
+        let line = match serde_json::to_string(&exchange) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize recorded exchange: {}", e);
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(file, "{}", line) {
+            error!("Failed to write recorded exchange: {}", e);
+        }
+    }
+
+    /// Replaces the values of redacted fields anywhere in `value`.
+    fn redact(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| {
+                        let value = match self.redacted.contains(&key.to_lowercase()) {
+                            true => Value::String(REDACTED.to_string()),
+                            false => self.redact(value),
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.iter().map(|v| self.redact(v)).collect()),
+            other => other.clone(),
+        }
+    }
+}
+
+/// Answers requests with recorded responses.
+pub(crate) struct Replayer {
+    exchanges: AsyncMutex<VecDeque<RecordedExchange>>,
+}
+
+impl Replayer {
+    /// Returns the response of the first unused exchange with the same method and path.
+    pub(crate) async fn respond(&self, method: &Method, url: &str) -> Result<Value> {
+        let path = relative_path(url);
+        let mut exchanges = self.exchanges.lock().await;
+        let position = exchanges
+            .iter()
+            .position(|e| e.method == method.as_str() && e.path == path);
+        match position.and_then(|i| exchanges.remove(i)) {
+            Some(exchange) => {
+                debug!("Replaying recorded response for {} {}", method, path);
+                Ok(exchange.response)
+            }
+            None => {
+                error!("No recorded response for {} {}", method, path);
+                Err(anyhow!("No recorded response for {} {}", method, path))
+            }
+        }
+    }
+}
+
+impl Filemaker {
+    /// Records every request sent by this client, and the response it received, to `path`.
+    ///
+    /// Exchanges are appended to the file as one JSON object per line (NDJSON). The session
+    /// token is never recorded, and the values of `redacted_fields` (matched
+    /// case-insensitively at any depth of request and response bodies) are replaced with
+    /// [`REDACTED`]. Clients derived from this one record to the same file.
+    ///
+    /// # Arguments
+    /// * `path` - The NDJSON file to append to; created if missing
+    /// * `redacted_fields` - Names of fields whose values must not be written
+    ///
+    /// # Returns
+    /// * `Result<Self>` - The recording client, or an error if the file cannot be opened
+    pub fn with_recording<I, S>(
+        mut self,
+        path: impl AsRef<Path>,
+        redacted_fields: I,
+    ) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open recording {}", path.display()))?;
+        info!("Recording Data API exchanges to {}", path.display());
+        self.recorder = Some(Arc::new(Recorder {
+            file: Mutex::new(file),
+            redacted: redacted_fields
+                .into_iter()
+                .map(|f| f.as_ref().to_lowercase())
+                .collect(),
+        }));
+        Ok(self)
+    }
+
+    /// Creates a client that answers requests from a recording instead of a server.
+    ///
+    /// Each request is answered with the response of the first not yet replayed exchange
+    /// with the same method and path, and fails if there is none. Calling the methods that
+    /// produced a recording therefore runs the recorded responses through the same parsing
+    /// code, which reproduces parsing bugs offline.
+    ///
+    /// ```rust,ignore
+    /// let filemaker = Filemaker::replay("bug-1234.ndjson", "Sales", "Invoices")?;
+    /// let invoices = filemaker.get_all_records::<Invoice>().await;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `path` - The NDJSON file written by [`Filemaker::with_recording`]
+    /// * `database` - The database the recording was made against
+    /// * `table` - The layout the recording was made against
+    ///
+    /// # Returns
+    /// * `Result<Filemaker>` - The replaying client, or an error if the recording cannot be read
+    pub fn replay(path: impl AsRef<Path>, database: &str, table: &str) -> Result<Filemaker> {
+        let exchanges = read_recording(path)?;
+        info!("Replaying {} recorded exchanges", exchanges.len());
+        let mut filemaker = Self::offline(database, table)?;
+        filemaker.replayer = Some(Arc::new(Replayer {
+            exchanges: AsyncMutex::new(exchanges.into()),
+        }));
+        Ok(filemaker)
+    }
+}
+
+/// Reads the exchanges of an NDJSON recording, skipping blank lines.
+///
+/// # Arguments
+/// * `path` - The recording written by [`Filemaker::with_recording`]
+///
+/// # Returns
+/// * `Result<Vec<RecordedExchange>>` - The exchanges in recording order, or an error naming the malformed line
+pub fn read_recording(path: impl AsRef<Path>) -> Result<Vec<RecordedExchange>> {
+    let path = path.as_ref();
+    let file =
+        File::open(path).with_context(|| format!("Failed to open recording {}", path.display()))?;
+    let mut exchanges = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let exchange = serde_json::from_str(&line).with_context(|| {
+            format!(
+                "Malformed exchange on line {} of {}",
+                index + 1,
+                path.display()
+            )
+        })?;
+        exchanges.push(exchange);
+    }
+    Ok(exchanges)
+}
+
+/// Returns the part of a Data API URL from `/databases` on.
+fn relative_path(url: &str) -> &str {
+    url.find("/databases").map_or(url, |i| &url[i..])
+}
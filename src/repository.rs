@@ -0,0 +1,238 @@
+//! A typed data layer over a single layout.
+
+use crate::{query, Filemaker, ModelField, Record, NO_RECORDS_MATCH_CODE};
+use anyhow::{anyhow, Result};
+use futures::stream::{Stream, StreamExt};
+use log::*;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// A typed model of the field data of one layout.
+///
+/// ```rust,ignore
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Invoice {
+///     #[serde(rename = "InvoiceNumber")]
+///     number: String,
+///     #[serde(rename = "Total")]
+///     total: f64,
+/// }
+///
+/// impl FmRecordModel for Invoice {
+///     const LAYOUT: &'static str = "Invoices";
+/// }
+/// ```
+pub trait FmRecordModel: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The name of the layout the model is read from and written to.
+    const LAYOUT: &'static str;
+
+    /// Returns the fields of the model, checked by [`Filemaker::verify_model`].
+    ///
+    /// By default they are read from the model's `Deserialize` implementation, which works
+    /// for structs deriving it. Override this for models deserialized another way, e.g. with
+    /// `#[serde(flatten)]` fields.
+    fn fields() -> Vec<ModelField> {
+        crate::verify::model_fields::<Self>()
+    }
+}
+
+/// A layout known at compile time, together with the model of its records.
+///
+/// Implemented by the marker types emitted by [`Filemaker::generate_layouts`], so layouts
+/// are opened with [`Filemaker::open`] instead of by name:
+///
+/// ```rust,ignore
+/// let invoices = filemaker.open::<layouts::Invoices>();
+/// let unpaid = invoices.find_by("Status", "Unpaid").await?;
+/// ```
+pub trait Layout {
+    /// The name of the layout.
+    const NAME: &'static str;
+    /// The model of the layout's records.
+    type Record: FmRecordModel;
+}
+
+/// Typed access to the records of the layout bound to `T`.
+///
+/// Created with [`Filemaker::repository`]. Records are exchanged as [`Record<T>`] so the
+/// record and modification IDs travel with the data.
+pub struct Repository<T: FmRecordModel> {
+    filemaker: Filemaker,
+    model: PhantomData<T>,
+}
+
+impl<T: FmRecordModel> Clone for Repository<T> {
+    fn clone(&self) -> Self {
+        Self {
+            filemaker: self.filemaker.clone(),
+            model: PhantomData,
+        }
+    }
+}
+
+impl Filemaker {
+    /// Creates a repository for the layout of model `T`, sharing this client's session.
+    pub fn repository<T: FmRecordModel>(&self) -> Repository<T> {
+        Repository {
+            filemaker: self.with_layout(T::LAYOUT),
+            model: PhantomData,
+        }
+    }
+
+    /// Opens layout `L` as a repository of its record model, sharing this client's session.
+    ///
+    /// Unlike [`Filemaker::repository`], the layout is `L::NAME`, so one model can be used
+    /// with several layouts showing the same fields.
+    pub fn open<L: Layout>(&self) -> Repository<L::Record> {
+        Repository {
+            filemaker: self.with_layout(L::NAME),
+            model: PhantomData,
+        }
+    }
+}
+
+impl<T: FmRecordModel> Repository<T> {
+    /// Returns the client bound to the model's layout.
+    pub fn filemaker(&self) -> &Filemaker {
+        &self.filemaker
+    }
+
+    /// Gets a record by its ID.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the record
+    ///
+    /// # Returns
+    /// * `Result<Record<T>>` - The typed record, or an error
+    pub async fn get(&self, id: &str) -> Result<Record<T>> {
+        let record = self.filemaker.get_record_by_id(id).await?;
+        Self::parse(record)
+    }
+
+    /// Finds all records whose `field` exactly equals `value`.
+    ///
+    /// # Arguments
+    /// * `field` - The field to match
+    /// * `value` - The literal value the field must equal
+    ///
+    /// # Returns
+    /// * `Result<Vec<Record<T>>>` - The matching records, empty if none match, or an error
+    pub async fn find_by(&self, field: &str, value: impl ToString) -> Result<Vec<Record<T>>> {
+        let url = format!(
+            "{}/databases/{}/layouts/{}/_find",
+            self.filemaker.base_url()?,
+            self.filemaker.database,
+            self.filemaker.table
+        );
+        let body = json!({
+            "query": [{ field: query::exact_match(&value.to_string()) }],
+            "limit": u32::MAX,
+        });
+        let response = self
+            .filemaker
+            .authenticated_request(&url, Method::POST, Some(body))
+            .await?;
+
+        if Filemaker::first_message_code(&response) == Some(NO_RECORDS_MATCH_CODE) {
+            return Ok(vec![]);
+        }
+        let Some(data) = response
+            .get("response")
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.as_array())
+        else {
+            error!("Failed to retrieve find results: {:?}", response);
+            return Err(Filemaker::response_error(
+                &response,
+                "Failed to retrieve find results",
+            ));
+        };
+        data.iter().cloned().map(Self::parse).collect()
+    }
+
+    /// Creates a new record from `data`.
+    ///
+    /// # Returns
+    /// * `Result<Record<T>>` - The created record as stored by the server, or an error
+    pub async fn insert(&self, data: &T) -> Result<Record<T>> {
+        let created = self
+            .filemaker
+            .create_record(Self::field_data(data)?)
+            .await?;
+        Self::parse(created.record)
+    }
+
+    /// Saves a record, creating it if it has no record ID yet and updating it otherwise.
+    ///
+    /// Updates send the record's `modId`, so the server rejects the write if the record was
+    /// modified by someone else since it was read.
+    ///
+    /// # Returns
+    /// * `Result<Record<T>>` - The saved record as stored by the server, or an error
+    pub async fn save(&self, record: &Record<T>) -> Result<Record<T>> {
+        if record.record_id.is_empty() {
+            return self.insert(&record.data).await;
+        }
+
+        let mod_id = Some(record.mod_id.as_str()).filter(|m| !m.is_empty());
+        let response = self
+            .filemaker
+            .patch_record(
+                &record.record_id,
+                serde_json::to_value(Self::field_data(&record.data)?)?,
+                mod_id,
+            )
+            .await?;
+        if Filemaker::first_message_code(&response) != Some("0") {
+            error!("Failed to save record {}: {:?}", record.record_id, response);
+            return Err(Filemaker::response_error(
+                &response,
+                "Failed to save record",
+            ));
+        }
+        self.get(&record.record_id).await
+    }
+
+    /// Deletes a record by its ID.
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        self.filemaker
+            .delete_record_response(id)
+            .await
+            .and_then(|r| match Filemaker::first_message_code(&r) {
+                Some("0") => Ok(()),
+                _ => Err(Filemaker::response_error(&r, "Failed to delete record")),
+            })
+    }
+
+    /// Streams every record of the layout, fetching `page_size` records per request.
+    ///
+    /// See [`Filemaker::stream_records`].
+    pub fn stream_all(&self, page_size: u64) -> impl Stream<Item = Result<Record<T>>> + '_ {
+        self.filemaker
+            .stream_records(page_size)
+            .map(|record| record.and_then(Self::parse))
+    }
+
+    /// Deserializes a raw Data API record into a typed record.
+    fn parse(record: Value) -> Result<Record<T>> {
+        crate::error::deserialize_at(&record, "").map_err(|e| {
+            error!("Failed to deserialize record: {}. Record: {:?}", e, record);
+            anyhow::Error::new(e)
+        })
+    }
+
+    /// Serializes a model into the field data map expected by the Data API.
+    fn field_data(data: &T) -> Result<HashMap<String, Value>> {
+        match serde_json::to_value(data)? {
+            Value::Object(map) => Ok(map.into_iter().collect()),
+            other => Err(anyhow!(
+                "Record model must serialize to an object, found {}",
+                other
+            )),
+        }
+    }
+}
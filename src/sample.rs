@@ -0,0 +1,147 @@
+//! Sampling records of large tables.
+//!
+//! Schema inference and test fixtures need a few representative rows, not the whole table.
+//! [`Filemaker::sample_records`] picks them with one of the [`SampleStrategy`] variants,
+//! trading request count against how evenly the sample covers the table.
+
+use crate::{Cursor, Filemaker};
+use anyhow::Result;
+use futures::{StreamExt, TryStreamExt};
+use log::*;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::hash::{BuildHasher, RandomState};
+
+/// The number of records fetched per request when streaming a reservoir sample.
+const SAMPLE_PAGE_SIZE: u64 = 500;
+
+/// The number of random offsets fetched in parallel.
+const SAMPLE_CONCURRENCY: usize = 4;
+
+/// How [`Filemaker::sample_records`] picks its records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleStrategy {
+    /// The first records of the table, in one request. Cheapest, but biased towards the
+    /// oldest records.
+    First,
+    /// Records at random positions, fetched one request each after a request for the
+    /// table size. Uniform as long as the table does not change while sampling.
+    RandomOffsets,
+    /// A uniform sample kept while streaming every record of the table. Reads the whole
+    /// table but holds only the sample in memory.
+    Reservoir,
+}
+
+/// A small pseudo-random generator (SplitMix64), seeded from the process' hash keys.
+///
+/// Samples only need to be spread out, not unpredictable, so this avoids a dependency on a
+/// random number crate.
+struct SampleRng(u64);
+
+impl SampleRng {
+    fn new() -> Self {
+        Self(RandomState::new().hash_one(std::time::Instant::now()))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..bound`; `bound` must not be zero.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Picks `n` distinct positions in `1..=total` (Floyd's algorithm), in ascending order.
+fn random_offsets(rng: &mut SampleRng, n: u64, total: u64) -> BTreeSet<u64> {
+    let mut offsets = BTreeSet::new();
+    for upper in (total - n + 1)..=total {
+        let offset = rng.below(upper) + 1;
+        if !offsets.insert(offset) {
+            offsets.insert(upper);
+        }
+    }
+    offsets
+}
+
+impl Filemaker {
+    /// Fetches up to `n` records of the layout without reading the whole table.
+    ///
+    /// Records are returned in table order. Tables with fewer than `n` records are returned
+    /// whole.
+    ///
+    /// ```rust,ignore
+    /// let rows = filemaker.sample_records(50, SampleStrategy::RandomOffsets).await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `n` - The number of records to sample
+    /// * `strategy` - How the records are picked
+    ///
+    /// # Returns
+    /// * `Result<Vec<Value>>` - The raw sampled records, or an error if a request fails
+    pub async fn sample_records(&self, n: u64, strategy: SampleStrategy) -> Result<Vec<Value>> {
+        if n == 0 {
+            return Ok(vec![]);
+        }
+        let records = match strategy {
+            SampleStrategy::First => self.fetch_page(&Cursor::new(n)).await?.records,
+            SampleStrategy::RandomOffsets => self.sample_random_offsets(n).await?,
+            SampleStrategy::Reservoir => self.sample_reservoir(n).await?,
+        };
+        debug!(
+            "Sampled {} records of {} with {:?}",
+            records.len(),
+            self.layout(),
+            strategy
+        );
+        Ok(records)
+    }
+
+    async fn sample_random_offsets(&self, n: u64) -> Result<Vec<Value>> {
+        let first = self.fetch_page(&Cursor::new(1)).await?;
+        let total = first.found_count;
+        if total <= n {
+            return Ok(self.fetch_page(&Cursor::new(total.max(1))).await?.records);
+        }
+        let offsets = random_offsets(&mut SampleRng::new(), n, total);
+        futures::stream::iter(offsets)
+            .map(|offset| async move {
+                let cursor = Cursor {
+                    offset,
+                    ..Cursor::new(1)
+                };
+                Ok(self.fetch_page(&cursor).await?.records)
+            })
+            .buffered(SAMPLE_CONCURRENCY)
+            .try_concat()
+            .await
+    }
+
+    async fn sample_reservoir(&self, n: u64) -> Result<Vec<Value>> {
+        let mut rng = SampleRng::new();
+        // Records are kept with their position so the sample can be returned in table order
+        let mut reservoir: Vec<(u64, Value)> = Vec::new();
+        let mut records = std::pin::pin!(self.stream_records(SAMPLE_PAGE_SIZE));
+        let mut seen = 0u64;
+        while let Some(record) = records.next().await {
+            let record = record?;
+            if seen < n {
+                reservoir.push((seen, record));
+            } else {
+                let slot = rng.below(seen + 1);
+                if slot < n {
+                    reservoir[slot as usize] = (seen, record);
+                }
+            }
+            seen += 1;
+        }
+        reservoir.sort_by_key(|(position, _)| *position);
+        Ok(reservoir.into_iter().map(|(_, record)| record).collect())
+    }
+}
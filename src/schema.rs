@@ -0,0 +1,231 @@
+//! Guessing field types from sampled records.
+//!
+//! When layout metadata is unavailable (e.g. the account may not read it, or the data comes
+//! from an export), [`infer_schema`] inspects a sample of records, such as one taken with
+//! [`Filemaker::sample_records`](crate::Filemaker::sample_records), and reports the type,
+//! value range and share of empty values of every field, along with the number format text
+//! numbers are written in.
+
+use crate::coerce::{to_date, to_naive_timestamp, to_number};
+use crate::NumberFormat;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The type [`infer_schema`] guessed for a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InferredType {
+    /// Every value is a number, or text that parses as one.
+    Number,
+    /// Every value is a date in one of the Data API's date formats.
+    Date,
+    /// Every value is a timestamp in one of the Data API's timestamp formats.
+    Timestamp,
+    /// Every value is `Yes`/`No`, `True`/`False` or `Y`/`N`. Flags stored as `1`/`0` are
+    /// reported as numbers, since they cannot be told apart from small counts.
+    Bool,
+    /// Any other values.
+    Text,
+    /// The field was empty in every sampled record.
+    Empty,
+}
+
+/// The smallest and largest value of a field, by its inferred type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueRange {
+    /// The range of a [`InferredType::Number`] field.
+    Number { min: f64, max: f64 },
+    /// The range of a [`InferredType::Date`] field.
+    Date { min: NaiveDate, max: NaiveDate },
+    /// The range of a [`InferredType::Timestamp`] field.
+    Timestamp {
+        min: NaiveDateTime,
+        max: NaiveDateTime,
+    },
+    /// The shortest and longest value of a [`InferredType::Text`] field, in characters.
+    TextLength { min: usize, max: usize },
+}
+
+/// What [`infer_schema`] found out about one field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldProfile {
+    /// The field name.
+    pub name: String,
+    /// The guessed type.
+    pub field_type: InferredType,
+    /// The number of sampled records.
+    pub records: u64,
+    /// The number of sampled records in which the field was empty or missing.
+    pub empty: u64,
+    /// The smallest and largest value, if the type has a range and any value was present.
+    pub range: Option<ValueRange>,
+}
+
+impl FieldProfile {
+    /// The share of sampled records in which the field was empty, from 0 to 1.
+    pub fn null_rate(&self) -> f64 {
+        match self.records {
+            0 => 0.0,
+            records => self.empty as f64 / records as f64,
+        }
+    }
+}
+
+/// The result of [`infer_schema`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaReport {
+    /// The number of sampled records.
+    pub records: u64,
+    /// The profiles of all fields seen in the sample, ordered by name.
+    pub fields: Vec<FieldProfile>,
+    /// The number format text numbers are written in: [`NumberFormat::EUROPEAN`] if a number
+    /// field only parses that way, [`NumberFormat::US`] otherwise. Pass it to
+    /// [`Filemaker::with_number_format`](crate::Filemaker::with_number_format).
+    pub number_format: NumberFormat,
+}
+
+impl SchemaReport {
+    /// Returns the profile of the field `name`, if it occurred in the sample.
+    pub fn field(&self, name: &str) -> Option<&FieldProfile> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+/// Guesses the type, value range and null rate of every field of a sample of records.
+///
+/// Records may be raw records as returned by the Data API or plain field maps. A field is
+/// given the first of number, date, timestamp and boolean that all of its non-empty values
+/// fit, and text otherwise; a single stray value makes it text, so clean samples give the
+/// most useful results. Fields missing from a record count as empty.
+///
+/// ```rust,ignore
+/// let sample = filemaker.sample_records(200, SampleStrategy::Reservoir).await?;
+/// let schema = infer_schema(&sample);
+/// let filemaker = filemaker.with_number_format(schema.number_format);
+/// ```
+///
+/// # Arguments
+/// * `sample` - The sampled records
+///
+/// # Returns
+/// * `SchemaReport` - The profile of every field seen
+pub fn infer_schema(sample: &[Value]) -> SchemaReport {
+    let mut values: BTreeMap<&str, Vec<&Value>> = BTreeMap::new();
+    for record in sample {
+        let fields = record.get("fieldData").unwrap_or(record);
+        for (name, value) in fields.as_object().into_iter().flatten() {
+            let present = values.entry(name).or_default();
+            if !is_empty(value) {
+                present.push(value);
+            }
+        }
+    }
+
+    let records = sample.len() as u64;
+    let mut number_format = NumberFormat::US;
+    let fields = values
+        .into_iter()
+        .map(|(name, values)| {
+            let (field_type, range) = match numbers(&values, &NumberFormat::US) {
+                Some(range) => (InferredType::Number, Some(range)),
+                None => match numbers(&values, &NumberFormat::EUROPEAN) {
+                    Some(range) => {
+                        number_format = NumberFormat::EUROPEAN;
+                        (InferredType::Number, Some(range))
+                    }
+                    None => classify(&values),
+                },
+            };
+            FieldProfile {
+                name: name.to_string(),
+                field_type,
+                records,
+                empty: records - values.len() as u64,
+                range,
+            }
+        })
+        .collect();
+
+    SchemaReport {
+        records,
+        fields,
+        number_format,
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(text) => text.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// Returns the range of the values if all of them are numbers in `format`.
+fn numbers(values: &[&Value], format: &NumberFormat) -> Option<ValueRange> {
+    let numbers: Vec<f64> = values
+        .iter()
+        .map(|value| to_number(value, format).ok().flatten())
+        .collect::<Option<_>>()?;
+    let min = numbers.iter().copied().reduce(f64::min)?;
+    let max = numbers.iter().copied().reduce(f64::max)?;
+    Some(ValueRange::Number { min, max })
+}
+
+/// Classifies values that are not all numbers.
+fn classify(values: &[&Value]) -> (InferredType, Option<ValueRange>) {
+    if values.is_empty() {
+        return (InferredType::Empty, None);
+    }
+    let dates: Option<Vec<NaiveDate>> = values
+        .iter()
+        .map(|value| to_date(value).ok().flatten())
+        .collect();
+    if let Some(dates) = dates {
+        let (min, max) = (dates.iter().min(), dates.iter().max());
+        let range = min.zip(max).map(|(min, max)| ValueRange::Date {
+            min: *min,
+            max: *max,
+        });
+        return (InferredType::Date, range);
+    }
+    let timestamps: Option<Vec<NaiveDateTime>> = values
+        .iter()
+        .map(|value| to_naive_timestamp(value).ok().flatten())
+        .collect();
+    if let Some(timestamps) = timestamps {
+        let (min, max) = (timestamps.iter().min(), timestamps.iter().max());
+        let range = min.zip(max).map(|(min, max)| ValueRange::Timestamp {
+            min: *min,
+            max: *max,
+        });
+        return (InferredType::Timestamp, range);
+    }
+    if values.iter().all(|value| is_bool_word(value)) {
+        return (InferredType::Bool, None);
+    }
+    let lengths: Vec<usize> = values
+        .iter()
+        .map(|value| match value {
+            Value::String(text) => text.chars().count(),
+            other => other.to_string().chars().count(),
+        })
+        .collect();
+    let (min, max) = (lengths.iter().min(), lengths.iter().max());
+    let range = min.zip(max).map(|(min, max)| ValueRange::TextLength {
+        min: *min,
+        max: *max,
+    });
+    (InferredType::Text, range)
+}
+
+fn is_bool_word(value: &Value) -> bool {
+    match value {
+        Value::Bool(_) => true,
+        Value::String(text) => matches!(
+            text.trim().to_ascii_lowercase().as_str(),
+            "yes" | "no" | "y" | "n" | "true" | "false"
+        ),
+        _ => false,
+    }
+}
@@ -0,0 +1,73 @@
+//! Running FileMaker scripts through the script endpoint.
+
+use crate::{query, Filemaker};
+use anyhow::Result;
+use log::*;
+use reqwest::Method;
+
+/// The outcome of a script run with [`Filemaker::run_script`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptResult {
+    /// The FileMaker error the script ended with, `0` if it succeeded.
+    pub error_code: u32,
+    /// The value the script returned with `Exit Script`, if any.
+    pub result: Option<String>,
+}
+
+impl ScriptResult {
+    /// Returns whether the script ended without an error.
+    pub fn succeeded(&self) -> bool {
+        self.error_code == 0
+    }
+}
+
+impl Filemaker {
+    /// Runs a script in the context of the layout, without a find or record request.
+    ///
+    /// A script that ends with an error does not fail the call; its code is returned in
+    /// [`ScriptResult::error_code`]. The call fails if the Data API rejects the request.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the script
+    /// * `parameter` - The script parameter, passed as `script.param`
+    ///
+    /// # Returns
+    /// * `Result<ScriptResult>` - The script's error code and result, or an error
+    pub async fn run_script(&self, name: &str, parameter: Option<&str>) -> Result<ScriptResult> {
+        let mut url = format!(
+            "{}/databases/{}/layouts/{}/script/{}",
+            self.base_url()?,
+            self.database,
+            self.table,
+            query::encode_url_component(name)
+        );
+        if let Some(parameter) = parameter {
+            url.push_str("?script.param=");
+            url.push_str(&query::encode_url_component(parameter));
+        }
+
+        debug!("Running script {} on layout {}", name, self.layout());
+        let response = self.authenticated_request(&url, Method::GET, None).await?;
+        let Some(result) = response
+            .get("response")
+            .filter(|_| Self::api_error(&response).is_none())
+        else {
+            error!("Failed to run script {}: {:?}", name, response);
+            return Err(Self::response_error(&response, "Failed to run script"));
+        };
+
+        let error_code = result
+            .get("scriptError")
+            .and_then(|e| e.as_str())
+            .and_then(|e| e.parse().ok())
+            .unwrap_or(0);
+        let result = result
+            .get("scriptResult")
+            .and_then(|r| r.as_str())
+            .map(str::to_string);
+        if error_code != 0 {
+            warn!("Script {} ended with error {}", name, error_code);
+        }
+        Ok(ScriptResult { error_code, result })
+    }
+}
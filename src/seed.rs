@@ -0,0 +1,120 @@
+//! Populating layouts with generated records, e.g. before load tests.
+
+use crate::{Filemaker, FmRecordModel, Repository};
+use anyhow::Result;
+use log::*;
+use tokio::task::JoinSet;
+
+/// Outcome of seeding a layout.
+#[derive(Debug, Default)]
+pub struct SeedSummary {
+    /// The IDs of the created records, in generation order.
+    pub created: Vec<String>,
+    /// Generated records that could not be created, by index, together with the reason.
+    pub failed: Vec<(usize, anyhow::Error)>,
+}
+
+impl Filemaker {
+    /// Creates `n` generated records in the layout of model `T`.
+    ///
+    /// See [`Repository::seed`].
+    pub async fn seed<T, F>(
+        &self,
+        n: usize,
+        concurrency: usize,
+        generator: F,
+    ) -> Result<SeedSummary>
+    where
+        T: FmRecordModel,
+        F: FnMut(usize) -> T,
+    {
+        self.repository::<T>().seed(n, concurrency, generator).await
+    }
+}
+
+impl<T: FmRecordModel> Repository<T> {
+    /// Creates `n` records produced by `generator`, running up to `concurrency` creates in
+    /// parallel.
+    ///
+    /// The generator is called with the index of each record. A failing record does not
+    /// abort seeding; it is reported in [`SeedSummary::failed`].
+    ///
+    /// # Arguments
+    /// * `n` - The number of records to create
+    /// * `concurrency` - The maximum number of create requests in flight at once (at least 1)
+    /// * `generator` - Produces the record with the given index
+    ///
+    /// # Returns
+    /// * `Result<SeedSummary>` - The created record IDs and the failures
+    pub async fn seed<F>(
+        &self,
+        n: usize,
+        concurrency: usize,
+        mut generator: F,
+    ) -> Result<SeedSummary>
+    where
+        F: FnMut(usize) -> T,
+    {
+        let limiter = self.filemaker().limiter(concurrency, None);
+        let mut tasks = JoinSet::new();
+        for index in 0..n {
+            let data = generator(index);
+            let repository = self.clone();
+            let mut permit = limiter.acquire().await;
+            tasks.spawn(async move {
+                let result = repository.insert(&data).await;
+                if let Err(e) = &result {
+                    permit.observe_error(e);
+                }
+                drop(permit);
+                (index, result.map(|record| record.record_id))
+            });
+        }
+
+        let mut created = Vec::with_capacity(n);
+        let mut summary = SeedSummary::default();
+        while let Some(joined) = tasks.join_next().await {
+            match joined? {
+                (index, Ok(record_id)) => created.push((index, record_id)),
+                (index, Err(e)) => {
+                    error!("Failed to seed record {}: {}", index, e);
+                    summary.failed.push((index, e));
+                }
+            }
+        }
+        created.sort_by_key(|(index, _)| *index);
+        summary.created = created.into_iter().map(|(_, id)| id).collect();
+        summary.failed.sort_by_key(|(index, _)| *index);
+
+        info!(
+            "Seeded {} records into {}, {} failed",
+            summary.created.len(),
+            T::LAYOUT,
+            summary.failed.len()
+        );
+        Ok(summary)
+    }
+
+    /// Creates `n` records with random field data generated by the `fake` crate.
+    ///
+    /// Requires the `fake` feature.
+    ///
+    /// ```rust,ignore
+    /// #[derive(serde::Serialize, serde::Deserialize, fake::Dummy)]
+    /// struct Customer {
+    ///     #[dummy(faker = "fake::faker::name::en::Name()")]
+    ///     #[serde(rename = "Name")]
+    ///     name: String,
+    /// }
+    ///
+    /// filemaker.repository::<Customer>().seed_fake(10_000, 8).await?;
+    /// ```
+    #[cfg(feature = "fake")]
+    pub async fn seed_fake(&self, n: usize, concurrency: usize) -> Result<SeedSummary>
+    where
+        T: fake::Dummy<fake::Faker>,
+    {
+        use fake::{Fake, Faker};
+        self.seed(n, concurrency, |_| Faker.fake::<T>()).await
+    }
+}
@@ -0,0 +1,347 @@
+//! Replacing expired sessions and ending sessions.
+//!
+//! When a session expires, every request in flight fails with error 952 at about the same
+//! time. Only the first of them creates a new session; the others wait for it and retry with
+//! the fresh token, so the server sees one login rather than one per request.
+//!
+//! Clients created with [`Filemaker::new`] log in again with the credentials they were
+//! created with, or those of a provider set with [`Filemaker::with_credential_provider`];
+//! clients created with [`Filemaker::new_with_fmid_token`] log in with a Claris ID token;
+//! clients created with [`Filemaker::new_with_endpoints`] re-create the session on the
+//! healthiest endpoint.
+//!
+//! Sessions are ended with [`Filemaker::logout`], or by a [`FilemakerSession`] guard when it
+//! is closed or dropped.
+
+use crate::accounting;
+use crate::failover::EndpointPool;
+use crate::{Filemaker, REDACTED};
+use anyhow::{anyhow, Result};
+use log::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Supplies the username and password to log in with when a session expires.
+pub(crate) type Credentials = Arc<dyn Fn() -> Result<(String, String)> + Send + Sync>;
+
+/// Returns credentials that are always `username` and `password`.
+pub(crate) fn fixed_credentials(username: &str, password: &str) -> Credentials {
+    let (username, password) = (username.to_string(), password.to_string());
+    Arc::new(move || Ok((username.clone(), password.clone())))
+}
+
+/// Options sent with every login of a client, see [`Filemaker::new_with_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionOptions {
+    /// Credentials for externally referenced FileMaker files, sent as `fmDataSource`.
+    ///
+    /// Solutions whose related tables live in other files need these to read them; without
+    /// them the Data API cannot open the external file.
+    pub data_sources: Vec<DataSource>,
+}
+
+impl SessionOptions {
+    /// Adds the credentials of an external FileMaker data source.
+    ///
+    /// # Arguments
+    /// * `database` - The name of the external file, as referenced by the solution
+    /// * `username` - The account name in the external file
+    /// * `password` - The password of the account
+    pub fn with_data_source(
+        mut self,
+        database: impl Into<String>,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.data_sources.push(DataSource {
+            database: database.into(),
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Returns the body of a login request.
+    pub(crate) fn body(&self) -> Value {
+        if self.data_sources.is_empty() {
+            return json!({});
+        }
+        let sources: Vec<Value> = self
+            .data_sources
+            .iter()
+            .map(|source| {
+                json!({
+                    "database": source.database,
+                    "username": source.username,
+                    "password": source.password,
+                })
+            })
+            .collect();
+        json!({ "fmDataSource": sources })
+    }
+}
+
+/// The credentials of an external FileMaker data source.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DataSource {
+    /// The name of the external file.
+    pub database: String,
+    /// The account name in the external file.
+    pub username: String,
+    /// The password of the account.
+    pub password: String,
+}
+
+impl std::fmt::Debug for DataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataSource")
+            .field("database", &self.database)
+            .field("username", &self.username)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+/// The FileMaker error code for an invalid or expired session token.
+const INVALID_TOKEN_CODE: &str = "952";
+
+impl Filemaker {
+    /// Returns whether a response was rejected because its session token is no longer valid.
+    pub(crate) fn is_session_expired(response: &Value) -> bool {
+        Self::first_message_code(response) == Some(INVALID_TOKEN_CODE)
+    }
+
+    /// Sets the provider of the credentials used to log in again when the session expires.
+    ///
+    /// By default a client logs in again with the credentials it was created with. A
+    /// provider is asked each time a new session is needed, so rotated passwords, e.g. read
+    /// from a secret store, are picked up without recreating the client.
+    ///
+    /// # Arguments
+    /// * `provider` - Returns the username and password to log in with
+    ///
+    /// # Returns
+    /// * `Self` - The client using the provider
+    pub fn with_credential_provider<F>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Result<(String, String)> + Send + Sync + 'static,
+    {
+        debug!("Using a credential provider for new sessions");
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    /// Returns the username and password to create a new session on `pool` with.
+    pub(crate) fn login_credentials(&self, pool: &EndpointPool) -> Result<(String, String)> {
+        match &self.credentials {
+            Some(provider) => provider(),
+            None => Ok((pool.username().to_string(), pool.password().to_string())),
+        }
+    }
+
+    /// Returns whether the client can create a new session without failover endpoints.
+    fn can_log_in_again(&self) -> bool {
+        self.credentials.is_some() || self.fmid_tokens.is_some()
+    }
+
+    /// Sends a request, logging in again and retrying once if the session expired.
+    ///
+    /// Used by clients without failover endpoints; see [`Filemaker::failover_request`].
+    pub(crate) async fn reauthenticating_request(
+        &self,
+        url: &str,
+        method: Method,
+        body: Option<Value>,
+    ) -> Result<Value> {
+        let token = self.token.lock().await.clone();
+        let response = self
+            .send_authenticated_request(url, method.clone(), body.clone())
+            .await?;
+        match token {
+            Some(stale) if self.can_log_in_again() && Self::is_session_expired(&response) => {
+                self.refresh_session(None, &stale).await?;
+                self.record_retry();
+                self.send_authenticated_request(url, method, body).await
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Replaces the session whose token was `stale` with a new one, on the healthiest
+    /// endpoint of `pool` if the client fails over.
+    ///
+    /// Refreshes are single-flight: a request that finds the token already replaced while
+    /// waiting for its turn reuses the new session instead of creating another.
+    pub(crate) async fn refresh_session(
+        &self,
+        pool: Option<&EndpointPool>,
+        stale: &str,
+    ) -> Result<()> {
+        let _guard = self.session_refresh.lock().await;
+        if self.token.lock().await.as_deref() != Some(stale) {
+            debug!("Session was already refreshed by another request");
+            return Ok(());
+        }
+        accounting::closed(stale);
+        if self.promote_standby().await {
+            info!("Session expired, switched to the standby session");
+            return Ok(());
+        }
+        info!("Session expired, creating a new one");
+        match (pool, &self.fmid_tokens, &self.credentials) {
+            (Some(pool), _, _) => {
+                self.pin_session(pool, None).await?;
+            }
+            (None, Some(provider), _) => {
+                let id_token = provider()?;
+                let token = Self::get_fmid_session_token(
+                    &self.client,
+                    &self.base_url()?,
+                    &self.database(),
+                    &id_token,
+                    &self.session_options,
+                )
+                .await?;
+                *self.token.lock().await = Some(token);
+            }
+            (None, None, Some(provider)) => {
+                let (username, password) = provider()?;
+                let token = Self::get_session_token_at(
+                    &self.client,
+                    &self.base_url()?,
+                    &self.database(),
+                    &username,
+                    &password,
+                    &self.session_options,
+                )
+                .await?;
+                *self.token.lock().await = Some(token);
+            }
+            (None, None, None) => return Err(anyhow!("No credentials to log in again with")),
+        }
+        Ok(())
+    }
+
+    /// Ends the session on the server and clears the session token.
+    ///
+    /// FileMaker Server caps the number of concurrent Data API sessions, and a session that
+    /// is not ended stays open until it expires after a period without use. Clones share the
+    /// session, so none of them can send requests afterwards. A warm standby session is
+    /// ended too. Logging out of a client without a session does nothing.
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok once the session is ended, or an error if the server rejects the request
+    pub async fn logout(&self) -> Result<()> {
+        let _guard = self.session_refresh.lock().await;
+        if let Some(standby) = &self.standby {
+            standby.close().await;
+        }
+        let Some(token) = self.token.lock().await.take() else {
+            debug!("No session to log out of");
+            return Ok(());
+        };
+        accounting::closed(&token);
+        let url = format!(
+            "{}/databases/{}/sessions/{}",
+            self.base_url()?,
+            self.database,
+            token
+        );
+        let response: Value = self
+            .client
+            .delete(&url)
+            .send()
+            .await
+            .map_err(|e| anyhow!(e).context("Failed to end session"))?
+            .json()
+            .await?;
+        if let Some(api_error) = Self::api_error(&response) {
+            error!("Failed to end session: {}", api_error);
+            return Err(anyhow::Error::new(api_error).context("Failed to end session"));
+        }
+        info!("Logged out of database {}", self.database());
+        Ok(())
+    }
+}
+
+/// A client whose session is ended when the guard is closed or dropped.
+///
+/// Clones of a [`Filemaker`] share its session, so the client itself cannot log out when it
+/// goes out of scope. Short-lived clients can be wrapped in this guard instead, so their
+/// sessions do not pile up on the server until it refuses new ones. Created with
+/// [`Filemaker::into_session`]; dereferences to the client.
+///
+/// Prefer [`FilemakerSession::close`], which waits for the logout and reports its errors.
+/// Dropping the guard only spawns the logout on the current Tokio runtime, which may not run
+/// if the runtime shuts down first; outside a runtime the session stays open until it
+/// expires.
+///
+/// ```rust,ignore
+/// let session = Filemaker::new("user", "pass", "Contacts", "People").await?.into_session();
+/// let records = session.get_records(1, 10).await?;
+/// session.close().await?;
+/// ```
+pub struct FilemakerSession {
+    filemaker: Option<Filemaker>,
+}
+
+impl FilemakerSession {
+    /// Ends the session and waits for the server to confirm it.
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok once the session is ended, or an error if the server rejects the request
+    pub async fn close(mut self) -> Result<()> {
+        match self.filemaker.take() {
+            Some(filemaker) => filemaker.logout().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for FilemakerSession {
+    type Target = Filemaker;
+
+    fn deref(&self) -> &Filemaker {
+        self.filemaker
+            .as_ref()
+            .expect("the client is only taken when the session is closed")
+    }
+}
+
+impl Drop for FilemakerSession {
+    fn drop(&mut self) {
+        let Some(filemaker) = self.filemaker.take() else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                debug!("Session guard dropped, logging out in the background");
+                runtime.spawn(async move {
+                    if let Err(e) = filemaker.logout().await {
+                        warn!("Failed to end the session of a dropped client: {:#}", e);
+                    }
+                });
+            }
+            Err(_) => warn!(
+                "Session guard dropped outside a Tokio runtime, the session stays open until it expires"
+            ),
+        }
+    }
+}
+
+impl Filemaker {
+    /// Wraps the client in a guard that ends its session when closed or dropped.
+    ///
+    /// Clones made before or from the guard share the session and stop working once it
+    /// ends.
+    ///
+    /// # Returns
+    /// * `FilemakerSession` - The guard owning the client
+    pub fn into_session(self) -> FilemakerSession {
+        FilemakerSession {
+            filemaker: Some(self),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! End-to-end smoke tests of a server, e.g. after upgrading FileMaker Server.
+
+use crate::import::key_string;
+use crate::{query, DeleteOptions, Filemaker};
+use anyhow::{anyhow, Result};
+use log::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Configures [`run_smoke`]: the server to test and the record to test it with.
+#[derive(Debug, Clone, Default)]
+pub struct SmokeConfig {
+    /// The base URL of the Data API, e.g. `https://fm.example.com/fmi/data/vLatest`.
+    pub endpoint: String,
+    /// The username to log in with.
+    pub username: String,
+    /// The password to log in with.
+    pub password: String,
+    /// The database to test.
+    pub database: String,
+    /// The layout the test record is created on.
+    pub layout: String,
+    /// The field data of the test record; the find step searches for all of it.
+    pub record: HashMap<String, Value>,
+    /// The field data written by the update step.
+    pub update: HashMap<String, Value>,
+    /// A container field to download from; the container step is skipped without one.
+    pub container_field: Option<String>,
+    /// A script to run on the layout; the script step is skipped without one.
+    pub script: Option<String>,
+}
+
+impl SmokeConfig {
+    /// Creates a configuration for the given server and layout, with an empty test record.
+    pub fn new(
+        endpoint: impl Into<String>,
+        username: impl Into<String>,
+        password: impl Into<String>,
+        database: impl Into<String>,
+        layout: impl Into<String>,
+    ) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            username: username.into(),
+            password: password.into(),
+            database: database.into(),
+            layout: layout.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The outcome of one step of a smoke test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmokeOutcome {
+    /// The step succeeded.
+    Passed,
+    /// The step failed, for the given reason.
+    Failed(String),
+    /// The step was not run, for the given reason.
+    Skipped(String),
+}
+
+/// A step of a smoke test and how it went.
+#[derive(Debug, Clone)]
+pub struct SmokeStep {
+    /// The name of the step, e.g. `create`.
+    pub name: &'static str,
+    /// Whether the step passed.
+    pub outcome: SmokeOutcome,
+    /// How long the step took.
+    pub duration: Duration,
+}
+
+/// The outcome of [`run_smoke`], printable as a pass/fail report.
+#[derive(Debug, Clone, Default)]
+pub struct SmokeReport {
+    /// The steps, in the order they ran.
+    pub steps: Vec<SmokeStep>,
+}
+
+impl SmokeReport {
+    /// Returns whether no step failed.
+    pub fn passed(&self) -> bool {
+        !self
+            .steps
+            .iter()
+            .any(|step| matches!(step.outcome, SmokeOutcome::Failed(_)))
+    }
+
+    fn count(&self, matching: fn(&SmokeOutcome) -> bool) -> usize {
+        self.steps.iter().filter(|s| matching(&s.outcome)).count()
+    }
+
+    async fn step<T, F>(&mut self, name: &'static str, step: F) -> Option<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let started = Instant::now();
+        let result = step.await;
+        let duration = started.elapsed();
+        let (outcome, value) = match result {
+            Ok(value) => (SmokeOutcome::Passed, Some(value)),
+            Err(e) => {
+                error!("Smoke step {} failed: {:#}", name, e);
+                (SmokeOutcome::Failed(format!("{:#}", e)), None)
+            }
+        };
+        self.steps.push(SmokeStep {
+            name,
+            outcome,
+            duration,
+        });
+        value
+    }
+
+    fn skip(&mut self, name: &'static str, reason: &str) {
+        self.steps.push(SmokeStep {
+            name,
+            outcome: SmokeOutcome::Skipped(reason.to_string()),
+            duration: Duration::ZERO,
+        });
+    }
+}
+
+impl fmt::Display for SmokeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            let millis = step.duration.as_millis();
+            match &step.outcome {
+                SmokeOutcome::Passed => writeln!(f, "PASS  {:<10} {:>6} ms", step.name, millis)?,
+                SmokeOutcome::Failed(reason) => {
+                    writeln!(f, "FAIL  {:<10} {:>6} ms  {}", step.name, millis, reason)?
+                }
+                SmokeOutcome::Skipped(reason) => {
+                    writeln!(f, "SKIP  {:<10}           {}", step.name, reason)?
+                }
+            }
+        }
+        write!(
+            f,
+            "{} passed, {} failed, {} skipped",
+            self.count(|o| *o == SmokeOutcome::Passed),
+            self.count(|o| matches!(o, SmokeOutcome::Failed(_))),
+            self.count(|o| matches!(o, SmokeOutcome::Skipped(_)))
+        )
+    }
+}
+
+/// Runs an end-to-end scenario against a server and reports each step.
+///
+/// The steps are: `connect`, `create` the test record, `find` it by its field data, `update`
+/// it and read the update back, download a `container`, run a `script`, and `delete` the test
+/// record. A failing step does not stop the scenario, except that steps needing the session
+/// or the test record are skipped without them; the record is deleted whenever it was
+/// created. The `fmsmoke` binary runs this with settings from the environment.
+///
+/// # Arguments
+/// * `config` - The server, layout and test record
+///
+/// # Returns
+/// * `SmokeReport` - The outcome of every step
+pub async fn run_smoke(config: &SmokeConfig) -> SmokeReport {
+    let mut report = SmokeReport::default();
+    let connect = Filemaker::new_with_endpoints(
+        [config.endpoint.as_str()],
+        &config.username,
+        &config.password,
+        &config.database,
+        &config.layout,
+    );
+    let Some(filemaker) = report.step("connect", connect).await else {
+        for name in ["create", "find", "update", "container", "script", "delete"] {
+            report.skip(name, "not connected");
+        }
+        return report;
+    };
+
+    let created = report
+        .step("create", filemaker.create_record_ids(config.record.clone()))
+        .await;
+    match &created {
+        Some(created) => {
+            report
+                .step("find", find_record(&filemaker, config, &created.record_id))
+                .await;
+            report
+                .step(
+                    "update",
+                    update_record(&filemaker, config, &created.record_id),
+                )
+                .await;
+        }
+        None => {
+            report.skip("find", "no test record");
+            report.skip("update", "no test record");
+        }
+    }
+    match &config.container_field {
+        Some(field) => {
+            report
+                .step("container", download_container(&filemaker, field))
+                .await;
+        }
+        None => report.skip("container", "no container field configured"),
+    }
+    match &config.script {
+        Some(script) => {
+            report.step("script", run_script(&filemaker, script)).await;
+        }
+        None => report.skip("script", "no script configured"),
+    }
+    match &created {
+        Some(created) => {
+            let options = DeleteOptions::default();
+            let delete = filemaker.delete_record_with(&created.record_id, &options);
+            report.step("delete", delete).await;
+        }
+        None => report.skip("delete", "no test record"),
+    }
+
+    match report.passed() {
+        true => info!("Smoke test of {} passed", config.endpoint),
+        false => warn!("Smoke test of {} failed", config.endpoint),
+    }
+    report
+}
+
+/// Finds the test record by all of its field data.
+async fn find_record(filemaker: &Filemaker, config: &SmokeConfig, record_id: &str) -> Result<()> {
+    let criteria = config
+        .record
+        .iter()
+        .map(|(field, value)| {
+            let value = format!("=={}", query::escape_find_value(&key_string(value)));
+            (field.clone(), value)
+        })
+        .collect();
+    let found = filemaker
+        .search::<Value>(vec![criteria], vec![], true, None)
+        .await?;
+    match found.response.data.iter().any(|r| r.record_id == record_id) {
+        true => Ok(()),
+        false => Err(anyhow!("Record {} was not found", record_id)),
+    }
+}
+
+/// Updates the test record and checks that the new values read back.
+async fn update_record(filemaker: &Filemaker, config: &SmokeConfig, record_id: &str) -> Result<()> {
+    filemaker
+        .update_record_guarded(record_id, config.update.clone(), None)
+        .await?;
+    let record = filemaker.get_record_by_id(record_id).await?;
+    let fields = &record["fieldData"];
+    for (field, expected) in &config.update {
+        let actual = fields.get(field).map(key_string).unwrap_or_default();
+        if actual != key_string(expected) {
+            return Err(anyhow!(
+                "{} reads back as '{}' instead of '{}'",
+                field,
+                actual,
+                key_string(expected)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the container of the first record with a non-empty `field`.
+async fn download_container(filemaker: &Filemaker, field: &str) -> Result<()> {
+    let criteria = HashMap::from([(field.to_string(), "*".to_string())]);
+    let found = filemaker
+        .search::<Value>(vec![criteria], vec![], true, Some(1))
+        .await?;
+    let url = found
+        .response
+        .data
+        .first()
+        .and_then(|record| record.data.get(field))
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("No record has a value in container field {}", field))?;
+    let response = filemaker.client.get(url).send().await?.error_for_status()?;
+    let bytes = response.bytes().await?;
+    debug!(
+        "Downloaded {} bytes from container field {}",
+        bytes.len(),
+        field
+    );
+    Ok(())
+}
+
+/// Runs `script`, failing if it ends with an error.
+async fn run_script(filemaker: &Filemaker, script: &str) -> Result<()> {
+    let result = filemaker.run_script(script, None).await?;
+    match result.succeeded() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "Script {} ended with error {}",
+            script,
+            result.error_code
+        )),
+    }
+}
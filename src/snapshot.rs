@@ -0,0 +1,563 @@
+//! Snapshots of a table to a directory, with a manifest verified on restore.
+
+use crate::budget::BudgetTracker;
+use crate::compression::{BoxedReader, BoxedWriter};
+use crate::import::key_string;
+use crate::{
+    Compression, DeleteSummary, DuplicatePolicy, ExportSink, FieldMapping, Filemaker,
+    ImportOptions, ImportSummary, StreamOptions,
+};
+#[cfg(feature = "encryption")]
+use crate::{DecryptingReader, EncryptingWriter, EncryptionKey};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+/// The name of the manifest file in a snapshot directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The version of the snapshot format written by [`Filemaker::snapshot_table`].
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Describes a snapshot, written next to its records as `manifest.json`.
+///
+/// [`Filemaker::restore_table`] checks the records against the manifest before writing any
+/// of them, so a snapshot that was modified, truncated or mixed up with another one is
+/// rejected instead of restored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    /// The version of the snapshot format.
+    pub version: u32,
+    /// The Data API the snapshot was taken from.
+    pub server: String,
+    /// The database the snapshot was taken from.
+    pub database: String,
+    /// The layout the snapshot was taken from.
+    pub layout: String,
+    /// When the snapshot was taken.
+    pub created_at: DateTime<Utc>,
+    /// The number of records in the snapshot.
+    pub record_count: u64,
+    /// The fields of the layout, in layout order.
+    pub fields: Vec<String>,
+    /// The name of the records file in the snapshot directory, e.g. `records.jsonl.gz`.
+    pub records_file: String,
+    /// Whether the records file is encrypted.
+    pub encrypted: bool,
+    /// The SHA-256 checksum of the records file as stored, in hexadecimal.
+    pub file_sha256: String,
+    /// The SHA-256 checksum of the records as JSON Lines, before compression and encryption.
+    pub records_sha256: String,
+}
+
+impl SnapshotManifest {
+    /// Reads the manifest of the snapshot in `directory`.
+    pub async fn load(directory: impl AsRef<Path>) -> Result<Self> {
+        let path = directory.as_ref().join(MANIFEST_FILE);
+        let manifest: Self = serde_json::from_slice(&tokio::fs::read(&path).await?)
+            .map_err(|e| anyhow!("Invalid snapshot manifest {}: {}", path.display(), e))?;
+        if manifest.version != SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "Unsupported snapshot version {} in {}",
+                manifest.version,
+                path.display()
+            ));
+        }
+        Ok(manifest)
+    }
+}
+
+/// Configures [`Filemaker::snapshot_table`].
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotOptions {
+    /// Page size and buffering limits of reading the table.
+    pub stream: StreamOptions,
+    /// How the records file is compressed.
+    pub compression: Compression,
+    /// The key to encrypt the records file with; `None` leaves it unencrypted.
+    #[cfg(feature = "encryption")]
+    pub key: Option<EncryptionKey>,
+}
+
+/// Decides whether a record of a snapshot is restored, given its field data.
+pub type RestoreFilter = Arc<dyn Fn(&HashMap<String, Value>) -> bool + Send + Sync>;
+
+/// Configures [`Filemaker::restore_table`].
+///
+/// To restore a snapshot taken before fields were renamed, map the old names to the new
+/// ones; the filter sees records under their names in the snapshot:
+///
+/// ```rust,ignore
+/// let options = RestoreOptions::default()
+///     .with_mapping(FieldMapping::new().map("Phone", "Phone Number").ignore("Fax"))
+///     .with_filter(|record| record.get("Status") != Some(&json!("Deleted")));
+/// ```
+#[derive(Clone, Default)]
+pub struct RestoreOptions {
+    /// How records are written, e.g. a key field matching them to existing records.
+    ///
+    /// Without a key field every record of the snapshot is created. The key field is named
+    /// as in the layout, after `mapping` is applied.
+    pub import: ImportOptions,
+    /// Renames or drops the fields of the snapshot to fit the layout.
+    pub mapping: FieldMapping,
+    /// Skips the records it returns `false` for; `None` restores every record.
+    pub filter: Option<RestoreFilter>,
+    /// Whether to delete the records of the layout whose key is not in the snapshot.
+    ///
+    /// Requires a key field in `import`.
+    pub delete_missing: bool,
+    /// The maximum number of delete requests in flight at once (at least 1).
+    pub delete_concurrency: usize,
+    /// Whether to only compare the snapshot with the layout and report the planned changes.
+    pub dry_run: bool,
+    /// The key the records file was encrypted with.
+    #[cfg(feature = "encryption")]
+    pub key: Option<EncryptionKey>,
+}
+
+impl std::fmt::Debug for RestoreOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("RestoreOptions");
+        debug
+            .field("import", &self.import)
+            .field("mapping", &self.mapping)
+            .field("filter", &self.filter.is_some())
+            .field("delete_missing", &self.delete_missing)
+            .field("delete_concurrency", &self.delete_concurrency)
+            .field("dry_run", &self.dry_run);
+        #[cfg(feature = "encryption")]
+        debug.field("key", &self.key);
+        debug.finish()
+    }
+}
+
+impl RestoreOptions {
+    /// Writes the fields of the snapshot per `mapping`.
+    pub fn with_mapping(mut self, mapping: FieldMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    /// Restores only the records `filter` returns `true` for.
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&HashMap<String, Value>) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+}
+
+/// The changes a restore makes to the layout, found by comparing the snapshot with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RestorePlan {
+    /// The number of records to create, because no record of the layout has their key.
+    pub creates: u64,
+    /// The number of records to update, because their field data differs from the snapshot.
+    pub updates: u64,
+    /// The number of records left as they are.
+    pub unchanged: u64,
+    /// The number of records of the layout to delete, because their key is not in the
+    /// snapshot.
+    pub deletes: u64,
+}
+
+impl std::fmt::Display for RestorePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} to create, {} to update, {} unchanged, {} to delete",
+            self.creates, self.updates, self.unchanged, self.deletes
+        )
+    }
+}
+
+/// Outcome of [`Filemaker::restore_table`].
+#[derive(Debug)]
+pub struct RestoreReport {
+    /// The manifest of the restored snapshot.
+    pub manifest: SnapshotManifest,
+    /// The number of records skipped by the filter.
+    pub filtered: u64,
+    /// The planned changes, if the snapshot was compared with the layout.
+    ///
+    /// Set for dry runs and when deleting missing records.
+    pub plan: Option<RestorePlan>,
+    /// Whether this was a dry run that wrote nothing.
+    pub dry_run: bool,
+    /// The records created, updated and skipped.
+    pub summary: ImportSummary,
+    /// The records deleted because their key is not in the snapshot.
+    pub deleted: DeleteSummary,
+}
+
+/// Writes records as JSON Lines while checksumming them.
+struct SnapshotSink<'a> {
+    writer: BoxedWriter<'a>,
+    hasher: Sha256,
+    fields: Vec<String>,
+}
+
+impl ExportSink for SnapshotSink<'_> {
+    async fn start(&mut self, fields: &[String]) -> Result<()> {
+        self.fields = fields.to_vec();
+        Ok(())
+    }
+
+    async fn write_record(&mut self, record: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.hasher.update(&line);
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        self.writer.shutdown().await?;
+        Ok(())
+    }
+}
+
+impl Filemaker {
+    /// Takes a snapshot of every record of the table into `directory`.
+    ///
+    /// The records are written as JSON Lines to `records.jsonl`, compressed and encrypted per
+    /// `options`, next to a [`SnapshotManifest`] holding the record count, the field list,
+    /// checksums of the records, the source server, database and layout, and the time.
+    /// Existing snapshot files in `directory` are replaced.
+    ///
+    /// # Arguments
+    /// * `directory` - The directory to write the snapshot to; created if missing
+    /// * `options` - Paging, compression and encryption settings
+    ///
+    /// # Returns
+    /// * `Result<SnapshotManifest>` - The manifest of the snapshot, or an error
+    pub async fn snapshot_table(
+        &self,
+        directory: impl AsRef<Path>,
+        options: SnapshotOptions,
+    ) -> Result<SnapshotManifest> {
+        let directory = directory.as_ref();
+        tokio::fs::create_dir_all(directory).await?;
+        let created_at = Utc::now();
+        #[cfg(feature = "encryption")]
+        let encrypted = options.key.is_some();
+        #[cfg(not(feature = "encryption"))]
+        let encrypted = false;
+        let records_file = format!(
+            "records.jsonl{}{}",
+            options.compression.extension(),
+            if encrypted { ".enc" } else { "" }
+        );
+        let path = directory.join(&records_file);
+
+        let file = tokio::fs::File::create(&path).await?;
+        let mut sink = SnapshotSink {
+            writer: options.compression.wrap(encrypt(file, &options)),
+            hasher: Sha256::new(),
+            fields: vec![],
+        };
+        let record_count = self.export_to(&mut sink, options.stream).await?;
+
+        let manifest = SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            server: self.base_url().unwrap_or_default(),
+            database: self.database(),
+            layout: self.layout(),
+            created_at,
+            record_count,
+            fields: sink.fields,
+            records_file,
+            encrypted,
+            file_sha256: sha256_file(&path).await?,
+            records_sha256: hex(&sink.hasher.finalize()),
+        };
+        tokio::fs::write(
+            directory.join(MANIFEST_FILE),
+            serde_json::to_vec_pretty(&manifest)?,
+        )
+        .await?;
+        info!(
+            "Took a snapshot of {} records of {} into {}",
+            record_count,
+            manifest.layout,
+            directory.display()
+        );
+        Ok(manifest)
+    }
+
+    /// Restores the records of a snapshot taken by [`Filemaker::snapshot_table`] into the
+    /// layout.
+    ///
+    /// The whole snapshot is read and checked against its manifest first: the checksums of
+    /// the records file and of the records, the record count and the field list must match.
+    /// Nothing is written if any of them differs. The records' field data is then filtered,
+    /// mapped to the layout's fields, and imported with [`Filemaker::import_records`] per
+    /// `options.import`; record IDs are not preserved. With `options.delete_missing`, records
+    /// of the layout whose key is not in the snapshot are deleted afterwards.
+    ///
+    /// With `options.dry_run`, the snapshot is only compared with the current records of the
+    /// layout, matched by the key field of `options.import`, and the planned creates,
+    /// updates and deletes are logged and returned in [`RestoreReport::plan`].
+    ///
+    /// # Arguments
+    /// * `directory` - The directory holding the snapshot
+    /// * `options` - Import, mapping, filter, deletion, dry-run and decryption settings
+    ///
+    /// # Returns
+    /// * `Result<RestoreReport>` - The manifest, the planned changes and the import summary, or an error if the snapshot fails verification or the import fails
+    pub async fn restore_table(
+        &self,
+        directory: impl AsRef<Path>,
+        options: RestoreOptions,
+    ) -> Result<RestoreReport> {
+        let directory = directory.as_ref();
+        if options.delete_missing && options.import.key_field.is_none() {
+            return Err(anyhow!(
+                "Deleting records missing from a snapshot requires a key field"
+            ));
+        }
+        let manifest = SnapshotManifest::load(directory).await?;
+        let rows = read_snapshot(directory, &manifest, &options).await?;
+        if manifest.layout != self.layout() {
+            warn!(
+                "Restoring a snapshot of {} into {}",
+                manifest.layout,
+                self.layout()
+            );
+        }
+
+        let total = rows.len();
+        let rows: Vec<HashMap<String, Value>> = rows
+            .into_iter()
+            .filter(|row| options.filter.as_ref().is_none_or(|filter| filter(row)))
+            .map(|row| options.mapping.apply(row))
+            .collect();
+        let filtered = (total - rows.len()) as u64;
+
+        let mut report = RestoreReport {
+            manifest,
+            filtered,
+            plan: None,
+            dry_run: options.dry_run,
+            summary: ImportSummary::default(),
+            deleted: DeleteSummary::default(),
+        };
+        let mut missing = vec![];
+        if options.dry_run || options.delete_missing {
+            let (plan, ids) = self.plan_restore(&rows, &options).await?;
+            info!(
+                "Restore of {} into {}: {}",
+                directory.display(),
+                self.layout(),
+                plan
+            );
+            report.plan = Some(plan);
+            missing = ids;
+        }
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        report.summary = self.import_records(rows, &options.import).await?;
+        if !missing.is_empty() {
+            let budget = Arc::new(BudgetTracker::new(options.import.budget));
+            report.deleted = self
+                .delete_records_within(missing, options.delete_concurrency, None, &budget)
+                .await?;
+            report.deleted.budget = budget.status();
+        }
+        info!(
+            "Restored the snapshot in {} into {}",
+            directory.display(),
+            self.layout()
+        );
+        Ok(report)
+    }
+
+    /// Compares the rows of a snapshot with the records of the layout.
+    ///
+    /// Returns the planned changes and the IDs of the records to delete.
+    async fn plan_restore(
+        &self,
+        rows: &[HashMap<String, Value>],
+        options: &RestoreOptions,
+    ) -> Result<(RestorePlan, Vec<String>)> {
+        let mut plan = RestorePlan::default();
+        let Some(key_field) = &options.import.key_field else {
+            plan.creates = rows.len() as u64;
+            return Ok((plan, vec![]));
+        };
+
+        let mut current: HashMap<String, Value> = HashMap::new();
+        let mut records = std::pin::pin!(self.stream_records_with(StreamOptions::default()));
+        while let Some(record) = records.try_next().await? {
+            if let Some(key) = record.get("fieldData").and_then(|f| f.get(key_field)) {
+                current.insert(key_string(key), record);
+            }
+        }
+
+        let mut restored = HashSet::new();
+        for row in rows {
+            let Some(key) = row.get(key_field).map(key_string) else {
+                plan.creates += 1;
+                continue;
+            };
+            match current.get(&key) {
+                None => plan.creates += 1,
+                Some(record)
+                    if options.import.duplicate_policy == DuplicatePolicy::Update
+                        && differs(row, record) =>
+                {
+                    plan.updates += 1
+                }
+                Some(_) => plan.unchanged += 1,
+            }
+            restored.insert(key);
+        }
+
+        let mut missing = vec![];
+        if options.delete_missing {
+            missing = current
+                .iter()
+                .filter(|(key, _)| !restored.contains(*key))
+                .filter_map(|(_, record)| record.get("recordId").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect();
+            plan.deletes = missing.len() as u64;
+        }
+        Ok((plan, missing))
+    }
+}
+
+/// Returns whether updating `record` with the field data of `row` would change it.
+fn differs(row: &HashMap<String, Value>, record: &Value) -> bool {
+    let field_data = record.get("fieldData");
+    row.iter().any(|(field, value)| {
+        field_data.and_then(|f| f.get(field)).map(key_string) != Some(key_string(value))
+    })
+}
+
+/// Reads the field data of the records of a snapshot, verifying it against its manifest.
+async fn read_snapshot(
+    directory: &Path,
+    manifest: &SnapshotManifest,
+    options: &RestoreOptions,
+) -> Result<Vec<HashMap<String, Value>>> {
+    let path = directory.join(&manifest.records_file);
+    if sha256_file(&path).await? != manifest.file_sha256 {
+        return Err(anyhow!(
+            "The checksum of {} does not match the snapshot manifest",
+            path.display()
+        ));
+    }
+
+    let compression = Compression::from_path(
+        manifest
+            .records_file
+            .strip_suffix(".enc")
+            .unwrap_or(&manifest.records_file),
+    )?;
+    let file = tokio::fs::File::open(&path).await?;
+    let reader = compression.wrap_reader(BufReader::new(decrypt(file, manifest, options)?));
+    let mut lines = BufReader::new(reader).lines();
+
+    let fields: HashSet<&str> = manifest.fields.iter().map(String::as_str).collect();
+    let mut hasher = Sha256::new();
+    let mut rows = vec![];
+    while let Some(line) = lines.next_line().await? {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+        let record: Value = serde_json::from_str(&line)?;
+        let row: HashMap<String, Value> = match record.get("fieldData") {
+            Some(field_data) => serde_json::from_value(field_data.clone())?,
+            None => HashMap::new(),
+        };
+        if let Some(field) = row.keys().find(|field| !fields.contains(field.as_str())) {
+            return Err(anyhow!(
+                "Record {} of the snapshot has field {}, which is not in the manifest",
+                rows.len() + 1,
+                field
+            ));
+        }
+        rows.push(row);
+    }
+
+    if rows.len() as u64 != manifest.record_count {
+        return Err(anyhow!(
+            "The snapshot holds {} records, but its manifest lists {}",
+            rows.len(),
+            manifest.record_count
+        ));
+    }
+    if hex(&hasher.finalize()) != manifest.records_sha256 {
+        return Err(anyhow!(
+            "The checksum of the snapshot's records does not match its manifest"
+        ));
+    }
+    Ok(rows)
+}
+
+/// Encrypts the records file if the options hold a key.
+fn encrypt<'a>(file: tokio::fs::File, options: &SnapshotOptions) -> BoxedWriter<'a> {
+    #[cfg(feature = "encryption")]
+    if let Some(key) = &options.key {
+        return Box::pin(EncryptingWriter::new(file, key));
+    }
+    let _ = options;
+    Box::pin(file)
+}
+
+/// Decrypts the records file if the manifest says it is encrypted.
+fn decrypt<'a>(
+    file: tokio::fs::File,
+    manifest: &SnapshotManifest,
+    options: &RestoreOptions,
+) -> Result<BoxedReader<'a>> {
+    if !manifest.encrypted {
+        return Ok(Box::pin(file));
+    }
+    #[cfg(feature = "encryption")]
+    {
+        let key = options
+            .key
+            .as_ref()
+            .ok_or_else(|| anyhow!("The snapshot is encrypted; set RestoreOptions::key"))?;
+        Ok(Box::pin(DecryptingReader::new(file, key)))
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = options;
+        Err(anyhow!(
+            "The snapshot is encrypted; restoring it requires the encryption feature"
+        ))
+    }
+}
+
+/// Computes the SHA-256 checksum of a file, in hexadecimal.
+async fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,126 @@
+//! Importing the rows of SQL queries into a layout.
+//!
+//! Rows are read through sqlx's database-agnostic [`AnyPool`], so the same code imports from
+//! Postgres, MySQL or SQLite depending on the connection URL and the enabled driver features
+//! (`sql-postgres`, `sql-mysql`, `sql-sqlite`).
+//!
+//! Requires the `sql-import` feature.
+
+use crate::{FieldMapping, Filemaker, ImportOptions, ImportSummary};
+use anyhow::Result;
+use base64::Engine;
+use futures::TryStreamExt;
+use log::*;
+use serde_json::{json, Value};
+use sqlx::any::{AnyRow, AnyTypeInfoKind};
+use sqlx::{AnyPool, AssertSqlSafe, Column, Row, ValueRef};
+use std::collections::HashMap;
+
+/// The number of rows read from the query before they are written to the layout.
+const SQL_IMPORT_CHUNK_SIZE: usize = 1000;
+
+impl Filemaker {
+    /// Imports the rows returned by an SQL query into the layout.
+    ///
+    /// Each row is converted to field data by column name and passed through `mapping`, then
+    /// written like [`Filemaker::import_records`] with `options`. Rows are read and written in
+    /// chunks of 1000, so large result sets are not held in memory at once. Integer, float,
+    /// boolean and text columns are supported; binary columns are imported as base64 text and
+    /// other types, such as dates, should be cast to text in the query.
+    ///
+    /// Drivers must be installed with [`sqlx::any::install_default_drivers`] before the
+    /// pool is connected.
+    ///
+    /// ```rust,ignore
+    /// sqlx::any::install_default_drivers();
+    /// let pool = sqlx::AnyPool::connect("postgres://localhost/crm").await?;
+    /// let mapping = FieldMapping::new().map("email", "Email").map("name", "Full Name");
+    /// let options = ImportOptions::with_key("Email", DuplicatePolicy::Update);
+    /// filemaker
+    ///     .import_from_sql(&pool, "SELECT email, name FROM customers", &mapping, &options)
+    ///     .await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `pool` - The connection pool of the source database
+    /// * `sql` - The query returning the rows to import
+    /// * `mapping` - Maps the query's columns to the layout's fields
+    /// * `options` - Duplicate detection settings
+    ///
+    /// # Returns
+    /// * `Result<ImportSummary>` - Counts of created, updated and skipped rows, or an error if the query fails
+    pub async fn import_from_sql(
+        &self,
+        pool: &AnyPool,
+        sql: &str,
+        mapping: &FieldMapping,
+        options: &ImportOptions,
+    ) -> Result<ImportSummary> {
+        let mut rows = sqlx::query(AssertSqlSafe(sql)).fetch(pool);
+        let mut summary = ImportSummary::default();
+        let mut chunk = Vec::with_capacity(SQL_IMPORT_CHUNK_SIZE);
+        let mut offset = 0;
+        loop {
+            let row = rows.try_next().await?;
+            if let Some(row) = &row {
+                chunk.push(mapping.apply(row_to_field_data(row)?));
+            }
+            if chunk.len() == SQL_IMPORT_CHUNK_SIZE || (row.is_none() && !chunk.is_empty()) {
+                let count = chunk.len();
+                let result = self
+                    .import_records(std::mem::take(&mut chunk), options)
+                    .await?;
+                summary.created += result.created;
+                summary.updated += result.updated;
+                summary.skipped += result.skipped;
+                summary.flagged.extend(result.flagged);
+                summary.failed.extend(
+                    result
+                        .failed
+                        .into_iter()
+                        .map(|(index, e)| (offset + index, e)),
+                );
+                offset += count;
+            }
+            if row.is_none() {
+                break;
+            }
+        }
+
+        info!(
+            "Imported {} SQL rows: {} created, {} updated, {} skipped, {} failed",
+            offset,
+            summary.created,
+            summary.updated,
+            summary.skipped,
+            summary.failed.len()
+        );
+        Ok(summary)
+    }
+}
+
+/// Converts a row to JSON values by column name.
+fn row_to_field_data(row: &AnyRow) -> Result<HashMap<String, Value>> {
+    let mut fields = HashMap::with_capacity(row.columns().len());
+    for column in row.columns() {
+        let index = column.ordinal();
+        let kind = row.try_get_raw(index)?.type_info().kind();
+        let value = match kind {
+            AnyTypeInfoKind::Null => Value::Null,
+            AnyTypeInfoKind::Bool => json!(row.try_get::<bool, _>(index)?),
+            AnyTypeInfoKind::SmallInt | AnyTypeInfoKind::Integer | AnyTypeInfoKind::BigInt => {
+                json!(row.try_get::<i64, _>(index)?)
+            }
+            AnyTypeInfoKind::Real | AnyTypeInfoKind::Double => {
+                json!(row.try_get::<f64, _>(index)?)
+            }
+            AnyTypeInfoKind::Text => json!(row.try_get::<String, _>(index)?),
+            AnyTypeInfoKind::Blob => {
+                let bytes = row.try_get::<Vec<u8>, _>(index)?;
+                json!(base64::engine::general_purpose::STANDARD.encode(bytes))
+            }
+        };
+        fields.insert(column.name().to_string(), value);
+    }
+    Ok(fields)
+}
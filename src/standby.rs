@@ -0,0 +1,280 @@
+//! Keeping a second session ready to replace the primary one.
+//!
+//! Data API sessions expire after a period without requests. Replacing an expired session
+//! costs the request that notices it a login round trip. A client with a warm standby keeps a
+//! second session alive in the background and swaps it in when the primary is about to
+//! expire, or as soon as a request finds the primary expired.
+
+use crate::accounting;
+use crate::failover::EndpointPool;
+use crate::{query, Filemaker, SessionOptions};
+use anyhow::{anyhow, Result};
+use log::*;
+use reqwest::{Client, Method};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Configures the warm standby session of [`Filemaker::with_standby_session`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StandbyOptions {
+    /// How long the server keeps an unused session, 15 minutes by default.
+    pub session_timeout: Duration,
+    /// How long before the primary session would expire the standby takes over.
+    pub rotate_margin: Duration,
+    /// How often the sessions are checked.
+    pub check_interval: Duration,
+}
+
+impl Default for StandbyOptions {
+    fn default() -> Self {
+        Self {
+            session_timeout: Duration::from_secs(15 * 60),
+            rotate_margin: Duration::from_secs(2 * 60),
+            check_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl StandbyOptions {
+    /// Returns how long a session may stay unused before it is replaced.
+    fn rotate_after(&self) -> Duration {
+        self.session_timeout.saturating_sub(self.rotate_margin)
+    }
+}
+
+/// The standby session shared by a client and its clones, and what is needed to replace it.
+pub(crate) struct Standby {
+    options: StandbyOptions,
+    client: Client,
+    pool: Arc<EndpointPool>,
+    database: String,
+    session_options: SessionOptions,
+    // The primary token of the clients sharing this standby
+    primary: Arc<Mutex<Option<String>>>,
+    primary_used: StdMutex<Instant>,
+    session: Mutex<Option<StandbySession>>,
+    // Held while the primary session is replaced, shared with the clients
+    session_refresh: Arc<Mutex<()>>,
+    // Set once the clients logged out; no standby is created afterwards
+    closed: AtomicBool,
+}
+
+struct StandbySession {
+    token: String,
+    // The endpoint the session was created on
+    base_url: String,
+    used: Instant,
+}
+
+impl Standby {
+    /// Notes that the primary session was just used.
+    pub(crate) fn primary_used(&self) {
+        *self.primary_used.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /// Takes the standby token if it belongs to the endpoint the primary is pinned to.
+    ///
+    /// The caller must hold the session refresh lock.
+    pub(crate) async fn take(&self) -> Option<String> {
+        let session = self.session.lock().await.take()?;
+        if session.base_url != self.pool.pinned_url() {
+            debug!(
+                "Discarding standby session of endpoint {}",
+                session.base_url
+            );
+            self.end_session(&session.base_url, &session.token).await;
+            return None;
+        }
+        self.primary_used();
+        Some(session.token)
+    }
+
+    /// Ends the standby session and stops creating new ones.
+    pub(crate) async fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Some(session) = self.session.lock().await.take() {
+            self.end_session(&session.base_url, &session.token).await;
+        }
+    }
+
+    /// Creates a new standby session on the pinned endpoint.
+    async fn replenish(&self) -> Result<()> {
+        let base_url = self.pool.pinned_url().to_string();
+        let token = Filemaker::get_session_token_at(
+            &self.client,
+            &base_url,
+            &self.database,
+            self.pool.username(),
+            self.pool.password(),
+            &self.session_options,
+        )
+        .await?;
+        debug!("Created standby session on endpoint {}", base_url);
+        *self.session.lock().await = Some(StandbySession {
+            token,
+            base_url,
+            used: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Checks both sessions once: rotates the primary if it is about to expire, keeps the
+    /// standby alive, and creates a standby if there is none.
+    async fn check(&self) -> Result<()> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let rotate_after = self.options.rotate_after();
+        let primary_idle = self
+            .primary_used
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed();
+        if primary_idle >= rotate_after {
+            self.rotate().await?;
+        }
+
+        let mut session = self.session.lock().await;
+        if let Some(standby) = session.as_mut() {
+            // Keep the standby fresh enough to last half a rotation once promoted
+            if standby.used.elapsed() >= rotate_after / 2 {
+                match self.keep_alive(standby).await {
+                    Ok(()) => standby.used = Instant::now(),
+                    Err(e) => {
+                        warn!("Standby session is no longer valid: {}", e);
+                        *session = None;
+                    }
+                }
+            }
+        }
+        let missing = session.is_none();
+        drop(session);
+        if missing {
+            self.replenish().await?;
+        }
+        Ok(())
+    }
+
+    /// Swaps the standby in for the primary session and ends the primary.
+    async fn rotate(&self) -> Result<()> {
+        let _guard = self.session_refresh.lock().await;
+        let Some(token) = self.take().await else {
+            return Ok(());
+        };
+        let stale = self.primary.lock().await.replace(token);
+        info!("Switched to the standby session before the primary expired");
+        if let Some(stale) = stale {
+            self.end_session(self.pool.pinned_url(), &stale).await;
+        }
+        Ok(())
+    }
+
+    /// Sends a cheap request with the standby token, so the server keeps the session.
+    async fn keep_alive(&self, standby: &StandbySession) -> Result<()> {
+        let url = format!(
+            "{}/databases/{}/layouts",
+            standby.base_url,
+            query::encode_url_component(&self.database)
+        );
+        accounting::used(&standby.token);
+        let response = self
+            .client
+            .request(Method::GET, &url)
+            .header("Authorization", format!("Bearer {}", standby.token))
+            .send()
+            .await?;
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "Keep-alive request failed with {}",
+                response.status()
+            )),
+        }
+    }
+
+    /// Ends a session on the server, logging rather than returning failures.
+    async fn end_session(&self, base_url: &str, token: &str) {
+        accounting::closed(token);
+        let url = format!(
+            "{}/databases/{}/sessions/{}",
+            base_url,
+            query::encode_url_component(&self.database),
+            token
+        );
+        if let Err(e) = self.client.delete(&url).send().await {
+            warn!("Failed to end session: {}", e);
+        }
+    }
+}
+
+/// Checks the sessions of `standby` until every client sharing it is dropped.
+async fn maintain(standby: Weak<Standby>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(standby) = standby.upgrade() else {
+            debug!("Client dropped, stopping standby session maintenance");
+            return;
+        };
+        if let Err(e) = standby.check().await {
+            warn!("Failed to maintain standby session: {}", e);
+        }
+    }
+}
+
+impl Filemaker {
+    /// Keeps a second session ready, so requests never wait for a login.
+    ///
+    /// A background task keeps the standby session alive and swaps it in shortly before the
+    /// primary session would expire for lack of use, ending the old one. A request that still
+    /// finds its session expired switches to the standby instead of logging in. A new
+    /// standby is created on the next check.
+    ///
+    /// Only clients created with [`Filemaker::new_with_endpoints`] keep the credentials
+    /// needed for this.
+    ///
+    /// # Arguments
+    /// * `options` - The session timeout of the server and how often to check the sessions
+    ///
+    /// # Returns
+    /// * `Result<Self>` - The client with a standby session, or an error if the login fails
+    pub async fn with_standby_session(mut self, options: StandbyOptions) -> Result<Self> {
+        let Some(pool) = self.endpoints.clone() else {
+            return Err(anyhow!(
+                "A standby session requires a client created with new_with_endpoints"
+            ));
+        };
+        let standby = Arc::new(Standby {
+            options,
+            client: self.client.clone(),
+            pool,
+            database: self.database(),
+            session_options: self.session_options.clone(),
+            primary: self.token.clone(),
+            primary_used: StdMutex::new(Instant::now()),
+            session: Mutex::new(None),
+            session_refresh: self.session_refresh.clone(),
+            closed: AtomicBool::new(false),
+        });
+        standby.replenish().await?;
+        tokio::spawn(maintain(Arc::downgrade(&standby), options.check_interval));
+        info!("Keeping a standby session");
+        self.standby = Some(standby);
+        Ok(self)
+    }
+
+    /// Replaces the primary session with the standby one, if there is one.
+    ///
+    /// The caller must hold the session refresh lock.
+    pub(crate) async fn promote_standby(&self) -> bool {
+        let Some(standby) = &self.standby else {
+            return false;
+        };
+        let Some(token) = standby.take().await else {
+            return false;
+        };
+        *self.token.lock().await = Some(token);
+        true
+    }
+}
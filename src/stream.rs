@@ -0,0 +1,278 @@
+//! Streaming access to large tables.
+
+use crate::{
+    query, ApiError, ErrorKindExt, Filemaker, FindOptions, GetOptions, ScriptCall,
+    NO_RECORDS_MATCH_CODE,
+};
+use anyhow::{anyhow, Result};
+use futures::stream::{self, Stream, TryStreamExt};
+use log::*;
+use reqwest::Method;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// Controls how records are paged and buffered while streaming.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// The number of records fetched per request.
+    pub page_size: u64,
+    /// The maximum number of fetched pages waiting to be consumed.
+    ///
+    /// Once this many pages are buffered, fetching pauses until the consumer catches up, so
+    /// at most `page_size * (max_in_flight_pages + 1)` records are held in memory.
+    pub max_in_flight_pages: usize,
+    /// A script run before each page is fetched (`script.prerequest`).
+    ///
+    /// For layouts whose data is only correct after a script has prepared it. The page
+    /// fails if the script reports an error.
+    pub pre_page_script: Option<ScriptCall>,
+    /// A field with unique, non-empty values to page by instead of by offset.
+    ///
+    /// Each page is found as the records whose key is greater than the last key streamed,
+    /// sorted by key, so records inserted or deleted while streaming do not shift later
+    /// pages and cause records to be skipped or returned twice. Records with an empty key
+    /// are not streamed.
+    pub key_field: Option<String>,
+}
+
+impl StreamOptions {
+    /// Creates options fetching `page_size` records per request with one page buffered ahead.
+    pub fn new(page_size: u64) -> Self {
+        Self {
+            page_size: page_size.max(1),
+            max_in_flight_pages: 1,
+            pre_page_script: None,
+            key_field: None,
+        }
+    }
+
+    /// Sets the maximum number of fetched pages waiting to be consumed (at least 1).
+    pub fn with_max_in_flight_pages(mut self, pages: usize) -> Self {
+        self.max_in_flight_pages = pages.max(1);
+        self
+    }
+
+    /// Bounds the number of buffered records, rounded up to whole pages.
+    pub fn with_max_in_flight_records(self, records: u64) -> Self {
+        let pages = records.div_ceil(self.page_size);
+        self.with_max_in_flight_pages(pages as usize)
+    }
+
+    /// Runs `script` before each page is fetched.
+    pub fn with_pre_page_script(mut self, script: ScriptCall) -> Self {
+        self.pre_page_script = Some(script);
+        self
+    }
+
+    /// Pages by ranges of `field`, which must hold unique, non-empty values, such as a
+    /// primary key.
+    pub fn with_key_field(mut self, field: impl Into<String>) -> Self {
+        self.key_field = Some(field.into());
+        self
+    }
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl Filemaker {
+    /// Streams every record of the table, fetching `page_size` records per request.
+    ///
+    /// Equivalent to [`Filemaker::stream_records_with`] with [`StreamOptions::new`].
+    ///
+    /// # Arguments
+    /// * `page_size` - The number of records fetched per request (at least 1)
+    ///
+    /// # Returns
+    /// * `impl Stream<Item = Result<Value>>` - The raw records, in table order
+    pub fn stream_records(&self, page_size: u64) -> impl Stream<Item = Result<Value>> + 'static {
+        self.stream_records_with(StreamOptions::new(page_size))
+    }
+
+    /// Streams every record of the table with bounded memory use.
+    ///
+    /// Pages are fetched by a background task into a buffer of at most
+    /// `options.max_in_flight_pages` pages. When the consumer is slower than the server the
+    /// buffer fills up and fetching pauses, so memory stays bounded no matter how large the
+    /// table is. Dropping the stream stops the background task.
+    ///
+    /// Pages are fetched by offset unless `options.key_field` is set; see
+    /// [`StreamOptions::key_field`] for streaming tables that are modified meanwhile.
+    ///
+    /// # Arguments
+    /// * `options` - Page size and buffering limits
+    ///
+    /// # Returns
+    /// * `impl Stream<Item = Result<Value>>` - The raw records, in table order
+    pub fn stream_records_with(
+        &self,
+        options: StreamOptions,
+    ) -> impl Stream<Item = Result<Value>> + 'static {
+        let page_size = options.page_size.max(1);
+        let (sender, receiver) =
+            mpsc::channel::<Result<Vec<Value>>>(options.max_in_flight_pages.max(1));
+        let filemaker = self.clone();
+
+        tokio::spawn(async move {
+            let script = options.pre_page_script.as_ref();
+            let mut offset = 1u64;
+            let mut after: Option<String> = None;
+            loop {
+                let page = match &options.key_field {
+                    Some(key_field) => {
+                        debug!("Streaming records with {} after {:?}", key_field, after);
+                        filemaker
+                            .stream_keyset_page(key_field, after.as_deref(), page_size, script)
+                            .await
+                    }
+                    None => {
+                        debug!("Streaming records from offset {}", offset);
+                        match filemaker.stream_page(offset, page_size, script).await {
+                            // The server reports an offset past the last record as "no records match"
+                            Err(e) if is_no_records_match(&e) => Ok(vec![]),
+                            page => page,
+                        }
+                    }
+                };
+                let is_last = match &page {
+                    Ok(records) => (records.len() as u64) < page_size,
+                    Err(_) => true,
+                };
+                let page = match (&options.key_field, page) {
+                    (Some(key_field), Ok(records)) if !is_last => {
+                        match records.last().and_then(|r| key_of(r, key_field)) {
+                            Some(key) => {
+                                after = Some(key);
+                                Ok(records)
+                            }
+                            None => Err(anyhow!(
+                                "Record without a {} key while streaming",
+                                key_field
+                            )),
+                        }
+                    }
+                    (_, page) => page,
+                };
+                let failed = page.is_err();
+                // Waits while the buffer is full; fails once the consumer is gone
+                if sender.send(page).await.is_err() {
+                    debug!("Record stream dropped at offset {}", offset);
+                    break;
+                }
+                if is_last || failed {
+                    break;
+                }
+                offset += page_size;
+            }
+        });
+
+        stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|page| (page, receiver))
+        })
+        .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// Fetches one page, running `script` before it if given.
+    async fn stream_page(
+        &self,
+        offset: u64,
+        limit: u64,
+        script: Option<&ScriptCall>,
+    ) -> Result<Vec<Value>> {
+        let Some(script) = script else {
+            return self.get_records(offset, limit).await;
+        };
+        let mut options = GetOptions {
+            offset: Some(offset),
+            limit: Some(limit),
+            ..Default::default()
+        };
+        options.scripts.pre_request = Some(script.clone());
+        let response = self.get_records_response(&options).await?;
+        check_pre_page_script(&response, script)?;
+        Self::records_in(&response)
+    }
+
+    /// Finds the page of records whose `key_field` comes after `after`, sorted by the key.
+    async fn stream_keyset_page(
+        &self,
+        key_field: &str,
+        after: Option<&str>,
+        limit: u64,
+        script: Option<&ScriptCall>,
+    ) -> Result<Vec<Value>> {
+        let criterion = match after {
+            Some(key) => format!(">{}", query::escape_find_value(key)),
+            None => "*".to_string(),
+        };
+        let mut options = FindOptions {
+            limit: Some(limit),
+            sort: vec![key_field.to_string()],
+            ..Default::default()
+        };
+        options.scripts.pre_request = script.cloned();
+        let url = format!(
+            "{}/databases/{}/layouts/{}/_find",
+            self.base_url()?,
+            self.database,
+            self.table
+        );
+        let criteria = [HashMap::from([(key_field.to_string(), criterion)])];
+        let body = options.body(&criteria, self.sort_tiebreaker());
+        let response = self
+            .authenticated_request(&url, Method::POST, Some(body))
+            .await?;
+        if Self::first_message_code(&response) == Some(NO_RECORDS_MATCH_CODE) {
+            return Ok(vec![]);
+        }
+        if let Some(script) = script {
+            check_pre_page_script(&response, script)?;
+        }
+        Self::records_in(&response)
+    }
+}
+
+/// Fails if the script run before a page reported an error.
+fn check_pre_page_script(response: &Value, script: &ScriptCall) -> Result<()> {
+    let script_error = response
+        .get("response")
+        .and_then(|r| r.get("scriptError.prerequest"))
+        .and_then(|e| e.as_str())
+        .and_then(|e| e.parse::<u32>().ok())
+        .filter(|code| *code != 0);
+    match script_error {
+        Some(code) => {
+            error!(
+                "Script {} failed with error {} before a page",
+                script.name, code
+            );
+            Err(anyhow::Error::new(ApiError {
+                code,
+                message: format!("Script {} failed", script.name),
+            })
+            .context("Pre-page script failed"))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Returns the value of `key_field` of a raw record as find criteria text.
+fn key_of(record: &Value, key_field: &str) -> Option<String> {
+    match record.get("fieldData")?.get(key_field)? {
+        Value::String(key) if key.is_empty() => None,
+        Value::String(key) => Some(key.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+fn is_no_records_match(error: &anyhow::Error) -> bool {
+    error
+        .api_error()
+        .is_some_and(|e| e.code.to_string() == NO_RECORDS_MATCH_CODE)
+}
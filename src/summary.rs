@@ -0,0 +1,111 @@
+//! Summary tables computed on the client.
+//!
+//! Summary fields are recalculated by FileMaker whenever a report needs them, which gets slow
+//! on large tables. [`Filemaker::refresh_summary`] computes the aggregates once with
+//! [`Filemaker::aggregate`] and upserts one row per group into a summary table that reports can
+//! read directly, like a materialized view.
+
+use crate::import::key_string;
+use crate::{Aggregate, Filemaker, UpsertOutcome};
+use anyhow::Result;
+use log::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// The number of summary rows written in parallel.
+const SUMMARY_CONCURRENCY: usize = 4;
+
+/// The outcome of [`Filemaker::refresh_summary`].
+#[derive(Debug, Default)]
+pub struct SummaryReport {
+    /// The number of source records read.
+    pub source_records: u64,
+    /// The number of summary rows created.
+    pub created: u64,
+    /// The number of existing summary rows updated.
+    pub updated: u64,
+    /// Groups whose summary row could not be written, by group value, with the reason.
+    pub failed: Vec<(String, anyhow::Error)>,
+}
+
+impl Filemaker {
+    /// Recomputes a summary table from the records of this client's layout.
+    ///
+    /// Source records are read page by page and grouped by the value of `group_by`; for each group
+    /// the `aggregates` are computed and written to the given summary fields of one row of
+    /// `target_layout`. Rows are matched on a `group_by` field of the same name in the
+    /// target layout: existing rows are updated and missing ones created. Rows of groups
+    /// that no longer occur in the source are left alone. A row that fails to write does not
+    /// abort the refresh.
+    ///
+    /// ```rust,ignore
+    /// let report = filemaker
+    ///     .with_layout("Invoices")
+    ///     .refresh_summary(
+    ///         "InvoiceTotals",
+    ///         "Customer",
+    ///         &[("Invoices", Aggregate::Count), ("Revenue", Aggregate::Sum("Total".into()))],
+    ///     )
+    ///     .await?;
+    /// ```
+    ///
+    /// # Arguments
+    /// * `target_layout` - The layout of the summary table
+    /// * `group_by` - The source field the records are grouped by, also the key field of the summary table
+    /// * `aggregates` - The summary fields and the aggregate written to each
+    ///
+    /// # Returns
+    /// * `Result<SummaryReport>` - Counts of written rows, or an error if reading the source or looking up existing rows fails
+    pub async fn refresh_summary(
+        &self,
+        target_layout: &str,
+        group_by: &str,
+        aggregates: &[(&str, Aggregate)],
+    ) -> Result<SummaryReport> {
+        let aggregations: Vec<Aggregate> = aggregates.iter().map(|(_, a)| a.clone()).collect();
+        let groups = self.aggregate(&[], &[group_by], &aggregations).await?;
+        let mut report = SummaryReport {
+            source_records: groups.iter().map(|group| group.count).sum(),
+            ..Default::default()
+        };
+
+        let mut keys = Vec::with_capacity(groups.len());
+        let mut rows: Vec<HashMap<String, Value>> = Vec::with_capacity(groups.len());
+        for group in groups {
+            let key = group.key.into_iter().next().unwrap_or(json!(""));
+            keys.push(key_string(&key));
+            let mut row = HashMap::from([(group_by.to_string(), key)]);
+            for ((target, aggregate), value) in aggregates.iter().zip(group.values) {
+                let value = match (aggregate, value) {
+                    (Aggregate::Count, _) => json!(group.count),
+                    (_, Some(value)) => json!(value),
+                    (_, None) => json!(""),
+                };
+                row.insert(target.to_string(), value);
+            }
+            rows.push(row);
+        }
+        let outcomes = self
+            .with_layout(target_layout)
+            .upsert_many(group_by, rows, SUMMARY_CONCURRENCY)
+            .await?;
+        for (key, outcome) in keys.into_iter().zip(outcomes) {
+            match outcome {
+                UpsertOutcome::Created { .. } => report.created += 1,
+                UpsertOutcome::Updated { .. } => report.updated += 1,
+                UpsertOutcome::Failed(e) => {
+                    error!("Failed to write summary row {}: {}", key, e);
+                    report.failed.push((key, e));
+                }
+            }
+        }
+        info!(
+            "Refreshed summary {}: {} created, {} updated, {} failed",
+            target_layout,
+            report.created,
+            report.updated,
+            report.failed.len()
+        );
+        Ok(report)
+    }
+}
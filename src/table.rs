@@ -0,0 +1,221 @@
+//! Rendering records as aligned text or Markdown tables.
+
+use crate::Record;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+/// The output format of a [`RecordTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableStyle {
+    /// Columns padded with spaces and separated by `|`, for terminals and logs.
+    #[default]
+    Text,
+    /// A GitHub-flavored Markdown table.
+    Markdown,
+}
+
+/// Formats records as a table with one row per record and one column per field.
+///
+/// ```rust,ignore
+/// let records = filemaker.get_records(1, 20).await?;
+/// println!(
+///     "{}",
+///     RecordTable::new()
+///         .with_columns(["Name", "Email"])
+///         .with_max_width(30)
+///         .render(&records)
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordTable {
+    columns: Option<Vec<String>>,
+    max_columns: Option<usize>,
+    max_width: usize,
+    record_id: bool,
+    style: TableStyle,
+}
+
+impl Default for RecordTable {
+    fn default() -> Self {
+        Self {
+            columns: None,
+            max_columns: None,
+            max_width: 40,
+            record_id: true,
+            style: TableStyle::Text,
+        }
+    }
+}
+
+impl RecordTable {
+    /// Creates a text table showing the record ID and every field, with cells truncated to
+    /// 40 characters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows only the given fields, in the given order.
+    pub fn with_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Shows at most `max_columns` fields.
+    pub fn with_max_columns(mut self, max_columns: usize) -> Self {
+        self.max_columns = Some(max_columns);
+        self
+    }
+
+    /// Truncates cells longer than `max_width` characters (at least 2) with `…`.
+    pub fn with_max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width.max(2);
+        self
+    }
+
+    /// Hides the `recordId` column.
+    pub fn without_record_id(mut self) -> Self {
+        self.record_id = false;
+        self
+    }
+
+    /// Sets the output format.
+    pub fn with_style(mut self, style: TableStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Renders raw Data API records, as returned by [`crate::Filemaker::get_records`].
+    pub fn render(&self, records: &[Value]) -> String {
+        let columns = self.columns(records);
+        let mut header: Vec<String> = columns.clone();
+        if self.record_id {
+            header.insert(0, "recordId".to_string());
+        }
+        let rows: Vec<Vec<String>> = records
+            .iter()
+            .map(|record| {
+                let id = record.get("recordId");
+                let fields = record.get("fieldData");
+                self.record_id
+                    .then(|| self.cell(id))
+                    .into_iter()
+                    .chain(
+                        columns
+                            .iter()
+                            .map(|c| self.cell(fields.and_then(|f| f.get(c)))),
+                    )
+                    .collect()
+            })
+            .collect();
+        self.layout(header, rows)
+    }
+
+    /// Renders typed records, as returned by a [`crate::Repository`].
+    pub fn render_records<T: Serialize>(&self, records: &[Record<T>]) -> Result<String> {
+        let raw = records
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<serde_json::Result<Vec<Value>>>()?;
+        Ok(self.render(&raw))
+    }
+
+    /// Returns the fields shown as columns: the configured ones, or every field found in the
+    /// records.
+    pub fn columns(&self, records: &[Value]) -> Vec<String> {
+        let mut columns = match &self.columns {
+            Some(columns) => columns.clone(),
+            None => {
+                let mut columns: Vec<String> = vec![];
+                for fields in records
+                    .iter()
+                    .filter_map(|r| r.get("fieldData").and_then(|f| f.as_object()))
+                {
+                    for name in fields.keys() {
+                        if !columns.contains(name) {
+                            columns.push(name.clone());
+                        }
+                    }
+                }
+                columns
+            }
+        };
+        if let Some(max_columns) = self.max_columns {
+            columns.truncate(max_columns);
+        }
+        columns
+    }
+
+    /// Renders a value as a single-line cell, truncated to the maximum width.
+    pub fn cell(&self, value: Option<&Value>) -> String {
+        let text = match value {
+            Some(Value::String(s)) => s.replace(['\r', '\n'], " "),
+            Some(Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+        match text.chars().count() > self.max_width {
+            true => text.chars().take(self.max_width - 1).chain(['…']).collect(),
+            false => text,
+        }
+    }
+
+    fn layout(&self, header: Vec<String>, rows: Vec<Vec<String>>) -> String {
+        let escape = |cell: &String| match self.style {
+            TableStyle::Markdown => cell.replace('|', "\\|"),
+            TableStyle::Text => cell.clone(),
+        };
+        let header: Vec<String> = header.iter().map(escape).collect();
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| row.iter().map(escape).collect())
+            .collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([header[i].chars().count()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            // Markdown separators need at least three dashes
+            .map(|width| match self.style {
+                TableStyle::Markdown => width.max(3),
+                TableStyle::Text => width,
+            })
+            .collect();
+        let line = |cells: &[String]| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            match self.style {
+                TableStyle::Text => padded.join(" | ").trim_end().to_string(),
+                TableStyle::Markdown => format!("| {} |", padded.join(" | ")),
+            }
+        };
+        let separator = match self.style {
+            TableStyle::Text => widths
+                .iter()
+                .map(|w| "-".repeat(*w))
+                .collect::<Vec<_>>()
+                .join("-+-"),
+            TableStyle::Markdown => format!(
+                "| {} |",
+                widths
+                    .iter()
+                    .map(|w| "-".repeat(*w))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            ),
+        };
+
+        let mut lines = vec![line(&header), separator];
+        lines.extend(rows.iter().map(|row| line(row)));
+        lines.join("\n")
+    }
+}
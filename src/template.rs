@@ -0,0 +1,230 @@
+//! Find requests with named placeholders.
+
+use crate::query::escape_find_value;
+use crate::{Filemaker, FindOptions, FindResult};
+use anyhow::{anyhow, Result};
+use log::*;
+use serde::de::DeserializeOwned;
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// A part of a criterion: operators and text as written, or a placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Parameter(String),
+}
+
+/// Find criteria with named placeholders, filled in from a parameter map.
+///
+/// A template is parsed from conditions of the form `Field = criteria`, separated by `;`
+/// or new lines; all conditions of a template belong to one find request, and
+/// [`QueryTemplate::or`] adds further requests. Everything after the first `=` of a
+/// condition is the criteria, with surrounding whitespace trimmed, so operators are written
+/// as usual: `Status = =={status}` matches exactly, `Total = >{min}` compares. Placeholders
+/// are written `{name}`, and `{{` and `}}` stand for literal braces.
+///
+/// Substituted values are escaped with [`crate::query::escape_find_value`], so a parameter
+/// is always matched literally and cannot add operators to the template.
+///
+/// ```rust,ignore
+/// let template: QueryTemplate = "Status = =={status}; Total = >{min}".parse()?;
+/// let params = HashMap::from([("status", "Open"), ("min", "100")]);
+/// let invoices = filemaker.search_template::<Invoice>(&template, &params, &FindOptions::default()).await?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryTemplate {
+    requests: Vec<Vec<(String, Vec<Segment>)>>,
+}
+
+impl QueryTemplate {
+    /// Parses a template of one find request.
+    ///
+    /// # Arguments
+    /// * `template` - `Field = criteria` conditions separated by `;` or new lines
+    ///
+    /// # Returns
+    /// * `Result<QueryTemplate>` - The template, or an error naming the malformed condition
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut conditions = vec![];
+        for condition in template.split([';', '\n']) {
+            if condition.trim().is_empty() {
+                continue;
+            }
+            let (field, criteria) = condition
+                .split_once('=')
+                .map(|(field, criteria)| (field.trim(), criteria.trim()))
+                .filter(|(field, _)| !field.is_empty())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Query template condition {:?} is not `Field = criteria`",
+                        condition
+                    )
+                })?;
+            conditions.push((field.to_string(), parse_criteria(criteria)?));
+        }
+        if conditions.is_empty() {
+            return Err(anyhow!("Query template has no conditions"));
+        }
+        Ok(Self {
+            requests: vec![conditions],
+        })
+    }
+
+    /// Adds the requests of `other`, so records matching either template are found.
+    pub fn or(mut self, other: QueryTemplate) -> Self {
+        self.requests.extend(other.requests);
+        self
+    }
+
+    /// Returns the names of the placeholders in the template.
+    pub fn parameters(&self) -> BTreeSet<&str> {
+        self.requests
+            .iter()
+            .flatten()
+            .flat_map(|(_, segments)| segments)
+            .filter_map(|segment| match segment {
+                Segment::Parameter(name) => Some(name.as_str()),
+                Segment::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    /// Fills in the placeholders, escaping every substituted value.
+    ///
+    /// Parameters the template does not use are ignored.
+    ///
+    /// # Arguments
+    /// * `params` - The value of each placeholder
+    ///
+    /// # Returns
+    /// * `Result<Vec<HashMap<String, String>>>` - The find requests, or an error naming a missing parameter
+    pub fn render<K, V>(&self, params: &HashMap<K, V>) -> Result<Vec<HashMap<String, String>>>
+    where
+        K: Borrow<str> + Eq + Hash,
+        V: AsRef<str>,
+    {
+        self.requests
+            .iter()
+            .map(|conditions| {
+                conditions
+                    .iter()
+                    .map(|(field, segments)| {
+                        let mut criteria = String::new();
+                        for segment in segments {
+                            match segment {
+                                Segment::Literal(text) => criteria.push_str(text),
+                                Segment::Parameter(name) => {
+                                    let value = params.get(name.as_str()).ok_or_else(|| {
+                                        anyhow!("Missing query template parameter {}", name)
+                                    })?;
+                                    criteria.push_str(&escape_find_value(value.as_ref()));
+                                }
+                            }
+                        }
+                        Ok((field.clone(), criteria))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl FromStr for QueryTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        Self::parse(template)
+    }
+}
+
+/// Splits criteria into literal text and placeholders.
+fn parse_criteria(criteria: &str) -> Result<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut literal = String::new();
+    let mut chars = criteria.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                if !closed {
+                    return Err(anyhow!(
+                        "Unterminated placeholder in query template criteria {:?}",
+                        criteria
+                    ));
+                }
+                let name = name.trim();
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(anyhow!(
+                        "Invalid placeholder {{{}}} in query template criteria {:?}",
+                        name,
+                        criteria
+                    ));
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Parameter(name.to_string()));
+            }
+            '}' => {
+                return Err(anyhow!(
+                    "Unmatched `}}` in query template criteria {:?}",
+                    criteria
+                ))
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+impl Filemaker {
+    /// Searches with the find requests of `template`, filled in from `params`.
+    ///
+    /// See [`QueryTemplate`] for the template syntax and escaping, and
+    /// [`Filemaker::search_with`] for `options`.
+    ///
+    /// # Arguments
+    /// * `template` - The find requests with placeholders
+    /// * `params` - The value of each placeholder
+    /// * `options` - Sorting, paging, portals, scripts and other find parameters
+    ///
+    /// # Returns
+    /// * `Result<Option<FindResult<T>>>` - The matching records, or an error if a parameter is missing or the find fails
+    pub async fn search_template<T, K, V>(
+        &self,
+        template: &QueryTemplate,
+        params: &HashMap<K, V>,
+        options: &FindOptions,
+    ) -> Result<Option<FindResult<T>>>
+    where
+        T: DeserializeOwned + Default,
+        K: Borrow<str> + Eq + Hash,
+        V: AsRef<str>,
+    {
+        let query = template.render(params)?;
+        debug!("Searching with query template rendered to {:?}", query);
+        self.search_with(query, options).await
+    }
+}
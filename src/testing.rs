@@ -0,0 +1,946 @@
+//! A stub Data API server for hermetic tests.
+//!
+//! [`StubServer`] runs a small in-memory HTTP server on a local port that emulates the parts
+//! of the FileMaker Data API this crate uses: sessions, records, finds, layout metadata and
+//! FileMaker's error codes. Point a client at it with [`Filemaker::new_with_endpoints`] to
+//! test paging, retries or token refresh without a FileMaker Server.
+//!
+//! ```rust,ignore
+//! let server = StubServer::start("Contacts", "admin", "secret").await?;
+//! server.insert_record("People", json!({ "Name": "Ada" }));
+//! let filemaker =
+//!     Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People").await?;
+//! ```
+//!
+//! Requires the `testing` feature.
+//!
+//! [`Filemaker::new_with_endpoints`]: crate::Filemaker::new_with_endpoints
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use base64::Engine;
+use log::*;
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// The path under which the stub serves the Data API.
+const API_PREFIX: &str = "/fmi/data/vLatest";
+
+/// The number of records returned by `GET records` when no `_limit` is given.
+const DEFAULT_LIMIT: usize = 100;
+
+/// An in-memory Data API server running on a local port.
+///
+/// The server stops when the value is dropped.
+pub struct StubServer {
+    url: String,
+    state: Arc<Mutex<StubState>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct StubState {
+    database: String,
+    username: String,
+    password: String,
+    // Claris ID tokens accepted instead of the password, as by FileMaker Cloud
+    fmid_tokens: HashSet<String>,
+    // Credentials of external data sources logins must send in `fmDataSource`
+    data_sources: Vec<Value>,
+    // A header and value every request must carry, as if sent through a proxy
+    proxy_auth: Option<(String, String)>,
+    tokens: HashSet<String>,
+    next_token: u64,
+    layouts: BTreeMap<String, StubLayout>,
+    next_record_id: u64,
+    injected_errors: VecDeque<(u32, String)>,
+    // Messages added to the next successful response, as non-fatal problems
+    injected_warnings: Vec<(u32, String)>,
+    requests: Vec<String>,
+    scripts: HashMap<String, StubScript>,
+}
+
+/// A script of the stub, given the script parameter and returning its error code and result.
+type StubScript = Arc<dyn Fn(Option<&str>) -> (u32, Option<String>) + Send + Sync>;
+
+#[derive(Default)]
+struct StubLayout {
+    fields: BTreeSet<String>,
+    records: Vec<StubRecord>,
+    // Returned by the layout metadata endpoint instead of describing `fields` as text
+    metadata: Option<Value>,
+}
+
+struct StubRecord {
+    record_id: u64,
+    mod_id: u64,
+    field_data: Map<String, Value>,
+    // Related rows by portal name
+    portal_data: Map<String, Value>,
+}
+
+impl StubRecord {
+    /// Applies the `portalData` of a write: rows with a `recordId` edit that related row,
+    /// the others are appended. Returns the ID of a related row that does not exist.
+    fn write_portals(&mut self, body: &Value) -> Result<(), String> {
+        let Some(portals) = body.get("portalData").and_then(|p| p.as_object()) else {
+            return Ok(());
+        };
+        for (name, writes) in portals {
+            let rows = self
+                .portal_data
+                .entry(name.clone())
+                .or_insert_with(|| json!([]))
+                .as_array_mut()
+                .expect("portal rows");
+            for write in writes.as_array().into_iter().flatten() {
+                let Some(fields) = write.as_object() else {
+                    continue;
+                };
+                match fields.get("recordId").and_then(|id| id.as_str()) {
+                    Some(id) => {
+                        let row = rows
+                            .iter_mut()
+                            .find(|row| row["recordId"] == id)
+                            .ok_or_else(|| id.to_string())?;
+                        for (field, value) in fields
+                            .iter()
+                            .filter(|(f, _)| !matches!(f.as_str(), "recordId" | "modId"))
+                        {
+                            row[field] = value.clone();
+                        }
+                    }
+                    None => {
+                        let mut row = fields.clone();
+                        row.insert("recordId".to_string(), json!((rows.len() + 1).to_string()));
+                        row.insert("modId".to_string(), json!("0"));
+                        rows.push(Value::Object(row));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Value {
+        self.to_json_with(&HashMap::new())
+    }
+
+    /// Returns the record with the portals and portal ranges requested by `query`.
+    fn to_json_with(&self, query: &HashMap<String, String>) -> Value {
+        let included: Option<Vec<String>> = query
+            .get("portal")
+            .and_then(|names| serde_json::from_str(names).ok());
+        let portal_data: Map<String, Value> = self
+            .portal_data
+            .iter()
+            .filter(|(name, _)| included.as_ref().is_none_or(|names| names.contains(name)))
+            .map(|(name, rows)| {
+                let offset = parse_number(query.get(&format!("_offset.{}", name)), 1);
+                let limit = parse_number(query.get(&format!("_limit.{}", name)), DEFAULT_LIMIT);
+                let rows: Vec<Value> = rows
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .skip(offset.max(1) - 1)
+                    .take(limit)
+                    .cloned()
+                    .collect();
+                (name.clone(), Value::Array(rows))
+            })
+            .collect();
+        json!({
+            "fieldData": self.field_data,
+            "portalData": portal_data,
+            "recordId": self.record_id.to_string(),
+            "modId": self.mod_id.to_string(),
+        })
+    }
+}
+
+impl StubServer {
+    /// Starts a server hosting one database that accepts the given credentials.
+    ///
+    /// # Arguments
+    /// * `database` - The name of the hosted database
+    /// * `username` - The account name sessions must use
+    /// * `password` - The password sessions must use
+    ///
+    /// # Returns
+    /// * `Result<StubServer>` - The running server, or an error if no local port could be bound
+    pub async fn start(database: &str, username: &str, password: &str) -> Result<StubServer> {
+        let state = Arc::new(Mutex::new(StubState {
+            database: database.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            next_record_id: 1,
+            ..Default::default()
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let router = Router::new().fallback(handle).with_state(state.clone());
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("Stub server failed: {}", e);
+            }
+        });
+        debug!("Stub server listening on {}", address);
+        Ok(StubServer {
+            url: format!("http://{}{}", address, API_PREFIX),
+            state,
+            task,
+        })
+    }
+
+    /// Returns the base URL of the Data API, to use as `FM_URL` or as an endpoint.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Creates an empty layout with the given fields.
+    pub fn add_layout<I, S>(&self, layout: &str, fields: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut state = self.lock();
+        let entry = state.layouts.entry(layout.to_string()).or_default();
+        entry.fields.extend(fields.into_iter().map(Into::into));
+    }
+
+    /// Sets the `response` returned by the layout metadata endpoint, e.g. to describe field
+    /// types, portals and value lists. By default every field is described as a text field.
+    pub fn set_layout_metadata(&self, layout: &str, metadata: Value) {
+        let mut state = self.lock();
+        state
+            .layouts
+            .entry(layout.to_string())
+            .or_default()
+            .metadata = Some(metadata);
+    }
+
+    /// Inserts a record directly, bypassing the API, and returns its record ID.
+    ///
+    /// The layout is created if needed, and the record's fields are added to it.
+    pub fn insert_record(&self, layout: &str, field_data: Value) -> String {
+        let field_data = match field_data {
+            Value::Object(fields) => fields,
+            _ => Map::new(),
+        };
+        let mut state = self.lock();
+        let record_id = state.next_record_id;
+        state.next_record_id += 1;
+        let entry = state.layouts.entry(layout.to_string()).or_default();
+        entry.fields.extend(field_data.keys().cloned());
+        entry.records.push(StubRecord {
+            record_id,
+            mod_id: 0,
+            field_data,
+            portal_data: Map::new(),
+        });
+        record_id.to_string()
+    }
+
+    /// Sets the related rows a record shows in a portal.
+    ///
+    /// Each row holds the related field data under qualified names, e.g. `Orders::Total`;
+    /// the rows are given record IDs in order, starting at 1.
+    pub fn set_portal(&self, layout: &str, record_id: &str, portal: &str, rows: Vec<Value>) {
+        let rows: Vec<Value> = rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut row)| {
+                row["recordId"] = json!((index + 1).to_string());
+                row["modId"] = json!("0");
+                row
+            })
+            .collect();
+        let mut state = self.lock();
+        let record = state
+            .layouts
+            .get_mut(layout)
+            .and_then(|l| {
+                l.records
+                    .iter_mut()
+                    .find(|r| r.record_id.to_string() == record_id)
+            })
+            .expect("record exists");
+        record
+            .portal_data
+            .insert(portal.to_string(), Value::Array(rows));
+    }
+
+    /// Adds a script that the script endpoint runs with the request's `script.param`.
+    ///
+    /// The script returns its error code and result. Running a script that was not added
+    /// ends with error 104.
+    pub fn add_script<F>(&self, name: &str, script: F)
+    where
+        F: Fn(Option<&str>) -> (u32, Option<String>) + Send + Sync + 'static,
+    {
+        self.lock()
+            .scripts
+            .insert(name.to_string(), Arc::new(script));
+    }
+
+    /// Returns the records of a layout in the shape the Data API returns them.
+    pub fn records(&self, layout: &str) -> Vec<Value> {
+        self.lock()
+            .layouts
+            .get(layout)
+            .map(|l| l.records.iter().map(StubRecord::to_json).collect())
+            .unwrap_or_default()
+    }
+
+    /// Makes the next request other than a login fail with the given FileMaker error.
+    ///
+    /// Errors queue up; each is returned once.
+    pub fn inject_error(&self, code: u32, message: &str) {
+        self.lock()
+            .injected_errors
+            .push_back((code, message.to_string()));
+    }
+
+    /// Adds a message with the given code to the next successful response, as the Data API
+    /// reports non-fatal problems of otherwise successful requests.
+    pub fn inject_warning(&self, code: u32, message: &str) {
+        self.lock()
+            .injected_warnings
+            .push((code, message.to_string()));
+    }
+
+    /// Accepts `token` as a Claris ID token in `FMID` logins, as FileMaker Cloud does.
+    pub fn accept_fmid_token(&self, token: &str) {
+        self.lock().fmid_tokens.insert(token.to_string());
+    }
+
+    /// Makes logins fail unless their `fmDataSource` holds these credentials for the
+    /// external file `database`, as if the hosted file referenced it.
+    pub fn require_data_source(&self, database: &str, username: &str, password: &str) {
+        self.lock().data_sources.push(json!({
+            "database": database,
+            "username": username,
+            "password": password,
+        }));
+    }
+
+    /// Makes every request fail with status 407 unless it carries `header` with `value`,
+    /// like a proxy in front of the Data API.
+    pub fn require_proxy_auth(&self, header: &str, value: &str) {
+        self.lock().proxy_auth = Some((header.to_string(), value.to_string()));
+    }
+
+    /// Invalidates all session tokens, so requests fail with error 952 until clients log in again.
+    pub fn expire_sessions(&self) {
+        self.lock().tokens.clear();
+    }
+
+    /// Invalidates one session token; tokens are issued as `stub-token-1`, `stub-token-2`, ...
+    pub fn expire_session(&self, token: &str) {
+        self.lock().tokens.remove(token);
+    }
+
+    /// Returns the number of sessions currently open.
+    pub fn session_count(&self) -> usize {
+        self.lock().tokens.len()
+    }
+
+    /// Returns the requests received so far, as `METHOD /path?query` relative to [`StubServer::url`].
+    pub fn requests(&self) -> Vec<String> {
+        self.lock().requests.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StubState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Handles every request of the stub, adding injected warnings to successful responses.
+async fn handle(
+    State(state): State<Arc<Mutex<StubState>>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let (response, warnings) = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        let response = route(&mut state, method, uri, headers, body);
+        let warnings = match response.status().is_success() {
+            true => std::mem::take(&mut state.injected_warnings),
+            false => vec![],
+        };
+        (response, warnings)
+    };
+    if warnings.is_empty() {
+        return response;
+    }
+    let status = response.status();
+    let Ok(bytes) = axum::body::to_bytes(response.into_body(), usize::MAX).await else {
+        return failure(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            1630,
+            "Unreadable response",
+        );
+    };
+    let mut json: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    if let Some(messages) = json.get_mut("messages").and_then(|m| m.as_array_mut()) {
+        messages.extend(
+            warnings
+                .into_iter()
+                .map(|(code, message)| json!({ "code": code.to_string(), "message": message })),
+        );
+    }
+    (status, Json(json)).into_response()
+}
+
+/// Routes every request of the stub.
+fn route(
+    state: &mut StubState,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(path) = uri.path().strip_prefix(API_PREFIX) else {
+        return failure(StatusCode::NOT_FOUND, 3, "Command is unavailable");
+    };
+    let relative = match uri.query() {
+        Some(query) => format!("{} {}?{}", method, path, query),
+        None => format!("{} {}", method, path),
+    };
+    state.requests.push(relative);
+    let proxy_authorized = state.proxy_auth.as_ref().is_none_or(|(header, value)| {
+        headers.get(header).and_then(|h| h.to_str().ok()) == Some(value.as_str())
+    });
+    if !proxy_authorized {
+        return (
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED,
+            "Proxy authentication required",
+        )
+            .into_response();
+    }
+
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let query = parse_query(uri.query().unwrap_or_default());
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    match (&method, segments.as_slice()) {
+        (&Method::POST, ["databases", database, "sessions"]) => {
+            state.login(database, &headers, &body)
+        }
+        (&Method::DELETE, ["databases", _, "sessions", token]) => {
+            state.tokens.remove(*token);
+            success(json!({}))
+        }
+        (&Method::GET, ["databases"]) => success(json!({
+            "databases": [{ "name": state.database }]
+        })),
+        _ => {
+            if !state.authorized(&headers) {
+                return failure(
+                    StatusCode::UNAUTHORIZED,
+                    952,
+                    "Invalid FileMaker Data API token (*)",
+                );
+            }
+            if let Some((code, message)) = state.injected_errors.pop_front() {
+                return failure(StatusCode::INTERNAL_SERVER_ERROR, code, &message);
+            }
+            match (&method, segments.as_slice()) {
+                (&Method::DELETE, ["databases", _]) => success(json!({})),
+                (&Method::GET, ["databases", _, "layouts"]) => {
+                    let layouts: Vec<Value> = state
+                        .layouts
+                        .keys()
+                        .map(|name| json!({ "name": name }))
+                        .collect();
+                    success(json!({ "layouts": layouts }))
+                }
+                (_, ["databases", _, "layouts", layout, rest @ ..]) => {
+                    state.layout_request(&method, layout, rest, &query, &body)
+                }
+                _ => failure(StatusCode::NOT_FOUND, 3, "Command is unavailable"),
+            }
+        }
+    }
+}
+
+impl StubState {
+    fn login(&mut self, database: &str, headers: &HeaderMap, body: &Value) -> Response {
+        if database != self.database {
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                802,
+                "Unable to open file",
+            );
+        }
+        let authorization = headers.get("Authorization").and_then(|h| h.to_str().ok());
+        let fmid = authorization
+            .and_then(|h| h.strip_prefix("FMID "))
+            .is_some_and(|token| self.fmid_tokens.contains(token));
+        let credentials = authorization
+            .and_then(|h| h.strip_prefix("Basic "))
+            .and_then(|b| base64::engine::general_purpose::STANDARD.decode(b).ok())
+            .and_then(|b| String::from_utf8(b).ok());
+        if !fmid && credentials != Some(format!("{}:{}", self.username, self.password)) {
+            return failure(
+                StatusCode::UNAUTHORIZED,
+                212,
+                "Invalid user account and/or password; please try again",
+            );
+        }
+        let sent = body["fmDataSource"].as_array().cloned().unwrap_or_default();
+        if let Some(missing) = self.data_sources.iter().find(|s| !sent.contains(s)) {
+            return failure(
+                StatusCode::UNAUTHORIZED,
+                212,
+                &format!(
+                    "Invalid user account and/or password for {}",
+                    missing["database"].as_str().unwrap_or_default()
+                ),
+            );
+        }
+        self.next_token += 1;
+        let token = format!("stub-token-{}", self.next_token);
+        self.tokens.insert(token.clone());
+        success(json!({ "token": token }))
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .is_some_and(|token| self.tokens.contains(token))
+    }
+
+    fn layout_request(
+        &mut self,
+        method: &Method,
+        layout: &str,
+        rest: &[&str],
+        query: &HashMap<String, String>,
+        body: &Value,
+    ) -> Response {
+        let database = self.database.clone();
+        let next_record_id = self.next_record_id;
+        let Some(entry) = self.layouts.get_mut(layout) else {
+            return failure(StatusCode::INTERNAL_SERVER_ERROR, 105, "Layout is missing");
+        };
+
+        match (method, rest) {
+            (&Method::GET, ["script", name]) => {
+                let (code, result) = match self.scripts.get(*name) {
+                    Some(script) => script(query.get("script.param").map(String::as_str)),
+                    None => (104, None),
+                };
+                let mut response = json!({ "scriptError": code.to_string() });
+                if let Some(result) = result {
+                    response["scriptResult"] = json!(result);
+                }
+                success(response)
+            }
+            (&Method::GET, []) => {
+                if let Some(metadata) = &entry.metadata {
+                    return success(metadata.clone());
+                }
+                let fields: Vec<Value> = entry
+                    .fields
+                    .iter()
+                    .map(|name| json!({ "name": name, "type": "normal", "result": "text" }))
+                    .collect();
+                success(json!({ "fieldMetaData": fields, "portalMetaData": {} }))
+            }
+            (&Method::GET, ["records"]) => {
+                let mut records: Vec<&StubRecord> = entry.records.iter().collect();
+                if let Some(sort) = query.get("_sort") {
+                    let sort: Vec<Value> = serde_json::from_str(sort).unwrap_or_default();
+                    sort_records(&mut records, &sort);
+                }
+                let offset = parse_number(query.get("_offset"), 1);
+                let limit = parse_number(query.get("_limit"), DEFAULT_LIMIT);
+                found_set(
+                    &database,
+                    layout,
+                    entry.records.len(),
+                    &records,
+                    offset,
+                    limit,
+                    query,
+                )
+            }
+            (&Method::POST, ["records"]) => {
+                let field_data = field_data(body);
+                if let Some(field) = unknown_field(entry, &field_data) {
+                    return field_missing(field);
+                }
+                let mut record = StubRecord {
+                    record_id: next_record_id,
+                    mod_id: 0,
+                    field_data,
+                    portal_data: Map::new(),
+                };
+                if let Err(id) = record.write_portals(body) {
+                    return related_missing(id);
+                }
+                entry.records.push(record);
+                self.next_record_id += 1;
+                success(json!({ "recordId": next_record_id.to_string(), "modId": "0" }))
+            }
+            (_, ["records", id]) => {
+                let Some(index) = entry
+                    .records
+                    .iter()
+                    .position(|r| r.record_id.to_string() == *id)
+                else {
+                    return failure(StatusCode::INTERNAL_SERVER_ERROR, 101, "Record is missing");
+                };
+                match *method {
+                    Method::GET => {
+                        let record = entry.records[index].to_json_with(query);
+                        let data_info = data_info(&database, layout, entry.records.len(), 1, 1);
+                        success(json!({ "dataInfo": data_info, "data": [record] }))
+                    }
+                    Method::PATCH => {
+                        let field_data = field_data(body);
+                        if let Some(field) = unknown_field(entry, &field_data) {
+                            return field_missing(field);
+                        }
+                        let record = &mut entry.records[index];
+                        let expected = body.get("modId").and_then(|m| m.as_str());
+                        if expected.is_some_and(|m| m != record.mod_id.to_string()) {
+                            return failure(
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                306,
+                                "Record modification ID does not match",
+                            );
+                        }
+                        if let Err(id) = record.write_portals(body) {
+                            return related_missing(id);
+                        }
+                        record.field_data.extend(field_data);
+                        record.mod_id += 1;
+                        success(json!({ "modId": record.mod_id.to_string() }))
+                    }
+                    Method::DELETE => {
+                        entry.records.remove(index);
+                        success(json!({}))
+                    }
+                    _ => failure(StatusCode::METHOD_NOT_ALLOWED, 3, "Command is unavailable"),
+                }
+            }
+            (&Method::POST, ["_find"]) => {
+                let requests = body
+                    .get("query")
+                    .and_then(|q| q.as_array())
+                    .cloned()
+                    .unwrap_or_default();
+                if requests.is_empty() {
+                    return failure(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        1708,
+                        "Parameter value is invalid",
+                    );
+                }
+                for request in &requests {
+                    for field in request.as_object().into_iter().flat_map(|r| r.keys()) {
+                        if field != "omit" && !entry.fields.contains(field) {
+                            return field_missing(field);
+                        }
+                    }
+                }
+                let mut records: Vec<&StubRecord> = entry
+                    .records
+                    .iter()
+                    .filter(|record| find_matches(record, &requests))
+                    .collect();
+                if let Some(sort) = body.get("sort").and_then(|s| s.as_array()) {
+                    sort_records(&mut records, sort);
+                }
+                let offset = body.get("offset").and_then(|o| o.as_u64()).unwrap_or(1) as usize;
+                let limit = body
+                    .get("limit")
+                    .and_then(|l| l.as_u64())
+                    .map_or(DEFAULT_LIMIT, |l| l.min(usize::MAX as u64) as usize);
+                found_set(
+                    &database,
+                    layout,
+                    entry.records.len(),
+                    &records,
+                    offset,
+                    limit,
+                    &portal_parameters(body),
+                )
+            }
+            _ => failure(StatusCode::NOT_FOUND, 3, "Command is unavailable"),
+        }
+    }
+}
+
+/// Converts the portal parameters of a find body to their query string names.
+fn portal_parameters(body: &Value) -> HashMap<String, String> {
+    let mut parameters = HashMap::new();
+    for (name, value) in body.as_object().into_iter().flatten() {
+        let name = match name.as_str() {
+            "portal" => name.clone(),
+            _ if name.starts_with("offset.") || name.starts_with("limit.") => format!("_{}", name),
+            _ => continue,
+        };
+        let value = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        parameters.insert(name, value);
+    }
+    parameters
+}
+
+/// Builds the response for a found set, or error 401 if the requested range is empty.
+fn found_set(
+    database: &str,
+    layout: &str,
+    total: usize,
+    records: &[&StubRecord],
+    offset: usize,
+    limit: usize,
+    portals: &HashMap<String, String>,
+) -> Response {
+    let data: Vec<Value> = records
+        .iter()
+        .skip(offset.max(1) - 1)
+        .take(limit)
+        .map(|r| r.to_json_with(portals))
+        .collect();
+    if data.is_empty() {
+        return failure(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            401,
+            "No records match the request",
+        );
+    }
+    let data_info = data_info(database, layout, total, records.len(), data.len());
+    success(json!({ "dataInfo": data_info, "data": data }))
+}
+
+fn data_info(database: &str, layout: &str, total: usize, found: usize, returned: usize) -> Value {
+    json!({
+        "database": database,
+        "layout": layout,
+        "table": layout,
+        "totalRecordCount": total,
+        "foundCount": found,
+        "returnedCount": returned,
+    })
+}
+
+fn success(response: Value) -> Response {
+    Json(json!({
+        "response": response,
+        "messages": [{ "code": "0", "message": "OK" }],
+    }))
+    .into_response()
+}
+
+fn failure(status: StatusCode, code: u32, message: &str) -> Response {
+    let body = json!({
+        "response": {},
+        "messages": [{ "code": code.to_string(), "message": message }],
+    });
+    (status, Json(body)).into_response()
+}
+
+fn field_missing(field: &str) -> Response {
+    warn!("Stub server: field {} is missing", field);
+    failure(StatusCode::INTERNAL_SERVER_ERROR, 102, "Field is missing")
+}
+
+fn field_data(body: &Value) -> Map<String, Value> {
+    body.get("fieldData")
+        .and_then(|f| f.as_object())
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn related_missing(record_id: String) -> Response {
+    failure(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        101,
+        &format!("Related record {} is missing", record_id),
+    )
+}
+
+fn unknown_field<'a>(layout: &StubLayout, field_data: &'a Map<String, Value>) -> Option<&'a str> {
+    field_data
+        .keys()
+        .find(|field| !layout.fields.contains(*field))
+        .map(String::as_str)
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| {
+            let decode = |part: &str| {
+                percent_decode_str(&part.replace('+', " "))
+                    .decode_utf8_lossy()
+                    .into_owned()
+            };
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+fn parse_number(value: Option<&String>, default: usize) -> usize {
+    value.and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Sorts records by `[{ "fieldName": .., "sortOrder": "ascend" | "descend" }]`.
+fn sort_records(records: &mut [&StubRecord], sort: &[Value]) {
+    records.sort_by(|a, b| {
+        for rule in sort {
+            let Some(field) = rule.get("fieldName").and_then(|f| f.as_str()) else {
+                continue;
+            };
+            let descending = rule.get("sortOrder").and_then(|o| o.as_str()) == Some("descend");
+            let ordering = compare_values(
+                a.field_data.get(field).unwrap_or(&Value::Null),
+                b.field_data.get(field).unwrap_or(&Value::Null),
+            );
+            let ordering = if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        a.record_id.cmp(&b.record_id)
+    });
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        _ => text(a).to_lowercase().cmp(&text(b).to_lowercase()),
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Applies find requests the way FileMaker does: a record is found if it matches any
+/// non-omit request and no omit request.
+fn find_matches(record: &StubRecord, requests: &[Value]) -> bool {
+    let mut found = false;
+    let mut has_find_request = false;
+    for request in requests {
+        let Some(criteria) = request.as_object() else {
+            continue;
+        };
+        let omit = criteria.get("omit").is_some_and(|o| text(o) == "true");
+        let matches =
+            criteria
+                .iter()
+                .filter(|(field, _)| *field != "omit")
+                .all(|(field, criterion)| {
+                    matches_criterion(
+                        record.field_data.get(field).unwrap_or(&Value::Null),
+                        &text(criterion),
+                    )
+                });
+        if omit && matches {
+            return false;
+        }
+        if !omit {
+            has_find_request = true;
+            found |= matches;
+        }
+    }
+    found || !has_find_request
+}
+
+/// Matches a field value against one find criterion.
+///
+/// Supports exact (`==`), empty (`=`), non-empty (`*`), comparison (`>`, `>=`, `<`, `<=`)
+/// and the default "word begins with" matching, with backslash-escaped operators.
+fn matches_criterion(value: &Value, criterion: &str) -> bool {
+    let value_text = text(value);
+    if criterion == "=" {
+        return value_text.is_empty();
+    }
+    if criterion == "*" {
+        return !value_text.is_empty();
+    }
+    if let Some(exact) = criterion.strip_prefix("==") {
+        return value_text == unescape(exact);
+    }
+    for (operator, accepted) in [
+        (">=", [Ordering::Greater, Ordering::Equal]),
+        ("<=", [Ordering::Less, Ordering::Equal]),
+        (">", [Ordering::Greater, Ordering::Greater]),
+        ("<", [Ordering::Less, Ordering::Less]),
+    ] {
+        if let Some(operand) = criterion.strip_prefix(operator) {
+            let ordering = compare_values(value, &Value::String(unescape(operand)));
+            return !value_text.is_empty() && accepted.contains(&ordering);
+        }
+    }
+    if let Some(equal) = criterion.strip_prefix('=') {
+        return value_text.to_lowercase() == unescape(equal).to_lowercase();
+    }
+
+    let wanted = unescape(criterion.trim_end_matches('*')).to_lowercase();
+    let value_text = value_text.to_lowercase();
+    value_text.starts_with(&wanted)
+        || value_text
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word.starts_with(&wanted))
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,146 @@
+//! A request budget shared by every client of a process.
+
+use crate::Filemaker;
+use log::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// Caps the concurrent requests and the request rate of all clients sharing it.
+///
+/// Per-client limits such as [`crate::AdaptiveConcurrency`] only see the traffic of one
+/// client, so a service with a client per layout can still overload the server in sum.
+/// Clones of a throttle share one budget; give the same throttle to every client with
+/// [`Filemaker::with_throttle`] and their requests wait for a free slot. Clients derived
+/// with [`Filemaker::with_layout`] keep the throttle of their parent.
+///
+/// ```rust,ignore
+/// let throttle = FmThrottle::new(8).with_rate(20, Duration::from_secs(1));
+/// let invoices = Filemaker::new("user", "pass", "Billing", "Invoices")
+///     .await?
+///     .with_throttle(throttle.clone());
+/// let customers = Filemaker::new("user", "pass", "Billing", "Customers")
+///     .await?
+///     .with_throttle(throttle);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FmThrottle {
+    inner: Arc<ThrottleState>,
+}
+
+#[derive(Debug)]
+struct ThrottleState {
+    max_concurrent: usize,
+    permits: Arc<Semaphore>,
+    // The minimum time between the starts of two requests; None is unlimited
+    interval: Option<Duration>,
+    // The earliest time the next request may start
+    next_start: Mutex<Instant>,
+}
+
+/// A slot of an [`FmThrottle`], held while a request is in flight.
+pub(crate) struct ThrottlePermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl FmThrottle {
+    /// Creates a throttle allowing at most `max_concurrent` requests in flight at once,
+    /// without a rate limit.
+    ///
+    /// # Arguments
+    /// * `max_concurrent` - The number of requests in flight across all clients (at least 1)
+    pub fn new(max_concurrent: usize) -> Self {
+        Self::with_state(max_concurrent.max(1), None)
+    }
+
+    /// Also limits the clients to `requests` requests per `per`, spaced evenly.
+    ///
+    /// Call this before sharing the throttle; the returned throttle has a budget of its own.
+    ///
+    /// # Arguments
+    /// * `requests` - The number of requests per period (at least 1)
+    /// * `per` - The length of the period
+    pub fn with_rate(self, requests: u32, per: Duration) -> Self {
+        let interval = per / requests.max(1);
+        Self::with_state(self.inner.max_concurrent, Some(interval))
+    }
+
+    fn with_state(max_concurrent: usize, interval: Option<Duration>) -> Self {
+        Self {
+            inner: Arc::new(ThrottleState {
+                max_concurrent,
+                permits: Arc::new(Semaphore::new(max_concurrent)),
+                interval,
+                next_start: Mutex::new(Instant::now()),
+            }),
+        }
+    }
+
+    /// Returns the number of requests allowed in flight at once.
+    pub fn max_concurrent(&self) -> usize {
+        self.inner.max_concurrent
+    }
+
+    /// Returns the number of requests currently in flight through this throttle.
+    pub fn in_flight(&self) -> usize {
+        self.inner.max_concurrent - self.inner.permits.available_permits()
+    }
+
+    /// Waits for a free slot and the next start time allowed by the rate limit.
+    pub(crate) async fn acquire(&self) -> ThrottlePermit {
+        let permit = self
+            .inner
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the throttle semaphore is never closed");
+        if let Some(interval) = self.inner.interval {
+            let start = {
+                let mut next_start = self
+                    .inner
+                    .next_start
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                let start = (*next_start).max(Instant::now());
+                *next_start = start + interval;
+                start
+            };
+            if start > Instant::now() {
+                trace!("Throttled request waits {:?}", start - Instant::now());
+                tokio::time::sleep_until(start).await;
+            }
+        }
+        ThrottlePermit { _permit: permit }
+    }
+}
+
+impl Filemaker {
+    /// Sends the requests of this client through a throttle shared with other clients.
+    ///
+    /// Every Data API request waits for the throttle before it is sent and holds its slot
+    /// until the response is read. Logins are not throttled.
+    ///
+    /// # Arguments
+    /// * `throttle` - The shared throttle, usually a clone of one created at startup
+    ///
+    /// # Returns
+    /// * `Self` - The throttled client
+    pub fn with_throttle(mut self, throttle: FmThrottle) -> Self {
+        debug!(
+            "Using a shared throttle of {} concurrent requests",
+            throttle.max_concurrent()
+        );
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Waits for the client's throttle, if it has one.
+    pub(crate) async fn throttle_permit(&self) -> Option<ThrottlePermit> {
+        match &self.throttle {
+            Some(throttle) => Some(throttle.acquire().await),
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Pseudo-transactions with compensating rollback.
+//!
+//! The Data API has no transactions. A [`Transaction`] records every write it performs
+//! together with the values it replaced, so a sequence of writes can be undone by applying the
+//! reverse operations if a later step fails.
+
+use crate::{Filemaker, Journal, PlannedOperation};
+use anyhow::{anyhow, Result};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A write performed within a [`Transaction`], with what is needed to revert it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum TransactionOperation {
+    /// A record was created; reverted by deleting it.
+    Created {
+        /// The ID of the created record.
+        record_id: String,
+    },
+    /// A record was updated; reverted by writing back the previous values of the changed fields.
+    Updated {
+        /// The ID of the updated record.
+        record_id: String,
+        /// The values of the changed fields before the update.
+        previous: Map<String, Value>,
+    },
+    /// A record was deleted; reverted by creating a record with its previous field data.
+    Deleted {
+        /// The ID the deleted record had.
+        record_id: String,
+        /// The field data of the record before it was deleted.
+        previous: Map<String, Value>,
+    },
+}
+
+/// Outcome of [`Transaction::rollback`].
+#[derive(Debug, Default)]
+pub struct RollbackReport {
+    /// The number of operations that were reverted.
+    pub reverted: usize,
+    /// Operations that could not be reverted, together with the reason.
+    pub failed: Vec<(TransactionOperation, anyhow::Error)>,
+}
+
+/// Records the writes made through it so they can be rolled back.
+///
+/// Created with [`Filemaker::transaction`]. Rollback is best-effort: records re-created after
+/// a delete get a new record ID, and writes made by other clients in the meantime are
+/// overwritten.
+///
+/// ```rust,ignore
+/// let mut transaction = filemaker.transaction();
+/// let result = async {
+///     let id = transaction.create(invoice).await?;
+///     transaction.update(&customer_id, balance).await?;
+///     anyhow::Ok(id)
+/// }
+/// .await;
+/// if result.is_err() {
+///     transaction.rollback().await?;
+/// }
+/// ```
+pub struct Transaction {
+    filemaker: Filemaker,
+    operations: Vec<TransactionOperation>,
+    journal: Option<Arc<Journal>>,
+}
+
+impl Filemaker {
+    /// Starts a pseudo-transaction on this client's layout.
+    pub fn transaction(&self) -> Transaction {
+        Transaction {
+            filemaker: self.clone(),
+            operations: vec![],
+            journal: None,
+        }
+    }
+}
+
+impl Transaction {
+    /// Returns the operations performed so far, oldest first.
+    pub fn operations(&self) -> &[TransactionOperation] {
+        &self.operations
+    }
+
+    /// Writes every operation to `journal` before and after it is sent, so an interrupted
+    /// transaction can be inspected and rolled back after a crash.
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Creates a record and records the creation.
+    ///
+    /// # Returns
+    /// * `Result<String>` - The ID of the created record, or an error
+    pub async fn create(&mut self, field_data: HashMap<String, Value>) -> Result<String> {
+        let planned = PlannedOperation::Create {
+            field_data: field_data.into_iter().collect(),
+        };
+        match self.execute(planned).await? {
+            TransactionOperation::Created { record_id } => Ok(record_id),
+            other => Err(anyhow!("Unexpected result of create: {:?}", other)),
+        }
+    }
+
+    /// Updates a record and records the previous values of the changed fields.
+    pub async fn update(&mut self, id: &str, field_data: HashMap<String, Value>) -> Result<()> {
+        let current = self.field_data(id).await?;
+        let previous = field_data
+            .keys()
+            .filter_map(|field| current.get(field).map(|v| (field.clone(), v.clone())))
+            .collect();
+        let planned = PlannedOperation::Update {
+            record_id: id.to_string(),
+            field_data: field_data.into_iter().collect(),
+            previous,
+        };
+        self.execute(planned).await.map(|_| ())
+    }
+
+    /// Deletes a record and records its field data.
+    pub async fn delete(&mut self, id: &str) -> Result<()> {
+        let planned = PlannedOperation::Delete {
+            record_id: id.to_string(),
+            previous: self.field_data(id).await?,
+        };
+        self.execute(planned).await.map(|_| ())
+    }
+
+    /// Keeps all changes and returns the operations that were performed.
+    pub fn commit(self) -> Vec<TransactionOperation> {
+        info!(
+            "Committed transaction with {} operations",
+            self.operations.len()
+        );
+        self.operations
+    }
+
+    /// Reverts all recorded operations, newest first.
+    ///
+    /// Reverting continues past failures; operations that could not be reverted are
+    /// reported in the returned [`RollbackReport`].
+    pub async fn rollback(self) -> Result<RollbackReport> {
+        let mut report = RollbackReport::default();
+        for operation in self.operations.into_iter().rev() {
+            match self.filemaker.revert(&operation).await {
+                Ok(()) => report.reverted += 1,
+                Err(e) => {
+                    error!("Failed to revert {:?}: {}", operation, e);
+                    report.failed.push((operation, e));
+                }
+            }
+        }
+        info!(
+            "Rolled back transaction: {} reverted, {} failed",
+            report.reverted,
+            report.failed.len()
+        );
+        Ok(report)
+    }
+
+    /// Sends a planned operation, journaling it if a journal is attached.
+    async fn execute(&mut self, planned: PlannedOperation) -> Result<TransactionOperation> {
+        let seq = match &self.journal {
+            Some(journal) => Some(
+                journal
+                    .pending(&self.filemaker.layout(), planned.clone())
+                    .await?,
+            ),
+            None => None,
+        };
+        let result = self.filemaker.apply_planned(&planned).await;
+        if let (Some(journal), Some(seq)) = (&self.journal, seq) {
+            match &result {
+                Ok(operation) => journal.completed(seq, operation.clone()).await?,
+                Err(e) => journal.failed(seq, e).await?,
+            }
+        }
+        let operation = result?;
+        debug!("Transaction recorded {:?}", operation);
+        self.operations.push(operation.clone());
+        Ok(operation)
+    }
+
+    async fn field_data(&self, id: &str) -> Result<Map<String, Value>> {
+        let record = self.filemaker.get_record_by_id(id).await?;
+        match record.get("fieldData") {
+            Some(Value::Object(fields)) => Ok(fields.clone()),
+            _ => Err(anyhow!("Record {} has no field data", id)),
+        }
+    }
+}
+
+impl Filemaker {
+    /// Applies the reverse of a recorded operation.
+    pub(crate) async fn revert(&self, operation: &TransactionOperation) -> Result<()> {
+        match operation {
+            TransactionOperation::Created { record_id } => {
+                let response = self.delete_record_response(record_id).await?;
+                match Self::api_error(&response) {
+                    Some(api_error) => {
+                        Err(anyhow::Error::new(api_error).context("Failed to delete record"))
+                    }
+                    None => Ok(()),
+                }
+            }
+            TransactionOperation::Updated {
+                record_id,
+                previous,
+            } => {
+                let field_data = previous.clone().into_iter().collect();
+                self.update_record_guarded(record_id, field_data, None)
+                    .await
+                    .map(|_| ())
+            }
+            TransactionOperation::Deleted { previous, .. } => {
+                let field_data = previous.clone().into_iter().collect();
+                self.create_record_ids(field_data).await.map(|_| ())
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::coerce::{self, NumberFormat};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::fmt;
+
+/// A single FileMaker field value.
+///
+/// The Data API only ever returns numbers and text for field data; empty fields come back as
+/// empty text. `FmValue` models exactly those cases and offers conversions that follow
+/// FileMaker's conventions (see [`crate::coerce`]).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum FmValue {
+    /// An empty field.
+    #[default]
+    Empty,
+    /// A number field, or a calculation with a number result.
+    Number(f64),
+    /// Any other field, including dates, times and timestamps in their text form.
+    Text(String),
+}
+
+impl FmValue {
+    /// Returns `true` if the field is empty.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, FmValue::Empty)
+    }
+
+    /// Interprets the value as a boolean following FileMaker's conventions.
+    ///
+    /// See [`coerce::to_bool`].
+    pub fn as_bool(&self) -> Result<bool> {
+        coerce::to_bool(&Value::from(self.clone()))
+    }
+
+    /// Interprets the value as a number, parsing text with the given format.
+    ///
+    /// See [`coerce::to_number`].
+    pub fn as_number(&self, format: &NumberFormat) -> Result<Option<f64>> {
+        coerce::to_number(&Value::from(self.clone()), format)
+    }
+
+    /// Returns the text of the value, if it is a text value.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            FmValue::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FmValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FmValue::Empty => Ok(()),
+            FmValue::Number(n) => write!(f, "{}", n),
+            FmValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl From<Value> for FmValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => FmValue::Empty,
+            Value::Bool(b) => FmValue::from(b),
+            Value::Number(n) => n.as_f64().map(FmValue::Number).unwrap_or_default(),
+            Value::String(s) if s.is_empty() => FmValue::Empty,
+            Value::String(s) => FmValue::Text(s),
+            other => FmValue::Text(other.to_string()),
+        }
+    }
+}
+
+impl From<FmValue> for Value {
+    fn from(value: FmValue) -> Self {
+        match value {
+            FmValue::Empty => Value::String(String::new()),
+            FmValue::Number(n) => serde_json::Number::from_f64(n)
+                .map(Value::Number)
+                .unwrap_or_else(|| Value::String(String::new())),
+            FmValue::Text(text) => Value::String(text),
+        }
+    }
+}
+
+impl From<bool> for FmValue {
+    /// Booleans are stored as `1` and `0`, FileMaker's usual flag representation.
+    fn from(value: bool) -> Self {
+        FmValue::Number(if value { 1.0 } else { 0.0 })
+    }
+}
+
+impl From<f64> for FmValue {
+    fn from(value: f64) -> Self {
+        FmValue::Number(value)
+    }
+}
+
+impl From<i64> for FmValue {
+    fn from(value: i64) -> Self {
+        FmValue::Number(value as f64)
+    }
+}
+
+impl From<String> for FmValue {
+    fn from(value: String) -> Self {
+        FmValue::from(Value::String(value))
+    }
+}
+
+impl From<&str> for FmValue {
+    fn from(value: &str) -> Self {
+        FmValue::from(value.to_string())
+    }
+}
+
+impl TryFrom<FmValue> for bool {
+    type Error = anyhow::Error;
+
+    fn try_from(value: FmValue) -> Result<Self> {
+        value.as_bool()
+    }
+}
+
+impl TryFrom<&FmValue> for bool {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &FmValue) -> Result<Self> {
+        value.as_bool()
+    }
+}
+
+impl TryFrom<FmValue> for f64 {
+    type Error = anyhow::Error;
+
+    /// Converts with the default [`NumberFormat`]; empty values are an error.
+    fn try_from(value: FmValue) -> Result<Self> {
+        value
+            .as_number(&NumberFormat::default())?
+            .ok_or_else(|| anyhow!("Expected a number, found an empty value"))
+    }
+}
+
+impl Serialize for FmValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Value::from(self.clone()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FmValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Value::deserialize(deserializer).map(FmValue::from)
+    }
+}
@@ -0,0 +1,377 @@
+//! Checking typed record models against the layouts they are bound to.
+//!
+//! A model's fields are read from its `Deserialize` implementation: the derive macro of serde
+//! lists the (renamed) field names of a struct, and each field's type is known by the kind of
+//! value it asks the deserializer for. [`Filemaker::verify_model`] compares them with the
+//! layout metadata, so a field renamed, removed or retyped in the FileMaker file is found
+//! before reading records fails, e.g. in CI against a staging server.
+
+use crate::{Filemaker, FmRecordModel};
+use anyhow::Result;
+use log::*;
+use serde::de::{self, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use std::fmt;
+
+/// The kind of value a field of a record model holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModelFieldType {
+    /// A number, e.g. `f64` or `i64`; matches number fields.
+    Number,
+    /// Text, e.g. `String` or a unit-only enum; matches all but number fields.
+    Text,
+    /// A `bool`, which the Data API never returns; FileMaker stores booleans as numbers.
+    Boolean,
+    /// Anything the field holds, e.g. [`crate::FmValue`] or `serde_json::Value`.
+    Any,
+    /// A sequence, map or struct, which no field of `fieldData` holds.
+    Nested,
+}
+
+impl ModelFieldType {
+    /// Returns whether values of a field with the given result type can be read into this
+    /// type.
+    fn accepts(self, result: &str) -> bool {
+        match self {
+            ModelFieldType::Any => true,
+            ModelFieldType::Number => result == "number",
+            ModelFieldType::Text => result != "number",
+            ModelFieldType::Boolean | ModelFieldType::Nested => false,
+        }
+    }
+}
+
+impl fmt::Display for ModelFieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ModelFieldType::Number => "number",
+            ModelFieldType::Text => "text",
+            ModelFieldType::Boolean => "bool",
+            ModelFieldType::Any => "any value",
+            ModelFieldType::Nested => "nested value",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A field of a record model, as returned by [`FmRecordModel::fields`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelField {
+    /// The field name as used in `fieldData`.
+    pub name: String,
+    /// The kind of value the field holds.
+    pub kind: ModelFieldType,
+    /// Whether the field is an `Option`.
+    pub optional: bool,
+}
+
+/// A model field whose type does not match the layout field of the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MistypedField {
+    /// The field name.
+    pub name: String,
+    /// The kind of value the model expects.
+    pub expected: ModelFieldType,
+    /// The result type of the layout field, e.g. `text` or `number`.
+    pub result: String,
+}
+
+/// The outcome of [`Filemaker::verify_model`], printable as a report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModelReport {
+    /// The layout the model was checked against.
+    pub layout: String,
+    /// Fields of the model that are not on the layout.
+    pub missing: Vec<String>,
+    /// Fields on the layout that are not in the model, in layout order.
+    pub extra: Vec<String>,
+    /// Fields whose type in the model does not match the layout.
+    pub mistyped: Vec<MistypedField>,
+}
+
+impl ModelReport {
+    /// Returns `true` if records of the layout can be read into the model: no field of the
+    /// model is missing or mistyped. Extra layout fields are ignored when reading.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mistyped.is_empty()
+    }
+}
+
+impl fmt::Display for ModelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.missing {
+            writeln!(f, "MISSING   {}", name)?;
+        }
+        for field in &self.mistyped {
+            writeln!(
+                f,
+                "MISTYPED  {}: the model expects {}, the layout holds {}",
+                field.name, field.expected, field.result
+            )?;
+        }
+        for name in &self.extra {
+            writeln!(f, "EXTRA     {}", name)?;
+        }
+        write!(
+            f,
+            "{}: {} missing, {} mistyped, {} extra",
+            self.layout,
+            self.missing.len(),
+            self.mistyped.len(),
+            self.extra.len()
+        )
+    }
+}
+
+impl Filemaker {
+    /// Checks the fields of model `T` against the metadata of its layout.
+    ///
+    /// Model fields are read with [`FmRecordModel::fields`]. Fields excluded by
+    /// [`Filemaker::with_field_filter`], if set, are not reported as extra.
+    ///
+    /// ```rust,ignore
+    /// let report = filemaker.verify_model::<Invoice>().await?;
+    /// assert!(report.is_ok(), "{}", report);
+    /// ```
+    ///
+    /// # Returns
+    /// * `Result<ModelReport>` - The differences, or an error if the layout metadata cannot be read
+    pub async fn verify_model<T: FmRecordModel>(&self) -> Result<ModelReport> {
+        let mut layout_fields = self.with_layout(T::LAYOUT).layout_fields().await?;
+        if let Some(filter) = self.field_filter() {
+            layout_fields.retain(|field| !filter.excludes(&field.name));
+        }
+        let model_fields = T::fields();
+
+        let mut report = ModelReport {
+            layout: T::LAYOUT.to_string(),
+            ..Default::default()
+        };
+        for field in &model_fields {
+            match layout_fields.iter().find(|f| f.name == field.name) {
+                None => report.missing.push(field.name.clone()),
+                Some(layout_field) if !field.kind.accepts(&layout_field.result) => {
+                    report.mistyped.push(MistypedField {
+                        name: field.name.clone(),
+                        expected: field.kind,
+                        result: layout_field.result.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        report.extra = layout_fields
+            .into_iter()
+            .filter(|field| !model_fields.iter().any(|f| f.name == field.name))
+            .map(|field| field.name)
+            .collect();
+        if report.is_ok() {
+            debug!("Model of layout {} matches: {}", T::LAYOUT, report);
+        } else {
+            warn!("Model of layout {} does not match: {}", T::LAYOUT, report);
+        }
+        Ok(report)
+    }
+}
+
+/// Reads the fields of `T` from its `Deserialize` implementation.
+///
+/// The struct is deserialized once to learn its field names, then once per field with only
+/// that field present, recording what the field asks for. Every run ends with an error, so
+/// no value is ever built. Models not deserialized as a struct, e.g. with flattened fields,
+/// have no fields listed.
+pub(crate) fn model_fields<'de, T: Deserialize<'de>>() -> Vec<ModelField> {
+    let mut names = None;
+    let _ = T::deserialize(StructProbe::Names(&mut names));
+    let Some(names) = names else {
+        warn!(
+            "{} is not deserialized as a struct; its fields are unknown",
+            std::any::type_name::<T>()
+        );
+        return vec![];
+    };
+    names
+        .iter()
+        .map(|name| {
+            let mut field = ModelField {
+                name: name.to_string(),
+                kind: ModelFieldType::Any,
+                optional: false,
+            };
+            let _ = T::deserialize(StructProbe::Field(&mut field));
+            field
+        })
+        .collect()
+}
+
+/// Ends a probing run.
+#[derive(Debug)]
+struct Probed;
+
+impl fmt::Display for Probed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("probed")
+    }
+}
+
+impl std::error::Error for Probed {}
+
+impl de::Error for Probed {
+    fn custom<M: fmt::Display>(_message: M) -> Self {
+        Probed
+    }
+}
+
+/// Deserializes the model itself: either records its field names, or offers one field.
+enum StructProbe<'a> {
+    Names(&'a mut Option<&'static [&'static str]>),
+    Field(&'a mut ModelField),
+}
+
+impl<'de> Deserializer<'de> for StructProbe<'_> {
+    type Error = Probed;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Probed> {
+        Err(Probed)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Probed> {
+        match self {
+            StructProbe::Names(names) => {
+                *names = Some(fields);
+                Err(Probed)
+            }
+            StructProbe::Field(field) => visitor.visit_map(OneField {
+                field,
+                key_taken: false,
+            }),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+/// A map holding only the probed field.
+struct OneField<'a> {
+    field: &'a mut ModelField,
+    key_taken: bool,
+}
+
+impl<'de> MapAccess<'de> for OneField<'_> {
+    type Error = Probed;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Probed> {
+        if self.key_taken {
+            return Ok(None);
+        }
+        self.key_taken = true;
+        let name: &str = &self.field.name;
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Probed> {
+        seed.deserialize(FieldProbe(self.field))
+    }
+}
+
+/// Records the kind of value a field asks for.
+struct FieldProbe<'a>(&'a mut ModelField);
+
+impl FieldProbe<'_> {
+    fn record<T>(self, kind: ModelFieldType) -> Result<T, Probed> {
+        self.0.kind = kind;
+        Err(Probed)
+    }
+}
+
+macro_rules! probe_as {
+    ($kind:ident: $($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Probed> {
+                self.record(ModelFieldType::$kind)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for FieldProbe<'_> {
+    type Error = Probed;
+
+    probe_as!(Any: deserialize_any deserialize_ignored_any deserialize_unit deserialize_bytes
+        deserialize_byte_buf);
+    probe_as!(Boolean: deserialize_bool);
+    probe_as!(Number: deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64);
+    probe_as!(Text: deserialize_char deserialize_str deserialize_string deserialize_identifier);
+    probe_as!(Nested: deserialize_seq deserialize_map);
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Probed> {
+        self.0.optional = true;
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Probed> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _visitor: V,
+    ) -> Result<V::Value, Probed> {
+        self.record(ModelFieldType::Any)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Probed> {
+        self.record(ModelFieldType::Nested)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Probed> {
+        self.record(ModelFieldType::Nested)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Probed> {
+        self.record(ModelFieldType::Nested)
+    }
+
+    // Enums of unit variants are written as their variant name
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Probed> {
+        self.record(ModelFieldType::Text)
+    }
+}
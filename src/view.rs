@@ -0,0 +1,282 @@
+//! Least-privilege client handles.
+//!
+//! [`ReadOnlyView`] only exposes read operations and [`ScopedClient`] only hands out clients
+//! for a fixed set of layouts, so a subsystem given one of them cannot reach beyond what it
+//! was granted. Both also apply a [`LayoutPolicy`] to the underlying client, so the
+//! restriction holds for every request they send.
+
+use crate::error::PolicyError;
+use crate::{
+    Access, Cursor, ExportSink, Filemaker, FindResult, Page, RecordId, RequestPreview,
+    SampleStrategy, StreamOptions,
+};
+use anyhow::Result;
+use futures::Stream;
+use log::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::io::AsyncWrite;
+
+/// A client handle that can only read records.
+///
+/// Created with [`Filemaker::read_only_view`].
+#[derive(Clone)]
+pub struct ReadOnlyView {
+    filemaker: Filemaker,
+}
+
+/// A client handle that can only access a fixed set of layouts.
+///
+/// Created with [`Filemaker::scoped_to`].
+#[derive(Clone)]
+pub struct ScopedClient {
+    filemaker: Filemaker,
+    layouts: Vec<String>,
+}
+
+impl Filemaker {
+    /// Returns a handle exposing only the read operations of this client.
+    pub fn read_only_view(&self) -> ReadOnlyView {
+        let policy = self
+            .layout_policy()
+            .cloned()
+            .unwrap_or_default()
+            .read_only();
+        ReadOnlyView {
+            filemaker: self.clone().with_layout_policy(policy),
+        }
+    }
+
+    /// Returns a handle that only hands out clients for the given layouts.
+    ///
+    /// # Arguments
+    /// * `layouts` - The layouts the handle may access
+    ///
+    /// # Returns
+    /// * `ScopedClient` - A handle restricted to `layouts`
+    pub fn scoped_to<I, S>(&self, layouts: I) -> ScopedClient
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let layouts: Vec<String> = layouts.into_iter().map(Into::into).collect();
+        let policy = self
+            .layout_policy()
+            .cloned()
+            .unwrap_or_default()
+            .restrict_to(layouts.clone());
+        debug!("Scoped client to layouts {:?}", layouts);
+        ScopedClient {
+            filemaker: self.clone().with_layout_policy(policy),
+            layouts,
+        }
+    }
+}
+
+impl ReadOnlyView {
+    /// Returns a read-only view of another layout of the same database.
+    pub fn with_layout(&self, layout: &str) -> ReadOnlyView {
+        ReadOnlyView {
+            filemaker: self.filemaker.with_layout(layout),
+        }
+    }
+
+    /// Returns the name of the database the view reads from.
+    pub fn database(&self) -> String {
+        self.filemaker.database()
+    }
+
+    /// Returns the name of the layout the view reads from.
+    pub fn layout(&self) -> String {
+        self.filemaker.layout()
+    }
+
+    /// See [`Filemaker::get_records`].
+    pub async fn get_records<T>(&self, start: T, limit: T) -> Result<Vec<Value>>
+    where
+        T: Sized + Clone + std::fmt::Display + std::str::FromStr + TryFrom<usize>,
+    {
+        self.filemaker.get_records(start, limit).await
+    }
+
+    /// See [`Filemaker::get_all_records_raw`].
+    pub async fn get_all_records_raw(&self) -> Result<Vec<Value>> {
+        self.filemaker.get_all_records_raw().await
+    }
+
+    /// See [`Filemaker::get_all_records`].
+    pub async fn get_all_records<T>(&self) -> Result<Vec<T>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.filemaker.get_all_records().await
+    }
+
+    /// See [`Filemaker::get_number_of_records`].
+    pub async fn get_number_of_records(&self) -> Result<u64> {
+        self.filemaker.get_number_of_records().await
+    }
+
+    /// See [`Filemaker::get_record_ids`].
+    pub async fn get_record_ids(
+        &self,
+        query: Option<Vec<HashMap<String, String>>>,
+    ) -> Result<Vec<RecordId>> {
+        self.filemaker.get_record_ids(query).await
+    }
+
+    /// See [`Filemaker::get_record_by_id`].
+    pub async fn get_record_by_id<T>(&self, id: T) -> Result<Value>
+    where
+        T: std::fmt::Display,
+    {
+        self.filemaker.get_record_by_id(id).await
+    }
+
+    /// See [`Filemaker::get_record_if_modified`].
+    pub async fn get_record_if_modified<T>(
+        &self,
+        id: T,
+        known_mod_id: &str,
+    ) -> Result<Option<Value>>
+    where
+        T: std::fmt::Display,
+    {
+        self.filemaker
+            .get_record_if_modified(id, known_mod_id)
+            .await
+    }
+
+    /// See [`Filemaker::search`].
+    pub async fn search<T>(
+        &self,
+        query: Vec<HashMap<String, String>>,
+        sort: Vec<String>,
+        ascending: bool,
+        limit: Option<u64>,
+    ) -> Result<FindResult<T>>
+    where
+        T: serde::de::DeserializeOwned + Default,
+    {
+        self.filemaker.search(query, sort, ascending, limit).await
+    }
+
+    /// See [`Filemaker::advanced_search`].
+    pub async fn advanced_search(
+        &self,
+        fields: HashMap<String, Value>,
+        sort: Vec<String>,
+        ascending: bool,
+    ) -> Result<Vec<Value>> {
+        self.filemaker
+            .advanced_search(fields, sort, ascending)
+            .await
+    }
+
+    /// See [`Filemaker::explain`].
+    pub fn explain(
+        &self,
+        query: Vec<HashMap<String, String>>,
+        sort: Vec<String>,
+        ascending: bool,
+        limit: Option<u64>,
+    ) -> Result<RequestPreview> {
+        self.filemaker.explain(query, sort, ascending, limit)
+    }
+
+    /// See [`Filemaker::fetch_page`].
+    pub async fn fetch_page(&self, cursor: &Cursor) -> Result<Page> {
+        self.filemaker.fetch_page(cursor).await
+    }
+
+    /// See [`Filemaker::stream_records`].
+    pub fn stream_records(&self, page_size: u64) -> impl Stream<Item = Result<Value>> + 'static {
+        self.filemaker.stream_records(page_size)
+    }
+
+    /// See [`Filemaker::stream_records_with`].
+    pub fn stream_records_with(
+        &self,
+        options: StreamOptions,
+    ) -> impl Stream<Item = Result<Value>> + 'static {
+        self.filemaker.stream_records_with(options)
+    }
+
+    /// See [`Filemaker::sample_records`].
+    pub async fn sample_records(&self, n: u64, strategy: SampleStrategy) -> Result<Vec<Value>> {
+        self.filemaker.sample_records(n, strategy).await
+    }
+
+    /// See [`Filemaker::export_to`].
+    pub async fn export_to<S>(&self, sink: &mut S, options: StreamOptions) -> Result<u64>
+    where
+        S: ExportSink,
+    {
+        self.filemaker.export_to(sink, options).await
+    }
+
+    /// See [`Filemaker::export_jsonl`].
+    pub async fn export_jsonl<W>(&self, writer: &mut W, options: StreamOptions) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        self.filemaker.export_jsonl(writer, options).await
+    }
+
+    /// See [`Filemaker::export_csv`].
+    pub async fn export_csv<W>(&self, writer: &mut W, options: StreamOptions) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        self.filemaker.export_csv(writer, options).await
+    }
+
+    /// See [`Filemaker::get_row_names`].
+    pub async fn get_row_names(&self) -> Result<Vec<String>> {
+        self.filemaker.get_row_names().await
+    }
+
+    /// See [`Filemaker::get_row_names_from_metadata`].
+    pub async fn get_row_names_from_metadata(&self) -> Result<Vec<String>> {
+        self.filemaker.get_row_names_from_metadata().await
+    }
+
+    /// See [`Filemaker::get_field_types`].
+    pub async fn get_field_types(&self) -> Result<Vec<(String, String)>> {
+        self.filemaker.get_field_types().await
+    }
+}
+
+impl ScopedClient {
+    /// Returns the layouts the handle may access.
+    pub fn layouts(&self) -> &[String] {
+        &self.layouts
+    }
+
+    /// Returns a client for one of the permitted layouts.
+    ///
+    /// The client keeps the handle's layout policy, so it cannot be used to reach other
+    /// layouts through [`Filemaker::with_layout`] either.
+    ///
+    /// # Arguments
+    /// * `layout` - The name of the layout
+    ///
+    /// # Returns
+    /// * `Result<Filemaker>` - A client bound to `layout`, or a [`PolicyError`] if the layout is out of scope
+    pub fn layout(&self, layout: &str) -> Result<Filemaker> {
+        if !self.layouts.iter().any(|l| l == layout) {
+            warn!("Layout {} is outside of the client's scope", layout);
+            return Err(PolicyError {
+                layout: layout.to_string(),
+                access: Access::Read,
+            }
+            .into());
+        }
+        Ok(self.filemaker.with_layout(layout))
+    }
+
+    /// Returns a read-only view of one of the permitted layouts.
+    pub fn read_only(&self, layout: &str) -> Result<ReadOnlyView> {
+        Ok(self.layout(layout)?.read_only_view())
+    }
+}
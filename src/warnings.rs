@@ -0,0 +1,94 @@
+//! Non-fatal problems reported in successful Data API responses.
+//!
+//! A response whose first message has code 0 succeeded, but it can still report problems:
+//! further messages with other codes, e.g. 401 when a portal or related find matched no
+//! records, and errors of scripts run along with the request. Every client logs them as
+//! warnings; [`FindResult::warnings`](crate::FindResult) and
+//! [`Page::warnings`](crate::Page) carry them for callers, and [`response_warnings`] reads
+//! them from any raw response.
+
+use crate::Filemaker;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The script errors a response may report, and where they came from.
+const SCRIPT_ERROR_KEYS: [(&str, WarningSource); 3] = [
+    ("scriptError.prerequest", WarningSource::PreRequestScript),
+    ("scriptError.presort", WarningSource::PreSortScript),
+    ("scriptError", WarningSource::Script),
+];
+
+/// Where a [`ResponseWarning`] was reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WarningSource {
+    /// A message of the response besides the first one.
+    Message,
+    /// The script run before the request (`scriptError.prerequest`).
+    PreRequestScript,
+    /// The script run before sorting (`scriptError.presort`).
+    PreSortScript,
+    /// The script run after the request, or by the script endpoint (`scriptError`).
+    Script,
+}
+
+/// A non-fatal problem reported in a successful Data API response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseWarning {
+    /// The FileMaker error code.
+    pub code: u32,
+    /// The message of the server, or a description of the script error.
+    pub message: String,
+    /// Where the problem was reported.
+    pub source: WarningSource,
+}
+
+/// Returns the non-fatal problems reported in a successful Data API response.
+///
+/// Failed responses return no warnings; their first message is the error.
+///
+/// ```rust,ignore
+/// let response = filemaker.delete_record("42").await?;
+/// for warning in response_warnings(&response) {
+///     log::warn!("FileMaker reported {} ({:?}): {}", warning.code, warning.source, warning.message);
+/// }
+/// ```
+pub fn response_warnings(response: &Value) -> Vec<ResponseWarning> {
+    if Filemaker::api_error(response).is_some() {
+        return vec![];
+    }
+    let messages = response
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|message| {
+            let code = message
+                .get("code")
+                .and_then(|c| c.as_str())
+                .and_then(|c| c.parse::<u32>().ok())
+                .filter(|code| *code != 0)?;
+            Some(ResponseWarning {
+                code,
+                message: message
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                source: WarningSource::Message,
+            })
+        });
+    let scripts = SCRIPT_ERROR_KEYS.iter().filter_map(|(key, source)| {
+        let code = response
+            .get("response")
+            .and_then(|r| r.get(*key))
+            .and_then(|c| c.as_str())
+            .and_then(|c| c.parse::<u32>().ok())
+            .filter(|code| *code != 0)?;
+        Some(ResponseWarning {
+            code,
+            message: format!("Script ended with error {}", code),
+            source: *source,
+        })
+    });
+    messages.chain(scripts).collect()
+}
@@ -0,0 +1,65 @@
+//! Exporting from the stub Data API server into compressed files.
+
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{Compression, Filemaker, StreamOptions};
+use serde_json::json;
+use std::io::Read;
+
+#[tokio::test]
+async fn compresses_exports_by_extension() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "Age"]);
+    for i in 0..5 {
+        server.insert_record(
+            "People",
+            json!({ "Name": format!("Person {}", i), "Age": i }),
+        );
+    }
+    let filemaker =
+        Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+            .await
+            .unwrap();
+    let mut expected = vec![];
+    filemaker
+        .export_jsonl(&mut expected, StreamOptions::new(2))
+        .await
+        .unwrap();
+    let directory = std::env::temp_dir().join(format!("filemaker-export-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let gzip = directory.join("people.jsonl.gz");
+    let exported = filemaker
+        .export_to_file(&gzip, StreamOptions::new(2))
+        .await
+        .unwrap();
+    assert_eq!(exported, 5);
+    let mut decompressed = vec![];
+    flate2::read::GzDecoder::new(std::fs::File::open(&gzip).unwrap())
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, expected);
+
+    let zstd = directory.join("people.jsonl.zst");
+    filemaker
+        .export_to_file(&zstd, StreamOptions::new(2))
+        .await
+        .unwrap();
+    let decompressed = zstd::decode_all(std::fs::File::open(&zstd).unwrap()).unwrap();
+    assert_eq!(decompressed, expected);
+
+    assert_eq!(
+        Compression::from_path("people.csv.gz").unwrap(),
+        Compression::Gzip
+    );
+    assert_eq!(
+        Compression::from_path("people.csv").unwrap(),
+        Compression::None
+    );
+    assert!(filemaker
+        .export_to_file(directory.join("people.gz"), StreamOptions::new(2))
+        .await
+        .is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,32 @@
+//! Logging in to external FileMaker data sources along with the database.
+//!
+//! Kept apart from the other stub server tests because it sets the global `FM_URL`.
+
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{ErrorKind, ErrorKindExt, Filemaker, SessionOptions};
+use serde_json::json;
+
+#[tokio::test]
+async fn logs_in_to_external_data_sources() {
+    let server = StubServer::start("Orders", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("Orders", ["Item"]);
+    server.insert_record("Orders", json!({ "Item": "Bolt" }));
+    server.require_data_source("Inventory", "stock", "s3cret");
+    Filemaker::set_fm_url(server.url()).unwrap();
+
+    let rejected = Filemaker::new("admin", "secret", "Orders", "Orders").await;
+    assert_eq!(rejected.err().unwrap().kind(), ErrorKind::Auth);
+
+    let options = SessionOptions::default().with_data_source("Inventory", "stock", "s3cret");
+    assert!(!format!("{:?}", options).contains("s3cret"));
+    let filemaker = Filemaker::new_with_options("admin", "secret", "Orders", "Orders", options)
+        .await
+        .unwrap();
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 1);
+
+    // Logins after the session expired send the data sources again
+    server.expire_sessions();
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 1);
+}
@@ -0,0 +1,124 @@
+//! Encrypting exports from the stub Data API server.
+
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{
+    DecryptingReader, EncryptingWriter, EncryptionKey, Filemaker, RestoreOptions, SnapshotOptions,
+    StreamOptions,
+};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+async fn encrypt(plaintext: &[u8], key: &EncryptionKey) -> Vec<u8> {
+    let mut writer = EncryptingWriter::new(vec![], key);
+    writer.write_all(plaintext).await.unwrap();
+    writer.shutdown().await.unwrap();
+    writer.into_inner()
+}
+
+async fn decrypt(ciphertext: &[u8], key: &EncryptionKey) -> std::io::Result<Vec<u8>> {
+    let mut plaintext = vec![];
+    DecryptingReader::new(ciphertext, key)
+        .read_to_end(&mut plaintext)
+        .await?;
+    Ok(plaintext)
+}
+
+#[tokio::test]
+async fn encrypts_and_detects_tampering() {
+    let key = EncryptionKey::generate();
+    for size in [0, 1, 64 * 1024, 2 * 64 * 1024 + 5] {
+        let plaintext: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let ciphertext = encrypt(&plaintext, &key).await;
+        assert_ne!(ciphertext[16..], plaintext[..]);
+        assert_eq!(decrypt(&ciphertext, &key).await.unwrap(), plaintext);
+    }
+
+    let plaintext = vec![7u8; 100_000];
+    let ciphertext = encrypt(&plaintext, &key).await;
+    let mut tampered = ciphertext.clone();
+    tampered[20] ^= 1;
+    assert!(decrypt(&tampered, &key).await.is_err());
+    assert!(decrypt(&ciphertext[..16 + 64 * 1024 + 16], &key)
+        .await
+        .is_err());
+    assert!(decrypt(&ciphertext, &EncryptionKey::generate())
+        .await
+        .is_err());
+    assert!(decrypt(b"not encrypted at all", &key).await.is_err());
+
+    assert_eq!(EncryptionKey::from_hex(&key.to_hex()).unwrap(), key);
+    assert!(EncryptionKey::from_hex("abc").is_err());
+    assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+}
+
+#[tokio::test]
+async fn exports_encrypted_files() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "Age"]);
+    for i in 0..5 {
+        server.insert_record(
+            "People",
+            json!({ "Name": format!("Person {}", i), "Age": i }),
+        );
+    }
+    let filemaker =
+        Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+            .await
+            .unwrap();
+    let mut expected = vec![];
+    filemaker
+        .export_csv(&mut expected, StreamOptions::new(2))
+        .await
+        .unwrap();
+
+    let path = std::env::temp_dir().join(format!(
+        "filemaker-encrypted-{}.csv.enc",
+        std::process::id()
+    ));
+    let key = EncryptionKey::from_bytes([42; 32]);
+    let exported = filemaker
+        .export_to_file_encrypted(&path, &key, StreamOptions::new(2))
+        .await
+        .unwrap();
+    assert_eq!(exported, 5);
+    let ciphertext = std::fs::read(&path).unwrap();
+    assert!(!String::from_utf8_lossy(&ciphertext).contains("Person"));
+    assert_eq!(decrypt(&ciphertext, &key).await.unwrap(), expected);
+    std::fs::remove_file(&path).unwrap();
+
+    let directory = std::env::temp_dir().join(format!(
+        "filemaker-encrypted-snapshot-{}",
+        std::process::id()
+    ));
+    let manifest = filemaker
+        .snapshot_table(
+            &directory,
+            SnapshotOptions {
+                key: Some(key.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert!(manifest.encrypted);
+    assert_eq!(manifest.records_file, "records.jsonl.enc");
+    assert!(filemaker
+        .restore_table(&directory, RestoreOptions::default())
+        .await
+        .is_err());
+    let report = filemaker
+        .restore_table(
+            &directory,
+            RestoreOptions {
+                key: Some(key),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.summary.created, 5);
+    assert_eq!(server.records("People").len(), 10);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,40 @@
+//! Logging in to FileMaker Cloud with Claris ID tokens.
+//!
+//! Kept apart from the other stub server tests because it sets the global `FM_URL`.
+
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{ErrorKind, ErrorKindExt, Filemaker};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn logs_in_with_claris_id_tokens() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name"]);
+    server.insert_record("People", json!({ "Name": "Ada" }));
+    server.accept_fmid_token("id-token-1");
+    server.accept_fmid_token("id-token-2");
+    Filemaker::set_fm_url(server.url()).unwrap();
+
+    let rejected = Filemaker::new_with_fmid_token("forged", "Contacts", "People").await;
+    assert_eq!(rejected.err().unwrap().kind(), ErrorKind::Auth);
+
+    let refreshed = Arc::new(AtomicUsize::new(0));
+    let counter = refreshed.clone();
+    let filemaker = Filemaker::new_with_fmid_token("id-token-1", "Contacts", "People")
+        .await
+        .unwrap()
+        .with_fmid_token_provider(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok("id-token-2".to_string())
+        });
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 1);
+
+    server.expire_sessions();
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 1);
+    assert_eq!(refreshed.load(Ordering::SeqCst), 1);
+    assert_eq!(server.session_count(), 1);
+}
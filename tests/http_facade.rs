@@ -0,0 +1,159 @@
+//! The REST facade tested against the stub Data API server.
+
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{facade, Filemaker};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+async fn start() -> (StubServer, String) {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "Age"]);
+    for i in 0..3 {
+        server.insert_record(
+            "People",
+            json!({ "Name": format!("Person {}", i), "Age": i }),
+        );
+    }
+    let filemaker =
+        Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+            .await
+            .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let router = facade::router_with_change_poll_interval(filemaker, Duration::from_millis(20));
+        axum::serve(listener, router).await.unwrap()
+    });
+    (server, format!("http://{}/layouts/People", address))
+}
+
+#[tokio::test]
+async fn lists_and_pages_records() {
+    let (_server, base) = start().await;
+    let client = reqwest::Client::new();
+
+    let page: Value = client
+        .get(format!("{}/records?limit=2&sort=Age&descending=true", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["foundCount"], json!(3));
+    assert_eq!(page["records"][0]["fields"]["Age"], json!(2));
+
+    let next = page["next"].as_str().unwrap();
+    let page: Value = client
+        .get(format!("{}/records?cursor={}", base, next))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["records"].as_array().unwrap().len(), 1);
+    assert_eq!(page["next"], Value::Null);
+}
+
+#[tokio::test]
+async fn creates_updates_finds_and_deletes() {
+    let (server, base) = start().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/records", base))
+        .json(&json!({ "fields": { "Name": "Ada", "Age": 36 } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let created: Value = response.json().await.unwrap();
+    let id = created["id"].as_str().unwrap().to_string();
+
+    let response = client
+        .patch(format!("{}/records/{}", base, id))
+        .json(&json!({ "fields": { "Age": 37 }, "modId": "5" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 409);
+
+    let updated: Value = client
+        .patch(format!("{}/records/{}", base, id))
+        .json(&json!({ "fields": { "Age": 37 }, "modId": created["modId"] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["fields"]["Age"], json!(37));
+
+    let found: Value = client
+        .post(format!("{}/_find", base))
+        .json(&json!({ "query": [{ "Name": "==Ada" }] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(found["records"][0]["id"], json!(id));
+
+    let response = client
+        .delete(format!("{}/records/{}", base, id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    assert_eq!(server.records("People").len(), 3);
+
+    let response = client
+        .get(format!("{}/records/{}", base, id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["code"], json!(101));
+}
+
+#[tokio::test]
+async fn streams_changes_as_server_sent_events() {
+    let (server, base) = start().await;
+    let client = reqwest::Client::new();
+
+    let mut response = client
+        .get(format!("{}/changes", base))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    // Let the first poll establish the baseline
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let id = server.insert_record("People", json!({ "Name": "Grace" }));
+
+    let mut received = String::new();
+    while !received.contains("\n\n") {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+            .await
+            .expect("event within timeout")
+            .unwrap()
+            .unwrap();
+        received.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+    assert!(received.contains("event: change"));
+    let data = received
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .unwrap();
+    let event: Value = serde_json::from_str(data).unwrap();
+    assert_eq!(event["change"], json!("created"));
+    assert_eq!(event["record"]["id"], json!(id));
+    assert_eq!(event["record"]["fields"]["Name"], json!("Grace"));
+}
@@ -0,0 +1,49 @@
+//! Exporting from the stub Data API server into an in-memory object store.
+
+use filemaker_lib::object_store::memory::InMemory;
+use filemaker_lib::object_store::path::Path;
+use filemaker_lib::object_store::ObjectStoreExt;
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{Filemaker, StreamOptions};
+use serde_json::json;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn exports_tables_into_object_stores() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "Age"]);
+    for i in 0..5 {
+        server.insert_record(
+            "People",
+            json!({ "Name": format!("Person {}", i), "Age": i }),
+        );
+    }
+    let filemaker =
+        Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+            .await
+            .unwrap();
+    let store = Arc::new(InMemory::new());
+
+    let path = Path::from("exports/people.csv");
+    let exported = filemaker
+        .export_to_object_store(store.clone(), &path, StreamOptions::new(2))
+        .await
+        .unwrap();
+    assert_eq!(exported, 5);
+    let mut expected = vec![];
+    filemaker
+        .export_csv(&mut expected, StreamOptions::new(2))
+        .await
+        .unwrap();
+    let uploaded = store.get(&path).await.unwrap().bytes().await.unwrap();
+    assert_eq!(uploaded.as_ref(), expected.as_slice());
+
+    let unknown = Path::from("exports/people.xlsx");
+    assert!(filemaker
+        .export_to_object_store(store.clone(), &unknown, StreamOptions::new(2))
+        .await
+        .is_err());
+    assert!(store.head(&unknown).await.is_err());
+}
@@ -0,0 +1,98 @@
+//! Property tests for find-criteria escaping and URL encoding.
+
+use filemaker_lib::{query, QueryTemplate};
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+/// Strings built mostly from FileMaker find operators and URL-reserved characters.
+fn operator_laden() -> impl Strategy<Value = String> {
+    proptest::collection::vec(
+        prop_oneof![
+            Just("=".to_string()),
+            Just("==".to_string()),
+            Just("..".to_string()),
+            Just("...".to_string()),
+            Just("\\".to_string()),
+            prop::sample::select(vec![
+                '!', '<', '>', '≤', '≥', '…', '?', '@', '#', '*', '"', '~', '/', '.', '%', '&',
+                '+', ' ', '\n',
+            ])
+            .prop_map(String::from),
+            "[a-zA-Z0-9äß€]{1,3}",
+        ],
+        0..24,
+    )
+    .prop_map(|parts| parts.concat())
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_strings_keep_invariants(input in any::<String>()) {
+        query::fuzz_query_escaping(&input);
+    }
+
+    #[test]
+    fn operator_laden_strings_keep_invariants(input in operator_laden()) {
+        query::fuzz_query_escaping(&input);
+    }
+
+    #[test]
+    fn escaping_is_idempotent_after_unescape(input in operator_laden()) {
+        let escaped = query::escape_find_value(&input);
+        prop_assert_eq!(
+            query::escape_find_value(&query::unescape_find_value(&escaped)),
+            escaped
+        );
+    }
+
+    #[test]
+    fn template_parameters_are_substituted_literally(input in operator_laden()) {
+        let template = QueryTemplate::parse("Name = =={name}").unwrap();
+        let query = template.render(&HashMap::from([("name", input.as_str())])).unwrap();
+        let criteria = &query[0]["Name"];
+        let literal = criteria.strip_prefix("==").unwrap();
+        prop_assert!(query::is_literal(literal), "{:?}", criteria);
+        prop_assert_eq!(query::unescape_find_value(literal), input);
+    }
+}
+
+#[test]
+fn unescaped_operators_are_not_literal() {
+    for criteria in ["==a", "a*", "1..5", "\"x\"", "@", "a\\"] {
+        assert!(!query::is_literal(criteria), "{criteria:?}");
+    }
+    for criteria in ["abc", "a.b", "a\\.\\.b", "\\*", "\\\\"] {
+        assert!(query::is_literal(criteria), "{criteria:?}");
+    }
+}
+
+#[test]
+fn parses_query_templates() {
+    let template = QueryTemplate::parse("Status = =={status}; Total = >{min}\nNote = {{{note}}}")
+        .unwrap()
+        .or("Status = Urgent".parse().unwrap());
+    assert_eq!(
+        template.parameters().into_iter().collect::<Vec<_>>(),
+        ["min", "note", "status"]
+    );
+    let params = HashMap::from([("status", "Open*"), ("min", "10"), ("note", "a")]);
+    let query = template.render(&params).unwrap();
+    assert_eq!(query.len(), 2);
+    assert_eq!(query[0]["Status"], "==Open\\*");
+    assert_eq!(query[0]["Total"], ">10");
+    assert_eq!(query[0]["Note"], "{a}");
+    assert_eq!(query[1]["Status"], "Urgent");
+
+    let missing = template.render(&HashMap::from([("status", "Open")]));
+    assert!(missing.unwrap_err().to_string().contains("min"));
+    for invalid in [
+        "",
+        "Status",
+        "= x",
+        "Status = {",
+        "Status = {a b}",
+        "Status = }",
+    ] {
+        assert!(QueryTemplate::parse(invalid).is_err(), "{invalid:?}");
+    }
+}
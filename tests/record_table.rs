@@ -0,0 +1,46 @@
+//! Rendering records as text and Markdown tables.
+
+use filemaker_lib::{RecordTable, TableStyle};
+use serde_json::{json, Value};
+
+fn records() -> Vec<Value> {
+    vec![
+        json!({ "recordId": "1", "modId": "0", "fieldData": { "Name": "Ada", "Age": 36 } }),
+        json!({ "recordId": "12", "modId": "3", "fieldData": { "Name": "Grace | Hopper", "Notes": "a\nb" } }),
+    ]
+}
+
+#[test]
+fn renders_aligned_text() {
+    let table = RecordTable::new().render(&records());
+    assert_eq!(
+        table,
+        [
+            "recordId | Age | Name           | Notes",
+            "---------+-----+----------------+------",
+            "1        | 36  | Ada            |",
+            "12       |     | Grace | Hopper | a b",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn renders_selected_columns_as_markdown() {
+    let table = RecordTable::new()
+        .with_columns(["Name"])
+        .without_record_id()
+        .with_max_width(8)
+        .with_style(TableStyle::Markdown)
+        .render(&records());
+    assert_eq!(
+        table,
+        [
+            "| Name      |",
+            "| --------- |",
+            "| Ada       |",
+            "| Grace \\|… |",
+        ]
+        .join("\n")
+    );
+}
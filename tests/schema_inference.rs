@@ -0,0 +1,69 @@
+//! Guessing field types from sampled records.
+
+use chrono::NaiveDate;
+use filemaker_lib::{infer_schema, InferredType, NumberFormat, ValueRange};
+use serde_json::json;
+
+#[test]
+fn infers_types_ranges_and_null_rates() {
+    let sample = vec![
+        json!({ "recordId": "1", "fieldData": { "Age": 36, "Born": "12/10/1815", "Paid": "Yes", "Name": "Ada", "Note": "" } }),
+        json!({ "recordId": "2", "fieldData": { "Age": "1,000", "Born": "12/09/1906", "Paid": "no", "Name": "Grace", "Note": "" } }),
+        json!({ "recordId": "3", "fieldData": { "Age": "", "Born": "", "Paid": "Y", "Name": 7, "Seen": "2024-05-01 10:00:00" } }),
+        json!({ "recordId": "4", "fieldData": { "Age": 2, "Born": "06/23/1912", "Paid": "N", "Name": "Alan" } }),
+    ];
+    let schema = infer_schema(&sample);
+    assert_eq!(schema.records, 4);
+    assert_eq!(schema.number_format, NumberFormat::US);
+    let names: Vec<_> = schema.fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["Age", "Born", "Name", "Note", "Paid", "Seen"]);
+
+    let age = schema.field("Age").unwrap();
+    assert_eq!(age.field_type, InferredType::Number);
+    assert_eq!(
+        age.range,
+        Some(ValueRange::Number {
+            min: 2.0,
+            max: 1000.0
+        })
+    );
+    assert_eq!(age.null_rate(), 0.25);
+
+    let born = schema.field("Born").unwrap();
+    assert_eq!(born.field_type, InferredType::Date);
+    assert_eq!(
+        born.range,
+        Some(ValueRange::Date {
+            min: NaiveDate::from_ymd_opt(1815, 12, 10).unwrap(),
+            max: NaiveDate::from_ymd_opt(1912, 6, 23).unwrap(),
+        })
+    );
+
+    assert_eq!(schema.field("Paid").unwrap().field_type, InferredType::Bool);
+    let name = schema.field("Name").unwrap();
+    assert_eq!(name.field_type, InferredType::Text);
+    assert_eq!(name.range, Some(ValueRange::TextLength { min: 1, max: 5 }));
+    assert_eq!(
+        schema.field("Note").unwrap().field_type,
+        InferredType::Empty
+    );
+    let seen = schema.field("Seen").unwrap();
+    assert_eq!(seen.field_type, InferredType::Timestamp);
+    assert_eq!(seen.null_rate(), 0.75);
+}
+
+#[test]
+fn detects_european_number_format() {
+    let sample = vec![json!({ "Total": "1.234,50" }), json!({ "Total": "12,5" })];
+    let schema = infer_schema(&sample);
+    assert_eq!(schema.number_format, NumberFormat::EUROPEAN);
+    let total = schema.field("Total").unwrap();
+    assert_eq!(total.field_type, InferredType::Number);
+    assert_eq!(
+        total.range,
+        Some(ValueRange::Number {
+            min: 12.5,
+            max: 1234.5
+        })
+    );
+}
@@ -0,0 +1,52 @@
+//! Accounting of the sessions opened by the process.
+//!
+//! Kept apart from the other stub server tests because the accounting is process-wide.
+
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{Filemaker, SessionLimits};
+use serde_json::json;
+use std::time::Duration;
+
+async fn connect(server: &StubServer) -> Filemaker {
+    Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn accounts_for_open_sessions() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name"]);
+    server.insert_record("People", json!({ "Name": "Ada" }));
+
+    let first = connect(&server).await;
+    let open = Filemaker::open_sessions();
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].endpoint, server.url());
+    assert_eq!(open[0].database, "Contacts");
+    first.logout().await.unwrap();
+    assert!(Filemaker::open_sessions().is_empty());
+
+    Filemaker::set_session_limits(SessionLimits {
+        max_open: Some(2),
+        max_age: Some(Duration::ZERO),
+        close_excess: true,
+    });
+    let oldest = connect(&server).await;
+    let _second = connect(&server).await;
+    let _third = connect(&server).await;
+    assert_eq!(Filemaker::open_sessions().len(), 2);
+    for _ in 0..50 {
+        if server.session_count() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(server.session_count(), 2);
+    assert_eq!(Filemaker::check_sessions().len(), 2);
+
+    // The client whose session was closed logs in again
+    assert_eq!(oldest.get_records(1, 10).await.unwrap().len(), 1);
+}
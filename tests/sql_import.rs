@@ -0,0 +1,61 @@
+//! Importing rows of an SQLite query into the stub Data API server.
+
+use filemaker_lib::sqlx::any::{install_default_drivers, AnyPoolOptions};
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{DuplicatePolicy, FieldMapping, Filemaker, ImportOptions};
+use serde_json::json;
+
+#[tokio::test]
+async fn imports_mapped_rows_from_sqlite() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "Age", "Email"]);
+    server.insert_record(
+        "People",
+        json!({ "Name": "Old", "Age": "1", "Email": "ada@example.com" }),
+    );
+    let filemaker =
+        Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+            .await
+            .unwrap();
+
+    install_default_drivers();
+    // A single connection, so every query sees the same in-memory database
+    let pool = AnyPoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    for statement in [
+        "CREATE TABLE people (id INTEGER, full_name TEXT, age INTEGER, email TEXT)",
+        "INSERT INTO people VALUES (1, 'Ada', 36, 'ada@example.com')",
+        "INSERT INTO people VALUES (2, 'Grace', NULL, 'grace@example.com')",
+    ] {
+        sqlx::query(statement).execute(&pool).await.unwrap();
+    }
+
+    let mapping = FieldMapping::new()
+        .map("full_name", "Name")
+        .map("age", "Age")
+        .map("email", "Email")
+        .ignore("id");
+    let options = ImportOptions::with_key("Email", DuplicatePolicy::Update);
+    let summary = filemaker
+        .import_from_sql(
+            &pool,
+            "SELECT * FROM people ORDER BY id",
+            &mapping,
+            &options,
+        )
+        .await
+        .unwrap();
+    assert_eq!((summary.created, summary.updated), (1, 1));
+    assert!(summary.failed.is_empty());
+
+    let records = server.records("People");
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["fieldData"]["Name"], json!("Ada"));
+    assert_eq!(records[0]["fieldData"]["Age"], json!(36));
+    assert_eq!(records[1]["fieldData"]["Email"], json!("grace@example.com"));
+}
@@ -0,0 +1,82 @@
+//! Mirroring a layout of the stub Data API server into SQLite.
+
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::Filemaker;
+use serde_json::json;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{Connection, Row};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+fn database_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "filemaker-lib-{}-{}.sqlite",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+async fn rows(path: &PathBuf) -> Vec<(String, Option<String>)> {
+    let mut connection =
+        SqliteConnection::connect_with(&SqliteConnectOptions::new().filename(path))
+            .await
+            .unwrap();
+    sqlx::query("SELECT \"recordId\", \"Name\" FROM \"People\" ORDER BY \"recordId\"")
+        .fetch_all(&mut connection)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+}
+
+#[tokio::test]
+async fn mirrors_layout_incrementally() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "Age"]);
+    let ada = server.insert_record("People", json!({ "Name": "Ada", "Age": 36 }));
+    let grace = server.insert_record("People", json!({ "Name": "Grace", "Age": "" }));
+    let filemaker =
+        Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+            .await
+            .unwrap();
+    let path = database_path("mirror");
+
+    let report = filemaker.mirror_to_sqlite(&path).await.unwrap();
+    assert_eq!((report.inserted, report.updated, report.deleted), (2, 0, 0));
+    assert_eq!(
+        rows(&path).await,
+        vec![
+            (ada.clone(), Some("Ada".to_string())),
+            (grace.clone(), Some("Grace".to_string()))
+        ]
+    );
+
+    filemaker
+        .update_record(&ada, HashMap::from([("Name".to_string(), json!("Ada L."))]))
+        .await
+        .unwrap();
+    filemaker.delete_record(&grace).await.unwrap();
+    let report = filemaker.mirror_to_sqlite(&path).await.unwrap();
+    assert_eq!(
+        (
+            report.inserted,
+            report.updated,
+            report.deleted,
+            report.unchanged
+        ),
+        (0, 1, 1, 0)
+    );
+    assert_eq!(rows(&path).await, vec![(ada, Some("Ada L.".to_string()))]);
+
+    let report = filemaker.mirror_to_sqlite(&path).await.unwrap();
+    assert_eq!(
+        (report.inserted, report.updated, report.unchanged),
+        (0, 0, 1)
+    );
+    let _ = std::fs::remove_file(&path);
+}
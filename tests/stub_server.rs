@@ -0,0 +1,2391 @@
+//! Behaviors of the client tested against the stub Data API server.
+
+use chrono::TimeZone;
+use filemaker_lib::testing::StubServer;
+use filemaker_lib::{
+    query, read_recording, response_warnings, run_smoke, AdaptiveConcurrency, Aggregate,
+    AggregateGroup, ApiVersion, AuditFields, ChangeKind, Cursor, DeleteOptions, DeserializeError,
+    DuplicatePolicy, EmptyFind, ErrorKind, ErrorKindExt, ExportSink, FieldFilter, FieldMapping,
+    Filemaker, FilemakerError, FindOptions, FmConfig, FmThrottle, GetOptions, ImportOptions,
+    Layout, LayoutQuota, LeaseHeldError, ModelFieldType, Pacing, Portal, PortalRow, ProxyAuth,
+    PurgeOptions, QueryTemplate, QueueConfig, RecordHook, ResponseWarning, RestoreOptions,
+    RestorePlan, RetryBudget, SampleStrategy, ScriptCall, ScriptResult, Scripts, SmokeConfig,
+    SmokeOutcome, SnapshotManifest, SnapshotOptions, StandbyOptions, StreamOptions, Timeouts,
+    UpsertOutcome, WarningSource, WithRaw, WriteOptions, MANIFEST_FILE, REDACTED,
+};
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn connect(server: &StubServer) -> Filemaker {
+    Filemaker::new_with_endpoints([server.url()], "admin", "secret", "Contacts", "People")
+        .await
+        .expect("login to stub server")
+}
+
+async fn server_with_people(count: usize) -> StubServer {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "Age"]);
+    for i in 0..count {
+        server.insert_record(
+            "People",
+            json!({ "Name": format!("Person {}", i), "Age": i }),
+        );
+    }
+    server
+}
+
+#[tokio::test]
+async fn rejects_invalid_credentials() {
+    let server = server_with_people(0).await;
+    let result =
+        Filemaker::new_with_endpoints([server.url()], "admin", "wrong", "Contacts", "People").await;
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::Auth);
+}
+
+#[tokio::test]
+async fn creates_updates_and_deletes_records() {
+    let server = server_with_people(0).await;
+    let filemaker = connect(&server).await;
+
+    let added = filemaker
+        .add_record(HashMap::from([("Name".to_string(), json!("Ada"))]))
+        .await
+        .unwrap();
+    assert_eq!(added["success"], Value::Bool(true));
+    let id = added["result"]["recordId"].as_str().unwrap().to_string();
+
+    filemaker
+        .update_record(&id, HashMap::from([("Age".to_string(), json!(36))]))
+        .await
+        .unwrap();
+    let record = filemaker.get_record_by_id(&id).await.unwrap();
+    assert_eq!(record["fieldData"]["Age"], json!(36));
+    assert_eq!(record["modId"], json!("1"));
+
+    filemaker.delete_record(&id).await.unwrap();
+    assert!(server.records("People").is_empty());
+}
+
+#[tokio::test]
+async fn creates_records_with_structured_results() {
+    let server = server_with_people(0).await;
+    let filemaker = connect(&server).await;
+
+    let created = filemaker
+        .create_record(HashMap::from([("Name".to_string(), json!("Ada"))]))
+        .await
+        .unwrap();
+    assert_eq!(created.mod_id, "0");
+    assert_eq!(created.record["recordId"], json!(created.record_id));
+    assert_eq!(created.record["fieldData"]["Name"], "Ada");
+
+    let error = filemaker
+        .create_record(HashMap::from([("Missing".to_string(), json!("x"))]))
+        .await
+        .unwrap_err();
+    assert_eq!(error.api_error().unwrap().code, 102);
+    assert_eq!(server.records("People").len(), 1);
+
+    let requests = server.requests().len();
+    let ids = filemaker
+        .create_record_ids(HashMap::from([("Name".to_string(), json!("Grace"))]))
+        .await
+        .unwrap();
+    assert_eq!(server.requests().len(), requests + 1);
+    assert_eq!(ids.mod_id, "0");
+    let record = filemaker.get_record_by_id(&ids.record_id).await.unwrap();
+    assert_eq!(record["fieldData"]["Name"], "Grace");
+}
+
+#[tokio::test]
+async fn verifies_delete_results() {
+    let server = server_with_people(2).await;
+    let filemaker = connect(&server).await;
+    let ids = filemaker.get_record_ids(None).await.unwrap();
+
+    let error = filemaker.delete_record(999).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+    assert_eq!(error.filemaker_error(), Some(FilemakerError::RecordMissing));
+    let absent_ok = DeleteOptions {
+        already_absent_ok: true,
+    };
+    assert!(!filemaker.delete_record_with(999, &absent_ok).await.unwrap());
+
+    server.inject_error(301, "Record is in use by another user");
+    let error = filemaker
+        .delete_record_with(&ids[0].record_id, &absent_ok)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Locked);
+    assert_eq!(error.filemaker_error(), Some(FilemakerError::RecordLocked));
+    server.inject_error(200, "Record access is denied");
+    let error = filemaker
+        .delete_record(&ids[0].record_id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Auth);
+    assert_eq!(error.filemaker_error(), Some(FilemakerError::Other(200)));
+    assert_eq!(
+        error.api_error().unwrap().message,
+        "Record access is denied"
+    );
+    assert_eq!(
+        FilemakerError::from_code(509),
+        FilemakerError::FieldValidationFailed(509)
+    );
+    assert_eq!(FilemakerError::TokenExpired.code(), 952);
+    assert_eq!(FilemakerError::RecordNotFound.kind(), ErrorKind::NotFound);
+    assert_eq!(server.records("People").len(), 2);
+
+    assert!(filemaker
+        .delete_record_with(&ids[0].record_id, &DeleteOptions::default())
+        .await
+        .unwrap());
+    assert_eq!(server.records("People").len(), 1);
+}
+
+#[tokio::test]
+async fn returns_new_mod_id_from_updates() {
+    let server = server_with_people(1).await;
+    let filemaker = connect(&server).await;
+    let id = filemaker.get_record_ids(None).await.unwrap()[0]
+        .record_id
+        .clone();
+    let age = |age: u32| HashMap::from([("Age".to_string(), json!(age))]);
+
+    let mod_id = filemaker
+        .update_record_guarded(&id, age(40), Some("0"))
+        .await
+        .unwrap();
+    assert_eq!(mod_id, "1");
+    let mod_id = filemaker
+        .update_record_guarded(&id, age(41), Some(&mod_id))
+        .await
+        .unwrap();
+    assert_eq!(mod_id, "2");
+
+    let error = filemaker
+        .update_record_guarded(&id, age(42), Some("1"))
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Locked);
+    assert_eq!(server.records("People")[0]["fieldData"]["Age"], json!(41));
+}
+
+#[tokio::test]
+async fn streams_every_record_across_pages() {
+    for count in [0, 20, 25] {
+        let server = server_with_people(count).await;
+        let filemaker = connect(&server).await;
+        let records: Vec<Value> = filemaker.stream_records(10).try_collect().await.unwrap();
+        assert_eq!(records.len(), count);
+        assert_eq!(filemaker.get_record_ids(None).await.unwrap().len(), count);
+    }
+}
+
+#[tokio::test]
+async fn runs_script_before_each_streamed_page() {
+    let server = server_with_people(25).await;
+    let filemaker = connect(&server).await;
+    let options =
+        StreamOptions::new(10).with_pre_page_script(ScriptCall::new("Prepare").with_param("x"));
+    let records: Vec<Value> = filemaker
+        .stream_records_with(options)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 25);
+    let pages: Vec<String> = server
+        .requests()
+        .iter()
+        .map(|r| query::decode_url_component(r))
+        .filter(|r| r.starts_with("GET ") && r.contains("/records?"))
+        .collect();
+    assert_eq!(pages.len(), 3);
+    assert!(pages
+        .iter()
+        .all(|r| r.contains("script.prerequest=Prepare&script.prerequest.param=x")));
+}
+
+#[tokio::test]
+async fn streams_by_key_while_records_are_deleted() {
+    let server = server_with_people(10).await;
+    let filemaker = connect(&server).await;
+    let first = filemaker.get_record_ids(None).await.unwrap()[0]
+        .record_id
+        .clone();
+    let mut stream =
+        Box::pin(filemaker.stream_records_with(StreamOptions::new(2).with_key_field("Age")));
+
+    let mut ages = vec![stream.try_next().await.unwrap().unwrap()["fieldData"]["Age"].clone()];
+    filemaker.delete_record(&first).await.unwrap();
+    while let Some(record) = stream.try_next().await.unwrap() {
+        ages.push(record["fieldData"]["Age"].clone());
+    }
+
+    let expected: Vec<Value> = (0..10).map(|age| json!(age)).collect();
+    assert_eq!(ages, expected);
+}
+
+#[tokio::test]
+async fn finds_records_by_a_list_of_keys() {
+    let server = server_with_people(250).await;
+    let filemaker = connect(&server).await;
+    let ages: Vec<u32> = (0..250).step_by(2).chain([1, 1000]).collect();
+
+    let records = filemaker
+        .find_by_keys::<Value, _>("Age", ages.iter().chain([&4]))
+        .await
+        .unwrap();
+
+    let mut found: Vec<u64> = records
+        .iter()
+        .map(|r| r.data["Age"].as_u64().unwrap())
+        .collect();
+    found.sort();
+    let mut expected: Vec<u64> = (0..250).step_by(2).chain([1]).collect();
+    expected.sort();
+    assert_eq!(found, expected);
+    let finds = server
+        .requests()
+        .iter()
+        .filter(|r| r.ends_with("/_find"))
+        .count();
+    assert_eq!(finds, 2);
+}
+
+#[tokio::test]
+async fn searches_with_query_templates() {
+    let server = server_with_people(5).await;
+    server.insert_record("People", json!({ "Name": "Person*", "Age": 99 }));
+    let filemaker = connect(&server).await;
+    let template = QueryTemplate::parse("Name = =={name}").unwrap();
+
+    let found = filemaker
+        .search_template::<Value, _, _>(
+            &template,
+            &HashMap::from([("name", "Person*")]),
+            &FindOptions::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.response.data.len(), 1);
+    assert_eq!(found.response.data[0].data["Age"], json!(99));
+}
+
+#[tokio::test]
+async fn dedupes_records_matched_by_several_requests() {
+    let path = std::env::temp_dir().join(format!(
+        "filemaker-lib-dedupe-{}.ndjson",
+        std::process::id()
+    ));
+    let record = |id: &str| json!({ "fieldData": { "Name": id }, "recordId": id, "modId": "0" });
+    let exchange = json!({
+        "method": "POST",
+        "path": "/databases/Contacts/layouts/People/_find",
+        "response": {
+            "response": {
+                "dataInfo": {
+                    "database": "Contacts",
+                    "layout": "People",
+                    "table": "People",
+                    "totalRecordCount": 2,
+                    "foundCount": 3,
+                    "returnedCount": 3,
+                },
+                "data": [record("1"), record("2"), record("1")],
+            },
+            "messages": [{ "code": "0", "message": "OK" }],
+        },
+    });
+    std::fs::write(&path, format!("{exchange}\n{exchange}\n")).unwrap();
+    let filemaker = Filemaker::replay(&path, "Contacts", "People").unwrap();
+    let query = vec![
+        HashMap::from([("Name".to_string(), "1".to_string())]),
+        HashMap::from([("Name".to_string(), "2".to_string())]),
+    ];
+
+    let options = FindOptions::default();
+    let found = filemaker
+        .search_with::<Value>(query.clone(), &options)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.response.data.len(), 3);
+
+    let options = FindOptions {
+        dedupe: true,
+        ..Default::default()
+    };
+    let found = filemaker
+        .search_with::<Value>(query, &options)
+        .await
+        .unwrap()
+        .unwrap();
+    let ids: Vec<&str> = found
+        .response
+        .data
+        .iter()
+        .map(|r| r.record_id.as_str())
+        .collect();
+    assert_eq!(ids, ["1", "2"]);
+    assert_eq!(found.response.info.returned_count, 2);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn finds_operator_laden_values_exactly() {
+    let server = server_with_people(3).await;
+    server.insert_record("People", json!({ "Name": "==*Odd* \"name\"" }));
+    let filemaker = connect(&server).await;
+
+    let query = vec![HashMap::from([(
+        "Name".to_string(),
+        query::exact_match("==*Odd* \"name\""),
+    )])];
+    assert_eq!(
+        filemaker.get_record_ids(Some(query)).await.unwrap().len(),
+        1
+    );
+
+    let query = vec![HashMap::from([(
+        "Name".to_string(),
+        query::exact_match("Nobody"),
+    )])];
+    assert!(filemaker
+        .get_record_ids(Some(query))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn preserves_filemaker_error_codes() {
+    let server = server_with_people(1).await;
+    let filemaker = connect(&server).await;
+
+    server.inject_error(301, "Record is in use by another user");
+    let error = filemaker.get_record_by_id(1).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Locked);
+    assert_eq!(error.api_error().unwrap().code, 301);
+
+    let error = filemaker.get_record_by_id(999).await.unwrap_err();
+    assert_eq!(error.api_error().unwrap().code, 101);
+}
+
+#[tokio::test]
+async fn fails_over_to_a_reachable_endpoint() {
+    let server = server_with_people(2).await;
+    let unreachable = {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}/fmi/data/vLatest", listener.local_addr().unwrap())
+    };
+
+    let filemaker = Filemaker::new_with_endpoints(
+        [unreachable, server.url()],
+        "admin",
+        "secret",
+        "Contacts",
+        "People",
+    )
+    .await
+    .unwrap();
+    assert_eq!(filemaker.get_number_of_records().await.unwrap(), 2);
+
+    let status = filemaker.endpoint_status();
+    assert_eq!(status[0].consecutive_failures, 1);
+    assert!(status[1].pinned);
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Person {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Age")]
+    age: u32,
+}
+
+impl filemaker_lib::FmRecordModel for Person {
+    const LAYOUT: &'static str = "People";
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct NameOnly {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+#[tokio::test]
+async fn keeps_raw_json_alongside_typed_records() {
+    let server = server_with_people(2).await;
+    let filemaker = connect(&server).await;
+
+    let people = filemaker
+        .get_all_records::<WithRaw<NameOnly>>()
+        .await
+        .unwrap();
+    assert_eq!(people.len(), 2);
+    assert_eq!(people[1].name, "Person 1");
+    assert_eq!(people[1].raw["Age"], json!(1));
+
+    let query = vec![HashMap::from([(
+        "Name".to_string(),
+        "Person 1".to_string(),
+    )])];
+    let found = filemaker
+        .search::<WithRaw<NameOnly>>(query, vec![], true, None)
+        .await
+        .unwrap();
+    assert_eq!(found.response.data[0].data.typed.name, "Person 1");
+    assert_eq!(found.response.data[0].data.raw["Age"], json!(1));
+
+    server.insert_record("People", json!({ "Name": 7 }));
+    let error = filemaker
+        .get_all_records::<WithRaw<NameOnly>>()
+        .await
+        .unwrap_err();
+    let error = error.downcast_ref::<DeserializeError>().unwrap();
+    assert_eq!(error.path, "fieldData");
+    assert!(error.message.starts_with("Name:"), "{}", error.message);
+}
+
+#[tokio::test]
+async fn post_processes_records_before_deserialization() {
+    let server = server_with_people(2).await;
+    let filemaker = connect(&server)
+        .await
+        .with_post_processor(|_: &str, record: &mut Value| {
+            if let Some(fields) = record["fieldData"].as_object_mut() {
+                fields.remove("Age");
+            }
+        })
+        .with_post_processor(|layout: &str, record: &mut Value| {
+            let fields = record["fieldData"].as_object_mut().unwrap();
+            *fields = std::mem::take(fields)
+                .into_iter()
+                .map(|(name, value)| (name.to_lowercase(), value))
+                .collect();
+            fields.insert("layout".to_string(), json!(layout));
+        });
+
+    let records = filemaker.get_records(1, 10).await.unwrap();
+    assert_eq!(
+        records[0]["fieldData"],
+        json!({ "name": "Person 0", "layout": "People" })
+    );
+
+    #[derive(Debug, Default, serde::Deserialize)]
+    struct Lowercase {
+        name: String,
+    }
+    let query = vec![HashMap::from([(
+        "Name".to_string(),
+        "Person 1".to_string(),
+    )])];
+    let found = filemaker
+        .search::<Lowercase>(query, vec![], true, None)
+        .await
+        .unwrap();
+    assert_eq!(found.response.data[0].data.name, "Person 1");
+    assert_eq!(server.records("People")[0]["fieldData"]["Age"], json!(0));
+}
+
+#[tokio::test]
+async fn filters_fields_by_configurable_rules() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "g_Session", "zzTemp", "Notes"]);
+    server.insert_record(
+        "People",
+        json!({ "Name": "Ada", "g_Session": "1", "zzTemp": "x", "Notes": "n" }),
+    );
+    let filemaker = connect(&server).await;
+    let mut names = filemaker.get_row_names().await.unwrap();
+    names.sort();
+    assert_eq!(names, ["Name", "Notes", "zzTemp"]);
+
+    let filter = FieldFilter::none()
+        .with_pattern("^zz")
+        .unwrap()
+        .with_name("Notes");
+    let filemaker = filemaker.with_field_filter(filter);
+    let mut names = filemaker.get_row_names().await.unwrap();
+    names.sort();
+    assert_eq!(names, ["Name", "g_Session"]);
+
+    let mut exported = vec![];
+    filemaker
+        .export_jsonl(&mut exported, StreamOptions::default())
+        .await
+        .unwrap();
+    let line: Value = serde_json::from_slice(&exported).unwrap();
+    assert_eq!(
+        line["fieldData"],
+        json!({ "Name": "Ada", "g_Session": "1" })
+    );
+
+    let source = filemaker.generate_layouts(["People"]).await.unwrap();
+    assert!(source.contains("pub g_session: FmValue"));
+    assert!(!source.contains("zz_temp") && !source.contains("notes"));
+}
+
+#[tokio::test]
+async fn reads_field_names_of_empty_tables_from_metadata() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("People", ["Name", "g_Session", "Age"]);
+    let filemaker = connect(&server).await;
+
+    assert!(filemaker.get_row_names().await.is_err());
+    assert_eq!(
+        filemaker.get_row_names_from_metadata().await.unwrap(),
+        ["Age", "Name"]
+    );
+    assert_eq!(
+        filemaker.get_field_types().await.unwrap(),
+        [
+            ("Age".to_string(), "text".to_string()),
+            ("Name".to_string(), "text".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn seeds_generated_records() {
+    let server = server_with_people(0).await;
+    let filemaker = connect(&server).await;
+
+    let summary = filemaker
+        .seed(12, 4, |i| Person {
+            name: format!("Seed {}", i),
+            age: i as u32,
+        })
+        .await
+        .unwrap();
+    assert_eq!(summary.created.len(), 12);
+    assert!(summary.failed.is_empty());
+    assert_eq!(server.records("People").len(), 12);
+}
+
+#[tokio::test]
+async fn broadcasts_polled_changes() {
+    let server = server_with_people(2).await;
+    let filemaker = connect(&server).await;
+    let ids: Vec<String> = server
+        .records("People")
+        .iter()
+        .map(|r| r["recordId"].as_str().unwrap().to_string())
+        .collect();
+
+    let feed = filemaker.change_feed(std::time::Duration::from_millis(20));
+    let mut receiver = feed.subscribe();
+    // Let the first poll establish the baseline
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    filemaker
+        .update_record(&ids[0], HashMap::from([("Age".to_string(), json!(99))]))
+        .await
+        .unwrap();
+    filemaker.delete_record(&ids[1]).await.unwrap();
+    let created = server.insert_record("People", json!({ "Name": "Grace" }));
+
+    let mut changes = HashMap::new();
+    while changes.len() < 3 {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("change within timeout")
+            .unwrap();
+        changes.insert(event.record_id.clone(), event);
+    }
+    assert_eq!(changes[&ids[0]].change, ChangeKind::Updated);
+    assert_eq!(
+        changes[&ids[0]].record.as_ref().unwrap()["fieldData"]["Age"],
+        json!(99)
+    );
+    assert_eq!(changes[&ids[1]].change, ChangeKind::Deleted);
+    assert_eq!(changes[&created].change, ChangeKind::Created);
+}
+
+#[tokio::test]
+async fn reports_path_of_mismatched_field() {
+    let server = server_with_people(2).await;
+    server.insert_record("People", json!({ "Name": "Blank", "Age": "" }));
+    let filemaker = connect(&server).await;
+
+    let error = filemaker.get_all_records::<Person>().await.unwrap_err();
+    let error = error.downcast_ref::<DeserializeError>().unwrap();
+    assert_eq!(error.path, "fieldData.Age");
+    assert_eq!(error.field(), Some("Age"));
+
+    let error = filemaker
+        .repository::<Person>()
+        .find_by("Name", "Blank")
+        .await
+        .unwrap_err();
+    let error = error.downcast_ref::<DeserializeError>().unwrap();
+    assert_eq!(error.path, "fieldData.Age");
+}
+
+#[tokio::test]
+async fn searches_several_layouts_at_once() {
+    let server = server_with_people(3).await;
+    server.add_layout("Staff", ["Name", "Age"]);
+    server.insert_record("Staff", json!({ "Name": "Person 1", "Age": 50 }));
+    server.add_layout("Empty", ["Name"]);
+    let filemaker = connect(&server).await;
+
+    let query = vec![HashMap::from([(
+        "Name".to_string(),
+        query::exact_match("Person 1"),
+    )])];
+    let results = filemaker
+        .search_layouts(["People", "Staff", "Empty", "Missing"], query)
+        .await
+        .unwrap();
+    let layouts: Vec<&str> = results.records.iter().map(|r| r.layout.as_str()).collect();
+    assert_eq!(layouts, ["People", "Staff"]);
+    assert_eq!(
+        results.for_layout("Staff").next().unwrap()["fieldData"]["Age"],
+        json!(50)
+    );
+    assert_eq!(results.failed.len(), 1);
+    assert_eq!(results.failed[0].0, "Missing");
+    assert_eq!(results.failed[0].1.kind(), ErrorKind::NotFound);
+}
+
+#[tokio::test]
+async fn searches_several_databases_with_separate_sessions() {
+    let server = server_with_people(3).await;
+    let filemaker = connect(&server).await;
+    let sessions = server.session_count();
+
+    let query = vec![HashMap::from([("Name".to_string(), "Person".to_string())])];
+    let results = filemaker
+        .search_databases(["Contacts", "Archive"], "admin", "secret", query)
+        .await
+        .unwrap();
+    assert_eq!(results.for_database("Contacts").count(), 3);
+    assert_eq!(results.failed.len(), 1);
+    assert_eq!(results.failed[0].0, "Archive");
+    assert_eq!(server.session_count(), sessions + 1);
+}
+
+#[tokio::test]
+async fn handles_empty_finds_per_call() {
+    let server = server_with_people(2).await;
+    let filemaker = connect(&server).await;
+    let query = || vec![HashMap::from([("Name".to_string(), "Nobody".to_string())])];
+
+    let on_empty = |on_empty| FindOptions {
+        on_empty,
+        ..Default::default()
+    };
+
+    let empty = filemaker
+        .search_with::<Value>(query(), &on_empty(EmptyFind::Empty))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(empty.response.data.is_empty());
+    assert_eq!(empty.response.info.layout, "People");
+
+    let none = filemaker
+        .search_with::<Value>(query(), &on_empty(EmptyFind::None))
+        .await
+        .unwrap();
+    assert!(none.is_none());
+
+    let error = filemaker
+        .search_with::<Value>(query(), &on_empty(EmptyFind::Error))
+        .await
+        .unwrap_err();
+    assert_eq!(error.api_error().unwrap().code, 401);
+
+    let found = filemaker
+        .search_with::<Value>(
+            vec![HashMap::from([("Name".to_string(), "Person".to_string())])],
+            &on_empty(EmptyFind::None),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.response.data.len(), 2);
+}
+
+#[tokio::test]
+async fn passes_get_and_find_options() {
+    let server = server_with_people(5).await;
+    let filemaker = connect(&server).await;
+
+    let options = GetOptions {
+        offset: Some(2),
+        limit: Some(2),
+        sort: vec!["Name".to_string()],
+        ascending: false,
+        portals: Some(vec![Portal::new("Orders").with_range(1, 5)]),
+        scripts: Scripts {
+            after: Some(ScriptCall::new("Log Access").with_param("web")),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let records = filemaker.get_records_with(&options).await.unwrap();
+    let names: Vec<&str> = records
+        .iter()
+        .map(|r| r["fieldData"]["Name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Person 3", "Person 2"]);
+
+    let request = query::decode_url_component(server.requests().last().unwrap());
+    assert!(request.contains("portal=[\"Orders\"]"));
+    assert!(request.contains("_limit.Orders=5"));
+    assert!(request.contains("script=Log Access&script.param=web"));
+
+    let record = filemaker
+        .get_record_by_id_with(records[0]["recordId"].as_str().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(record["fieldData"]["Name"], "Person 3");
+    let request = query::decode_url_component(server.requests().last().unwrap());
+    assert!(request.contains("portal=") && !request.contains("_offset="));
+
+    let found = filemaker
+        .search_with::<Value>(
+            vec![HashMap::from([("Name".to_string(), "Person".to_string())])],
+            &FindOptions {
+                offset: Some(4),
+                sort: vec!["Age".to_string()],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.response.data.len(), 2);
+}
+
+#[tokio::test]
+async fn describes_layout_as_json_schema() {
+    let server = server_with_people(1).await;
+    let filemaker = connect(&server).await;
+
+    let schema = filemaker.describe_layout().await.unwrap();
+    assert_eq!(schema["title"], "People");
+    let fields = &schema["properties"]["fieldData"];
+    assert_eq!(fields["additionalProperties"], false);
+    assert_eq!(
+        fields["properties"]["Name"],
+        json!({ "type": "string", "x-filemaker-result": "text" })
+    );
+    let names: Vec<&String> = fields["properties"].as_object().unwrap().keys().collect();
+    assert_eq!(names, ["Age", "Name"]);
+}
+
+#[tokio::test]
+async fn purges_records_older_than_cutoff() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("Log", ["Message", "Created"]);
+    for (message, created) in [
+        ("old", "01/02/2026 08:00:00"),
+        ("older", "01/01/2026 23:59:59"),
+        ("new", "01/20/2026 12:00:00"),
+        ("undated", ""),
+    ] {
+        server.insert_record("Log", json!({ "Message": message, "Created": created }));
+    }
+    let filemaker = connect(&server).await.with_layout("Log");
+    let cutoff = chrono::Utc.with_ymd_and_hms(2026, 1, 10, 0, 0, 0).unwrap();
+
+    let dry_run = PurgeOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    let report = filemaker
+        .purge_older_than("Created", &cutoff, &dry_run)
+        .await
+        .unwrap();
+    assert_eq!(report.matched.len(), 2);
+    assert_eq!(report.deleted, 0);
+    assert_eq!(filemaker.get_number_of_records().await.unwrap(), 4);
+
+    let batches = Arc::new(AtomicUsize::new(0));
+    let counter = batches.clone();
+    let options = PurgeOptions {
+        batch_size: 1,
+        ..Default::default()
+    }
+    .with_progress(move |progress| {
+        assert_eq!(progress.total, 2);
+        counter.fetch_add(1, AtomicOrdering::SeqCst);
+    });
+    let report = filemaker
+        .purge_older_than("Created", &cutoff, &options)
+        .await
+        .unwrap();
+    assert_eq!(report.deleted, 2);
+    assert_eq!(batches.load(AtomicOrdering::SeqCst), 2);
+    let remaining: Vec<Value> = filemaker.get_records(1, 10).await.unwrap();
+    let messages: Vec<&Value> = remaining
+        .iter()
+        .map(|r| &r["fieldData"]["Message"])
+        .collect();
+    assert_eq!(messages, ["new", "undated"]);
+}
+
+#[tokio::test]
+async fn claims_queue_items_once() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout(
+        "Jobs",
+        ["Task", "Status", "ClaimedBy", "ClaimedAt", "Error"],
+    );
+    for (task, status) in [("a", "pending"), ("b", "pending"), ("c", "done")] {
+        server.insert_record(
+            "Jobs",
+            json!({ "Task": task, "Status": status, "ClaimedBy": "", "ClaimedAt": "", "Error": "" }),
+        );
+    }
+    let filemaker = connect(&server).await.with_layout("Jobs");
+    let config = QueueConfig {
+        error_field: Some("Error".to_string()),
+        ..Default::default()
+    };
+    let first = filemaker.queue(config.clone(), "worker-1");
+    let second = filemaker.queue(config.clone(), "worker-2");
+
+    let (a, b) = tokio::join!(first.claim(), second.claim());
+    let (a, b) = (a.unwrap().unwrap(), b.unwrap().unwrap());
+    assert_ne!(a.record_id, b.record_id);
+    assert_eq!(a.field_data["ClaimedBy"], "worker-1");
+    assert!(first.claim().await.unwrap().is_none());
+
+    first.complete(&a).await.unwrap();
+    second.fail(&b, "boom").await.unwrap();
+    let failed = filemaker.get_record_by_id(&b.record_id).await.unwrap();
+    assert_eq!(failed["fieldData"]["Status"], "failed");
+    assert_eq!(failed["fieldData"]["Error"], "boom");
+
+    let abandoned = server.insert_record(
+        "Jobs",
+        json!({ "Task": "d", "Status": "processing", "ClaimedBy": "worker-3", "ClaimedAt": "01/01/2000 00:00:00", "Error": "" }),
+    );
+    assert_eq!(
+        first.requeue_stale(Duration::from_secs(60)).await.unwrap(),
+        1
+    );
+    let requeued = first.claim().await.unwrap().unwrap();
+    assert_eq!(requeued.record_id, abandoned);
+
+    filemaker
+        .update_record(
+            &abandoned,
+            HashMap::from([("Status".to_string(), json!("pending"))]),
+        )
+        .await
+        .unwrap();
+    let error = first.complete(&requeued).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Locked);
+}
+
+#[tokio::test]
+async fn leases_records_to_one_owner() {
+    let server = StubServer::start("Contacts", "admin", "secret")
+        .await
+        .unwrap();
+    server.add_layout("Accounts", ["Name", "LockOwner", "LockExpires"]);
+    let id = server.insert_record(
+        "Accounts",
+        json!({ "Name": "Acme", "LockOwner": "", "LockExpires": "" }),
+    );
+    let abandoned = server.insert_record(
+        "Accounts",
+        json!({ "Name": "Globex", "LockOwner": "ghost", "LockExpires": "01/01/2000 00:00:00" }),
+    );
+    let filemaker = connect(&server).await.with_layout("Accounts");
+    let ttl = Duration::from_secs(60);
+
+    let lease = filemaker.lease_record(&id, "billing", ttl).await.unwrap();
+    assert_eq!(lease.owner, "billing");
+    let error = filemaker
+        .lease_record(&id, "reports", ttl)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Locked);
+    let held = error.downcast_ref::<LeaseHeldError>().unwrap();
+    assert_eq!(held.owner, "billing");
+
+    let lease = filemaker.renew_lease(&lease, ttl).await.unwrap();
+    filemaker.release_lease(&lease).await.unwrap();
+    let taken = filemaker.lease_record(&id, "reports", ttl).await.unwrap();
+    assert_eq!(
+        filemaker.release_lease(&lease).await.unwrap_err().kind(),
+        ErrorKind::Locked
+    );
+    filemaker.release_lease(&taken).await.unwrap();
+
+    assert_eq!(filemaker.expire_leases().await.unwrap(), 1);
+    let cleared = filemaker.get_record_by_id(&abandoned).await.unwrap();
+    assert_eq!(cleared["fieldData"]["LockOwner"], "");
+}
+
+#[tokio::test]
+async fn counts_requests_errors_and_bytes() {
+    let server = server_with_people(3).await;
+    let filemaker = connect(&server).await;
+    assert_eq!(filemaker.stats().requests, 0);
+
+    filemaker.get_records(1, 10).await.unwrap();
+    let nobody = vec![HashMap::from([("Name".to_string(), "Nobody".to_string())])];
+    filemaker
+        .search::<Value>(nobody, vec![], true, None)
+        .await
+        .unwrap_err();
+    filemaker
+        .with_layout("People")
+        .get_number_of_records()
+        .await
+        .unwrap();
+
+    let stats = filemaker.stats();
+    assert_eq!(stats.requests, 3);
+    assert_eq!(stats.retries, 0);
+    assert_eq!(stats.errors.get(&ErrorKind::NotFound), Some(&1));
+    assert_eq!(stats.error_count(), 1);
+    assert!(stats.bytes_sent > 0 && stats.bytes_received > stats.bytes_sent);
+    assert!(stats.average_latency > Duration::ZERO);
+}
+
+#[tokio::test]
+async fn replays_recorded_exchanges_offline() {
+    let path = std::env::temp_dir().join(format!(
+        "filemaker-lib-recording-{}.ndjson",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let server = server_with_people(3).await;
+    let recording = connect(&server)
+        .await
+        .with_recording(&path, ["age"])
+        .unwrap();
+    let live = recording.get_records(1, 10).await.unwrap();
+    let nobody = vec![HashMap::from([("Name".to_string(), "Nobody".to_string())])];
+    let live_error = recording
+        .search::<Value>(nobody.clone(), vec![], true, None)
+        .await
+        .unwrap_err();
+
+    let exchanges = read_recording(&path).unwrap();
+    assert_eq!(exchanges.len(), 2);
+    assert_eq!(exchanges[0].method, "GET");
+    assert!(exchanges[0]
+        .path
+        .starts_with("/databases/Contacts/layouts/People/records"));
+    assert_eq!(
+        exchanges[0].response["response"]["data"][0]["fieldData"]["Age"],
+        REDACTED
+    );
+
+    let replay = Filemaker::replay(&path, "Contacts", "People").unwrap();
+    let replayed = replay.get_records(1, 10).await.unwrap();
+    assert_eq!(replayed.len(), live.len());
+    assert_eq!(
+        replayed[0]["fieldData"]["Name"],
+        live[0]["fieldData"]["Name"]
+    );
+    let replayed_error = replay
+        .search::<Value>(nobody, vec![], true, None)
+        .await
+        .unwrap_err();
+    assert_eq!(replayed_error.api_error(), live_error.api_error());
+    assert!(replay.get_records(1, 10).await.is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+struct Staff;
+
+impl Layout for Staff {
+    const NAME: &'static str = "Staff";
+    type Record = Person;
+}
+
+#[tokio::test]
+async fn opens_layouts_by_marker_type() {
+    let server = server_with_people(1).await;
+    server.add_layout("Staff", ["Name", "Age"]);
+    server.insert_record("Staff", json!({ "Name": "Grace", "Age": 40 }));
+    let filemaker = connect(&server).await;
+
+    let staff = filemaker
+        .open::<Staff>()
+        .find_by("Name", "Grace")
+        .await
+        .unwrap();
+    assert_eq!(staff.len(), 1);
+    assert_eq!(staff[0].data.age, 40);
+
+    let source = filemaker.generate_layouts(["People"]).await.unwrap();
+    assert!(source.contains("pub struct PeopleRecord {"));
+    assert!(source.contains("#[serde(rename = \"Name\", default)]\n        pub name: FmValue,"));
+    assert!(source.contains("impl Layout for People {"));
+    assert!(source.contains("type Record = PeopleRecord;"));
+}
+
+#[tokio::test]
+async fn refreshes_expired_sessions_once_for_concurrent_requests() {
+    let server = server_with_people(3).await;
+    let filemaker = connect(&server).await;
+    server.expire_sessions();
+    let logins_before = logins(&server);
+
+    let counts = futures::future::try_join_all(
+        (0..8).map(|_| async { filemaker.get_number_of_records().await }),
+    )
+    .await
+    .unwrap();
+
+    assert!(counts.iter().all(|&count| count == 3));
+    assert_eq!(logins(&server) - logins_before, 1);
+    assert_eq!(server.session_count(), 1);
+}
+
+fn logins(server: &StubServer) -> usize {
+    server
+        .requests()
+        .iter()
+        .filter(|r| r.starts_with("POST") && r.ends_with("/sessions"))
+        .count()
+}
+
+#[tokio::test]
+async fn shares_a_retry_budget_across_an_import() {
+    let server = server_with_people(0).await;
+    let filemaker = connect(&server).await;
+    let rows = |count: usize| -> Vec<HashMap<String, Value>> {
+        (0..count)
+            .map(|i| HashMap::from([("Name".to_string(), json!(format!("Row {}", i)))]))
+            .collect()
+    };
+    let budget = RetryBudget {
+        min_attempts: 2,
+        retry_delay: Duration::ZERO,
+        ..RetryBudget::new(1, 10, 0.5)
+    };
+    let options = ImportOptions {
+        budget,
+        ..ImportOptions::default()
+    };
+
+    server.inject_error(301, "Record is in use by another user");
+    let summary = filemaker.import_records(rows(2), &options).await.unwrap();
+    assert_eq!(summary.created, 2);
+    assert_eq!(summary.budget.retries, 1);
+    assert!(!summary.budget.exhausted);
+
+    server.inject_error(509, "Field requires a valid value");
+    server.inject_error(509, "Field requires a valid value");
+    let summary = filemaker.import_records(rows(5), &options).await.unwrap();
+    assert_eq!(summary.created, 0);
+    assert_eq!(summary.failed.len(), 5);
+    assert_eq!(summary.budget.attempted, 2);
+    assert_eq!(summary.budget.failed, 2);
+    assert_eq!(summary.budget.skipped, 3);
+    assert_eq!(summary.budget.retries, 0);
+    assert!(summary.budget.exhausted);
+}
+
+#[tokio::test]
+async fn exports_into_sinks() {
+    let server = server_with_people(2).await;
+    server.insert_record("People", json!({ "Name": "Doe, \"Jo\"", "Age": null }));
+    let filemaker = connect(&server).await;
+
+    let mut csv = vec![];
+    let count = filemaker
+        .export_csv(&mut csv, StreamOptions::new(2))
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "Age,Name\n0,Person 0\n1,Person 1\n,\"Doe, \"\"Jo\"\"\"\n"
+    );
+
+    #[derive(Default)]
+    struct Collect {
+        fields: Vec<String>,
+        ids: Vec<String>,
+        finished: bool,
+    }
+    impl ExportSink for Collect {
+        async fn start(&mut self, fields: &[String]) -> anyhow::Result<()> {
+            self.fields = fields.to_vec();
+            Ok(())
+        }
+        async fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+            self.ids
+                .push(record["recordId"].as_str().unwrap().to_string());
+            Ok(())
+        }
+        async fn finish(&mut self) -> anyhow::Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+    let mut sink = Collect::default();
+    let count = filemaker
+        .export_to(&mut sink, StreamOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(sink.fields, ["Age", "Name"]);
+    assert_eq!(sink.ids.len(), 3);
+    assert!(sink.finished);
+}
+
+#[tokio::test]
+async fn snapshots_and_restores_tables() {
+    let server = server_with_people(5).await;
+    server.add_layout("Archive", ["Name", "Age"]);
+    let filemaker = connect(&server).await;
+    let directory =
+        std::env::temp_dir().join(format!("filemaker-lib-snapshot-{}", std::process::id()));
+
+    let manifest = filemaker
+        .snapshot_table(&directory, SnapshotOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(manifest.record_count, 5);
+    assert_eq!(manifest.fields, ["Age", "Name"]);
+    assert_eq!(manifest.database, "Contacts");
+    assert_eq!(manifest.layout, "People");
+    assert_eq!(manifest.server, server.url());
+    assert_eq!(SnapshotManifest::load(&directory).await.unwrap(), manifest);
+
+    let archive = filemaker.with_layout("Archive");
+    let report = archive
+        .restore_table(&directory, RestoreOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(report.summary.created, 5);
+    let mut names: Vec<Value> = server
+        .records("Archive")
+        .iter()
+        .map(|record| record["fieldData"]["Name"].clone())
+        .collect();
+    names.sort_by_key(|name| name.to_string());
+    assert_eq!(names[0], json!("Person 0"));
+    assert_eq!(names[4], json!("Person 4"));
+
+    // A modified records file or manifest is rejected before anything is written.
+    let records = directory.join(&manifest.records_file);
+    let original = std::fs::read(&records).unwrap();
+    std::fs::write(
+        &records,
+        String::from_utf8_lossy(&original).replace("Person 3", "Person 9"),
+    )
+    .unwrap();
+    assert!(archive
+        .restore_table(&directory, RestoreOptions::default())
+        .await
+        .is_err());
+    std::fs::write(&records, &original).unwrap();
+    let tampered = SnapshotManifest {
+        record_count: 4,
+        ..manifest.clone()
+    };
+    std::fs::write(
+        directory.join(MANIFEST_FILE),
+        serde_json::to_vec(&tampered).unwrap(),
+    )
+    .unwrap();
+    let error = archive
+        .restore_table(&directory, RestoreOptions::default())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("manifest lists 4"), "{error}");
+    assert_eq!(server.records("Archive").len(), 5);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn restores_snapshots_into_renamed_fields() {
+    let server = server_with_people(5).await;
+    server.add_layout("Renamed", ["Full Name", "Age"]);
+    let filemaker = connect(&server).await;
+    let directory = std::env::temp_dir().join(format!(
+        "filemaker-lib-snapshot-mapping-{}",
+        std::process::id()
+    ));
+    filemaker
+        .snapshot_table(&directory, SnapshotOptions::default())
+        .await
+        .unwrap();
+
+    let options = RestoreOptions::default()
+        .with_mapping(FieldMapping::new().map("Name", "Full Name"))
+        .with_filter(|record| record["Age"].as_i64().unwrap() >= 2);
+    let report = filemaker
+        .with_layout("Renamed")
+        .restore_table(&directory, options)
+        .await
+        .unwrap();
+    assert_eq!(report.filtered, 2);
+    assert_eq!(report.summary.created, 3);
+    let records = server.records("Renamed");
+    assert_eq!(records.len(), 3);
+    for record in records {
+        let name = record["fieldData"]["Full Name"].as_str().unwrap();
+        let age = record["fieldData"]["Age"].as_i64().unwrap();
+        assert_eq!(name, format!("Person {}", age));
+        assert!(age >= 2);
+        assert!(record["fieldData"].get("Name").is_none());
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn plans_restores_before_writing() {
+    let server = server_with_people(5).await;
+    let filemaker = connect(&server).await;
+    let directory = std::env::temp_dir().join(format!(
+        "filemaker-lib-snapshot-plan-{}",
+        std::process::id()
+    ));
+    filemaker
+        .snapshot_table(&directory, SnapshotOptions::default())
+        .await
+        .unwrap();
+
+    let id_of = |name: &str| {
+        server
+            .records("People")
+            .into_iter()
+            .find(|record| record["fieldData"]["Name"] == json!(name))
+            .unwrap()["recordId"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    filemaker
+        .update_record(
+            id_of("Person 1"),
+            HashMap::from([("Age".to_string(), json!(100))]),
+        )
+        .await
+        .unwrap();
+    filemaker.delete_record(id_of("Person 2")).await.unwrap();
+    server.insert_record("People", json!({ "Name": "Person 9", "Age": 9 }));
+
+    let options = RestoreOptions {
+        import: ImportOptions::with_key("Name", DuplicatePolicy::Update),
+        delete_missing: true,
+        dry_run: true,
+        ..Default::default()
+    };
+    let report = filemaker
+        .restore_table(&directory, options.clone())
+        .await
+        .unwrap();
+    let plan = report.plan.unwrap();
+    assert_eq!(
+        plan,
+        RestorePlan {
+            creates: 1,
+            updates: 1,
+            unchanged: 3,
+            deletes: 1,
+        }
+    );
+    assert_eq!(
+        plan.to_string(),
+        "1 to create, 1 to update, 3 unchanged, 1 to delete"
+    );
+    assert!(report.dry_run);
+    assert_eq!(report.summary.created + report.summary.updated, 0);
+    assert!(server
+        .records("People")
+        .iter()
+        .any(|record| record["fieldData"]["Name"] == json!("Person 9")));
+
+    let report = filemaker
+        .restore_table(
+            &directory,
+            RestoreOptions {
+                dry_run: false,
+                ..options
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.summary.created, 1);
+    assert_eq!(report.summary.updated, 4);
+    assert_eq!(report.deleted.deleted.len(), 1);
+    let mut people: Vec<(String, i64)> = server
+        .records("People")
+        .iter()
+        .map(|record| {
+            (
+                record["fieldData"]["Name"].as_str().unwrap().to_string(),
+                record["fieldData"]["Age"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    people.sort();
+    assert_eq!(
+        people,
+        (0..5)
+            .map(|i| (format!("Person {}", i), i))
+            .collect::<Vec<_>>()
+    );
+
+    assert!(filemaker
+        .restore_table(
+            &directory,
+            RestoreOptions {
+                delete_missing: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn runs_scripts() {
+    let server = server_with_people(0).await;
+    server.add_script("Greet Someone", |param| match param {
+        Some(name) => (0, Some(format!("Hello, {}!", name))),
+        None => (5, None),
+    });
+    let filemaker = connect(&server).await;
+
+    let result = filemaker
+        .run_script("Greet Someone", Some("Ada & Grace"))
+        .await
+        .unwrap();
+    assert!(result.succeeded());
+    assert_eq!(result.result.as_deref(), Some("Hello, Ada & Grace!"));
+    assert!(server
+        .requests()
+        .last()
+        .unwrap()
+        .ends_with("/layouts/People/script/Greet%20Someone?script.param=Ada%20%26%20Grace"));
+
+    let result = filemaker.run_script("Greet Someone", None).await.unwrap();
+    assert_eq!(
+        result,
+        ScriptResult {
+            error_code: 5,
+            result: None
+        }
+    );
+    assert_eq!(
+        filemaker
+            .run_script("Missing", None)
+            .await
+            .unwrap()
+            .error_code,
+        104
+    );
+
+    let error = filemaker
+        .with_layout("Nowhere")
+        .run_script("Greet Someone", None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.filemaker_error(), Some(FilemakerError::LayoutMissing));
+}
+
+#[tokio::test]
+async fn tracks_layout_stats_and_quotas() {
+    let server = server_with_people(3).await;
+    server.insert_record("Invoices", json!({ "Total": 10 }));
+    let filemaker = connect(&server)
+        .await
+        .with_layout_quota(LayoutQuota::new(Duration::from_secs(3600)).with_max_requests(2));
+    for _ in 0..4 {
+        filemaker.get_records(1, 10).await.unwrap();
+    }
+    let invoices = filemaker.with_layout("Invoices");
+    invoices.get_records(1, 10).await.unwrap();
+    assert!(invoices.get_record_by_id(999).await.is_err());
+
+    let stats = filemaker.stats();
+    let people = stats.layouts["People"];
+    assert_eq!(people.requests, 4);
+    assert_eq!(people.errors, 0);
+    assert!(people.bytes_received > 0);
+    assert_eq!(people.quota_exceeded, 1);
+    let invoices = stats.layouts["Invoices"];
+    assert_eq!(invoices.requests, 2);
+    assert_eq!(invoices.errors, 1);
+    assert_eq!(invoices.quota_exceeded, 0);
+}
+
+#[tokio::test]
+async fn adapts_bulk_concurrency() {
+    let server = server_with_people(12).await;
+    let filemaker = connect(&server)
+        .await
+        .with_adaptive_concurrency(AdaptiveConcurrency {
+            min: 1,
+            max: 8,
+            target_latency: Duration::from_millis(1),
+            ..Default::default()
+        });
+    let rows = (0..12)
+        .map(|i| HashMap::from([("Name".to_string(), json!(format!("Person {}", i)))]))
+        .collect();
+    let outcomes = filemaker.upsert_many("Name", rows, 4).await.unwrap();
+    assert_eq!(outcomes.len(), 12);
+    assert!(outcomes
+        .iter()
+        .all(|o| !matches!(o, UpsertOutcome::Failed(_))));
+
+    let ids: Vec<String> = filemaker
+        .get_record_ids(None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|record| record.record_id)
+        .collect();
+    server.inject_error(802, "Unable to open file");
+    server.inject_error(301, "Record is in use by another user");
+    let summary = filemaker.delete_records(&ids, 4).await.unwrap();
+    assert_eq!(summary.deleted.len(), 10);
+    assert_eq!(summary.failed.len(), 2);
+    assert_eq!(server.records("People").len(), 2);
+}
+
+#[tokio::test]
+async fn paces_imports() {
+    let server = server_with_people(0).await;
+    let filemaker = connect(&server).await;
+    let pacing = Pacing {
+        delay: Duration::from_millis(20),
+        target_latency: Duration::ZERO,
+        max_delay: Duration::from_millis(40),
+    };
+    let rows = (0..3)
+        .map(|i| HashMap::from([("Name".to_string(), json!(format!("Person {}", i)))]))
+        .collect();
+    let options = ImportOptions {
+        pacing: Some(pacing),
+        ..Default::default()
+    };
+    let started = std::time::Instant::now();
+    let summary = filemaker.import_records(rows, &options).await.unwrap();
+    assert_eq!(summary.created, 3);
+    assert_eq!(server.records("People").len(), 3);
+    // Every write overruns the zero target, so the pause grows from 20ms to the 40ms cap
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn rotates_to_standby_sessions() {
+    let server = server_with_people(1).await;
+    let logins = |server: &StubServer| {
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("POST /databases/Contacts/sessions"))
+            .count()
+    };
+    let filemaker = connect(&server)
+        .await
+        .with_standby_session(StandbyOptions {
+            session_timeout: Duration::from_secs(3600),
+            rotate_margin: Duration::ZERO,
+            check_interval: Duration::from_secs(3600),
+        })
+        .await
+        .unwrap();
+    assert_eq!(server.session_count(), 2);
+
+    // The primary expires; the request switches to the standby without logging in
+    server.expire_session("stub-token-1");
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 1);
+    assert_eq!(logins(&server), 2);
+    assert_eq!(server.session_count(), 1);
+
+    let filemaker = connect(&server)
+        .await
+        .with_standby_session(StandbyOptions {
+            session_timeout: Duration::from_millis(600),
+            rotate_margin: Duration::from_millis(200),
+            check_interval: Duration::from_millis(20),
+        })
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    // The idle primary was replaced and ended, and a new standby created
+    assert!(server
+        .requests()
+        .iter()
+        .any(|r| r.starts_with("DELETE /databases/Contacts/sessions/stub-token-3")));
+    assert!(logins(&server) >= 5);
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 1);
+    assert_eq!(server.session_count(), 3);
+}
+
+#[tokio::test]
+async fn runs_smoke_tests() {
+    let server = server_with_people(1).await;
+    server.add_script("Ping", |_| (0, Some("pong".to_string())));
+    let mut config = SmokeConfig::new(server.url(), "admin", "secret", "Contacts", "People");
+    config.record = HashMap::from([("Name".to_string(), json!("Smoke test"))]);
+    config.update = HashMap::from([("Age".to_string(), json!(42))]);
+    config.script = Some("Ping".to_string());
+
+    let report = run_smoke(&config).await;
+    assert!(report.passed(), "{}", report);
+    let outcomes: Vec<_> = report
+        .steps
+        .iter()
+        .map(|step| (step.name, step.outcome.clone()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("connect", SmokeOutcome::Passed),
+            ("create", SmokeOutcome::Passed),
+            ("find", SmokeOutcome::Passed),
+            ("update", SmokeOutcome::Passed),
+            (
+                "container",
+                SmokeOutcome::Skipped("no container field configured".to_string())
+            ),
+            ("script", SmokeOutcome::Passed),
+            ("delete", SmokeOutcome::Passed),
+        ]
+    );
+    assert_eq!(server.records("People").len(), 1);
+    assert!(report
+        .to_string()
+        .ends_with("6 passed, 0 failed, 1 skipped"));
+
+    config.password = "wrong".to_string();
+    let report = run_smoke(&config).await;
+    assert!(!report.passed());
+    assert_eq!(report.steps.len(), 7);
+    assert!(report.to_string().starts_with("FAIL  connect"));
+}
+
+#[tokio::test]
+async fn reads_layout_metadata() {
+    let server = server_with_people(1).await;
+    let filemaker = connect(&server).await;
+    let metadata = filemaker.get_layout_metadata().await.unwrap();
+    assert_eq!(metadata.fields.len(), 2);
+    assert_eq!(metadata.fields[0].repetitions, 1);
+    assert!(metadata.portals.is_empty());
+
+    server.set_layout_metadata(
+        "People",
+        json!({
+            "fieldMetaData": [
+                { "name": "Name", "type": "normal", "result": "text", "notEmpty": true,
+                  "maxCharacters": 40, "maxRepeat": 1 },
+                { "name": "Status", "type": "normal", "result": "text", "valueList": "Statuses" },
+                { "name": "Phones", "type": "normal", "result": "text", "maxRepeat": 3 },
+                { "name": "g_Today", "type": "normal", "result": "date", "global": true },
+            ],
+            "portalMetaData": {
+                "Orders": [{ "name": "Orders::Total", "type": "normal", "result": "number" }],
+            },
+            "valueLists": [{
+                "name": "Statuses",
+                "type": "customList",
+                "values": [{ "value": "A", "displayValue": "Active" }],
+            }],
+        }),
+    );
+    let metadata = filemaker.get_layout_metadata().await.unwrap();
+    let name = &metadata.fields[0];
+    assert!(name.not_empty);
+    assert_eq!(name.max_characters, 40);
+    assert_eq!(metadata.fields[1].value_list.as_deref(), Some("Statuses"));
+    assert_eq!(metadata.fields[2].repetitions, 3);
+    assert!(metadata.fields[3].global);
+    assert_eq!(metadata.fields[3].result, "date");
+    assert_eq!(metadata.portals["Orders"][0].name, "Orders::Total");
+    assert_eq!(metadata.portals["Orders"][0].result, "number");
+    assert_eq!(metadata.value_lists[0].values[0].display_value, "Active");
+
+    let error = filemaker
+        .with_layout("Missing")
+        .get_layout_metadata()
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+}
+
+#[tokio::test]
+async fn fetches_portal_rows() {
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Person {
+        #[serde(rename = "Name")]
+        name: String,
+    }
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Order {
+        #[serde(rename = "Orders::Total")]
+        total: u64,
+    }
+
+    let server = server_with_people(2).await;
+    let ids: Vec<String> = server
+        .records("People")
+        .iter()
+        .map(|r| r["recordId"].as_str().unwrap().to_string())
+        .collect();
+    let orders = (1..=4)
+        .map(|i| json!({ "Orders::Total": i * 10 }))
+        .collect();
+    server.set_portal("People", &ids[0], "Orders", orders);
+    server.set_portal(
+        "People",
+        &ids[0],
+        "Notes",
+        vec![json!({ "Notes::Text": "hi" })],
+    );
+    let filemaker = connect(&server).await;
+
+    let options = GetOptions {
+        portals: Some(vec![Portal::new("Orders").with_range(2, 2)]),
+        ..Default::default()
+    };
+    let records = filemaker.get_records_as::<Person>(&options).await.unwrap();
+    assert_eq!(records[0].data.name, "Person 0");
+    assert_eq!(records[0].portal_names(), ["Orders"]);
+    let rows = records[0].portal("Orders").unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].record_id, "2");
+    assert_eq!(rows[0].get("Total"), Some(&json!(20)));
+    assert_eq!(rows[1].get("Orders::Total"), Some(&json!(30)));
+    assert!(records[0].portal("Notes").unwrap().is_empty());
+    assert!(records[1].portal("Orders").unwrap().is_empty());
+
+    let record = filemaker
+        .get_record_by_id_as::<Person>(&ids[0], &GetOptions::default())
+        .await
+        .unwrap();
+    let orders: Vec<Order> = record.portal_as("Orders").unwrap();
+    assert_eq!(orders.iter().map(|o| o.total).sum::<u64>(), 100);
+    assert_eq!(
+        record.portal("Notes").unwrap()[0].get("Text"),
+        Some(&json!("hi"))
+    );
+
+    let error = record.portal_as::<Person>("Orders").unwrap_err();
+    let error = error.downcast_ref::<DeserializeError>().unwrap();
+    assert_eq!(error.path, "portalData.Orders[0]");
+}
+
+#[tokio::test]
+async fn runs_record_hooks() {
+    struct Audit(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl RecordHook for Audit {
+        fn before_create<'a>(
+            &'a self,
+            _layout: &'a str,
+            field_data: &'a mut Map<String, Value>,
+        ) -> BoxFuture<'a, anyhow::Result<()>> {
+            Box::pin(async move {
+                field_data.insert("Age".to_string(), json!(99));
+                Ok(())
+            })
+        }
+
+        fn after_create<'a>(&'a self, layout: &'a str, record_id: &'a str) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                let event = format!("created {} on {}", record_id, layout);
+                self.0.lock().unwrap().push(event);
+            })
+        }
+
+        fn before_update<'a>(
+            &'a self,
+            _layout: &'a str,
+            record_id: &'a str,
+            field_data: &'a mut Map<String, Value>,
+        ) -> BoxFuture<'a, anyhow::Result<()>> {
+            Box::pin(async move {
+                match field_data.get("Name") == Some(&json!("")) {
+                    true => Err(anyhow::anyhow!("Record {} needs a name", record_id)),
+                    false => Ok(()),
+                }
+            })
+        }
+
+        fn after_delete<'a>(&'a self, _layout: &'a str, record_id: &'a str) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("deleted {}", record_id));
+            })
+        }
+    }
+
+    let server = server_with_people(0).await;
+    let events = Arc::new(std::sync::Mutex::new(vec![]));
+    let filemaker = connect(&server).await.with_hook(Audit(events.clone()));
+
+    let fields = HashMap::from([("Name".to_string(), json!("Ada"))]);
+    let created = filemaker.create_record(fields).await.unwrap();
+    assert_eq!(created.record["fieldData"]["Age"], json!(99));
+
+    let rename = HashMap::from([("Name".to_string(), json!("Ada L."))]);
+    filemaker
+        .update_record_guarded(&created.record_id, rename, None)
+        .await
+        .unwrap();
+    let patches = |server: &StubServer| {
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("PATCH"))
+            .count()
+    };
+    assert_eq!(patches(&server), 1);
+
+    let blank = HashMap::from([("Name".to_string(), json!(""))]);
+    let error = filemaker
+        .update_record(&created.record_id, blank)
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", error).contains("needs a name"));
+    assert_eq!(patches(&server), 1);
+    assert_eq!(server.records("People")[0]["fieldData"]["Name"], "Ada L.");
+
+    filemaker.delete_record(&created.record_id).await.unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            format!("created {} on People", created.record_id),
+            format!("deleted {}", created.record_id),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn stamps_audit_fields() {
+    let server = server_with_people(0).await;
+    server.add_layout("People", ["ModifiedBy", "ModifiedAt", "SourceSystem"]);
+    let audit = AuditFields::default()
+        .with_modified_by("ModifiedBy", "billing-sync")
+        .with_modified_at("ModifiedAt")
+        .with_source_system("SourceSystem", "billing");
+    let filemaker = connect(&server)
+        .await
+        .with_server_timezone(chrono_tz::Europe::Berlin)
+        .with_audit_fields(audit);
+
+    let fields = HashMap::from([
+        ("Name".to_string(), json!("Ada")),
+        ("ModifiedBy".to_string(), json!("someone else")),
+    ]);
+    filemaker.create_record_ids(fields).await.unwrap();
+    let record = &server.records("People")[0]["fieldData"];
+    assert_eq!(record["ModifiedBy"], "billing-sync");
+    assert_eq!(record["SourceSystem"], "billing");
+    let stamped = filemaker
+        .to_utc_timestamp(&record["ModifiedAt"])
+        .unwrap()
+        .unwrap();
+    assert!((chrono::Utc::now() - stamped).num_seconds().abs() < 5);
+
+    server.insert_record("People", json!({ "Name": "Grace" }));
+    let grace = server.records("People")[1]["recordId"].clone();
+    let rename = HashMap::from([("Name".to_string(), json!("Grace H."))]);
+    filemaker
+        .update_record(grace.as_str().unwrap(), rename)
+        .await
+        .unwrap();
+    let record = &server.records("People")[1]["fieldData"];
+    assert_eq!(record["Name"], "Grace H.");
+    assert_eq!(record["ModifiedBy"], "billing-sync");
+    assert_eq!(record["SourceSystem"], "billing");
+}
+
+#[tokio::test]
+async fn writes_portal_rows() {
+    let server = server_with_people(0).await;
+    let filemaker = connect(&server).await;
+
+    let fields = HashMap::from([("Name".to_string(), json!("Ada"))]);
+    let options = WriteOptions::default()
+        .with_portal_row("Orders", PortalRow::new([("Orders::Total", json!(10))]))
+        .with_portal_row("Orders", PortalRow::new([("Orders::Total", json!(20))]));
+    let added = filemaker.add_record_with(fields, &options).await.unwrap();
+    assert_eq!(added["success"], json!(true));
+    let id = added["result"]["recordId"].as_str().unwrap().to_string();
+
+    let record = filemaker
+        .get_record_by_id_as::<Value>(&id, &GetOptions::default())
+        .await
+        .unwrap();
+    let rows = record.portal("Orders").unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].get("Total"), Some(&json!(20)));
+
+    let rename = HashMap::from([("Name".to_string(), json!("Ada L."))]);
+    let options = WriteOptions::default()
+        .with_portal_row(
+            "Orders",
+            PortalRow::new([("Orders::Total", json!(15))]).with_record_id(&rows[0].record_id),
+        )
+        .with_portal_row("Orders", PortalRow::new([("Orders::Total", json!(30))]));
+    filemaker
+        .update_record_with(&id, rename, &options)
+        .await
+        .unwrap();
+    let record = filemaker
+        .get_record_by_id_as::<Value>(&id, &GetOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(record.data["Name"], "Ada L.");
+    let totals: Vec<Value> = record
+        .portal("Orders")
+        .unwrap()
+        .iter()
+        .map(|row| row.get("Total").cloned().unwrap())
+        .collect();
+    assert_eq!(totals, [json!(15), json!(20), json!(30)]);
+
+    let missing = WriteOptions::default().with_portal_row(
+        "Orders",
+        PortalRow::new([("Orders::Total", json!(0))]).with_record_id("99"),
+    );
+    let response = filemaker
+        .update_record_with(&id, HashMap::new(), &missing)
+        .await
+        .unwrap();
+    assert_eq!(response["messages"][0]["code"], "101");
+}
+
+#[tokio::test]
+async fn logs_out() {
+    let server = server_with_people(1).await;
+    let filemaker = connect(&server).await;
+    let clone = filemaker.clone();
+    assert_eq!(server.session_count(), 1);
+
+    filemaker.logout().await.unwrap();
+    assert_eq!(server.session_count(), 0);
+    assert!(server
+        .requests()
+        .contains(&"DELETE /databases/Contacts/sessions/stub-token-1".to_string()));
+    assert!(clone.get_records(1, 10).await.is_err());
+    filemaker.logout().await.unwrap();
+
+    let filemaker = connect(&server)
+        .await
+        .with_standby_session(StandbyOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(server.session_count(), 2);
+    filemaker.logout().await.unwrap();
+    assert_eq!(server.session_count(), 0);
+}
+
+#[tokio::test]
+async fn ends_sessions_of_guards() {
+    let server = server_with_people(1).await;
+    let session = connect(&server).await.into_session();
+    assert_eq!(session.get_records(1, 10).await.unwrap().len(), 1);
+    session.close().await.unwrap();
+    assert_eq!(server.session_count(), 0);
+
+    let session = connect(&server).await.into_session();
+    assert_eq!(server.session_count(), 1);
+    drop(session);
+    for _ in 0..50 {
+        if server.session_count() == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(server.session_count(), 0);
+}
+
+#[tokio::test]
+async fn shares_a_throttle_between_clients() {
+    let server = server_with_people(2).await;
+    server.add_layout("Companies", ["Name"]);
+    server.insert_record("Companies", json!({ "Name": "Acme" }));
+    let throttle = FmThrottle::new(1).with_rate(20, Duration::from_secs(1));
+    let people = connect(&server).await.with_throttle(throttle.clone());
+    let companies = connect(&server)
+        .await
+        .with_layout("Companies")
+        .with_throttle(throttle.clone());
+    assert_eq!(throttle.max_concurrent(), 1);
+
+    // Six requests 50 ms apart take at least 250 ms in sum
+    let started = std::time::Instant::now();
+    tokio::join!(
+        async {
+            for _ in 0..3 {
+                people.get_records(1, 10).await.unwrap();
+            }
+        },
+        async {
+            for _ in 0..3 {
+                companies.get_records(1, 10).await.unwrap();
+            }
+        }
+    );
+    assert!(started.elapsed() >= Duration::from_millis(250));
+    assert_eq!(throttle.in_flight(), 0);
+}
+
+#[tokio::test]
+async fn refreshes_summary_tables() {
+    let server = server_with_people(0).await;
+    server.add_layout("Invoices", ["Customer", "Total"]);
+    server.add_layout(
+        "InvoiceTotals",
+        ["Customer", "Invoices", "Revenue", "Largest"],
+    );
+    for (customer, total) in [
+        ("acme", "10"),
+        ("acme", "25"),
+        ("globex", "5"),
+        ("acme", ""),
+    ] {
+        server.insert_record("Invoices", json!({ "Customer": customer, "Total": total }));
+    }
+    server.insert_record(
+        "InvoiceTotals",
+        json!({ "Customer": "globex", "Invoices": 9 }),
+    );
+    let filemaker = connect(&server).await.with_layout("Invoices");
+
+    let report = filemaker
+        .refresh_summary(
+            "InvoiceTotals",
+            "Customer",
+            &[
+                ("Invoices", Aggregate::Count),
+                ("Revenue", Aggregate::Sum("Total".to_string())),
+                ("Largest", Aggregate::Max("Total".to_string())),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.source_records, 4);
+    assert_eq!((report.created, report.updated), (1, 1));
+    assert!(report.failed.is_empty());
+
+    let totals = server.records("InvoiceTotals");
+    assert_eq!(totals.len(), 2);
+    let globex = &totals[0]["fieldData"];
+    assert_eq!(globex["Invoices"], json!(1));
+    assert_eq!(globex["Revenue"], json!(5.0));
+    let acme = &totals[1]["fieldData"];
+    assert_eq!(acme["Customer"], "acme");
+    assert_eq!(acme["Invoices"], json!(3));
+    assert_eq!(acme["Revenue"], json!(35.0));
+    assert_eq!(acme["Largest"], json!(25.0));
+}
+
+#[tokio::test]
+async fn aggregates_records() {
+    let server = server_with_people(0).await;
+    server.add_layout("Invoices", ["Region", "Status", "Total"]);
+    for (region, status, total) in [
+        ("north", "paid", "10"),
+        ("north", "paid", "20"),
+        ("north", "open", "99"),
+        ("south", "paid", "5"),
+        ("south", "paid", ""),
+    ] {
+        server.insert_record(
+            "Invoices",
+            json!({ "Region": region, "Status": status, "Total": total }),
+        );
+    }
+    let filemaker = connect(&server).await.with_layout("Invoices");
+
+    let groups = filemaker
+        .aggregate(
+            &[HashMap::from([("Status".to_string(), "paid".to_string())])],
+            &["Region", "Status"],
+            &[
+                Aggregate::Count,
+                Aggregate::Sum("Total".to_string()),
+                Aggregate::Avg("Total".to_string()),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        groups,
+        vec![
+            AggregateGroup {
+                key: vec![json!("north"), json!("paid")],
+                count: 2,
+                values: vec![Some(2.0), Some(30.0), Some(15.0)],
+            },
+            AggregateGroup {
+                key: vec![json!("south"), json!("paid")],
+                count: 2,
+                values: vec![Some(2.0), Some(5.0), Some(5.0)],
+            },
+        ]
+    );
+
+    let overall = filemaker
+        .aggregate(&[], &[], &[Aggregate::Min("Total".to_string())])
+        .await
+        .unwrap();
+    assert_eq!(overall.len(), 1);
+    assert_eq!((overall[0].count, overall[0].values[0]), (5, Some(5.0)));
+
+    server.insert_record(
+        "Invoices",
+        json!({ "Region": "west", "Status": "paid", "Total": "n/a" }),
+    );
+    let error = filemaker
+        .aggregate(&[], &["Region"], &[Aggregate::Sum("Total".to_string())])
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", error).contains("Field Total"));
+}
+
+#[tokio::test]
+async fn samples_records() {
+    let server = server_with_people(20).await;
+    let filemaker = connect(&server).await;
+    let ages = |records: Vec<Value>| -> Vec<u64> {
+        records
+            .iter()
+            .map(|record| record["fieldData"]["Age"].as_u64().unwrap())
+            .collect()
+    };
+
+    let first = filemaker.sample_records(3, SampleStrategy::First).await;
+    assert_eq!(ages(first.unwrap()), vec![0, 1, 2]);
+
+    for strategy in [SampleStrategy::RandomOffsets, SampleStrategy::Reservoir] {
+        let sample = ages(filemaker.sample_records(5, strategy).await.unwrap());
+        assert_eq!(sample.len(), 5, "{:?}", strategy);
+        assert!(
+            sample.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            strategy
+        );
+        assert!(sample.iter().all(|age| *age < 20));
+
+        let all = filemaker.sample_records(50, strategy).await.unwrap();
+        assert_eq!(ages(all), (0..20).collect::<Vec<_>>());
+    }
+    assert!(filemaker
+        .sample_records(0, SampleStrategy::Reservoir)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn breaks_sort_ties_by_a_unique_field() {
+    let server = server_with_people(0).await;
+    server.add_layout("People", ["RecordID"]);
+    for (i, age) in [30, 20, 30, 20].into_iter().enumerate() {
+        server.insert_record(
+            "People",
+            json!({ "Name": format!("Person {}", i), "Age": age, "RecordID": i }),
+        );
+    }
+    let filemaker = connect(&server).await.with_sort_tiebreaker("RecordID");
+    assert_eq!(filemaker.sort_tiebreaker(), Some("RecordID"));
+
+    let cursor = Cursor::new(2).with_sort(vec!["Age".to_string()], false);
+    let page = filemaker.fetch_page(&cursor).await.unwrap();
+    assert_eq!(page.records.len(), 2);
+    let request = query::decode_url_component(server.requests().last().unwrap());
+    assert!(request.contains(
+        r#"_sort=[{"fieldName":"Age","sortOrder":"descend"},{"fieldName":"RecordID","sortOrder":"descend"}]"#
+    ));
+
+    let preview = filemaker
+        .explain(
+            vec![],
+            vec!["Age".to_string(), "RecordID".to_string()],
+            true,
+            None,
+        )
+        .unwrap();
+    assert_eq!(preview.body.unwrap()["sort"].as_array().unwrap().len(), 2);
+    filemaker.get_records(1, 10).await.unwrap();
+    let request = query::decode_url_component(server.requests().last().unwrap());
+    assert!(!request.contains("_sort"));
+}
+
+#[tokio::test]
+async fn logs_in_again_when_sessions_expire() {
+    // The only test relying on FM_URL, which is global
+    let server = server_with_people(2).await;
+    Filemaker::set_fm_url(server.url()).unwrap();
+    let filemaker = Filemaker::new("admin", "secret", "Contacts", "People")
+        .await
+        .unwrap();
+
+    server.expire_sessions();
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 2);
+    assert_eq!(server.session_count(), 1);
+    assert_eq!(filemaker.stats().retries, 1);
+
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let counter = lookups.clone();
+    let filemaker = filemaker.with_credential_provider(move || {
+        counter.fetch_add(1, AtomicOrdering::SeqCst);
+        Ok(("admin".to_string(), "secret".to_string()))
+    });
+    server.expire_sessions();
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 2);
+    assert_eq!(lookups.load(AtomicOrdering::SeqCst), 1);
+
+    let filemaker = filemaker.with_credential_provider(|| Err(anyhow::anyhow!("vault sealed")));
+    server.expire_sessions();
+    let error = filemaker.get_records(1, 10).await.unwrap_err();
+    assert!(format!("{:#}", error).contains("vault sealed"));
+}
+
+#[tokio::test]
+async fn talks_to_several_servers_with_configs() {
+    let config = FmConfig::new("https://fm.example.com/fmi/data/v1/");
+    assert_eq!(config.base_url, "https://fm.example.com");
+    assert_eq!(config.api_version, ApiVersion::V1);
+    let config = FmConfig::new("https://fm.example.com");
+    assert_eq!(
+        config.data_api_url(),
+        "https://fm.example.com/fmi/data/vLatest"
+    );
+    let config = config.with_api_version(ApiVersion::V2);
+    assert_eq!(config.data_api_url(), "https://fm.example.com/fmi/data/v2");
+    assert_eq!("VLATEST".parse::<ApiVersion>().unwrap(), ApiVersion::Latest);
+    assert!("v3".parse::<ApiVersion>().is_err());
+
+    let first = server_with_people(1).await;
+    let second = server_with_people(3).await;
+    let timeouts = Timeouts {
+        connect: Some(Duration::from_secs(5)),
+        request: Some(Duration::from_secs(30)),
+    };
+    let mut clients = vec![];
+    for server in [&first, &second] {
+        let config = FmConfig::new(server.url()).with_timeouts(timeouts);
+        let filemaker = Filemaker::with_config(config, "admin", "secret", "Contacts", "People")
+            .await
+            .unwrap();
+        clients.push(filemaker);
+    }
+    assert_eq!(clients[0].get_records(1, 10).await.unwrap().len(), 1);
+    assert_eq!(clients[1].get_records(1, 10).await.unwrap().len(), 3);
+
+    // Logins after the session expired go to the configured server as well
+    second.expire_sessions();
+    assert_eq!(clients[1].get_records(1, 10).await.unwrap().len(), 3);
+    assert_eq!(second.session_count(), 1);
+}
+
+#[tokio::test]
+async fn authorizes_with_fronting_proxies() {
+    let server = server_with_people(2).await;
+    server.require_proxy_auth("X-Gateway-Authorization", "Bearer gateway-token");
+
+    let config = FmConfig::new(server.url());
+    assert!(
+        Filemaker::with_config(config, "admin", "secret", "Contacts", "People")
+            .await
+            .is_err()
+    );
+
+    let proxy_auth = ProxyAuth::bearer("gateway-token").with_header("X-Gateway-Authorization");
+    assert!(!format!("{:?}", proxy_auth).contains("gateway-token"));
+    let config = FmConfig::new(server.url()).with_proxy_auth(proxy_auth);
+    let filemaker = Filemaker::with_config(config, "admin", "secret", "Contacts", "People")
+        .await
+        .unwrap();
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn builds_clients_step_by_step() {
+    let server = server_with_people(2).await;
+    let builder = Filemaker::builder()
+        .url(server.url())
+        .username("admin")
+        .password("secret")
+        .database("Contacts")
+        .timeout(Duration::from_secs(30))
+        .accept_invalid_certs(false);
+    assert!(!format!("{:?}", builder).contains("secret"));
+
+    let missing = builder.clone().build().await.err().unwrap();
+    assert!(missing.to_string().contains("layout"));
+
+    let filemaker = builder.layout("People").build().await.unwrap();
+    assert_eq!(filemaker.layout(), "People");
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn builds_clients_with_a_custom_http_client() {
+    let server = server_with_people(2).await;
+    server.require_proxy_auth("X-Gateway-Authorization", "Bearer gateway-token");
+    let builder = Filemaker::builder()
+        .url(server.url())
+        .username("admin")
+        .password("secret")
+        .database("Contacts")
+        .layout("People");
+    assert!(builder.clone().build().await.is_err());
+
+    let headers = reqwest::header::HeaderMap::from_iter([(
+        reqwest::header::HeaderName::from_static("x-gateway-authorization"),
+        reqwest::header::HeaderValue::from_static("Bearer gateway-token"),
+    )]);
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+    // The builder's own proxy settings do not apply to a custom client
+    let filemaker = builder
+        .proxy_auth(ProxyAuth::bearer("other-token").with_header("X-Gateway-Authorization"))
+        .with_client(client)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(filemaker.get_records(1, 10).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn surfaces_warnings_of_successful_responses() {
+    let server = server_with_people(2).await;
+    let filemaker = connect(&server).await;
+
+    server.inject_warning(401, "No records match the request");
+    let query = vec![HashMap::from([("Age".to_string(), ">=0".to_string())])];
+    let found = filemaker
+        .search::<Value>(query, vec![], true, None)
+        .await
+        .unwrap();
+    assert_eq!(found.response.data.len(), 2);
+    assert_eq!(
+        found.warnings,
+        vec![ResponseWarning {
+            code: 401,
+            message: "No records match the request".to_string(),
+            source: WarningSource::Message,
+        }]
+    );
+
+    server.inject_warning(101, "Record is missing");
+    let page = filemaker.fetch_page(&Cursor::new(10)).await.unwrap();
+    assert_eq!(page.warnings[0].code, 101);
+    let page = filemaker.fetch_page(&Cursor::new(10)).await.unwrap();
+    assert!(page.warnings.is_empty());
+
+    let response = json!({
+        "response": { "scriptError.prerequest": "3", "scriptError": "0" },
+        "messages": [{ "code": "0", "message": "OK" }],
+    });
+    let warnings = response_warnings(&response);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].source, WarningSource::PreRequestScript);
+    let failed = json!({ "messages": [{ "code": "401", "message": "No records match" }] });
+    assert!(response_warnings(&failed).is_empty());
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Contact {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Age")]
+    age: Option<f64>,
+    #[serde(rename = "Email")]
+    email: String,
+    #[serde(rename = "Active")]
+    active: bool,
+    #[serde(rename = "Notes", default)]
+    notes: filemaker_lib::FmValue,
+}
+
+impl filemaker_lib::FmRecordModel for Contact {
+    const LAYOUT: &'static str = "Contacts";
+}
+
+#[tokio::test]
+async fn verifies_models_against_layout_metadata() {
+    let server = server_with_people(1).await;
+    server.add_layout("Contacts", ["Name"]);
+    server.set_layout_metadata(
+        "Contacts",
+        json!({
+            "fieldMetaData": [
+                { "name": "Name", "type": "normal", "result": "text" },
+                { "name": "Age", "type": "normal", "result": "number" },
+                { "name": "Active", "type": "normal", "result": "number" },
+                { "name": "Phone", "type": "normal", "result": "text" },
+                { "name": "Notes", "type": "normal", "result": "text" },
+                { "name": "g_Today", "type": "normal", "result": "date", "global": true },
+            ],
+        }),
+    );
+    let filemaker = connect(&server).await;
+
+    let fields = <Contact as filemaker_lib::FmRecordModel>::fields();
+    assert_eq!(
+        fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.kind, f.optional))
+            .collect::<Vec<_>>(),
+        vec![
+            ("Name", ModelFieldType::Text, false),
+            ("Age", ModelFieldType::Number, true),
+            ("Email", ModelFieldType::Text, false),
+            ("Active", ModelFieldType::Boolean, false),
+            ("Notes", ModelFieldType::Any, false),
+        ]
+    );
+
+    let report = filemaker.verify_model::<Contact>().await.unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.layout, "Contacts");
+    assert_eq!(report.missing, vec!["Email"]);
+    assert_eq!(report.extra, vec!["Phone", "g_Today"]);
+    assert_eq!(report.mistyped.len(), 1);
+    assert_eq!(report.mistyped[0].name, "Active");
+    assert_eq!(report.mistyped[0].expected, ModelFieldType::Boolean);
+    assert_eq!(report.mistyped[0].result, "number");
+    assert!(report.to_string().contains("MISSING   Email"));
+
+    let filtered = filemaker.clone().with_field_filter(FieldFilter::default());
+    let report = filtered.verify_model::<Contact>().await.unwrap();
+    assert_eq!(report.extra, vec!["Phone"]);
+
+    // People holds text ages in the stub, which a u32 cannot be read from
+    let report = filemaker.verify_model::<Person>().await.unwrap();
+    assert!(report.missing.is_empty());
+    assert_eq!(report.mistyped[0].name, "Age");
+}